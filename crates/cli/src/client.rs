@@ -1,17 +1,148 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, error, warn};
+use ::daemon::auth::Credentials;
+use ::daemon::handlers::{VersionResponse, MAX_BATCH_ADDRESSES};
+use ::daemon::validation::qualify_repo_name;
+use ::daemon::work_dirs::{DiskUsage, SweepReport};
+use onchain::contract_interaction::Costs;
+
+/// How long a request may take unless `--timeout` or `DGIT_TIMEOUT` says
+/// otherwise; longer than the daemon's own request timeout, so a slow
+/// request gets the daemon's answer rather than the CLI giving up first.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Attempts for a GET that fails to connect or gets a 502, 503 or 504.
+const GET_ATTEMPTS: u32 = 3;
 
 #[derive(Clone)]
 pub struct DaemonClient {
     client: Client,
     base_url: String,
+    /// The URL the client was created with, before a socket is bridged.
+    daemon_url: String,
+    credentials: Option<Credentials>,
+    chain_id: Option<u64>,
+    timeout: Option<Duration>,
+}
+
+/// Why a request to the daemon failed, so commands can tell a repository
+/// that does not exist from a daemon that is not running.
+#[derive(Debug)]
+pub enum DaemonError {
+    /// 404: the repository, or whatever else the request names, does not exist.
+    NotFound { action: &'static str, body: String },
+    /// 401 or 403: no credentials, or the account lacks the role.
+    Unauthorized { action: &'static str, status: StatusCode, body: String },
+    /// Any other answer that is not a success.
+    ServerError { action: &'static str, status: StatusCode, body: String },
+    /// The daemon could not be reached, or did not answer in time.
+    Network(reqwest::Error),
+    /// The daemon answered with something other than the expected JSON.
+    Decode { what: &'static str, source: serde_json::Error },
+}
+
+impl DaemonError {
+    /// Exit code for a command that fails with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DaemonError::NotFound { .. } => 2,
+            DaemonError::Unauthorized { .. } => 3,
+            DaemonError::Network(_) => 4,
+            DaemonError::ServerError { .. } | DaemonError::Decode { .. } => 1,
+        }
+    }
+
+    /// What to try next, for people.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            DaemonError::NotFound { .. } => Some("Check the repository name, and --chain if it lives on several chains"),
+            DaemonError::Unauthorized { .. } => Some("Check the active account with `dgit account current` and its roles with `dgit repo role list`"),
+            DaemonError::Network(e) if e.is_timeout() => Some("Raise the limit with --timeout or DGIT_TIMEOUT (in seconds, 0 for none)"),
+            DaemonError::Network(_) => Some("Is the daemon running? Start it with `dgit daemon`, or point --daemon-url at it"),
+            DaemonError::ServerError { .. } | DaemonError::Decode { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DaemonError::NotFound { action, body }
+            | DaemonError::Unauthorized { action, body, .. }
+            | DaemonError::ServerError { action, body, .. }
+                if !body.trim().is_empty() => write!(f, "{}: {}", action, body.trim()),
+            DaemonError::NotFound { action, .. } => write!(f, "{}: not found", action),
+            DaemonError::Unauthorized { action, status, .. } | DaemonError::ServerError { action, status, .. } => {
+                write!(f, "{}: daemon answered {}", action, status)
+            }
+            DaemonError::Network(e) if e.is_timeout() => write!(f, "The daemon did not answer in time"),
+            DaemonError::Network(e) if e.is_connect() => write!(f, "Could not connect to the daemon"),
+            DaemonError::Network(_) => write!(f, "Request to the daemon failed"),
+            DaemonError::Decode { what, .. } => write!(f, "{}", what),
+        }
+    }
+}
+
+impl std::error::Error for DaemonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DaemonError::Network(e) => Some(e),
+            DaemonError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the daemon has the API level a command needs.
+#[derive(Debug)]
+pub enum ApiCheck {
+    Supported,
+    TooOld(VersionResponse),
+    /// The daemon predates `/version`, so its level is not known.
+    Unknown,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateRepoResponse {
     pub repo: String,
     pub address: String,
+    #[serde(default)]
+    pub tx_hash: Option<String>,
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    /// Missing from daemons that predate chain ids.
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub gas_used: Option<u64>,
+    #[serde(default)]
+    pub fee_wei: Option<u128>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolveResponse {
+    pub repo: String,
+    pub registry: String,
+    pub address: String,
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForkResponse {
+    #[serde(flatten)]
+    pub created: CreateRepoResponse,
+    pub source: String,
+    pub objects_copied: usize,
+    pub refs_copied: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,108 +150,912 @@ pub struct RoleResponse {
     pub has_role: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchRoleResult {
+    pub address: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchRoleResponse {
+    pub results: Vec<BatchRoleResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoleListResponse {
+    pub repo: String,
+    pub admins: Vec<String>,
+    pub pushers: Vec<String>,
+    #[serde(default)]
+    pub readers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HasObjectsResponse {
+    pub hashes: Vec<String>,
+    pub exists: Vec<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefEntry {
+    pub name: String,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefsResponse {
+    pub refs: Vec<RefEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MissingObject {
+    pub hash: String,
+    pub cid: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrokenRef {
+    pub name: String,
+    pub target: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub repo: String,
+    pub ok: bool,
+    pub objects_checked: usize,
+    pub refs_checked: usize,
+    pub missing_objects: Vec<MissingObject>,
+    pub broken_refs: Vec<BrokenRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepinReport {
+    pub repo: String,
+    pub objects_checked: usize,
+    pub already_pinned: usize,
+    pub repinned: usize,
+    pub unrecoverable: Vec<MissingObject>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinInfo {
+    pub cid: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    pub referenced: bool,
+    pub shared: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinsResponse {
+    pub repo: String,
+    pub pins: Vec<PinInfo>,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnpinFailure {
+    pub cid: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnpinReport {
+    pub repo: String,
+    pub all: bool,
+    pub dry_run: bool,
+    pub unpinned: Vec<String>,
+    pub shared: Vec<String>,
+    pub recent: Vec<String>,
+    pub failed: Vec<UnpinFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FsckProblem {
+    pub severity: String,
+    pub kind: String,
+    #[serde(default)]
+    pub object: Option<String>,
+    #[serde(default)]
+    pub cid: Option<String>,
+    #[serde(default, rename = "ref")]
+    pub ref_name: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FsckSummary {
+    pub repo: String,
+    pub objects_checked: usize,
+    pub refs_checked: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub missing_objects: usize,
+    pub hash_mismatches: usize,
+    pub undecodable: usize,
+    pub dangling_refs: usize,
+    #[serde(default)]
+    pub unreachable_refs: usize,
+    pub broken_links: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FsckEvent {
+    Problem(FsckProblem),
+    Summary(FsckSummary),
+    Error { message: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrunedRef {
+    pub index: usize,
+    pub name: String,
+    pub target: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub repo: String,
+    pub dry_run: bool,
+    pub entries: usize,
+    pub active: usize,
+    pub pruned: Vec<PrunedRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GcReport {
+    pub repo: String,
+    pub dry_run: bool,
+    pub objects_checked: usize,
+    pub reachable: usize,
+    pub unreferenced: Vec<String>,
+    pub tx_hashes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub action: String,
+    pub repo: String,
+    pub actor: Option<String>,
+    #[serde(default)]
+    pub unverified_signer: Option<String>,
+    pub target: Option<String>,
+    pub role: Option<String>,
+    pub tx_hash: Option<String>,
+    #[serde(default)]
+    pub costs: Option<Costs>,
+    #[serde(default)]
+    pub push_options: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditResponse {
+    pub repo: String,
+    pub entries: Vec<AuditEntry>,
+}
+
+/// An object or ref recorded on chain, from `GET /repo/{repo}/history`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub block_number: u64,
+    pub tx_hash: String,
+    pub pusher: String,
+    /// `object` or `ref`.
+    pub kind: String,
+    pub name: String,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub repo: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub changes: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CostsResponse {
+    pub repo: String,
+    pub total: Costs,
+    pub by_action: BTreeMap<String, Costs>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnchorJob {
+    pub id: u64,
+    pub status: String,
+    pub attempts: u32,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub created: u64,
+    pub updated: u64,
+    pub object_count: usize,
+    pub ref_count: usize,
+    #[serde(default)]
+    pub tx_hashes: Vec<String>,
+    #[serde(default)]
+    pub costs: Costs,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnchoringStatusResponse {
+    pub repo: String,
+    pub enabled: bool,
+    pub jobs: Vec<AnchorJob>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddWebhookRequest {
+    pub url: String,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepoConfigResponse {
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeysRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_allowed_signers: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_signed_commits: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SigningKeys {
+    #[serde(default)]
+    pub gpg: String,
+    #[serde(default)]
+    pub ssh_allowed_signers: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeysResponse {
+    pub require_signed_commits: bool,
+    #[serde(default)]
+    pub signing_keys: SigningKeys,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtectionRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub deny_force_push: bool,
+    #[serde(default)]
+    pub deny_delete: bool,
+    #[serde(default)]
+    pub admins_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtectionResponse {
+    pub rules: Vec<ProtectionRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveProtectionResponse {
+    pub removed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookInfo {
+    pub url: String,
+    pub has_secret: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<WebhookInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveWebhookResponse {
+    pub removed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisibilityRequest {
+    pub visibility: String,
+}
+
 impl DaemonClient {
+    /// Client for the daemon at `base_url`, an `http(s)://` URL or
+    /// `unix:///path/to/socket` (or `unix:/path/to/socket`) for a daemon
+    /// serving on a socket.
     pub fn new(base_url: String) -> Self {
+        let daemon_url = base_url.clone();
+        let socket = base_url
+            .strip_prefix("unix://")
+            .or_else(|| base_url.strip_prefix("unix:"));
+        let base_url = match socket {
+            Some(path) => bridge_unix_socket(Path::new(path)).unwrap_or_else(|e| {
+                error!("Failed to connect to daemon socket {}: {:#}", path, e);
+                base_url.clone()
+            }),
+            None => base_url,
+        };
+
         Self {
-            client: Client::new(),
+            client: Client::builder().connect_timeout(CONNECT_TIMEOUT).build().unwrap_or_default(),
             base_url,
+            daemon_url,
+            credentials: None,
+            chain_id: None,
+            timeout: Some(DEFAULT_TIMEOUT),
         }
     }
 
-    pub async fn health_check(&self) -> Result<()> {
-        let url = format!("{}/health", self.base_url);
-        let response = self.client.get(&url).send().await?;
+    /// Limits each request to `timeout`; `None` waits as long as it takes.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            anyhow::bail!("Health check failed with status: {}", response.status())
-        }
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The daemon URL as given, which tokens for this daemon must name.
+    pub fn daemon_url(&self) -> &str {
+        &self.daemon_url
+    }
+
+    /// Authenticates subsequent requests as the given account.
+    pub fn with_credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Addresses repositories on `chain_id`, so the daemon keeps them apart
+    /// from same-named repositories on other chains.
+    pub fn with_chain(mut self, chain_id: Option<u64>) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    fn repo_key(&self, repo: &str) -> String {
+        qualify_repo_name(repo, self.chain_id)
+    }
+
+    /// Git remote URL of `repo` on this daemon.
+    pub fn repo_url(&self, repo: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), self.repo_key(repo))
+    }
+
+    /// `Authorization` header value for git commands talking to this daemon.
+    pub fn auth_header(&self) -> Option<String> {
+        self.credentials.as_ref().map(Credentials::basic_header)
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.get(url))
+    }
+
+    fn post(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.post(url))
+    }
+
+    fn put(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.put(url))
     }
 
-    pub async fn create_repo(&self, repo_name: &str) -> Result<CreateRepoResponse> {
-        let url = format!("{}/create-repo/{}", self.base_url, repo_name);
-        let response = self.client.post(&url).send().await?;
+    fn delete(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.delete(url))
+    }
 
-        if response.status().is_success() {
-            response.json().await.context("Failed to parse create repo response")
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to create repository: {}", error_text)
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.credentials {
+            Some(credentials) => request.header(reqwest::header::AUTHORIZATION, credentials.basic_header()),
+            None => request,
         }
     }
 
-    pub async fn grant_pusher_role(&self, repo: &str, address: &str) -> Result<()> {
-        let url = format!("{}/repo/{}/grant-pusher/{}", self.base_url, repo, address);
-        let response = self.client.post(&url).send().await?;
+    /// Sends `request`, failing with `action` in the message unless the
+    /// daemon answers with a success.
+    ///
+    /// GETs change nothing, so one that cannot connect, or that a daemon
+    /// restarting or behind a proxy answers with 502, 503 or 504, is tried
+    /// again, up to `GET_ATTEMPTS` times.
+    async fn send(&self, request: RequestBuilder, action: &'static str) -> Result<Response, DaemonError> {
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let mut request = request.build().map_err(DaemonError::Network)?;
+        let attempts = if request.method() == Method::GET { GET_ATTEMPTS } else { 1 };
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to grant pusher role: {}", error_text)
+        let mut attempt = 1;
+        loop {
+            let next = request.try_clone().filter(|_| attempt < attempts);
+            let result = match self.client.execute(request).await {
+                Ok(response) => check_status(response, action).await,
+                Err(e) => Err(DaemonError::Network(e)),
+            };
+            match (result, next) {
+                (Err(e), Some(next)) if is_transient(&e) => {
+                    let backoff = Duration::from_millis(500 << (attempt - 1));
+                    warn!("{:#}; retrying in {:?} (attempt {}/{})", e, backoff, attempt, attempts);
+                    tokio::time::sleep(backoff).await;
+                    request = next;
+                    attempt += 1;
+                }
+                (result, _) => return result,
+            }
         }
     }
 
-    pub async fn revoke_pusher_role(&self, repo: &str, address: &str) -> Result<()> {
-        let url = format!("{}/repo/{}/revoke-pusher/{}", self.base_url, repo, address);
-        let response = self.client.post(&url).send().await?;
+    pub async fn health_check(&self) -> Result<(), DaemonError> {
+        let url = format!("{}/health", self.base_url);
+        self.send(self.get(&url), "Health check failed").await?;
+        Ok(())
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to revoke pusher role: {}", error_text)
+    /// The daemon's version and API level, or `None` for a daemon that
+    /// predates `/version`.
+    pub async fn version(&self) -> Result<Option<VersionResponse>, DaemonError> {
+        let url = format!("{}/version", self.base_url);
+        match self.send(self.get(&url), "Failed to get daemon version").await {
+            Ok(response) => json(response, "Failed to parse version response").await.map(Some),
+            Err(DaemonError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn grant_admin_role(&self, repo: &str, address: &str) -> Result<()> {
-        let url = format!("{}/repo/{}/grant-admin/{}", self.base_url, repo, address);
-        let response = self.client.post(&url).send().await?;
+    /// Disk used by the daemon's scratch directories.
+    pub async fn disk_usage(&self) -> Result<DiskUsage, DaemonError> {
+        let url = format!("{}/admin/disk-usage", self.base_url);
+        let response = self.send(self.get(&url), "Failed to get disk usage").await?;
+        json(response, "Failed to parse disk usage").await
+    }
+
+    /// Has the daemon remove orphaned scratch directories now.
+    pub async fn sweep_work_dirs(&self) -> Result<SweepReport, DaemonError> {
+        let url = format!("{}/admin/gc", self.base_url);
+        let response = self.send(self.post(&url), "Failed to sweep scratch directories").await?;
+        json(response, "Failed to parse sweep report").await
+    }
+
+    /// Checks the daemon has API level `level` before a command that needs
+    /// it, so a daemon that is too old is reported as such rather than as
+    /// whatever error it answers an unknown endpoint with.
+    pub async fn require_api_level(&self, level: u32) -> Result<ApiCheck, DaemonError> {
+        Ok(match self.version().await? {
+            Some(version) if version.api_level >= level => ApiCheck::Supported,
+            Some(version) => ApiCheck::TooOld(version),
+            None => ApiCheck::Unknown,
+        })
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to grant admin role: {}", error_text)
+    pub async fn repo_exists(&self, repo: &str) -> Result<bool, DaemonError> {
+        let url = format!("{}/repo/{}/refs", self.base_url, self.repo_key(repo));
+        match self.send(self.get(&url), "Failed to look up repository").await {
+            Ok(_) => Ok(true),
+            Err(DaemonError::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn revoke_admin_role(&self, repo: &str, address: &str) -> Result<()> {
-        let url = format!("{}/repo/{}/revoke-admin/{}", self.base_url, repo, address);
-        let response = self.client.post(&url).send().await?;
+    pub async fn create_repo(&self, repo_name: &str) -> Result<CreateRepoResponse, DaemonError> {
+        let url = format!("{}/create-repo/{}", self.base_url, self.repo_key(repo_name));
+        let response = self.send(self.post(&url), "Failed to create repository").await?;
+        json(response, "Failed to parse create repo response").await
+    }
+
+    pub async fn repo_info(&self, repo: &str) -> Result<CreateRepoResponse, DaemonError> {
+        let url = format!("{}/repo/{}/info", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to get repository info").await?;
+        json(response, "Failed to parse repository info").await
+    }
+
+    pub async fn resolve_repo(&self, repo: &str) -> Result<ResolveResponse, DaemonError> {
+        let url = format!("{}/repo/{}/resolve", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to resolve repository").await?;
+        json(response, "Failed to parse resolve response").await
+    }
+
+    pub async fn repo_costs(&self, repo: &str) -> Result<CostsResponse, DaemonError> {
+        let url = format!("{}/repo/{}/costs", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to get repository costs").await?;
+        json(response, "Failed to parse repository costs").await
+    }
+
+    pub async fn anchoring_status(&self, repo: &str) -> Result<AnchoringStatusResponse, DaemonError> {
+        let url = format!("{}/repo/{}/anchoring-status", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to get anchoring status").await?;
+        json(response, "Failed to parse anchoring status").await
+    }
+
+    pub async fn fork_repo(&self, repo: &str, new_name: &str) -> Result<ForkResponse, DaemonError> {
+        let url = format!("{}/repo/{}/fork/{}", self.base_url, self.repo_key(repo), new_name);
+        let response = self.send(self.post(&url), "Failed to fork repository").await?;
+        json(response, "Failed to parse fork response").await
+    }
+
+    pub async fn grant_pusher_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/grant-pusher/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to grant pusher role").await?;
+        Ok(())
+    }
+
+    pub async fn revoke_pusher_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/revoke-pusher/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to revoke pusher role").await?;
+        Ok(())
+    }
+
+    /// Grants the pusher role to each of `addresses`, one transaction after
+    /// another, in requests of at most `MAX_BATCH_ADDRESSES`.
+    pub async fn grant_pushers(&self, repo: &str, addresses: &[String]) -> Result<Vec<BatchRoleResult>, DaemonError> {
+        self.change_pushers(repo, addresses, "grant-pushers", "Failed to grant pusher roles").await
+    }
+
+    /// Revokes the pusher role from each of `addresses`, like `grant_pushers`.
+    pub async fn revoke_pushers(&self, repo: &str, addresses: &[String]) -> Result<Vec<BatchRoleResult>, DaemonError> {
+        self.change_pushers(repo, addresses, "revoke-pushers", "Failed to revoke pusher roles").await
+    }
 
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to revoke admin role: {}", error_text)
+    async fn change_pushers(&self, repo: &str, addresses: &[String], endpoint: &str, action: &'static str) -> Result<Vec<BatchRoleResult>, DaemonError> {
+        let url = format!("{}/repo/{}/{}", self.base_url, self.repo_key(repo), endpoint);
+        let mut results = Vec::with_capacity(addresses.len());
+        for batch in addresses.chunks(MAX_BATCH_ADDRESSES) {
+            let response = self.send(self.post(&url).json(batch), action).await?;
+            let resp: BatchRoleResponse = json(response, "Failed to parse batch role response").await?;
+            results.extend(resp.results);
         }
+        Ok(results)
+    }
+
+    pub async fn grant_admin_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/grant-admin/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to grant admin role").await?;
+        Ok(())
+    }
+
+    pub async fn revoke_admin_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/revoke-admin/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to revoke admin role").await?;
+        Ok(())
+    }
+
+    pub async fn check_pusher_role(&self, repo: &str, address: &str) -> Result<bool, DaemonError> {
+        let url = format!("{}/repo/{}/check-pusher/{}", self.base_url, self.repo_key(repo), address);
+        let response = self.send(self.get(&url), "Failed to check pusher role").await?;
+        let role_resp: RoleResponse = json(response, "Failed to parse role response").await?;
+        Ok(role_resp.has_role)
+    }
+
+    pub async fn check_admin_role(&self, repo: &str, address: &str) -> Result<bool, DaemonError> {
+        let url = format!("{}/repo/{}/check-admin/{}", self.base_url, self.repo_key(repo), address);
+        let response = self.send(self.get(&url), "Failed to check admin role").await?;
+        let role_resp: RoleResponse = json(response, "Failed to parse role response").await?;
+        Ok(role_resp.has_role)
     }
 
-    pub async fn check_pusher_role(&self, repo: &str, address: &str) -> Result<bool> {
-        let url = format!("{}/repo/{}/check-pusher/{}", self.base_url, repo, address);
-        let response = self.client.get(&url).send().await?;
+    pub async fn grant_reader_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/grant-reader/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to grant reader role").await?;
+        Ok(())
+    }
+
+    pub async fn revoke_reader_role(&self, repo: &str, address: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/revoke-reader/{}", self.base_url, self.repo_key(repo), address);
+        self.send(self.post(&url), "Failed to revoke reader role").await?;
+        Ok(())
+    }
+
+    pub async fn check_reader_role(&self, repo: &str, address: &str) -> Result<bool, DaemonError> {
+        let url = format!("{}/repo/{}/check-reader/{}", self.base_url, self.repo_key(repo), address);
+        let response = self.send(self.get(&url), "Failed to check reader role").await?;
+        let role_resp: RoleResponse = json(response, "Failed to parse role response").await?;
+        Ok(role_resp.has_role)
+    }
+
+    pub async fn list_roles(&self, repo: &str) -> Result<RoleListResponse, DaemonError> {
+        let url = format!("{}/repo/{}/roles", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to list roles").await?;
+        json(response, "Failed to parse role list").await
+    }
+
+    /// Returns one flag per hash, in the same order as `hashes`.
+    pub async fn has_objects(&self, repo: &str, hashes: &[String]) -> Result<Vec<bool>, DaemonError> {
+        let url = format!("{}/repo/{}/has-objects", self.base_url, self.repo_key(repo));
+        let response = self.send(self.post(&url).json(hashes), "Failed to check objects").await?;
+        let resp: HasObjectsResponse = json(response, "Failed to parse object check response").await?;
+        Ok(resp.exists)
+    }
+
+    pub async fn list_refs(&self, repo: &str) -> Result<Vec<RefEntry>, DaemonError> {
+        let url = format!("{}/repo/{}/refs", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to list refs").await?;
+        let resp: RefsResponse = json(response, "Failed to parse refs response").await?;
+        Ok(resp.refs)
+    }
+
+    pub async fn add_webhook(&self, repo: &str, url: &str, secret: Option<String>) -> Result<(), DaemonError> {
+        let endpoint = format!("{}/repo/{}/webhooks", self.base_url, self.repo_key(repo));
+        let request = AddWebhookRequest { url: url.to_string(), secret };
+        self.send(self.post(&endpoint).json(&request), "Failed to add webhook").await?;
+        Ok(())
+    }
+
+    pub async fn list_webhooks(&self, repo: &str) -> Result<Vec<WebhookInfo>, DaemonError> {
+        let url = format!("{}/repo/{}/webhooks", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to list webhooks").await?;
+        let resp: WebhooksResponse = json(response, "Failed to parse webhooks response").await?;
+        Ok(resp.webhooks)
+    }
+
+    /// Returns whether a webhook with `url` existed.
+    pub async fn remove_webhook(&self, repo: &str, url: &str) -> Result<bool, DaemonError> {
+        let endpoint = format!("{}/repo/{}/webhooks", self.base_url, self.repo_key(repo));
+        let response = self.send(self.delete(&endpoint).query(&[("url", url)]), "Failed to remove webhook").await?;
+        let resp: RemoveWebhookResponse = json(response, "Failed to parse webhook removal response").await?;
+        Ok(resp.removed)
+    }
+
+    /// The repository config as JSON.
+    pub async fn get_repo_config(&self, repo: &str) -> Result<serde_json::Value, DaemonError> {
+        let url = format!("{}/repo/{}/config", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to get config").await?;
+        let resp: RepoConfigResponse = json(response, "Failed to parse config response").await?;
+        Ok(resp.config)
+    }
+
+    /// Applies `update`, which holds only the settings to change.
+    pub async fn update_repo_config(&self, repo: &str, update: &serde_json::Value) -> Result<serde_json::Value, DaemonError> {
+        let url = format!("{}/repo/{}/config", self.base_url, self.repo_key(repo));
+        let response = self.send(self.put(&url).json(update), "Failed to update config").await?;
+        let resp: RepoConfigResponse = json(response, "Failed to parse config response").await?;
+        Ok(resp.config)
+    }
+
+    pub async fn set_signing_keys(&self, repo: &str, request: &SigningKeysRequest) -> Result<SigningKeysResponse, DaemonError> {
+        let url = format!("{}/repo/{}/signing-keys", self.base_url, self.repo_key(repo));
+        let response = self.send(self.put(&url).json(request), "Failed to set signing keys").await?;
+        json(response, "Failed to parse signing keys response").await
+    }
+
+    pub async fn get_signing_keys(&self, repo: &str) -> Result<SigningKeysResponse, DaemonError> {
+        let url = format!("{}/repo/{}/signing-keys", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to get signing keys").await?;
+        json(response, "Failed to parse signing keys response").await
+    }
+
+    pub async fn add_protection(&self, repo: &str, rule: &ProtectionRule) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/protect", self.base_url, self.repo_key(repo));
+        self.send(self.post(&url).json(rule), "Failed to add protection rule").await?;
+        Ok(())
+    }
+
+    pub async fn list_protection(&self, repo: &str) -> Result<Vec<ProtectionRule>, DaemonError> {
+        let url = format!("{}/repo/{}/protect", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to list protection rules").await?;
+        let resp: ProtectionResponse = json(response, "Failed to parse protection rules response").await?;
+        Ok(resp.rules)
+    }
+
+    /// Returns whether a rule for `pattern` existed.
+    pub async fn remove_protection(&self, repo: &str, pattern: &str) -> Result<bool, DaemonError> {
+        let url = format!("{}/repo/{}/protect", self.base_url, self.repo_key(repo));
+        let response = self.send(self.delete(&url).query(&[("pattern", pattern)]), "Failed to remove protection rule").await?;
+        let resp: RemoveProtectionResponse = json(response, "Failed to parse protection removal response").await?;
+        Ok(resp.removed)
+    }
+
+    pub async fn verify_repo(&self, repo: &str) -> Result<VerifyReport, DaemonError> {
+        let url = format!("{}/repo/{}/verify", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to verify repository").await?;
+        json(response, "Failed to parse verify report").await
+    }
+
+    pub async fn repin_repo(&self, repo: &str) -> Result<RepinReport, DaemonError> {
+        let url = format!("{}/repo/{}/repin", self.base_url, self.repo_key(repo));
+        let response = self.send(self.post(&url), "Failed to re-pin repository").await?;
+        json(response, "Failed to parse repin report").await
+    }
+
+    pub async fn list_pins(&self, repo: &str) -> Result<PinsResponse, DaemonError> {
+        let url = format!("{}/repo/{}/pins", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to list pins").await?;
+        json(response, "Failed to parse pin list").await
+    }
+
+    pub async fn unpin_repo(&self, repo: &str, all: bool, dry_run: bool) -> Result<UnpinReport, DaemonError> {
+        let url = format!("{}/repo/{}/unpin?all={}&dry_run={}", self.base_url, self.repo_key(repo), all, dry_run);
+        let response = self.send(self.post(&url), "Failed to unpin repository").await?;
+        json(response, "Failed to parse unpin report").await
+    }
 
-        if response.status().is_success() {
-            let role_resp: RoleResponse = response.json().await?;
-            Ok(role_resp.has_role)
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to check pusher role: {}", error_text)
+    /// Runs a consistency check, handing each event to `on_event` as the
+    /// daemon streams it. The stream lasts as long as the check, so the
+    /// request timeout does not apply to it.
+    pub async fn fsck_repo(&self, repo: &str, mut on_event: impl FnMut(FsckEvent)) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/fsck", self.base_url, self.repo_key(repo));
+        let streaming = self.clone().with_timeout(None);
+        let mut response = streaming.send(streaming.post(&url), "Failed to check repository").await?;
+
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(DaemonError::Network)? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let event = serde_json::from_slice(&line)
+                    .map_err(|source| DaemonError::Decode { what: "Failed to parse fsck event", source })?;
+                on_event(event);
+            }
         }
+
+        Ok(())
+    }
+
+    /// The repository as a `git bundle` file.
+    pub async fn export_repo(&self, repo: &str) -> Result<Vec<u8>, DaemonError> {
+        let url = format!("{}/repo/{}/export", self.base_url, self.repo_key(repo));
+        let response = self.send(self.get(&url), "Failed to export repository").await?;
+        Ok(response.bytes().await.map_err(DaemonError::Network)?.to_vec())
+    }
+
+    pub async fn gc_repo(&self, repo: &str, dry_run: bool) -> Result<GcReport, DaemonError> {
+        let url = format!("{}/repo/{}/gc?dry_run={}", self.base_url, self.repo_key(repo), dry_run);
+        let response = self.send(self.post(&url), "Failed to collect garbage").await?;
+        json(response, "Failed to parse gc report").await
     }
 
-    pub async fn check_admin_role(&self, repo: &str, address: &str) -> Result<bool> {
-        let url = format!("{}/repo/{}/check-admin/{}", self.base_url, repo, address);
-        let response = self.client.get(&url).send().await?;
+    pub async fn prune_refs(&self, repo: &str, dry_run: bool) -> Result<PruneReport, DaemonError> {
+        let url = format!("{}/repo/{}/prune?dry_run={}", self.base_url, self.repo_key(repo), dry_run);
+        let response = self.send(self.post(&url), "Failed to prune refs").await?;
+        json(response, "Failed to parse prune report").await
+    }
+
+    pub async fn get_audit_log(&self, repo: &str, limit: usize) -> Result<AuditResponse, DaemonError> {
+        let url = format!("{}/repo/{}/audit?limit={}", self.base_url, self.repo_key(repo), limit);
+        let response = self.send(self.get(&url), "Failed to fetch audit log").await?;
+        json(response, "Failed to parse audit log").await
+    }
 
-        if response.status().is_success() {
-            let role_resp: RoleResponse = response.json().await?;
-            Ok(role_resp.has_role)
-        } else {
-            let error_text = response.text().await?;
-            anyhow::bail!("Failed to check admin role: {}", error_text)
+    pub async fn repo_history(&self, repo: &str, from_block: Option<u64>, to_block: Option<u64>) -> Result<HistoryResponse, DaemonError> {
+        let mut url = format!("{}/repo/{}/history", self.base_url, self.repo_key(repo));
+        let params: Vec<String> = [("from_block", from_block), ("to_block", to_block)]
+            .into_iter()
+            .filter_map(|(key, block)| block.map(|block| format!("{}={}", key, block)))
+            .collect();
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&params.join("&"));
         }
+        let response = self.send(self.get(&url), "Failed to fetch history").await?;
+        json(response, "Failed to parse history").await
+    }
+
+    pub async fn set_visibility(&self, repo: &str, visibility: &str) -> Result<(), DaemonError> {
+        let url = format!("{}/repo/{}/visibility", self.base_url, self.repo_key(repo));
+        let request = VisibilityRequest { visibility: visibility.to_string() };
+        self.send(self.put(&url).json(&request), "Failed to set visibility").await?;
+        Ok(())
+    }
+}
+
+/// `response` if it is a success, otherwise the error its status stands for.
+async fn check_status(response: Response, action: &'static str) -> Result<Response, DaemonError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    Err(match status {
+        StatusCode::NOT_FOUND => DaemonError::NotFound { action, body },
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => DaemonError::Unauthorized { action, status, body },
+        _ => DaemonError::ServerError { action, status, body },
+    })
+}
+
+/// Failures a retry may get past: no connection, or a daemon that is
+/// restarting or overloaded. A request that timed out is not retried, as
+/// it would likely time out again.
+fn is_transient(error: &DaemonError) -> bool {
+    match error {
+        DaemonError::Network(e) => e.is_connect(),
+        DaemonError::ServerError { status, .. } => matches!(
+            *status,
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        _ => false,
     }
-} 
\ No newline at end of file
+}
+
+/// The JSON body of `response`, read in full first so a broken connection
+/// is told apart from a body that does not parse.
+async fn json<T: DeserializeOwned>(response: Response, what: &'static str) -> Result<T, DaemonError> {
+    let body = response.bytes().await.map_err(DaemonError::Network)?;
+    serde_json::from_slice(&body).map_err(|source| DaemonError::Decode { what, source })
+}
+
+/// Asks the node at `rpc_url` which chain it serves (`eth_chainId`).
+pub async fn fetch_chain_id(rpc_url: &str) -> Result<u64> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_chainId",
+        "params": [],
+    });
+
+    let response: serde_json::Value = Client::new()
+        .post(rpc_url)
+        .json(&request)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach RPC endpoint {}", rpc_url))?
+        .json()
+        .await
+        .context("Failed to parse eth_chainId response")?;
+
+    let chain_id = response["result"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("RPC endpoint {} returned no chain id: {}", rpc_url, response))?;
+
+    u64::from_str_radix(chain_id.trim_start_matches("0x"), 16)
+        .with_context(|| format!("Invalid chain id '{}' from {}", chain_id, rpc_url))
+}
+
+/// Local `http://` URL forwarded to the daemon socket at `path`.
+///
+/// Neither reqwest nor git speak HTTP over a Unix socket, so for as long as
+/// the CLI runs, connections to a loopback port are passed through to the
+/// socket. Like any loopback port, it can be reached by other local users
+/// meanwhile.
+#[cfg(unix)]
+fn bridge_unix_socket(path: &Path) -> Result<String> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+
+    let path = path.to_path_buf();
+    tokio::spawn(async move {
+        loop {
+            let mut tcp = match listener.accept().await {
+                Ok((tcp, _)) => tcp,
+                Err(e) => {
+                    error!("Stopped forwarding to daemon socket {}: {}", path.display(), e);
+                    return;
+                }
+            };
+            let path = path.clone();
+            tokio::spawn(async move {
+                match tokio::net::UnixStream::connect(&path).await {
+                    Ok(mut uds) => {
+                        if let Err(e) = tokio::io::copy_bidirectional(&mut tcp, &mut uds).await {
+                            debug!("Connection to daemon socket {} ended: {}", path.display(), e);
+                        }
+                    }
+                    Err(e) => error!("Failed to connect to daemon socket {}: {}", path.display(), e),
+                }
+            });
+        }
+    });
+
+    debug!("Forwarding {} to daemon socket {}", addr, path.display());
+    Ok(format!("http://{}", addr))
+}
+
+#[cfg(not(unix))]
+fn bridge_unix_socket(_path: &Path) -> Result<String> {
+    anyhow::bail!("unix: daemon URLs are only supported on Unix")
+}
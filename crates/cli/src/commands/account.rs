@@ -1,9 +1,12 @@
 use anyhow::Result;
 use clap::Subcommand;
 use colored::*;
-use dialoguer::{Input, Password, Select};
+use dialoguer::{Confirm, Input, Password, Select};
+use serde::Serialize;
+use ::daemon::auth::key_address;
 
-use crate::config::{Account, Config};
+use crate::config::{Account, ChainSettings, Config};
+use crate::output::Output;
 
 #[derive(Subcommand)]
 pub enum AccountCommands {
@@ -17,15 +20,27 @@ pub enum AccountCommands {
         #[arg(short, long)]
         private_key: Option<String>,
 
-        /// Ethereum address
+        /// Ethereum address; derived from the private key, which it must match if given
         #[arg(short, long)]
         address: Option<String>,
+
+        /// Default chain id for this account's repositories
+        #[arg(long)]
+        chain: Option<u64>,
+
+        /// Default RPC URL for this account's repositories
+        #[arg(long)]
+        rpc_url: Option<String>,
     },
 
     /// Remove an account
     Remove {
         /// Account name to remove
         name: String,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        force: bool,
     },
 
     /// List all accounts
@@ -39,40 +54,80 @@ pub enum AccountCommands {
 
     /// Show the active account
     Current,
+
+    /// Print `address:token` credentials for git access to the daemon
+    Token {
+        /// Token lifetime in seconds
+        #[arg(long, default_value_t = 3600)]
+        ttl: u64,
+    },
+}
+
+/// An account as commands report it, without its private key.
+#[derive(Serialize)]
+struct AccountSummary<'a> {
+    name: &'a str,
+    address: &'a str,
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chain_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rpc_url: Option<&'a str>,
 }
 
-pub async fn handle_command(cmd: AccountCommands) -> Result<()> {
+impl<'a> AccountSummary<'a> {
+    fn new(account: &'a Account, active: bool) -> Self {
+        Self {
+            name: &account.name,
+            address: &account.address,
+            active,
+            chain_id: account.chain_id,
+            rpc_url: account.rpc_url.as_deref(),
+        }
+    }
+}
+
+pub async fn handle_command(cmd: AccountCommands, daemon_url: &str, non_interactive: bool, output: Output) -> Result<()> {
     let mut config = Config::load()?;
 
     match cmd {
-        AccountCommands::Add { name, private_key, address } => {
-            add_account(&mut config, name, private_key, address).await?;
+        AccountCommands::Add { name, private_key, address, chain, rpc_url } => {
+            let chain = ChainSettings { chain_id: chain, rpc_url };
+            add_account(&mut config, name, private_key, address, chain, non_interactive, output).await?;
         }
-        AccountCommands::Remove { name } => {
-            remove_account(&mut config, &name)?;
+        AccountCommands::Remove { name, force } => {
+            remove_account(&mut config, &name, force, non_interactive, output)?;
         }
         AccountCommands::List => {
-            list_accounts(&config);
+            list_accounts(&config, output);
         }
         AccountCommands::Switch { name } => {
-            switch_account(&mut config, name)?;
+            switch_account(&mut config, name, non_interactive, output)?;
         }
         AccountCommands::Current => {
-            show_current_account(&config);
+            show_current_account(&config, output);
+        }
+        AccountCommands::Token { ttl } => {
+            print_token(&config, daemon_url, ttl, output)?;
         }
     }
 
     Ok(())
 }
 
-async fn add_account(
+/// Adds an account, prompting for what was not given, and returns its name.
+pub(crate) async fn add_account(
     config: &mut Config,
     name: Option<String>,
     private_key: Option<String>,
-    address: Option<String>,
-) -> Result<()> {
+    given_address: Option<String>,
+    chain: ChainSettings,
+    non_interactive: bool,
+    output: Output,
+) -> Result<String> {
     let name = match name {
         Some(n) => n,
+        None if non_interactive => missing_flag("--name")?,
         None => Input::new()
             .with_prompt("Account name")
             .interact_text()?,
@@ -84,72 +139,128 @@ async fn add_account(
 
     let private_key = match private_key {
         Some(pk) => pk,
+        None if non_interactive => missing_flag("--private-key")?,
         None => Password::new()
             .with_prompt("Private key")
             .interact()?,
     };
 
-    let address = match address {
-        Some(addr) => addr,
-        None => Input::new()
-            .with_prompt("Ethereum address")
-            .interact_text()?,
-    };
+    let address = key_address(&private_key)?;
+    if let Some(given) = given_address {
+        // Checksummed or not, with or without the prefix.
+        let normalized = format!("0x{}", given.trim().trim_start_matches("0x").to_ascii_lowercase());
+        if normalized != address {
+            anyhow::bail!("Address {} does not match the private key, whose address is {}", given, address);
+        }
+    }
 
     let account = Account {
         name: name.clone(),
         private_key,
         address: address.clone(),
+        chain_id: chain.chain_id,
+        rpc_url: chain.rpc_url,
     };
 
     config.add_account(account)?;
 
-    println!("{}", format!("✓ Account '{}' added successfully", name).green());
-    println!("  Address: {}", address.cyan());
+    let active = config.active_account.as_deref() == Some(name.as_str());
+    if let Some(account) = config.accounts.get(&name) {
+        output.result(&AccountSummary::new(account, active), || {
+            println!("{}", format!("✓ Account '{}' added successfully", name).green());
+            println!("  Address: {}", address.cyan());
 
-    if config.accounts.len() == 1 {
-        println!("{}", format!("  Set as active account").yellow());
+            if config.accounts.len() == 1 {
+                println!("{}", format!("  Set as active account").yellow());
+            }
+        });
     }
 
-    Ok(())
+    Ok(name)
+}
+
+/// Asks before a destructive action. `--force` skips the prompt; without it,
+/// non-interactive mode refuses rather than guessing.
+pub fn confirm(prompt: &str, force: bool, non_interactive: bool) -> Result<bool> {
+    if force {
+        return Ok(true);
+    }
+
+    if non_interactive {
+        anyhow::bail!("Refusing to continue without --force in non-interactive mode");
+    }
+
+    Ok(Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()?)
 }
 
-fn remove_account(config: &mut Config, name: &str) -> Result<()> {
+fn missing_flag(flag: &str) -> Result<String> {
+    anyhow::bail!("{} is required in non-interactive mode", flag)
+}
+
+fn remove_account(config: &mut Config, name: &str, force: bool, non_interactive: bool, output: Output) -> Result<()> {
     if !config.accounts.contains_key(name) {
         anyhow::bail!("Account '{}' not found", name);
     }
 
+    if config.active_account.as_deref() == Some(name) {
+        match config.active_after_removal(name) {
+            Some(next) => output.message(format!("'{}' is the active account; '{}' will become active", name, next).yellow()),
+            None => output.message(format!("'{}' is the only account; no account will be active", name).yellow()),
+        }
+    }
+
+    if !confirm(&format!("Remove account '{}' and its private key?", name), force, non_interactive)? {
+        output.result(&serde_json::json!({ "removed": false }), || println!("Aborted"));
+        return Ok(());
+    }
+
     config.remove_account(name)?;
 
-    println!("{}", format!("✓ Account '{}' removed", name).green());
+    let result = serde_json::json!({ "removed": true, "name": name, "active_account": config.active_account });
+    output.result(&result, || {
+        println!("{}", format!("✓ Account '{}' removed", name).green());
 
-    if let Some(active) = &config.active_account {
-        println!("{}", format!("  Active account is now '{}'", active).yellow());
-    }
+        if let Some(active) = &config.active_account {
+            println!("{}", format!("  Active account is now '{}'", active).yellow());
+        }
+    });
 
     Ok(())
 }
 
-fn list_accounts(config: &Config) {
+fn list_accounts(config: &Config, output: Output) {
     let accounts = config.list_accounts();
+    let summaries: Vec<AccountSummary> = accounts
+        .iter()
+        .map(|(_, account, is_active)| AccountSummary::new(account, *is_active))
+        .collect();
+
+    output.result(&summaries, || {
+        if accounts.is_empty() {
+            println!("{}", "No accounts configured".yellow());
+            println!("Use 'dgit account add' to add an account");
+            return;
+        }
 
-    if accounts.is_empty() {
-        println!("{}", "No accounts configured".yellow());
-        println!("Use 'dgit account add' to add an account");
-        return;
-    }
-
-    println!("{}", "Configured accounts:".bold());
-    for (name, account, is_active) in accounts {
-        let status = if is_active { " (active)".green() } else { "".normal() };
-        println!("  {} {}{}", "•".cyan(), name.bold(), status);
-        println!("    Address: {}", account.address.dimmed());
-    }
+        println!("{}", "Configured accounts:".bold());
+        for (name, account, is_active) in &accounts {
+            let status = if *is_active { " (active)".green() } else { "".normal() };
+            println!("  {} {}{}", "•".cyan(), name.bold(), status);
+            println!("    Address: {}", account.address.dimmed());
+            if let Some(chain_id) = account.chain_id {
+                println!("    Chain id: {}", chain_id.to_string().dimmed());
+            }
+        }
+    });
 }
 
-fn switch_account(config: &mut Config, name: Option<String>) -> Result<()> {
+fn switch_account(config: &mut Config, name: Option<String>, non_interactive: bool, output: Output) -> Result<()> {
     let name = match name {
         Some(n) => n,
+        None if non_interactive => missing_flag("<NAME>")?,
         None => {
             let accounts: Vec<String> = config.accounts.keys().cloned().collect();
             if accounts.is_empty() {
@@ -168,23 +279,44 @@ fn switch_account(config: &mut Config, name: Option<String>) -> Result<()> {
     config.set_active_account(&name)?;
 
     if let Some(account) = config.accounts.get(&name) {
-        println!("{}", format!("✓ Switched to account '{}'", name).green());
-        println!("  Address: {}", account.address.cyan());
+        output.result(&AccountSummary::new(account, true), || {
+            println!("{}", format!("✓ Switched to account '{}'", name).green());
+            println!("  Address: {}", account.address.cyan());
+        });
     }
 
     Ok(())
 }
 
-fn show_current_account(config: &Config) {
-    match config.get_active_account() {
+fn show_current_account(config: &Config, output: Output) {
+    let account = config.get_active_account();
+    output.result(&account.map(|account| AccountSummary::new(account, true)), || match account {
         Some(account) => {
             println!("{}", "Active account:".bold());
             println!("  Name: {}", account.name.cyan());
             println!("  Address: {}", account.address);
+            if let Some(chain_id) = account.chain_id {
+                println!("  Chain id: {}", chain_id);
+            }
+            if let Some(rpc_url) = &account.rpc_url {
+                println!("  RPC URL: {}", rpc_url);
+            }
         }
         None => {
             println!("{}", "No active account".yellow());
             println!("Use 'dgit account add' to add an account");
         }
-    }
-} 
\ No newline at end of file
+    });
+}
+
+fn print_token(config: &Config, daemon_url: &str, ttl: u64, output: Output) -> Result<()> {
+    let Some(account) = config.get_active_account() else {
+        output.fail("No active account. Use 'dgit account add' to add one.");
+    };
+
+    let credentials = account.credentials(daemon_url, ttl)?;
+    let token = serde_json::json!({ "address": credentials.address, "token": credentials.token, "ttl": ttl });
+    output.result(&token, || println!("{}:{}", credentials.address, credentials.token));
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use clap::Subcommand;
+use colored::*;
+
+use crate::config::{Config, Settings};
+use crate::output::Output;
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Save a default: daemon_url, chain_id, output or ens_rpc_url
+    Set {
+        /// Setting name
+        key: String,
+
+        /// New value
+        value: String,
+    },
+
+    /// Print a saved default
+    Get {
+        /// Setting name
+        key: String,
+    },
+
+    /// Remove a saved default, going back to the built-in one
+    Unset {
+        /// Setting name
+        key: String,
+    },
+
+    /// List the saved defaults
+    List,
+}
+
+pub fn handle_command(cmd: ConfigCommands, output: Output) -> Result<()> {
+    let mut config = Config::load()?;
+
+    match cmd {
+        ConfigCommands::Set { key, value } => {
+            config.settings.set(&key, &value)?;
+            config.save()?;
+            output.result(&serde_json::json!({ "key": key, "value": value }), || {
+                println!("{}", format!("✓ Set {} to {}", key, value).green());
+            });
+        }
+        ConfigCommands::Get { key } => {
+            let value = config.settings.get(&key)?;
+            output.result(&serde_json::json!({ "key": key, "value": value }), || match &value {
+                Some(value) => println!("{}", value),
+                None => println!("{}", format!("{} is not set", key).yellow()),
+            });
+        }
+        ConfigCommands::Unset { key } => {
+            config.settings.unset(&key)?;
+            config.save()?;
+            output.result(&serde_json::json!({ "key": key, "value": null }), || {
+                println!("{}", format!("✓ Unset {}", key).green());
+            });
+        }
+        ConfigCommands::List => {
+            list_settings(&config.settings, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn list_settings(settings: &Settings, output: Output) -> Result<()> {
+    let mut values = Vec::new();
+    for key in Settings::KEYS {
+        values.push((key, settings.get(key)?));
+    }
+
+    output.result(settings, || {
+        for (key, value) in &values {
+            match value {
+                Some(value) => println!("{} = {}", key.cyan(), value),
+                None => println!("{} = {}", key.cyan(), "(not set)".dimmed()),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use clap::Subcommand;
+use colored::*;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::output::Output;
+
+#[derive(Subcommand)]
+pub enum ContactCommands {
+    /// Name an address, to use the name in role commands
+    Add {
+        /// Contact name
+        name: String,
+
+        /// Ethereum address
+        address: String,
+    },
+
+    /// List contacts
+    List,
+
+    /// Remove a contact
+    Remove {
+        /// Contact name
+        name: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Contact<'a> {
+    name: &'a str,
+    address: &'a str,
+}
+
+pub fn handle_command(cmd: ContactCommands, output: Output) -> Result<()> {
+    let mut config = Config::load()?;
+
+    match cmd {
+        ContactCommands::Add { name, address } => {
+            config.add_contact(&name, &address)?;
+            if config.accounts.contains_key(&name) {
+                output.message(format!("'{}' is also an account name; role commands will ask you to use the address", name).yellow());
+            }
+            output.result(&Contact { name: &name, address: &address }, || {
+                println!("{}", format!("✓ Contact '{}' added", name).green());
+                println!("  Address: {}", address.cyan());
+            });
+        }
+        ContactCommands::List => {
+            let mut contacts: Vec<Contact> = config.contacts.iter()
+                .map(|(name, address)| Contact { name, address })
+                .collect();
+            contacts.sort_by_key(|contact| contact.name);
+
+            output.result(&contacts, || {
+                if contacts.is_empty() {
+                    println!("{}", "No contacts".yellow());
+                    println!("Use 'dgit contact add <name> <address>' to add one");
+                    return;
+                }
+
+                println!("{}", "Contacts:".bold());
+                for contact in &contacts {
+                    println!("  {} {} {}", "•".cyan(), contact.name.bold(), contact.address.dimmed());
+                }
+            });
+        }
+        ContactCommands::Remove { name } => {
+            config.remove_contact(&name)?;
+            output.result(&serde_json::json!({ "removed": true, "name": name }), || {
+                println!("{}", format!("✓ Contact '{}' removed", name).green());
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -1,24 +1,457 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
 use colored::*;
-use std::process::Command;
-use tokio::signal;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use ::daemon::ServerConfig;
 
-pub async fn start_daemon(port: u16) -> Result<()> {
-    println!("{}", format!("Starting daemon on port {}...", port).green());
+use crate::client::DaemonClient;
+use crate::commands::repo::{check_api_level, run_git};
+use crate::config::Config;
+use crate::output::Output;
 
-    std::env::set_var("PORT", port.to_string());
+/// How long `start --detach` waits for the daemon to answer `/health`.
+const DETACH_READY_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-    let mut child = Command::new("cargo")
-        .args(&["run", "--package", "daemon"])
-        .env("PORT", port.to_string())
-        .spawn()?;
+#[derive(Subcommand)]
+pub enum DaemonCommands {
+    /// Start the daemon, in the foreground unless --detach is given
+    Start {
+        /// Run in the background and return once the daemon answers; stop it with `dgit daemon stop`
+        #[arg(long)]
+        detach: bool,
 
-    println!("{}", "Daemon started. Press Ctrl+C to stop.".yellow());
+        /// File the daemon logs to, rotated per DGIT_LOG_ROTATION [default with --detach: daemon.log in the dgit config directory]
+        #[arg(long, env = "DGIT_LOG_FILE")]
+        log_file: Option<PathBuf>,
+    },
 
-    signal::ctrl_c().await?;
+    /// Stop the daemon started with --detach
+    Stop {
+        /// Seconds to wait for it to exit after SIGTERM
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
 
-    println!("{}", "\nShutting down daemon...".yellow());
-    child.kill()?;
+    /// Show whether the daemon started with --detach is running
+    Status,
 
+    /// Remove scratch directories left behind by crashed daemons or failed requests on the daemon at --daemon-url
+    Gc,
+}
+
+pub async fn handle_command(command: Option<DaemonCommands>, port: u16, client: DaemonClient, output: Output) -> Result<()> {
+    match command {
+        None | Some(DaemonCommands::Start { detach: false, .. }) => start_daemon(port, output).await,
+        Some(DaemonCommands::Start { detach: true, log_file }) => start_detached(port, log_file, output).await,
+        Some(DaemonCommands::Stop { timeout }) => stop_daemon(Duration::from_secs(timeout), output).await,
+        Some(DaemonCommands::Status) => daemon_status(output).await,
+        Some(DaemonCommands::Gc) => sweep_work_dirs(client, output).await,
+    }
+}
+
+/// Runs the daemon in this process until Ctrl+C.
+pub async fn start_daemon(port: u16, output: Output) -> Result<()> {
+    output.message(format!("Starting daemon on port {}...", port).green());
+    let config = ServerConfig { port, ..ServerConfig::from_env() };
+    output.result(&serde_json::json!({ "port": port, "uds_path": config.uds_path, "pid": std::process::id() }), || {
+        if let Some(path) = &config.uds_path {
+            println!("  Socket: {} (--daemon-url unix://{})", path.display(), path.display());
+        }
+        println!("{}", "Press Ctrl+C to stop.".yellow());
+    });
+
+    // Logged as well, so a detached daemon's log says why it stopped.
+    if let Err(e) = ::daemon::run(config).await {
+        tracing::error!("Daemon stopped: {:#}", e);
+        return Err(e);
+    }
+
+    output.message("Daemon stopped".yellow());
+    Ok(())
+}
+
+/// What `start --detach` records about the daemon it started, so `stop`
+/// and `status` can find it.
+#[derive(Debug, Serialize, Deserialize)]
+struct PidFile {
+    pid: u32,
+    port: u16,
+    /// Where the daemon answers: its port, or its socket if TCP is off.
+    url: String,
+    log_file: PathBuf,
+    started: u64,
+}
+
+impl PidFile {
+    fn path() -> Result<PathBuf> {
+        Ok(Config::dir()?.join("daemon.pid"))
+    }
+
+    /// The pid file of a running daemon. One left behind by a daemon that
+    /// is gone is removed.
+    fn load() -> Result<Option<Self>> {
+        let path = Self::path()?;
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        let pid_file: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if !process_alive(pid_file.pid) {
+            tracing::warn!("Removing stale pid file {}: process {} is gone", path.display(), pid_file.pid);
+            Self::remove()?;
+            return Ok(None);
+        }
+        Ok(Some(pid_file))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn remove() -> Result<()> {
+        match std::fs::remove_file(Self::path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Starts the daemon as a background `dgit daemon start` process logging
+/// to `log_file`, and waits for it to answer `/health`.
+async fn start_detached(port: u16, log_file: Option<PathBuf>, output: Output) -> Result<()> {
+    if let Some(running) = PidFile::load()? {
+        bail!("A daemon is already running as pid {} on {}", running.pid, running.url);
+    }
+
+    let log_file = match log_file {
+        Some(path) => path,
+        None => Config::dir()?.join("daemon.log"),
+    };
+
+    let config = ServerConfig { port, ..ServerConfig::from_env() };
+    let url = match (&config.uds_path, config.tcp) {
+        (Some(path), false) => format!("unix://{}", path.display()),
+        _ => format!("http://127.0.0.1:{}", port),
+    };
+
+    let mut command = Command::new(std::env::current_exe().context("Failed to find the dgit executable")?);
+    // Everything, panics included, goes to the log file.
+    command
+        .args(["daemon", "--port", &port.to_string(), "start"])
+        .arg("--log-file")
+        .arg(&log_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Out of the terminal's process group, so Ctrl+C there does not reach it.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn().context("Failed to start the daemon")?;
+
+    let pid_file = PidFile { pid: child.id(), port, url, log_file, started: unix_now() };
+    pid_file.save()?;
+    output.message(format!("Started daemon as pid {}, waiting for it on {}...", pid_file.pid, pid_file.url).yellow());
+
+    let client = DaemonClient::new(pid_file.url.clone());
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            PidFile::remove()?;
+            bail!("Daemon exited with {} before answering; see {}", status, pid_file.log_file.display());
+        }
+        if client.health_check().await.is_ok() {
+            break;
+        }
+        if started.elapsed() >= DETACH_READY_TIMEOUT {
+            eprintln!("{}", format!("Warning: daemon is not answering yet after {:?}; see {}", DETACH_READY_TIMEOUT, pid_file.log_file.display()).yellow());
+            break;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    output.result(&pid_file, || {
+        println!("{}", format!("✓ Daemon running in the background as pid {}", pid_file.pid).green());
+        println!("  URL: {}", pid_file.url);
+        println!("  Log: {}", pid_file.log_file.display());
+    });
+    Ok(())
+}
+
+/// Sends SIGTERM to the detached daemon and waits for it to finish its
+/// requests in flight and exit.
+async fn stop_daemon(timeout: Duration, output: Output) -> Result<()> {
+    let Some(pid_file) = PidFile::load()? else {
+        output.result(&serde_json::json!({ "stopped": false, "running": false }), || {
+            println!("{}", "No detached daemon is running".yellow());
+        });
+        return Ok(());
+    };
+
+    output.message(format!("Stopping daemon (pid {})...", pid_file.pid).yellow());
+    signal(pid_file.pid, "TERM")?;
+
+    let started = Instant::now();
+    while process_alive(pid_file.pid) {
+        if started.elapsed() >= timeout {
+            bail!("Daemon (pid {}) did not exit within {:?}; it is still running", pid_file.pid, timeout);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    PidFile::remove()?;
+
+    output.result(&serde_json::json!({ "stopped": true, "pid": pid_file.pid }), || {
+        println!("{}", format!("✓ Daemon (pid {}) stopped", pid_file.pid).green());
+    });
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct DaemonStatus {
+    running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uptime_secs: Option<u64>,
+    /// Whether it answered `/health`; a daemon still starting up does not.
+    healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_file: Option<PathBuf>,
+}
+
+async fn daemon_status(output: Output) -> Result<()> {
+    let Some(pid_file) = PidFile::load()? else {
+        let status = DaemonStatus {
+            running: false, pid: None, port: None, url: None, uptime_secs: None, healthy: false, version: None, log_file: None,
+        };
+        output.result(&status, || println!("{}", "Daemon is not running".yellow()));
+        return Ok(());
+    };
+
+    let client = DaemonClient::new(pid_file.url.clone());
+    let healthy = client.health_check().await.is_ok();
+    let version = match client.version().await {
+        Ok(Some(version)) => Some(version.version),
+        _ => None,
+    };
+    let status = DaemonStatus {
+        running: true,
+        pid: Some(pid_file.pid),
+        port: Some(pid_file.port),
+        url: Some(pid_file.url),
+        uptime_secs: Some(unix_now().saturating_sub(pid_file.started)),
+        healthy,
+        version,
+        log_file: Some(pid_file.log_file),
+    };
+
+    output.result(&status, || {
+        println!("{}", format!("✓ Daemon is running as pid {}", pid_file.pid).green());
+        if let Some(url) = &status.url {
+            println!("  URL: {}", url);
+        }
+        if let Some(uptime) = status.uptime_secs {
+            println!("  Uptime: {}", format_uptime(uptime));
+        }
+        if let Some(version) = &status.version {
+            println!("  Version: {}", version);
+        }
+        if let Some(log_file) = &status.log_file {
+            println!("  Log: {}", log_file.display());
+        }
+        if !healthy {
+            println!("{}", "  Not answering /health; it may still be starting, see the log".yellow());
+        }
+    });
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct GcReport {
+    #[serde(flatten)]
+    sweep: ::daemon::work_dirs::SweepReport,
+    usage: ::daemon::work_dirs::DiskUsage,
+}
+
+/// Has the daemon sweep orphaned scratch directories now, then shows what
+/// its scratch directories use.
+async fn sweep_work_dirs(client: DaemonClient, output: Output) -> Result<()> {
+    check_api_level(&client, 5, output).await;
+
+    output.message("Removing orphaned scratch directories...".yellow());
+    let sweep = client.sweep_work_dirs().await?;
+    let usage = client.disk_usage().await?;
+    let report = GcReport { sweep, usage };
+
+    output.result(&report, || {
+        println!("{}", format!("✓ Removed {} orphaned directories, freeing {} bytes", report.sweep.removed, report.sweep.freed_bytes).green());
+        if report.sweep.failed > 0 {
+            println!("{}", format!("  {} could not be removed; see the daemon log", report.sweep.failed).yellow());
+        }
+        println!("  {}: {} bytes in {} directories", report.usage.root, report.usage.bytes, report.usage.directories);
+    });
+    Ok(())
+}
+
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+/// Whether a process with `pid` exists, via `kill -0`.
+fn process_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn signal(pid: u32, signal: &str) -> Result<()> {
+    let status = Command::new("kill")
+        .args([&format!("-{}", signal), &pid.to_string()])
+        .status()
+        .context("Failed to run kill")?;
+    if !status.success() {
+        bail!("Failed to send SIG{} to pid {}", signal, pid);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Runs a scratch repository through the whole pipeline: contract
+/// deployment, push (IPFS upload and anchoring) and clone. Exits non-zero
+/// if any stage fails.
+pub async fn selftest(client: DaemonClient, output: Output) -> Result<()> {
+    let nonce = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let name = format!("dgit-selftest-{}", nonce);
+    output.message(format!("Running self-test against {} with repository '{}'...", client.repo_url(&name), name).yellow());
+
+    let work_dir = tempfile::tempdir()?;
+    let mut stages = Stages { output, reports: Vec::new() };
+    let result = run_selftest(&client, &name, &nonce.to_string(), work_dir.path(), &mut stages).await;
+
+    let started = Instant::now();
+    drop(work_dir);
+    output.message(format!("✓ cleanup ({:.2?})", started.elapsed()).green());
+    output.message(format!("  Contracts cannot be removed; '{}' stays on chain", name));
+
+    let report = SelftestReport { repo: name, passed: result.is_ok(), stages: stages.reports };
+    if output.is_json() {
+        output.result(&report, || {});
+        if !report.passed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if !report.passed {
+        output.fail("Self-test failed");
+    }
+
+    println!("{}", "✓ Self-test passed".green());
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct SelftestReport {
+    repo: String,
+    passed: bool,
+    stages: Vec<StageReport>,
+}
+
+#[derive(Serialize)]
+struct StageReport {
+    name: String,
+    seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Self-test stages run so far, printed as they finish in text mode.
+struct Stages {
+    output: Output,
+    reports: Vec<StageReport>,
+}
+
+async fn run_selftest(client: &DaemonClient, name: &str, content: &str, work_dir: &Path, stages: &mut Stages) -> Result<()> {
+    let url = client.repo_url(name);
+    let source_dir = work_dir.join("source");
+    let source = source_dir.to_string_lossy();
+    let clone = work_dir.join("clone");
+
+    stages.run("health", client.health_check()).await?;
+    stages.run("create repo", async {
+        client.create_repo(name).await?;
+        Ok(())
+    }).await?;
+
+    stages.run("push", async {
+        std::fs::create_dir_all(&source_dir)?;
+        std::fs::write(source_dir.join("SELFTEST"), content)?;
+        run_git(client, &["-C", &source, "init", "--quiet"]).await?;
+        run_git(client, &["-C", &source, "symbolic-ref", "HEAD", "refs/heads/main"]).await?;
+        run_git(client, &["-C", &source, "add", "SELFTEST"]).await?;
+        run_git(client, &[
+            "-C", &source,
+            "-c", "user.name=dgit selftest",
+            "-c", "user.email=selftest@dgit.invalid",
+            "commit", "--quiet", "-m", "dgit self-test",
+        ]).await?;
+        run_git(client, &["-C", &source, "push", "--quiet", &url, "main"]).await
+    }).await?;
+
+    stages.run("clone", run_git(client, &["clone", "--quiet", &url, &clone.to_string_lossy()])).await?;
+
+    stages.run("verify", async {
+        let cloned = std::fs::read_to_string(clone.join("SELFTEST"))?;
+        if cloned != content {
+            bail!("cloned content {:?} does not match pushed {:?}", cloned, content);
+        }
+        Ok(())
+    }).await
+}
+
+impl Stages {
+    /// Awaits one self-test stage, recording how long it took and why it failed.
+    async fn run<T>(&mut self, name: &str, task: impl Future<Output = Result<T>>) -> Result<T> {
+        let started = Instant::now();
+        let result = task.await;
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(_) => self.output.message(format!("✓ {} ({:.2?})", name, elapsed).green()),
+            Err(e) if !self.output.is_json() => eprintln!("{}", format!("✗ {} failed after {:.2?}: {:#}", name, elapsed, e).red()),
+            Err(_) => {},
+        }
+        self.reports.push(StageReport {
+            name: name.to_string(),
+            seconds: elapsed.as_secs_f64(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+        result
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::Result;
+use clap::Args;
+use colored::*;
+use dialoguer::{Input, Select};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::client::{DaemonClient, DaemonError};
+use crate::commands::account::add_account;
+use crate::commands::repo::{active_credentials, resolve_chain_id};
+use crate::config::{ChainSettings, Config};
+use crate::output::Output;
+use crate::progress;
+use ::daemon::validation::validate_repo_name;
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Repository name (defaults to the name of the working tree's directory)
+    name: Option<String>,
+
+    /// Account to push with, instead of choosing one
+    #[arg(long)]
+    account: Option<String>,
+
+    /// Name of the git remote to add
+    #[arg(long, default_value = "dgit")]
+    remote: String,
+
+    /// Do not add a git remote
+    #[arg(long)]
+    no_remote: bool,
+}
+
+/// What `dgit init` set up; steps that were already done count as done.
+#[derive(Serialize)]
+struct InitSummary {
+    repo: String,
+    account: String,
+    address: String,
+    daemon_url: String,
+    created: bool,
+    granted_pusher: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote: Option<String>,
+    url: String,
+}
+
+/// Sets up the git working tree in the current directory to push to a
+/// repository on the daemon: an account, the daemon URL, the repository,
+/// the account's pusher role and a remote, each skipped if already done.
+/// Every step is saved as it completes, so running it again after a
+/// failure picks up at the step that failed.
+pub async fn run(
+    args: InitArgs,
+    daemon_url: String,
+    daemon_url_given: bool,
+    timeout: Option<Duration>,
+    non_interactive: bool,
+    output: Output,
+) -> Result<()> {
+    let work_tree = match git_work_tree() {
+        Some(work_tree) => work_tree,
+        None => output.fail("Not inside a git working tree. Run 'git init' first, or cd into a repository"),
+    };
+
+    let name = match &args.name {
+        Some(name) => name.clone(),
+        None => work_tree.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    let name = match validate_repo_name(&name) {
+        Ok(name) => name,
+        Err(e) => output.fail_with(1, e, Some("Pass a repository name: dgit init <name>")),
+    };
+    let resume = format!("Steps already done are kept; run 'dgit init {}' again to finish", name);
+
+    // The first two steps may prompt, so only the requests get spinners.
+    let mut config = Config::load()?;
+    let account = match choose_account(&mut config, args.account.as_deref(), non_interactive, output).await {
+        Ok(account) => account,
+        Err(e) => output.fail_with(1, format!("{:#}", e), Some(&resume)),
+    };
+    let address = config.accounts[&account].address.clone();
+    output.message(format!("✓ Using account '{}' ({})", account, address).green());
+
+    let daemon_url = match save_daemon_url(&mut config, daemon_url, daemon_url_given, non_interactive) {
+        Ok(daemon_url) => daemon_url,
+        Err(e) => output.fail_with(1, format!("{:#}", e), Some(&resume)),
+    };
+    output.message(format!("✓ Using daemon {}", daemon_url).green());
+
+    let chain = config.chain_for(&name, ChainSettings::default());
+    let chain_id = match resolve_chain_id(&chain).await {
+        Ok(chain_id) => chain_id,
+        Err(e) => output.fail_with(1, format!("{:#}", e), Some(&resume)),
+    };
+    let client = DaemonClient::new(daemon_url.clone())
+        .with_timeout(timeout)
+        .with_credentials(active_credentials(&config, &daemon_url))
+        .with_chain(chain_id);
+
+    let created = match progress::wait(output, format!("Creating repository '{}'...", name), create_repo(&client, &name)).await {
+        Ok(created) => created,
+        Err(e) => fail_step(output, e, &resume),
+    };
+    if created {
+        output.message(format!("✓ Repository '{}' created", name).green());
+    } else {
+        output.message(format!("✓ Repository '{}' already exists", name).green());
+    }
+
+    let granted = progress::wait(output, "Granting the pusher role...", grant_pusher(&client, &name, &address)).await;
+    let granted_pusher = match granted {
+        Ok(granted) => granted,
+        Err(e) => fail_step(output, e, &resume),
+    };
+    if granted_pusher {
+        output.message(format!("✓ Granted {} the pusher role", address).green());
+    } else {
+        output.message(format!("✓ {} may already push", address).green());
+    }
+
+    let url = client.repo_url(&name);
+    let remote = if args.no_remote {
+        None
+    } else {
+        if let Err(e) = add_remote(&work_tree, &args.remote, &url) {
+            output.fail_with(1, format!("{:#}", e), Some(&resume));
+        }
+        output.message(format!("✓ Remote '{}' points to {}", args.remote, url).green());
+        Some(args.remote)
+    };
+
+    let summary = InitSummary {
+        repo: name.clone(),
+        account,
+        address,
+        daemon_url,
+        created,
+        granted_pusher,
+        remote,
+        url,
+    };
+    output.result(&summary, || {
+        println!("{}", format!("✓ Ready to push to '{}'", name).green());
+        match &summary.remote {
+            Some(remote) => println!("  git push {} main", remote),
+            None => println!("  git push {} main", summary.url),
+        }
+    });
+
+    Ok(())
+}
+
+/// The account to push with: `--account`, else the one picked from a
+/// list that can add a new one, else the active account when prompts are
+/// off. The chosen account becomes the active one.
+async fn choose_account(config: &mut Config, given: Option<&str>, non_interactive: bool, output: Output) -> Result<String> {
+    let name = match given {
+        Some(name) => name.to_string(),
+        None if non_interactive => match &config.active_account {
+            Some(name) => name.clone(),
+            None => anyhow::bail!("No account configured. Pass --account, or add one with 'dgit account add'"),
+        },
+        None => {
+            let mut names: Vec<String> = config.accounts.keys().cloned().collect();
+            let default = config.active_account.as_ref()
+                .and_then(|active| names.iter().position(|name| name == active))
+                .unwrap_or(0);
+            let mut items = names.clone();
+            items.push("Add a new account".to_string());
+
+            let selection = Select::new()
+                .with_prompt("Account to push with")
+                .items(&items)
+                .default(default)
+                .interact()?;
+
+            if selection < names.len() {
+                names.swap_remove(selection)
+            } else {
+                add_account(config, None, None, None, ChainSettings::default(), false, output).await?
+            }
+        }
+    };
+
+    config.set_active_account(&name)?;
+    Ok(name)
+}
+
+/// Confirms the daemon URL unless it was given, and saves it as the
+/// default if it is not already.
+fn save_daemon_url(config: &mut Config, daemon_url: String, given: bool, non_interactive: bool) -> Result<String> {
+    let daemon_url = if given || non_interactive {
+        daemon_url
+    } else {
+        Input::new()
+            .with_prompt("Daemon URL")
+            .default(daemon_url)
+            .interact_text()?
+    };
+
+    if config.settings.daemon_url.as_deref() != Some(daemon_url.as_str()) {
+        config.settings.set("daemon_url", &daemon_url)?;
+        config.save()?;
+    }
+    Ok(daemon_url)
+}
+
+/// Creates `name` unless it exists; whether it was created.
+async fn create_repo(client: &DaemonClient, name: &str) -> Result<bool, DaemonError> {
+    if client.repo_exists(name).await? {
+        return Ok(false);
+    }
+    client.create_repo(name).await?;
+    Ok(true)
+}
+
+/// Grants `address` the pusher role unless it has it; whether it was granted.
+async fn grant_pusher(client: &DaemonClient, name: &str, address: &str) -> Result<bool, DaemonError> {
+    if client.check_pusher_role(name, address).await? {
+        return Ok(false);
+    }
+    client.grant_pusher_role(name, address).await?;
+    Ok(true)
+}
+
+fn fail_step(output: Output, e: DaemonError, resume: &str) -> ! {
+    let hint = match e.hint() {
+        Some(hint) => format!("{}\n{}", hint, resume),
+        None => resume.to_string(),
+    };
+    output.fail_with(e.exit_code(), format!("{:#}", anyhow::Error::new(e)), Some(&hint))
+}
+
+/// Top directory of the git working tree around the current directory.
+fn git_work_tree() -> Option<PathBuf> {
+    let output = Command::new("git").args(["rev-parse", "--show-toplevel"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Adds `remote` for `url`. An existing remote with that URL is left as
+/// it is; one pointing elsewhere is an error rather than overwritten.
+fn add_remote(work_tree: &Path, remote: &str, url: &str) -> Result<()> {
+    let existing = Command::new("git")
+        .current_dir(work_tree)
+        .args(["remote", "get-url", remote])
+        .output()?;
+    if existing.status.success() {
+        let existing = String::from_utf8_lossy(&existing.stdout).trim().to_string();
+        if existing == url {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Remote '{}' already points to {}. Pass --remote to add another, or run 'git remote set-url {} {}'",
+            remote, existing, remote, url,
+        );
+    }
+
+    let added = Command::new("git")
+        .current_dir(work_tree)
+        .args(["remote", "add", remote, url])
+        .output()?;
+    if !added.status.success() {
+        anyhow::bail!("git remote add failed: {}", String::from_utf8_lossy(&added.stderr).trim());
+    }
+    Ok(())
+}
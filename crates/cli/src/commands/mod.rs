@@ -1,3 +1,6 @@
 pub mod account;
+pub mod config;
+pub mod contact;
 pub mod daemon;
+pub mod init;
 pub mod repo;
\ No newline at end of file
@@ -1,262 +1,2376 @@
-use anyhow::Result;
-use clap::Subcommand;
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
 use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use ::daemon::auth::Credentials;
+use ::daemon::validation::validate_repo_name;
+use onchain::contract_interaction::{format_ether, format_gas};
 
-use crate::client::DaemonClient;
-use crate::config::Config;
+use crate::client::{fetch_chain_id, ApiCheck, BatchRoleResult, CreateRepoResponse, DaemonClient, DaemonError, FsckEvent, HistoryEntry, ProtectionRule, SigningKeysRequest, SigningKeysResponse};
+use crate::commands::account;
+use crate::config::{ChainSettings, Config};
+use crate::output::Output;
+use crate::progress::{self, Steps};
+
+// Tokens for daemon requests only need to outlive a single command.
+const AUTH_TOKEN_TTL_SECS: u64 = 300;
+
+/// First wait before `push` and `fetch` try a busy daemon again; it
+/// doubles with each try.
+const GIT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Args)]
+pub struct RepoArgs {
+    /// Chain id the repository lives on (overrides the repository and account settings)
+    #[arg(long, global = true)]
+    pub chain: Option<u64>,
+
+    /// RPC URL of the repository's chain; its chain id is used when `--chain` is not given
+    #[arg(long, global = true)]
+    pub rpc_url: Option<String>,
+
+    #[command(subcommand)]
+    pub command: RepoCommands,
+}
 
 #[derive(Subcommand)]
 pub enum RepoCommands {
     /// Create a new repository
     Create {
-        /// Repository name
+        /// Repository name, either `name` or `owner/name`
         name: String,
     },
 
+    /// Create a copy of a repository that shares its stored objects
+    Fork {
+        /// Repository to fork
+        name: String,
+
+        /// Name of the new repository
+        new_name: String,
+    },
+
     /// Repository role management
     #[command(subcommand)]
     Role(RoleCommands),
+
+    /// Hand the admin role over from the active account to another address (requires admin role)
+    Transfer {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address of the new admin, or a contact or account name
+        #[arg(long)]
+        to: String,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Push notification webhooks (requires admin role)
+    #[command(subcommand)]
+    Webhook(WebhookCommands),
+
+    /// Protected branch rules (changing them requires admin role)
+    #[command(subcommand)]
+    Protect(ProtectCommands),
+
+    /// Keys pushed commits must be signed with (changing them requires admin role)
+    #[command(subcommand)]
+    SigningKeys(SigningKeysCommands),
+
+    /// Repository settings stored on chain (changing them requires admin role)
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Make a repository public or private (requires admin role)
+    Visibility {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// New visibility
+        #[arg(value_parser = ["public", "private"])]
+        visibility: String,
+    },
+
+    /// Show a repository's contract address and chain
+    Info {
+        /// Repository name
+        name: String,
+    },
+
+    /// Look a repository up in the daemon's registry (REGISTRY_ADDRESS)
+    Resolve {
+        /// Repository name
+        name: String,
+    },
+
+    /// List the active refs of a repository
+    Refs {
+        /// Repository name
+        name: String,
+    },
+
+    /// Check which objects a repository already stores
+    HasObjects {
+        /// Repository name
+        name: String,
+
+        /// Object hashes to check
+        #[arg(required = true)]
+        hashes: Vec<String>,
+    },
+
+    /// Check that every on-chain object is retrievable from IPFS and every ref resolves
+    Verify {
+        /// Repository name
+        name: String,
+    },
+
+    /// Pin every on-chain object to the daemon's IPFS node again, fetching lost ones from the network (admin only)
+    Repin {
+        /// Repository name
+        name: String,
+    },
+
+    /// List the IPFS CIDs the daemon has pinned for a repository, with their sizes
+    Pins {
+        /// Repository name
+        name: String,
+    },
+
+    /// Remove the daemon's IPFS pins on objects the repository no longer records (admin only)
+    Unpin {
+        /// Repository name
+        name: String,
+
+        /// Unpin every object of the repository, for one that is being retired
+        #[arg(long)]
+        all: bool,
+
+        /// Only report what would be unpinned
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Download every object and check hashes, links and refs (exits non-zero on errors)
+    Fsck {
+        /// Repository name
+        name: String,
+    },
+
+    /// Collapse the on-chain ref list to the latest active entry per name
+    Prune {
+        /// Repository name
+        name: String,
+
+        /// Only report what would be pruned
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Save a repository as a git bundle that clones without the daemon
+    Export {
+        /// Repository name
+        name: String,
+
+        /// File to write the bundle to (default: <name>.bundle)
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Report objects no active ref reaches, and mark them inactive on-chain (requires admin role)
+    Gc {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Only report unreferenced objects
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Push refs of the repository in the current directory through git
+    Push {
+        /// Repository name
+        name: String,
+
+        /// Refspecs to push (git's push.default decides when none are given)
+        refspecs: Vec<String>,
+
+        /// Allow updates that are not fast-forwards
+        #[arg(short, long)]
+        force: bool,
+
+        /// Times to retry while the daemon is busy (429 or 503)
+        #[arg(long, default_value_t = 3)]
+        retries: u32,
+    },
+
+    /// Fetch refs into the repository in the current directory through git
+    Fetch {
+        /// Repository name
+        name: String,
+
+        /// Refspecs to fetch (the repository's HEAD into FETCH_HEAD when none are given)
+        refspecs: Vec<String>,
+
+        /// Times to retry while the daemon is busy (429 or 503)
+        #[arg(long, default_value_t = 3)]
+        retries: u32,
+    },
+
+    /// Copy every ref and object of a repository from one daemon to another
+    Mirror {
+        /// Repository name
+        name: String,
+
+        /// URL of the daemon to copy from
+        #[arg(long)]
+        from: String,
+
+        /// URL of the daemon to copy to (the repository is created there if needed)
+        #[arg(long)]
+        to: String,
+
+        /// Chain id of the destination repository, when it differs from the source's
+        #[arg(long)]
+        to_chain: Option<u64>,
+    },
+
+    /// Show recent role changes and pushes for a repository
+    Audit {
+        /// Repository name
+        name: String,
+
+        /// Maximum number of entries to show
+        #[arg(short, long, default_value_t = 100)]
+        limit: usize,
+    },
+
+    /// Show the objects and refs pushed on chain, push by push, with pushers and transactions
+    History {
+        /// Repository name
+        name: String,
+
+        /// First block to include (defaults to the last million blocks)
+        #[arg(long, alias = "from-block")]
+        since: Option<u64>,
+
+        /// Last block to include (defaults to the latest block)
+        #[arg(long)]
+        to_block: Option<u64>,
+    },
+
+    /// Show pushes waiting to be anchored on chain (DGIT_ASYNC_ANCHORING)
+    AnchoringStatus {
+        /// Repository name
+        name: String,
+    },
+
+    /// Show or save the chain a repository lives on (set with --chain/--rpc-url)
+    Chain {
+        /// Repository name
+        name: String,
+
+        /// Forget the repository's saved chain settings
+        #[arg(long, conflicts_with_all = ["chain", "rpc_url"])]
+        unset: bool,
+    },
+}
+
+impl RepoCommands {
+    /// Repository the command operates on, used to pick its chain.
+    fn repo(&self) -> &str {
+        match self {
+            RepoCommands::Create { name }
+            | RepoCommands::Fork { name, .. }
+            | RepoCommands::Info { name }
+            | RepoCommands::Resolve { name }
+            | RepoCommands::Refs { name }
+            | RepoCommands::HasObjects { name, .. }
+            | RepoCommands::Verify { name }
+            | RepoCommands::Repin { name }
+            | RepoCommands::Pins { name }
+            | RepoCommands::Unpin { name, .. }
+            | RepoCommands::Export { name, .. }
+            | RepoCommands::Fsck { name }
+            | RepoCommands::Prune { name, .. }
+            | RepoCommands::Audit { name, .. }
+            | RepoCommands::History { name, .. }
+            | RepoCommands::AnchoringStatus { name }
+            | RepoCommands::Mirror { name, .. }
+            | RepoCommands::Push { name, .. }
+            | RepoCommands::Fetch { name, .. }
+            | RepoCommands::Chain { name, .. } => name,
+            RepoCommands::Visibility { repo, .. }
+            | RepoCommands::Gc { repo, .. }
+            | RepoCommands::Transfer { repo, .. } => repo,
+            RepoCommands::Webhook(cmd) => match cmd {
+                WebhookCommands::Add { repo, .. }
+                | WebhookCommands::List { repo }
+                | WebhookCommands::Remove { repo, .. } => repo,
+            },
+            RepoCommands::Config(cmd) => match cmd {
+                ConfigCommands::Get { repo, .. } | ConfigCommands::Set { repo, .. } => repo,
+            },
+            RepoCommands::SigningKeys(cmd) => match cmd {
+                SigningKeysCommands::Set { repo, .. } | SigningKeysCommands::Get { repo } => repo,
+            },
+            RepoCommands::Protect(cmd) => match cmd {
+                ProtectCommands::Add { repo, .. }
+                | ProtectCommands::List { repo }
+                | ProtectCommands::Remove { repo, .. } => repo,
+            },
+            RepoCommands::Role(cmd) => match cmd {
+                RoleCommands::GrantPusher { repo, .. }
+                | RoleCommands::RevokePusher { repo, .. }
+                | RoleCommands::GrantAdmin { repo, .. }
+                | RoleCommands::RevokeAdmin { repo, .. }
+                | RoleCommands::CheckPusher { repo, .. }
+                | RoleCommands::CheckAdmin { repo, .. }
+                | RoleCommands::List { repo }
+                | RoleCommands::GrantReader { repo, .. }
+                | RoleCommands::RevokeReader { repo, .. }
+                | RoleCommands::CheckReader { repo, .. } => repo,
+            },
+        }
+    }
+
+    /// Daemon API level the command needs, if more than any daemon has.
+    fn api_level(&self) -> Option<u32> {
+        match self {
+            RepoCommands::AnchoringStatus { .. } => Some(1),
+            RepoCommands::Repin { .. } => Some(2),
+            RepoCommands::History { .. } => Some(6),
+            RepoCommands::Pins { .. } | RepoCommands::Unpin { .. } => Some(9),
+            RepoCommands::Role(RoleCommands::GrantPusher { address, from_file, .. })
+            | RepoCommands::Role(RoleCommands::RevokePusher { address, from_file, .. })
+                if is_batch(address, from_file.as_deref()) => Some(8),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum WebhookCommands {
+    /// Add a webhook called after every successful push
+    Add {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// URL to POST push events to
+        #[arg(short, long)]
+        url: String,
+
+        /// Secret used to sign deliveries (X-Dgit-Signature header)
+        #[arg(short, long)]
+        secret: Option<String>,
+    },
+
+    /// List a repository's webhooks
+    List {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+    },
+
+    /// Remove a webhook
+    Remove {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// URL of the webhook to remove
+        #[arg(short, long)]
+        url: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Show the whole config, or one setting
+    Get {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Setting to show (description, default_branch, visibility, ref_namespaces, a quota limit or any custom key)
+        key: Option<String>,
+    },
+
+    /// Change one setting; an empty value clears it
+    Set {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Setting to change (description, default_branch, visibility, ref_namespaces, a quota limit or any custom key)
+        key: String,
+
+        /// New value; custom keys take JSON, anything that is not JSON is stored as a string
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SigningKeysCommands {
+    /// Upload signing keys and/or toggle the signed commits requirement
+    Set {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// File with ASCII-armored GPG public keys (e.g. from `gpg --export --armor`)
+        #[arg(long)]
+        gpg: Option<String>,
+
+        /// SSH allowed signers file (`gpg.ssh.allowedSignersFile` format)
+        #[arg(long)]
+        ssh_allowed_signers: Option<String>,
+
+        /// Reject pushes whose new tips are not signed by one of the keys
+        #[arg(long)]
+        require_signed_commits: Option<bool>,
+    },
+
+    /// Show the repository's signing keys
+    Get {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProtectCommands {
+    /// Protect refs matching a pattern; adding an existing pattern replaces its rule
+    Add {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Ref pattern, `*` matches anything (e.g. refs/heads/main, refs/tags/*)
+        pattern: String,
+
+        /// Reject non-fast-forward updates
+        #[arg(long)]
+        deny_force_push: bool,
+
+        /// Reject deleting matching refs
+        #[arg(long)]
+        deny_delete: bool,
+
+        /// Only admins may update matching refs
+        #[arg(long)]
+        admins_only: bool,
+    },
+
+    /// List a repository's protection rules
+    List {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+    },
+
+    /// Remove the rule for a pattern
+    Remove {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Ref pattern of the rule to remove
+        pattern: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RoleCommands {
+    /// Grant pusher role to one or more addresses
+    GrantPusher {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to grant role to; repeat for several (uses active account if not specified)
+        #[arg(short, long)]
+        address: Vec<String>,
+
+        /// File listing addresses, contacts or account names, one per line; `#` starts a comment
+        #[arg(long)]
+        from_file: Option<PathBuf>,
+    },
+
+    /// Revoke pusher role from one or more addresses
+    RevokePusher {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to revoke role from; repeat for several (uses active account if not specified)
+        #[arg(short, long)]
+        address: Vec<String>,
+
+        /// File listing addresses, contacts or account names, one per line; `#` starts a comment
+        #[arg(long)]
+        from_file: Option<PathBuf>,
+    },
+
+    /// Grant admin role to an address
+    GrantAdmin {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to grant role to (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// Revoke admin role from an address
+    RevokeAdmin {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to revoke role from (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// Check if an address has pusher role
+    CheckPusher {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to check (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// Check if an address has admin role
+    CheckAdmin {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to check (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// List every admin, pusher and reader of a repository
+    List {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+    },
+
+    /// Grant reader role to an address
+    GrantReader {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to grant role to (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// Revoke reader role from an address
+    RevokeReader {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to revoke role from (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+
+    /// Check if an address has reader role
+    CheckReader {
+        /// Repository name
+        #[arg(short, long)]
+        repo: String,
+
+        /// Address, contact or account name to check (uses active account if not specified)
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+}
+
+pub async fn handle_command(args: RepoArgs, client: DaemonClient, non_interactive: bool, output: Output) -> Result<()> {
+    let mut config = Config::load()?;
+    let overrides = ChainSettings {
+        chain_id: args.chain,
+        rpc_url: args.rpc_url,
+    };
+    let cmd = args.command;
+
+    if let RepoCommands::Chain { name, unset } = &cmd {
+        return repo_chain(&mut config, name, overrides, *unset, output);
+    }
+
+    let chain = config.chain_for(cmd.repo(), overrides);
+    let chain_id = match resolve_chain_id(&chain).await {
+        Ok(chain_id) => chain_id,
+        Err(e) => output.fail(format!("{:#}", e)),
+    };
+
+    let credentials = active_credentials(&config, client.daemon_url());
+    let client = client
+        .with_credentials(credentials.clone())
+        .with_chain(chain_id);
+
+    if let Some(level) = cmd.api_level() {
+        check_api_level(&client, level, output).await;
+    }
+
+    match cmd {
+        RepoCommands::Create { name } => {
+            create_repo(client, &name, output).await?;
+        }
+        RepoCommands::Fork { name, new_name } => {
+            fork_repo(client, &name, &new_name, output).await?;
+        }
+        RepoCommands::Role(role_cmd) => {
+            handle_role_command(role_cmd, client, output).await?;
+        }
+        RepoCommands::Transfer { repo, to, force } => {
+            transfer_repo(client, &config, &repo, &to, force, non_interactive, output).await?;
+        }
+        RepoCommands::Webhook(webhook_cmd) => {
+            handle_webhook_command(webhook_cmd, client, output).await?;
+        }
+        RepoCommands::Config(config_cmd) => {
+            handle_config_command(config_cmd, client, output).await?;
+        }
+        RepoCommands::SigningKeys(signing_cmd) => {
+            handle_signing_keys_command(signing_cmd, client, output).await?;
+        }
+        RepoCommands::Protect(protect_cmd) => {
+            handle_protect_command(protect_cmd, client, output).await?;
+        }
+        RepoCommands::Visibility { repo, visibility } => {
+            set_visibility(client, &repo, &visibility, output).await?;
+        }
+        RepoCommands::Info { name } => {
+            repo_info(client, &name, chain_id, output).await?;
+        }
+        RepoCommands::Resolve { name } => {
+            resolve_repo(client, &name, output).await?;
+        }
+        RepoCommands::Refs { name } => {
+            list_refs(client, &name, output).await?;
+        }
+        RepoCommands::HasObjects { name, hashes } => {
+            has_objects(client, &name, &hashes, output).await?;
+        }
+        RepoCommands::Verify { name } => {
+            verify_repo(client, &name, output).await?;
+        }
+        RepoCommands::Repin { name } => {
+            repin_repo(client, &name, output).await?;
+        }
+        RepoCommands::Pins { name } => {
+            list_pins(client, &name, output).await?;
+        }
+        RepoCommands::Unpin { name, all, dry_run } => {
+            unpin_repo(client, &name, all, dry_run, output).await?;
+        }
+        RepoCommands::Prune { name, dry_run } => {
+            prune_refs(client, &name, dry_run, output).await?;
+        }
+        RepoCommands::Fsck { name } => {
+            fsck_repo(client, &name, output).await?;
+        }
+        RepoCommands::Export { name, out } => {
+            export_repo(client, &name, out, output).await?;
+        }
+        RepoCommands::Gc { repo, dry_run, force } => {
+            gc_repo(client, &repo, dry_run, force, non_interactive, output).await?;
+        }
+        RepoCommands::Push { name, refspecs, force, retries } => {
+            push_repo(client, &config, &name, &refspecs, force, retries, output).await?;
+        }
+        RepoCommands::Fetch { name, refspecs, retries } => {
+            fetch_repo(client, &name, &refspecs, retries, output).await?;
+        }
+        RepoCommands::Mirror { name, from, to, to_chain } => {
+            let source = DaemonClient::new(from)
+                .with_timeout(client.timeout())
+                .with_credentials(credentials.clone())
+                .with_chain(chain_id);
+            let destination = DaemonClient::new(to)
+                .with_timeout(client.timeout())
+                .with_credentials(credentials)
+                .with_chain(to_chain.or(chain_id));
+            mirror_repo(source, destination, &name, output).await?;
+        }
+        RepoCommands::Audit { name, limit } => {
+            show_audit_log(client, &name, limit, output).await?;
+        }
+        RepoCommands::History { name, since, to_block } => {
+            show_history(client, &name, since, to_block, output).await?;
+        }
+        RepoCommands::AnchoringStatus { name } => {
+            anchoring_status(client, &name, output).await?;
+        }
+        RepoCommands::Chain { .. } => unreachable!("handled before connecting"),
+    }
+
+    Ok(())
+}
+
+/// Chain id to address the repository with: the configured one, or the id
+/// reported by the configured RPC endpoint.
+pub(crate) async fn resolve_chain_id(chain: &ChainSettings) -> Result<Option<u64>> {
+    match (chain.chain_id, &chain.rpc_url) {
+        (Some(chain_id), _) => Ok(Some(chain_id)),
+        (None, Some(rpc_url)) => fetch_chain_id(rpc_url).await.map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+fn repo_chain(config: &mut Config, name: &str, overrides: ChainSettings, unset: bool, output: Output) -> Result<()> {
+    if unset {
+        config.set_repo_chain(name, ChainSettings::default())?;
+        output.result(&serde_json::json!({ "repo": name, "chain": ChainSettings::default() }), || {
+            println!("{}", format!("✓ Cleared chain settings for repository '{}'", name).green());
+        });
+        return Ok(());
+    }
+
+    if !overrides.is_empty() {
+        let settings = overrides.or(config.repos.get(name).unwrap_or(&ChainSettings::default()));
+        config.set_repo_chain(name, settings)?;
+        output.message(format!("✓ Saved chain settings for repository '{}'", name).green());
+    }
+
+    let saved = config.repos.contains_key(name);
+    let chain = config.chain_for(name, ChainSettings::default());
+    let source = if saved { "repository" } else { "active account" };
+
+    output.result(&serde_json::json!({ "repo": name, "chain": chain, "source": source }), || {
+        if chain.is_empty() {
+            println!("{}", format!("Repository '{}' uses the daemon's default chain", name).yellow());
+            return;
+        }
+
+        println!("{}", format!("Chain for repository '{}' (from {} settings):", name, source).bold());
+        if let Some(chain_id) = chain.chain_id {
+            println!("  Chain id: {}", chain_id.to_string().cyan());
+        }
+        if let Some(rpc_url) = &chain.rpc_url {
+            println!("  RPC URL: {}", rpc_url.cyan());
+        }
+    });
+
+    Ok(())
+}
+
+async fn create_repo(client: DaemonClient, name: &str, output: Output) -> Result<()> {
+    let name = match validate_repo_name(name) {
+        Ok(name) => name,
+        Err(e) => output.fail(e),
+    };
+    let name = name.as_str();
+
+    match progress::wait(output, format!("Creating repository '{}'...", name), client.create_repo(name)).await {
+        Ok(response) => output.result(&response, || {
+            println!("{}", format!("✓ Repository '{}' created successfully", name).green());
+            println!("  Contract address: {}", response.address.cyan());
+            if let Some(tx_hash) = &response.tx_hash {
+                println!("  Deployment tx:    {}", tx_hash);
+            }
+            if let Some(block_number) = response.block_number {
+                println!("  Block:            {}", block_number);
+            }
+            if let Some(chain_id) = response.chain_id {
+                println!("  Chain id:         {}", chain_id);
+            }
+            print_creation_cost(&response);
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn fork_repo(client: DaemonClient, name: &str, new_name: &str, output: Output) -> Result<()> {
+    if let Err(e) = validate_repo_name(new_name) {
+        output.fail(e);
+    }
+
+    match progress::wait(output, format!("Forking repository '{}' into '{}'...", name, new_name), client.fork_repo(name, new_name)).await {
+        Ok(response) => output.result(&response, || {
+            println!("{}", format!("✓ Repository '{}' forked from '{}'", new_name, response.source).green());
+            println!("  Contract address: {}", response.created.address.cyan());
+            if let Some(tx_hash) = &response.created.tx_hash {
+                println!("  Deployment tx:    {}", tx_hash);
+            }
+            println!("  Objects copied:   {}", response.objects_copied);
+            println!("  Refs copied:      {}", response.refs_copied);
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn handle_role_command(cmd: RoleCommands, client: DaemonClient, output: Output) -> Result<()> {
+    let config = Config::load()?;
+
+    match cmd {
+        RoleCommands::GrantPusher { repo, address, from_file } if is_batch(&address, from_file.as_deref()) => {
+            let addresses = get_addresses(address, from_file.as_deref(), &config).await?;
+            change_pushers(client, &repo, &addresses, true, &config, output).await?;
+        }
+        RoleCommands::GrantPusher { repo, address, .. } => {
+            let address = get_address(address.into_iter().next(), &config).await?;
+            grant_pusher_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::RevokePusher { repo, address, from_file } if is_batch(&address, from_file.as_deref()) => {
+            let addresses = get_addresses(address, from_file.as_deref(), &config).await?;
+            change_pushers(client, &repo, &addresses, false, &config, output).await?;
+        }
+        RoleCommands::RevokePusher { repo, address, .. } => {
+            let address = get_address(address.into_iter().next(), &config).await?;
+            revoke_pusher_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::GrantAdmin { repo, address } => {
+            let address = get_address(address, &config).await?;
+            grant_admin_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::RevokeAdmin { repo, address } => {
+            let address = get_address(address, &config).await?;
+            revoke_admin_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::CheckPusher { repo, address } => {
+            let address = get_address(address, &config).await?;
+            check_pusher_role(client, &repo, &address, config.alias_for(&address), output).await?;
+        }
+        RoleCommands::CheckAdmin { repo, address } => {
+            let address = get_address(address, &config).await?;
+            check_admin_role(client, &repo, &address, config.alias_for(&address), output).await?;
+        }
+        RoleCommands::List { repo } => {
+            let active = config.get_active_account().map(|account| account.address.clone());
+            list_roles(client, &repo, active.as_deref(), output).await?;
+        }
+        RoleCommands::GrantReader { repo, address } => {
+            let address = get_address(address, &config).await?;
+            grant_reader_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::RevokeReader { repo, address } => {
+            let address = get_address(address, &config).await?;
+            revoke_reader_role(client, &repo, &address, output).await?;
+        }
+        RoleCommands::CheckReader { repo, address } => {
+            let address = get_address(address, &config).await?;
+            check_reader_role(client, &repo, &address, config.alias_for(&address), output).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Refuses to run a command against a daemon that reports an API level
+/// below `level`. A daemon whose level cannot be read is only warned about,
+/// since the command may still work.
+pub(crate) async fn check_api_level(client: &DaemonClient, level: u32, output: Output) {
+    match client.require_api_level(level).await {
+        Ok(ApiCheck::Supported) => {},
+        Ok(ApiCheck::TooOld(version)) => output.fail(format!(
+            "The daemon is too old for this command: it is version {} with API level {}, and the command needs API level {}. Upgrade the daemon",
+            version.version, version.api_level, level,
+        )),
+        Ok(ApiCheck::Unknown) => {
+            eprintln!("{}", "Warning: the daemon does not report its version and may be too old for this command".yellow());
+        }
+        // The command itself would not get through either.
+        Err(e @ DaemonError::Network(_)) => fail_request(output, e),
+        Err(e) => {
+            eprintln!("{}", format!("Warning: could not check the daemon's version: {}", e).yellow());
+        }
+    }
+}
+
+/// Reports a failed daemon request with a hint and an exit code that tell
+/// a missing repository, a missing role and an unreachable daemon apart.
+pub(crate) fn fail_request(output: Output, e: DaemonError) -> ! {
+    let (code, hint) = (e.exit_code(), e.hint());
+    output.fail_with(code, format!("{:#}", anyhow::Error::new(e)), hint)
+}
+
+/// Credentials of the active account for the daemon at `daemon_url`, if it
+/// has a private key to sign with.
+pub(crate) fn active_credentials(config: &Config, daemon_url: &str) -> Option<Credentials> {
+    let account = config.get_active_account()?;
+    if account.private_key.is_empty() {
+        return None;
+    }
+
+    match account.credentials(daemon_url, AUTH_TOKEN_TTL_SECS) {
+        Ok(credentials) => Some(credentials),
+        Err(e) => {
+            eprintln!("{}", format!("Warning: {}", e).yellow());
+            None
+        }
+    }
+}
+
+/// What JSON output reports for a granted or revoked role.
+fn role_change(repo: &str, address: &str, role: &str, action: &str) -> serde_json::Value {
+    serde_json::json!({ "repo": repo, "address": address, "role": role, "action": action })
+}
+
+fn role_check(repo: &str, address: &str, alias: Option<&str>, role: &str, has_role: bool) -> serde_json::Value {
+    let mut check = serde_json::json!({ "repo": repo, "address": address, "role": role, "has_role": has_role });
+    if let Some(alias) = alias {
+        check["alias"] = alias.into();
+    }
+    check
+}
+
+/// `address` with the contact or account name it is known by, if any.
+fn display_address(address: &str, alias: Option<&str>) -> String {
+    match alias {
+        Some(alias) => format!("{} ({})", alias, address),
+        None => address.to_string(),
+    }
+}
+
+/// The address an `--address` argument names (see [`Config::resolve_address`]),
+/// or the active account's without one.
+async fn get_address(address: Option<String>, config: &Config) -> Result<String> {
+    match address {
+        Some(address) => config.resolve_address(&address).await,
+        None => {
+            config.get_active_account()
+                .map(|account| account.address.clone())
+                .ok_or_else(|| anyhow::anyhow!("No active account. Use 'dgit account add' to add one."))
+        }
+    }
+}
+
+/// Whether a pusher role change names more than one address, and so goes
+/// through the daemon's batch endpoints.
+fn is_batch(address: &[String], from_file: Option<&Path>) -> bool {
+    address.len() > 1 || from_file.is_some()
+}
+
+/// The addresses named by `--address` flags and then the lines of
+/// `from_file`, without repeats. Every entry is resolved before anything is
+/// sent, so a mistyped line changes no roles.
+async fn get_addresses(given: Vec<String>, from_file: Option<&Path>, config: &Config) -> Result<Vec<String>> {
+    let mut addresses: Vec<String> = Vec::new();
+    let mut add = |address: String| {
+        if !addresses.iter().any(|known| known.eq_ignore_ascii_case(&address)) {
+            addresses.push(address);
+        }
+    };
+
+    for entry in &given {
+        add(config.resolve_address(entry).await?);
+    }
+    if let Some(file) = from_file {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        for (line, entry) in address_lines(&content) {
+            let address = config.resolve_address(entry).await
+                .with_context(|| format!("{}, line {}", file.display(), line))?;
+            add(address);
+        }
+    }
+
+    match from_file {
+        Some(file) if addresses.is_empty() => anyhow::bail!("{} lists no addresses", file.display()),
+        _ => Ok(addresses),
+    }
+}
+
+/// Entries of an address list with their line numbers: one per line, with
+/// blank lines and anything after a `#` skipped.
+fn address_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content.lines().enumerate().filter_map(|(index, line)| {
+        let entry = line.split('#').next().unwrap_or_default().trim();
+        (!entry.is_empty()).then_some((index + 1, entry))
+    })
+}
+
+/// Grants or revokes the pusher role for each of `addresses` and reports
+/// every one; exits non-zero if any of them failed.
+async fn change_pushers(client: DaemonClient, repo: &str, addresses: &[String], grant: bool, config: &Config, output: Output) -> Result<()> {
+    let message = if grant {
+        format!("Granting pusher role to {} addresses for repository '{}'...", addresses.len(), repo)
+    } else {
+        format!("Revoking pusher role from {} addresses for repository '{}'...", addresses.len(), repo)
+    };
+    let request = async {
+        if grant {
+            client.grant_pushers(repo, addresses).await
+        } else {
+            client.revoke_pushers(repo, addresses).await
+        }
+    };
+    let results = match progress::wait(output, message, request).await {
+        Ok(results) => results,
+        Err(e) => fail_request(output, e),
+    };
+    let failed = results.iter().filter(|result| !result.ok).count();
+
+    let report = serde_json::json!({
+        "repo": repo,
+        "role": "pusher",
+        "action": if grant { "grant" } else { "revoke" },
+        "results": results,
+    });
+    output.result(&report, || print_batch_results(&results, config));
+
+    if failed == 0 {
+        let done = if grant { "granted to" } else { "revoked from" };
+        output.message(format!("✓ Pusher role {} {} addresses", done, results.len()).green());
+        Ok(())
+    } else if output.is_json() {
+        std::process::exit(1);
+    } else {
+        eprintln!("{}", format!("✗ {} of {} addresses failed", failed, results.len()).red());
+        std::process::exit(1);
+    }
+}
+
+fn print_batch_results(results: &[BatchRoleResult], config: &Config) {
+    let names: Vec<String> = results.iter()
+        .map(|result| display_address(&result.address, config.alias_for(&result.address)))
+        .collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    for (result, name) in results.iter().zip(&names) {
+        if result.ok {
+            println!("  {} {:<width$}  {}", "✓".green(), name, result.tx_hash.as_deref().unwrap_or_default().dimmed());
+        } else {
+            println!("  {} {:<width$}  {}", "✗".red(), name, result.error.as_deref().unwrap_or_default().red());
+        }
+    }
+}
+
+async fn grant_pusher_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Granting pusher role to {} for repository '{}'...", address, repo), client.grant_pusher_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "pusher", "grant"), || {
+            println!("{}", format!("✓ Pusher role granted to {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn revoke_pusher_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Revoking pusher role from {} for repository '{}'...", address, repo), client.revoke_pusher_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "pusher", "revoke"), || {
+            println!("{}", format!("✓ Pusher role revoked from {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn grant_admin_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Granting admin role to {} for repository '{}'...", address, repo), client.grant_admin_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "admin", "grant"), || {
+            println!("{}", format!("✓ Admin role granted to {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn revoke_admin_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Revoking admin role from {} for repository '{}'...", address, repo), client.revoke_admin_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "admin", "revoke"), || {
+            println!("{}", format!("✓ Admin role revoked from {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn check_pusher_role(client: DaemonClient, repo: &str, address: &str, alias: Option<&str>, output: Output) -> Result<()> {
+    match client.check_pusher_role(repo, address).await {
+        Ok(has_role) => output.result(&role_check(repo, address, alias, "pusher", has_role), || {
+            let address = display_address(address, alias);
+            if has_role {
+                println!("{}", format!("✓ {} has pusher role for repository '{}'", address, repo).green());
+            } else {
+                println!("{}", format!("✗ {} does not have pusher role for repository '{}'", address, repo).yellow());
+            }
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+/// Grants admin to `to` and only then revokes it from the active account, so
+/// the repository always has at least one admin. Stops before the revoke
+/// unless the new admin's role is confirmed on chain.
+async fn transfer_repo(client: DaemonClient, config: &Config, repo: &str, to: &str, force: bool, non_interactive: bool, output: Output) -> Result<()> {
+    let Some(from) = config.get_active_account().map(|account| account.address.clone()) else {
+        output.fail("No active account. Use 'dgit account add' to add one.");
+    };
+
+    let to = match config.resolve_address(to).await {
+        Ok(to) => to,
+        Err(e) => output.fail(format!("{:#}", e)),
+    };
+    let to = to.as_str();
+    if to.eq_ignore_ascii_case(&from) {
+        output.fail(format!("{} is the active account; transferring to it would only revoke your own admin role", to));
+    }
+
+    match client.check_admin_role(repo, &from).await {
+        Ok(true) => {}
+        Ok(false) => output.fail(format!("The active account {} is not an admin of repository '{}'", from, repo)),
+        Err(e) => fail_request(output, e),
+    }
+
+    let prompt = format!("Transfer admin of repository '{}' from {} to {}? You will lose admin access.", repo, from, to);
+    if !account::confirm(&prompt, force, non_interactive)? {
+        output.result(&serde_json::json!({ "repo": repo, "transferred": false }), || println!("Aborted"));
+        return Ok(());
+    }
+
+    let mut steps = Steps::new(output, 3);
+    let granted = steps.run(format!("Granting admin role to {} for repository '{}'...", to, repo), client.grant_admin_role(repo, to)).await;
+    if let Err(e) = granted {
+        fail_request(output, e);
+    }
+    output.message(format!("✓ Admin role granted to {}", to).green());
+
+    match steps.run(format!("Confirming {} is an admin...", to), client.check_admin_role(repo, to)).await {
+        Ok(true) => {}
+        Ok(false) => output.fail(format!("{} does not have the admin role yet; keeping {} as admin", to, from)),
+        Err(e) => output.fail(format!("Failed to confirm the new admin, keeping {} as admin: {}", from, e)),
+    }
+
+    match steps.run(format!("Revoking admin role from {}...", from), client.revoke_admin_role(repo, &from)).await {
+        Ok(_) => output.result(&serde_json::json!({ "repo": repo, "transferred": true, "from": from, "to": to }), || {
+            println!("{}", format!("✓ Transferred admin of repository '{}' to {}", repo, to).green());
+        }),
+        Err(e) => output.fail(format!(
+            "{} is now an admin, but revoking the role from {} failed: {}. Run 'dgit repo role revoke-admin --repo {}' to finish the transfer",
+            to, from, e, repo
+        )),
+    }
+
+    Ok(())
+}
+
+async fn check_admin_role(client: DaemonClient, repo: &str, address: &str, alias: Option<&str>, output: Output) -> Result<()> {
+    match client.check_admin_role(repo, address).await {
+        Ok(has_role) => output.result(&role_check(repo, address, alias, "admin", has_role), || {
+            let address = display_address(address, alias);
+            if has_role {
+                println!("{}", format!("✓ {} has admin role for repository '{}'", address, repo).green());
+            } else {
+                println!("{}", format!("✗ {} does not have admin role for repository '{}'", address, repo).yellow());
+            }
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn list_roles(client: DaemonClient, repo: &str, active_address: Option<&str>, output: Output) -> Result<()> {
+    let roles = match client.list_roles(repo).await {
+        Ok(roles) => roles,
+        Err(e) => fail_request(output, e),
+    };
+
+    output.result(&roles, || {
+        println!("{}", format!("Roles for repository '{}':", roles.repo).bold());
+        print_role_group("Admins", &roles.admins, active_address);
+        print_role_group("Pushers", &roles.pushers, active_address);
+        print_role_group("Readers", &roles.readers, active_address);
+    });
+
+    Ok(())
+}
+
+fn print_role_group(title: &str, addresses: &[String], active_address: Option<&str>) {
+    println!("  {}:", title);
+    if addresses.is_empty() {
+        println!("    {}", "(none)".dimmed());
+        return;
+    }
+
+    for address in addresses {
+        let is_active = active_address.is_some_and(|active| active.eq_ignore_ascii_case(address));
+        if is_active {
+            println!("    {} {}", address.cyan(), "(active account)".green());
+        } else {
+            println!("    {}", address);
+        }
+    }
+}
+
+async fn grant_reader_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Granting reader role to {} for repository '{}'...", address, repo), client.grant_reader_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "reader", "grant"), || {
+            println!("{}", format!("✓ Reader role granted to {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn revoke_reader_role(client: DaemonClient, repo: &str, address: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Revoking reader role from {} for repository '{}'...", address, repo), client.revoke_reader_role(repo, address)).await {
+        Ok(_) => output.result(&role_change(repo, address, "reader", "revoke"), || {
+            println!("{}", format!("✓ Reader role revoked from {}", address).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn check_reader_role(client: DaemonClient, repo: &str, address: &str, alias: Option<&str>, output: Output) -> Result<()> {
+    match client.check_reader_role(repo, address).await {
+        Ok(has_role) => output.result(&role_check(repo, address, alias, "reader", has_role), || {
+            let address = display_address(address, alias);
+            if has_role {
+                println!("{}", format!("✓ {} has reader role for repository '{}'", address, repo).green());
+            } else {
+                println!("{}", format!("✗ {} does not have reader role for repository '{}'", address, repo).yellow());
+            }
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn set_visibility(client: DaemonClient, repo: &str, visibility: &str, output: Output) -> Result<()> {
+    match progress::wait(output, format!("Making repository '{}' {}...", repo, visibility), client.set_visibility(repo, visibility)).await {
+        Ok(_) => output.result(&serde_json::json!({ "repo": repo, "visibility": visibility }), || {
+            println!("{}", format!("✓ Repository '{}' is now {}", repo, visibility).green());
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn handle_webhook_command(cmd: WebhookCommands, client: DaemonClient, output: Output) -> Result<()> {
+    match cmd {
+        WebhookCommands::Add { repo, url, secret } => {
+            let signed = secret.is_some();
+            match client.add_webhook(&repo, &url, secret).await {
+                Ok(_) => output.result(&serde_json::json!({ "repo": repo, "url": url, "has_secret": signed }), || {
+                    println!("{}", format!("✓ Webhook {} added to repository '{}'", url, repo).green());
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+        WebhookCommands::List { repo } => {
+            match client.list_webhooks(&repo).await {
+                Ok(webhooks) => output.result(&webhooks, || {
+                    if webhooks.is_empty() {
+                        println!("{}", format!("Repository '{}' has no webhooks", repo).yellow());
+                        return;
+                    }
+                    println!("{}", format!("Webhooks for repository '{}':", repo).bold());
+                    for webhook in &webhooks {
+                        let signed = if webhook.has_secret { " (signed)".green() } else { "".normal() };
+                        println!("  {} {}{}", "•".cyan(), webhook.url, signed);
+                    }
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+        WebhookCommands::Remove { repo, url } => {
+            match client.remove_webhook(&repo, &url).await {
+                Ok(removed) => output.result(&serde_json::json!({ "repo": repo, "url": url, "removed": removed }), || {
+                    if removed {
+                        println!("{}", format!("✓ Webhook {} removed from repository '{}'", url, repo).green());
+                    } else {
+                        println!("{}", format!("No webhook {} on repository '{}'", url, repo).yellow());
+                    }
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Settings with their own field in the config; other keys live in `extra`.
+const CONFIG_FIELDS: &[&str] = &["description", "default_branch", "visibility", "ref_namespaces"];
+
+/// Per-repository limits, kept under `quota`.
+const QUOTA_FIELDS: &[&str] = &["max_object_bytes", "max_push_bytes", "max_repo_objects"];
+
+async fn handle_config_command(cmd: ConfigCommands, client: DaemonClient, output: Output) -> Result<()> {
+    match cmd {
+        ConfigCommands::Get { repo, key } => {
+            let config = match client.get_repo_config(&repo).await {
+                Ok(config) => config,
+                Err(e) => fail_request(output, e),
+            };
+
+            match key {
+                // Already JSON, whichever the output format.
+                None => println!("{}", serde_json::to_string_pretty(&config)?),
+                Some(key) => {
+                    let key = key.replace('-', "_");
+                    let value = if CONFIG_FIELDS.contains(&key.as_str()) {
+                        config.get(&key)
+                    } else if QUOTA_FIELDS.contains(&key.as_str()) {
+                        config.get("quota").and_then(|quota| quota.get(&key))
+                    } else {
+                        config.get("extra").and_then(|extra| extra.get(&key))
+                    };
+                    match value {
+                        Some(value) => output.result(&serde_json::json!({ "repo": repo, "key": key, "value": value }), || match value {
+                            serde_json::Value::String(value) => println!("{}", value),
+                            value => println!("{}", value),
+                        }),
+                        None if output.is_json() => output.fail(format!("'{}' is not set for repository '{}'", key, repo)),
+                        None => {
+                            eprintln!("{}", format!("'{}' is not set for repository '{}'", key, repo).yellow());
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+        }
+        ConfigCommands::Set { repo, key, value } => {
+            let key = key.replace('-', "_");
+            let update = if key == "ref_namespaces" {
+                let namespaces: Vec<&str> = value.split(',').map(str::trim).filter(|namespace| !namespace.is_empty()).collect();
+                serde_json::json!({ "ref_namespaces": namespaces })
+            } else if CONFIG_FIELDS.contains(&key.as_str()) {
+                serde_json::json!({ key.as_str(): value })
+            } else if QUOTA_FIELDS.contains(&key.as_str()) {
+                let limit: u64 = if value.is_empty() {
+                    0
+                } else {
+                    match value.parse() {
+                        Ok(limit) => limit,
+                        Err(_) => output.fail(format!("{} must be a whole number, got '{}'", key, value)),
+                    }
+                };
+                serde_json::json!({ key.as_str(): limit })
+            } else {
+                let value = if value.is_empty() {
+                    serde_json::Value::Null
+                } else {
+                    serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value))
+                };
+                serde_json::json!({ "extra": { key.as_str(): value } })
+            };
+
+            match client.update_repo_config(&repo, &update).await {
+                Ok(config) => output.result(&config, || {
+                    println!("{}", format!("✓ Set {} for repository '{}'", key, repo).green());
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_signing_keys_command(cmd: SigningKeysCommands, client: DaemonClient, output: Output) -> Result<()> {
+    match cmd {
+        SigningKeysCommands::Set { repo, gpg, ssh_allowed_signers, require_signed_commits } => {
+            let read = |path: Option<String>| -> Result<Option<String>> {
+                path.map(|path| std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e)))
+                    .transpose()
+            };
+            let request = match (read(gpg), read(ssh_allowed_signers)) {
+                (Ok(gpg), Ok(ssh_allowed_signers)) => SigningKeysRequest { gpg, ssh_allowed_signers, require_signed_commits },
+                (Err(e), _) | (_, Err(e)) => output.fail(e),
+            };
+
+            match client.set_signing_keys(&repo, &request).await {
+                Ok(keys) => output.result(&keys, || {
+                    println!("{}", format!("✓ Signing keys of repository '{}' updated", repo).green());
+                    print_signing_keys(&keys);
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+        SigningKeysCommands::Get { repo } => {
+            match client.get_signing_keys(&repo).await {
+                Ok(keys) => output.result(&keys, || print_signing_keys(&keys)),
+                Err(e) => fail_request(output, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_signing_keys(keys: &SigningKeysResponse) {
+    let required = if keys.require_signed_commits { "required".green() } else { "not required".yellow() };
+    println!("  Signed commits: {}", required);
+    if !keys.signing_keys.gpg.is_empty() {
+        println!("  GPG keys:\n{}", keys.signing_keys.gpg.trim_end());
+    }
+    if !keys.signing_keys.ssh_allowed_signers.is_empty() {
+        println!("  SSH allowed signers:\n{}", keys.signing_keys.ssh_allowed_signers.trim_end());
+    }
+}
+
+async fn handle_protect_command(cmd: ProtectCommands, client: DaemonClient, output: Output) -> Result<()> {
+    match cmd {
+        ProtectCommands::Add { repo, pattern, deny_force_push, deny_delete, admins_only } => {
+            let rule = ProtectionRule { pattern, deny_force_push, deny_delete, admins_only };
+            match client.add_protection(&repo, &rule).await {
+                Ok(_) => output.result(&rule, || {
+                    println!("{}", format!("✓ Protected {} in repository '{}'", rule.pattern, repo).green());
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+        ProtectCommands::List { repo } => {
+            match client.list_protection(&repo).await {
+                Ok(rules) => output.result(&rules, || {
+                    if rules.is_empty() {
+                        println!("{}", format!("Repository '{}' has no protected refs", repo).yellow());
+                        return;
+                    }
+                    println!("{}", format!("Protected refs of repository '{}':", repo).bold());
+                    for rule in &rules {
+                        let flags: Vec<&str> = [
+                            (rule.deny_force_push, "no force push"),
+                            (rule.deny_delete, "no delete"),
+                            (rule.admins_only, "admins only"),
+                        ]
+                        .into_iter()
+                        .filter_map(|(set, flag)| set.then_some(flag))
+                        .collect();
+                        println!("  {} {} {}", "•".cyan(), rule.pattern, format!("({})", flags.join(", ")).dimmed());
+                    }
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+        ProtectCommands::Remove { repo, pattern } => {
+            match client.remove_protection(&repo, &pattern).await {
+                Ok(removed) => output.result(&serde_json::json!({ "repo": repo, "pattern": pattern, "removed": removed }), || {
+                    if removed {
+                        println!("{}", format!("✓ Removed protection of {} from repository '{}'", pattern, repo).green());
+                    } else {
+                        println!("{}", format!("No protection rule for {} on repository '{}'", pattern, repo).yellow());
+                    }
+                }),
+                Err(e) => fail_request(output, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve_repo(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    match client.resolve_repo(repo).await {
+        Ok(resolved) => output.result(&resolved, || {
+            println!("{}", format!("✓ '{}' is registered", resolved.repo).green());
+            println!("  Contract address: {}", resolved.address.cyan());
+            println!("  Registry:         {}", resolved.registry);
+            if let Some(chain_id) = resolved.chain_id {
+                println!("  Chain id:         {}", chain_id);
+            }
+        }),
+        Err(e) => output.fail(e),
+    }
+
+    Ok(())
+}
+
+/// Gas and fee of the deployment, known only to the daemon that deployed it.
+fn print_creation_cost(response: &CreateRepoResponse) {
+    if let Some(gas_used) = response.gas_used {
+        println!("  Gas used:         {}", format_gas(gas_used));
+    }
+    if let Some(fee_wei) = response.fee_wei.filter(|fee| *fee > 0) {
+        println!("  Fee:              {} ether", format_ether(fee_wei));
+    }
 }
 
-#[derive(Subcommand)]
-pub enum RoleCommands {
-    /// Grant pusher role to an address
-    GrantPusher {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
-
-        /// Address to grant role to (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+async fn repo_info(client: DaemonClient, repo: &str, expected_chain: Option<u64>, output: Output) -> Result<()> {
+    let info = match client.repo_info(repo).await {
+        Ok(info) => info,
+        Err(e) => output.fail(e),
+    };
+    // Older daemons have no costs endpoint; the rest of the info stands.
+    let costs = client.repo_costs(repo).await.ok().filter(|costs| costs.total.txs > 0);
 
-    /// Revoke pusher role from an address
-    RevokePusher {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
+    let mut json = serde_json::to_value(&info)?;
+    if let Some(costs) = &costs {
+        json["costs"] = serde_json::to_value(costs)?;
+    }
+    output.result(&json, || {
+        println!("{}", format!("Repository '{}'", info.repo).bold());
+        println!("  Contract address: {}", info.address.cyan());
+        match info.chain_id {
+            Some(chain_id) => println!("  Chain id:         {}", chain_id.to_string().cyan()),
+            None => println!("  Chain id:         {}", "unknown".dimmed()),
+        }
+        if let Some(tx_hash) = &info.tx_hash {
+            println!("  Deployment tx:    {}", tx_hash);
+        }
+        if let Some(block_number) = info.block_number {
+            println!("  Block:            {}", block_number);
+        }
+        print_creation_cost(&info);
+        if let Some(costs) = &costs {
+            println!("  Total spent:      {}", costs.total);
+            for (action, action_costs) in &costs.by_action {
+                println!("    {:<15} {}", format!("{}:", action), action_costs);
+            }
+        }
+    });
 
-        /// Address to revoke role from (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+    // The daemon refuses writes to a repository on another chain than its
+    // endpoints; this catches local settings that point elsewhere.
+    if let (Some(expected), Some(actual)) = (expected_chain, info.chain_id) {
+        if expected != actual {
+            output.fail(format!("Repository is on chain {}, but the chain settings select {}", actual, expected));
+        }
+    }
 
-    /// Grant admin role to an address
-    GrantAdmin {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
+    Ok(())
+}
 
-        /// Address to grant role to (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+async fn list_refs(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    let refs = match client.list_refs(repo).await {
+        Ok(refs) => refs,
+        Err(e) => fail_request(output, e),
+    };
 
-    /// Revoke admin role from an address
-    RevokeAdmin {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
+    output.result(&refs, || {
+        if refs.is_empty() {
+            println!("{}", format!("Repository '{}' has no refs", repo).yellow());
+            return;
+        }
 
-        /// Address to revoke role from (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+        for r in &refs {
+            println!("{} {}", r.target.dimmed(), r.name);
+        }
+    });
 
-    /// Check if an address has pusher role
-    CheckPusher {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
+    Ok(())
+}
 
-        /// Address to check (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+async fn has_objects(client: DaemonClient, repo: &str, hashes: &[String], output: Output) -> Result<()> {
+    let exists = match client.has_objects(repo, hashes).await {
+        Ok(exists) => exists,
+        Err(e) => fail_request(output, e),
+    };
 
-    /// Check if an address has admin role
-    CheckAdmin {
-        /// Repository name
-        #[arg(short, long)]
-        repo: String,
+    let present: BTreeMap<&str, bool> = hashes.iter().map(String::as_str).zip(exists.iter().copied()).collect();
+    output.result(&present, || {
+        for (hash, present) in hashes.iter().zip(&exists) {
+            if *present {
+                println!("{} {}", "✓".green(), hash);
+            } else {
+                println!("{} {}", "✗".red(), hash);
+            }
+        }
+    });
 
-        /// Address to check (uses active account if not specified)
-        #[arg(short, long)]
-        address: Option<String>,
-    },
+    Ok(())
 }
 
-pub async fn handle_command(cmd: RepoCommands, client: DaemonClient) -> Result<()> {
-    match cmd {
-        RepoCommands::Create { name } => {
-            create_repo(client, &name).await?;
+async fn verify_repo(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    let report = match progress::wait(output, format!("Verifying repository '{}'...", repo), client.verify_repo(repo)).await {
+        Ok(report) => report,
+        Err(e) => fail_request(output, e),
+    };
+
+    output.result(&report, || {
+        println!("  Objects checked: {}", report.objects_checked);
+        println!("  Refs checked: {}", report.refs_checked);
+
+        for object in &report.missing_objects {
+            println!("  {} {}", "✗".red(), object.error);
         }
-        RepoCommands::Role(role_cmd) => {
-            handle_role_command(role_cmd, client).await?;
+        for r in &report.broken_refs {
+            println!("  {} {}", "✗".red(), r.reason);
         }
+    });
+
+    if report.ok {
+        output.message(format!("✓ Repository '{}' is consistent", report.repo).green());
+        Ok(())
+    } else if output.is_json() {
+        // The report says what is wrong; only the exit status is left.
+        std::process::exit(1);
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "✗ Repository '{}' has {} missing objects and {} broken refs",
+                report.repo, report.missing_objects.len(), report.broken_refs.len()
+            ).red()
+        );
+        std::process::exit(1);
     }
+}
 
-    Ok(())
+async fn repin_repo(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    let report = match progress::wait(output, format!("Re-pinning objects of repository '{}'...", repo), client.repin_repo(repo)).await {
+        Ok(report) => report,
+        Err(e) => fail_request(output, e),
+    };
+
+    output.result(&report, || {
+        println!("  Objects checked: {}", report.objects_checked);
+        println!("  Already pinned: {}", report.already_pinned);
+        println!("  Re-pinned: {}", report.repinned);
+
+        for object in &report.unrecoverable {
+            println!("  {} object {} (CID {}): {}", "✗".red(), object.hash, object.cid, object.error.dimmed());
+        }
+    });
+
+    if report.unrecoverable.is_empty() {
+        output.message(format!("✓ Every object of '{}' is pinned", report.repo).green());
+        Ok(())
+    } else if output.is_json() {
+        std::process::exit(1);
+    } else {
+        eprintln!("{}", format!("✗ {} objects of '{}' could not be recovered", report.unrecoverable.len(), report.repo).red());
+        std::process::exit(1);
+    }
 }
 
-async fn create_repo(client: DaemonClient, name: &str) -> Result<()> {
-    println!("{}", format!("Creating repository '{}'...", name).yellow());
+async fn list_pins(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    let response = match progress::wait(output, format!("Listing pins of repository '{}'...", repo), client.list_pins(repo)).await {
+        Ok(response) => response,
+        Err(e) => fail_request(output, e),
+    };
 
-    match client.create_repo(name).await {
-        Ok(response) => {
-            println!("{}", format!("✓ Repository '{}' created successfully", name).green());
-            println!("  Contract address: {}", response.address.cyan());
+    output.result(&response, || {
+        if response.pins.is_empty() {
+            println!("{}", format!("No pins recorded for repository '{}'", response.repo).yellow());
+            return;
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to create repository: {}", e).red());
-            std::process::exit(1);
+
+        for pin in &response.pins {
+            let size = pin.size.map(|size| format!("{} bytes", size)).unwrap_or_else(|| "size unknown".to_string());
+            let mut notes = Vec::new();
+            if !pin.referenced {
+                notes.push("unreferenced");
+            }
+            if pin.shared {
+                notes.push("shared");
+            }
+            println!("  {} {} ({}) {}", pin.cid, pin.hash.dimmed(), size, notes.join(", ").yellow());
         }
-    }
+        println!("  Pins: {}, {} bytes", response.pins.len(), response.total_bytes);
+    });
 
     Ok(())
 }
 
-async fn handle_role_command(cmd: RoleCommands, client: DaemonClient) -> Result<()> {
-    let config = Config::load()?;
+async fn unpin_repo(client: DaemonClient, repo: &str, all: bool, dry_run: bool, output: Output) -> Result<()> {
+    let report = match progress::wait(output, format!("Unpinning objects of repository '{}'...", repo), client.unpin_repo(repo, all, dry_run)).await {
+        Ok(report) => report,
+        Err(e) => fail_request(output, e),
+    };
 
-    match cmd {
-        RoleCommands::GrantPusher { repo, address } => {
-            let address = get_address(address, &config)?;
-            grant_pusher_role(client, &repo, &address).await?;
-        }
-        RoleCommands::RevokePusher { repo, address } => {
-            let address = get_address(address, &config)?;
-            revoke_pusher_role(client, &repo, &address).await?;
+    output.result(&report, || {
+        for cid in &report.unpinned {
+            println!("  {} {}", "•".yellow(), cid);
         }
-        RoleCommands::GrantAdmin { repo, address } => {
-            let address = get_address(address, &config)?;
-            grant_admin_role(client, &repo, &address).await?;
+        for cid in &report.shared {
+            println!("  {} {} {}", "•".dimmed(), cid, "(kept: another repository uses it)".dimmed());
         }
-        RoleCommands::RevokeAdmin { repo, address } => {
-            let address = get_address(address, &config)?;
-            revoke_admin_role(client, &repo, &address).await?;
+        for cid in &report.recent {
+            println!("  {} {} {}", "•".dimmed(), cid, "(kept: pinned by a push that may still be anchoring it)".dimmed());
         }
-        RoleCommands::CheckPusher { repo, address } => {
-            let address = get_address(address, &config)?;
-            check_pusher_role(client, &repo, &address).await?;
+        for failure in &report.failed {
+            println!("  {} {}: {}", "✗".red(), failure.cid, failure.error.dimmed());
         }
-        RoleCommands::CheckAdmin { repo, address } => {
-            let address = get_address(address, &config)?;
-            check_admin_role(client, &repo, &address).await?;
+
+        if report.dry_run {
+            println!("{}", format!("{} pins of repository '{}' would be removed", report.unpinned.len(), report.repo).yellow());
+        } else {
+            println!("{}", format!("✓ Removed {} pins of repository '{}'", report.unpinned.len(), report.repo).green());
         }
-    }
+    });
 
-    Ok(())
+    if report.failed.is_empty() {
+        Ok(())
+    } else if output.is_json() {
+        std::process::exit(1);
+    } else {
+        eprintln!("{}", format!("✗ {} pins of '{}' could not be removed", report.failed.len(), report.repo).red());
+        std::process::exit(1);
+    }
 }
 
-fn get_address(address: Option<String>, config: &Config) -> Result<String> {
-    match address {
-        Some(addr) => Ok(addr),
-        None => {
-            config.get_active_account()
-                .map(|account| account.address.clone())
-                .ok_or_else(|| anyhow::anyhow!("No active account. Use 'dgit account add' to add one."))
+async fn prune_refs(client: DaemonClient, repo: &str, dry_run: bool, output: Output) -> Result<()> {
+    let report = match client.prune_refs(repo, dry_run).await {
+        Ok(report) => report,
+        Err(e) => fail_request(output, e),
+    };
+
+    output.result(&report, || {
+        println!("  Ref entries on chain: {}", report.entries);
+        println!("  Active refs: {}", report.active);
+        for r in &report.pruned {
+            println!("  {} #{} {} -> {} ({})", "•".yellow(), r.index, r.name, r.target, r.reason.dimmed());
         }
-    }
+
+        if report.pruned.is_empty() {
+            println!("{}", format!("✓ Repository '{}' has no stale ref entries", report.repo).green());
+        } else if report.dry_run {
+            println!("{}", format!("{} stale ref entries in repository '{}'", report.pruned.len(), report.repo).yellow());
+        } else {
+            println!("{}", format!("✓ Pruned {} stale ref entries from what the daemon serves", report.pruned.len()).green());
+        }
+    });
+
+    Ok(())
 }
 
-async fn grant_pusher_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    println!("{}", format!("Granting pusher role to {} for repository '{}'...", address, repo).yellow());
+async fn fsck_repo(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    output.message(format!("Checking repository '{}'...", repo).yellow());
 
-    match client.grant_pusher_role(repo, address).await {
-        Ok(_) => {
-            println!("{}", format!("✓ Pusher role granted to {}", address).green());
+    let mut summary = None;
+    let mut failure = None;
+    let mut problems = Vec::new();
+    let result = client.fsck_repo(repo, |event| match event {
+        // Shown as they stream in; JSON output lists them with the summary.
+        FsckEvent::Problem(problem) if output.is_json() => problems.push(problem),
+        FsckEvent::Problem(problem) => {
+            let marker = if problem.severity == "error" { "✗".red() } else { "!".yellow() };
+            println!("  {} {} {}", marker, problem.kind.dimmed(), problem.detail);
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to grant pusher role: {}", e).red());
+        FsckEvent::Summary(report) => summary = Some(report),
+        FsckEvent::Error { message } => failure = Some(message),
+    }).await;
+
+    if let Err(e) = result {
+        output.fail(format!("Failed to check repository: {}", e));
+    }
+    if let Some(message) = failure {
+        output.fail(format!("Check did not finish: {}", message));
+    }
+    let Some(summary) = summary else {
+        output.fail("Check ended without a summary");
+    };
+
+    if output.is_json() {
+        output.result(&serde_json::json!({ "summary": summary, "problems": problems }), || {});
+        if summary.errors > 0 {
             std::process::exit(1);
         }
+        return Ok(());
+    }
+
+    println!("  Objects checked: {}", summary.objects_checked);
+    println!("  Refs checked: {}", summary.refs_checked);
+    for (count, label) in [
+        (summary.missing_objects, "missing objects"),
+        (summary.hash_mismatches, "hash mismatches"),
+        (summary.undecodable, "undecodable objects"),
+        (summary.dangling_refs, "dangling refs"),
+        (summary.unreachable_refs, "unreachable refs"),
+        (summary.broken_links, "broken links"),
+    ] {
+        if count > 0 {
+            println!("  {}: {}", label, count);
+        }
+    }
+
+    if summary.errors > 0 {
+        output.fail(format!("Repository '{}' has {} errors and {} warnings", summary.repo, summary.errors, summary.warnings));
+    } else if summary.warnings > 0 {
+        println!("{}", format!("! Repository '{}' is usable, with {} warnings", summary.repo, summary.warnings).yellow());
+    } else {
+        println!("{}", format!("✓ Repository '{}' is consistent", summary.repo).green());
     }
 
     Ok(())
 }
 
-async fn revoke_pusher_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    println!("{}", format!("Revoking pusher role from {} for repository '{}'...", address, repo).yellow());
+async fn export_repo(client: DaemonClient, name: &str, out: Option<String>, output: Output) -> Result<()> {
+    let out = out.unwrap_or_else(|| format!("{}.bundle", name.replace('/', "-")));
+    let bundle = match progress::wait(output, format!("Exporting repository '{}'...", name), client.export_repo(name)).await {
+        Ok(bundle) => bundle,
+        Err(e) => fail_request(output, e),
+    };
 
-    match client.revoke_pusher_role(repo, address).await {
-        Ok(_) => {
-            println!("{}", format!("✓ Pusher role revoked from {}", address).green());
+    if let Err(e) = std::fs::write(&out, &bundle) {
+        output.fail(format!("Failed to write {}: {}", out, e));
+    }
+
+    output.result(&serde_json::json!({ "repo": name, "path": out, "bytes": bundle.len() }), || {
+        println!("{}", format!("✓ Exported repository '{}' to {} ({} bytes)", name, out, bundle.len()).green());
+        println!("  Clone it with: git clone {}", out);
+    });
+    Ok(())
+}
+
+async fn gc_repo(client: DaemonClient, repo: &str, dry_run: bool, force: bool, non_interactive: bool, output: Output) -> Result<()> {
+    // Always look first, so the prompt can say what would be deactivated.
+    let finding = format!("Finding objects no ref of '{}' reaches...", repo);
+    let report = match progress::wait(output, finding, client.gc_repo(repo, true)).await {
+        Ok(report) => report,
+        Err(e) => fail_request(output, e),
+    };
+
+    output.message(format!("  Objects checked: {}", report.objects_checked));
+    output.message(format!("  Reachable from active refs: {}", report.reachable));
+    for hash in &report.unreferenced {
+        output.message(format!("  {} {}", "•".yellow(), hash));
+    }
+
+    if report.unreferenced.is_empty() {
+        output.result(&report, || {
+            println!("{}", format!("✓ Repository '{}' has no unreferenced objects", repo).green());
+        });
+        return Ok(());
+    }
+
+    output.message(format!("{} unreferenced objects in repository '{}'", report.unreferenced.len(), repo).yellow());
+    if dry_run {
+        output.result(&report, || {});
+        return Ok(());
+    }
+
+    let prompt = format!("Mark {} objects inactive on-chain?", report.unreferenced.len());
+    if !account::confirm(&prompt, force, non_interactive)? {
+        output.result(&report, || println!("Aborted"));
+        return Ok(());
+    }
+
+    match progress::wait(output, "Marking objects inactive on chain...", client.gc_repo(repo, false)).await {
+        Ok(report) => output.result(&report, || {
+            println!("{}", format!("✓ Marked {} objects inactive", report.unreferenced.len()).green());
+            for tx_hash in &report.tx_hashes {
+                println!("  Transaction: {}", tx_hash.cyan());
+            }
+        }),
+        Err(e) => fail_request(output, e),
+    }
+
+    Ok(())
+}
+
+async fn mirror_repo(source: DaemonClient, destination: DaemonClient, name: &str, output: Output) -> Result<()> {
+    let source_url = source.repo_url(name);
+    let destination_url = destination.repo_url(name);
+    output.message(format!("Mirroring {} to {}...", source_url, destination_url).yellow());
+
+    let mut steps = Steps::new(output, 4);
+    let refs = match steps.run("Reading the source repository...", source.list_refs(name)).await {
+        Ok(refs) if refs.is_empty() => output.fail(format!("Repository '{}' has no refs to mirror", name)),
+        Ok(refs) => refs.len(),
+        Err(e) => output.fail_with(e.exit_code(), format!("Failed to read source repository: {}", e), e.hint()),
+    };
+    output.message(format!("  Source has {} refs", refs));
+
+    let created = match steps.run("Checking the destination repository...", destination.repo_exists(name)).await {
+        Ok(true) => {
+            output.message("  Destination repository exists");
+            false
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to revoke pusher role: {}", e).red());
-            std::process::exit(1);
+        Ok(false) => {
+            let creating = format!("Creating repository '{}' on the destination...", name);
+            if let Err(e) = progress::wait(output, creating, destination.create_repo(name)).await {
+                output.fail_with(e.exit_code(), format!("Failed to create destination repository: {}", e), e.hint());
+            }
+            true
         }
+        Err(e) => output.fail_with(e.exit_code(), format!("Failed to check destination repository: {}", e), e.hint()),
+    };
+
+    let work_dir = tempfile::tempdir()?;
+    let mirror_path = work_dir.path().join("mirror.git");
+    let mirror_path = mirror_path.to_string_lossy();
+
+    let cloned = steps.run("Fetching from the source...", run_git(&source, &["clone", "--mirror", &source_url, &mirror_path])).await;
+    if let Err(e) = cloned {
+        output.fail(format!("Failed to fetch from source: {:#}", e));
+    }
+
+    let pushed = steps.run("Pushing to the destination...", run_git(&destination, &["-C", &mirror_path, "push", "--mirror", &destination_url])).await;
+    if let Err(e) = pushed {
+        output.fail(format!("Failed to push to destination: {:#}", e));
     }
 
+    let mirrored = serde_json::json!({
+        "repo": name,
+        "source": source_url,
+        "destination": destination_url,
+        "refs": refs,
+        "created": created,
+    });
+    output.result(&mirrored, || {
+        println!("{}", format!("✓ Mirrored repository '{}' to {}", name, destination_url).green());
+    });
     Ok(())
 }
 
-async fn grant_admin_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    println!("{}", format!("Granting admin role to {} for repository '{}'...", address, repo).yellow());
+/// Runs git against `daemon`, passing its credentials as an extra header so
+/// they never end up in a remote URL or on disk.
+pub(crate) async fn run_git(daemon: &DaemonClient, args: &[&str]) -> Result<()> {
+    let mut cmd = tokio::process::Command::new("git");
+    if let Some(auth_header) = daemon.auth_header() {
+        cmd.arg("-c").arg(format!("http.extraHeader=Authorization: {}", auth_header));
+    }
 
-    match client.grant_admin_role(repo, address).await {
-        Ok(_) => {
-            println!("{}", format!("✓ Admin role granted to {}", address).green());
+    let output = cmd.args(args).output().await?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(())
+}
+
+async fn push_repo(client: DaemonClient, config: &Config, name: &str, refspecs: &[String], force: bool, retries: u32, output: Output) -> Result<()> {
+    ensure_repo_exists(&client, name, output).await;
+
+    let Some(account) = config.get_active_account() else {
+        output.fail("No active account. Use 'dgit account add' to add one.");
+    };
+    match client.check_pusher_role(name, &account.address).await {
+        Ok(true) => {}
+        Ok(false) => output.fail(format!(
+            "Account {} may not push to '{}'. An admin can allow it with 'dgit repo role grant-pusher --repo {} --address {}'",
+            account.address, name, name, account.address,
+        )),
+        Err(e) => eprintln!("{}", format!("Warning: could not check the pusher role: {}", e).yellow()),
+    }
+
+    let url = client.repo_url(name);
+    output.message(format!("Pushing to {}...", url).yellow());
+
+    let mut args = vec!["push", "--porcelain"];
+    if !output.is_json() {
+        args.push("--progress");
+    }
+    if force {
+        args.push("--force");
+    }
+    args.push(&url);
+    args.extend(refspecs.iter().map(String::as_str));
+
+    let run = run_git_with_retries(&client, &args, retries, output).await?;
+    let refs = parse_push_porcelain(&run.stdout);
+    if !run.success && refs.is_empty() {
+        output.fail(format!("git push failed: {}", run.stderr.trim()));
+    }
+
+    let rejected = refs.iter().filter(|change| change.status == RefStatus::Rejected).count();
+    let pushed = serde_json::json!({
+        "repo": name,
+        "url": url,
+        "attempts": run.attempts,
+        "refs": refs,
+    });
+    output.result(&pushed, || print_ref_changes(&refs));
+
+    if run.success {
+        output.message(format!("✓ Pushed to '{}'", name).green());
+        Ok(())
+    } else if output.is_json() {
+        std::process::exit(1);
+    } else {
+        eprintln!("{}", format!("✗ {} refs were rejected", rejected).red());
+        std::process::exit(1);
+    }
+}
+
+async fn fetch_repo(client: DaemonClient, name: &str, refspecs: &[String], retries: u32, output: Output) -> Result<()> {
+    ensure_repo_exists(&client, name, output).await;
+
+    let url = client.repo_url(name);
+    output.message(format!("Fetching from {}...", url).yellow());
+
+    let mut args = vec!["fetch"];
+    if !output.is_json() {
+        args.push("--progress");
+    }
+    args.push(&url);
+    args.extend(refspecs.iter().map(String::as_str));
+
+    let run = run_git_with_retries(&client, &args, retries, output).await?;
+    if !run.success {
+        output.fail(format!("git fetch failed: {}", run.stderr.trim()));
+    }
+
+    let refs = parse_fetch_output(&run.stderr);
+    let fetched = serde_json::json!({
+        "repo": name,
+        "url": url,
+        "attempts": run.attempts,
+        "refs": refs,
+    });
+    output.result(&fetched, || {
+        print_ref_changes(&refs);
+        println!("{}", format!("✓ Fetched from '{}'", name).green());
+    });
+    Ok(())
+}
+
+async fn ensure_repo_exists(client: &DaemonClient, name: &str, output: Output) {
+    match client.repo_exists(name).await {
+        Ok(true) => {}
+        Ok(false) => output.fail(format!("Repository '{}' does not exist. Create it with 'dgit repo create {}'", name, name)),
+        Err(e) => fail_request(output, e),
+    }
+}
+
+/// How a ref changed, from the flag git prints next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum RefStatus {
+    Updated,
+    Forced,
+    New,
+    Deleted,
+    Rejected,
+    UpToDate,
+}
+
+impl RefStatus {
+    fn from_flag(flag: char) -> Option<Self> {
+        Some(match flag {
+            ' ' | 't' => RefStatus::Updated,
+            '+' => RefStatus::Forced,
+            '*' => RefStatus::New,
+            '-' => RefStatus::Deleted,
+            '!' => RefStatus::Rejected,
+            '=' => RefStatus::UpToDate,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RefChange {
+    status: RefStatus,
+    from: String,
+    to: String,
+    /// git's note on the change: the commit range, or why it was rejected.
+    summary: String,
+}
+
+/// Ref lines of `git push --porcelain`: `<flag>\t<from>:<to>\t<summary>`.
+fn parse_push_porcelain(stdout: &str) -> Vec<RefChange> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let status = RefStatus::from_flag(fields.next()?.chars().next()?)?;
+            let (from, to) = fields.next()?.split_once(':')?;
+            Some(RefChange {
+                status,
+                from: from.to_string(),
+                to: to.to_string(),
+                summary: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Ref lines of `git fetch`, such as ` * [new branch]  main -> origin/main`.
+fn parse_fetch_output(stderr: &str) -> Vec<RefChange> {
+    stderr
+        .lines()
+        // Progress lines end in carriage returns; the ref lines follow the last.
+        .filter_map(|line| line.rsplit('\r').next())
+        .filter_map(|line| {
+            let mut chars = line.chars();
+            if chars.next()? != ' ' {
+                return None;
+            }
+            let status = RefStatus::from_flag(chars.next()?)?;
+            let rest = chars.as_str().trim_start();
+
+            let (summary, rest) = match rest.strip_prefix('[') {
+                Some(bracketed) => {
+                    let (summary, rest) = bracketed.split_once(']')?;
+                    (format!("[{}]", summary), rest)
+                }
+                None => {
+                    let (summary, rest) = rest.split_once(char::is_whitespace)?;
+                    (summary.to_string(), rest)
+                }
+            };
+            let (from, to) = rest.split_once(" -> ")?;
+            Some(RefChange {
+                status,
+                from: from.trim().to_string(),
+                to: to.split_whitespace().next()?.to_string(),
+                summary,
+            })
+        })
+        .collect()
+}
+
+fn print_ref_changes(changes: &[RefChange]) {
+    for change in changes {
+        let line = format!("{} -> {} {}", change.from, change.to, change.summary.dimmed());
+        match change.status {
+            RefStatus::New => println!("  {} {}", "new       ".green(), line),
+            RefStatus::Updated => println!("  {} {}", "updated   ".green(), line),
+            RefStatus::Forced => println!("  {} {}", "forced    ".yellow(), line),
+            RefStatus::Deleted => println!("  {} {}", "deleted   ".yellow(), line),
+            RefStatus::Rejected => println!("  {} {}", "rejected  ".red(), line),
+            RefStatus::UpToDate => println!("  {} {}", "up to date".dimmed(), line),
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to grant admin role: {}", e).red());
-            std::process::exit(1);
+    }
+}
+
+/// What a git run printed, and how many tries it took.
+struct GitRun {
+    success: bool,
+    stdout: String,
+    stderr: String,
+    attempts: u32,
+}
+
+/// Runs git against `daemon`, trying again with growing delays while the
+/// daemon turns requests away with 429 or 503.
+async fn run_git_with_retries(daemon: &DaemonClient, args: &[&str], retries: u32, output: Output) -> Result<GitRun> {
+    let mut attempts = 1;
+    loop {
+        let mut run = run_git_with_progress(daemon, args, !output.is_json()).await?;
+        run.attempts = attempts;
+        if run.success || attempts > retries || !daemon_busy(&run.stderr) {
+            return Ok(run);
         }
+
+        let delay = GIT_RETRY_DELAY * 2u32.pow((attempts - 1).min(6));
+        output.message(format!("The daemon is busy, retrying in {}s ({}/{})...", delay.as_secs(), attempts, retries).yellow());
+        tokio::time::sleep(delay).await;
+        attempts += 1;
     }
+}
 
-    Ok(())
+/// Like `run_git`, but keeps what git prints and, with `show_progress`,
+/// passes its stderr through as it comes.
+async fn run_git_with_progress(daemon: &DaemonClient, args: &[&str], show_progress: bool) -> Result<GitRun> {
+    let mut cmd = tokio::process::Command::new("git");
+    if let Some(auth_header) = daemon.auth_header() {
+        cmd.arg("-c").arg(format!("http.extraHeader=Authorization: {}", auth_header));
+    }
+    let mut child = cmd
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+
+    let mut stdout = child.stdout.take().context("git stdout was not captured")?;
+    let mut stderr = child.stderr.take().context("git stderr was not captured")?;
+    let read_stdout = async {
+        let mut seen = Vec::new();
+        stdout.read_to_end(&mut seen).await?;
+        Ok::<_, std::io::Error>(seen)
+    };
+    let read_stderr = async {
+        let mut seen = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut echo = tokio::io::stderr();
+        loop {
+            let read = stderr.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            if show_progress {
+                echo.write_all(&chunk[..read]).await?;
+            }
+            seen.extend_from_slice(&chunk[..read]);
+        }
+        Ok::<_, std::io::Error>(seen)
+    };
+    let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+    let status = child.wait().await?;
+
+    Ok(GitRun {
+        success: status.success(),
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        attempts: 1,
+    })
 }
 
-async fn revoke_admin_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    println!("{}", format!("Revoking admin role from {} for repository '{}'...", address, repo).yellow());
+/// Whether git gave up because the daemon was busy: rate limited, or out of
+/// slots for fetches and pushes.
+fn daemon_busy(stderr: &str) -> bool {
+    ["429", "503"].iter().any(|code| {
+        stderr.contains(&format!("returned error: {}", code)) || stderr.contains(&format!("HTTP {}", code))
+    })
+}
 
-    match client.revoke_admin_role(repo, address).await {
-        Ok(_) => {
-            println!("{}", format!("✓ Admin role revoked from {}", address).green());
+async fn show_audit_log(client: DaemonClient, repo: &str, limit: usize, output: Output) -> Result<()> {
+    let audit = match client.get_audit_log(repo, limit).await {
+        Ok(audit) => audit,
+        Err(e) => fail_request(output, e),
+    };
+    if output.is_json() {
+        output.result(&audit, || {});
+        return Ok(());
+    }
+
+    if audit.entries.is_empty() {
+        println!("{}", format!("No audit entries for repository '{}'", audit.repo).yellow());
+        return Ok(());
+    }
+
+    println!("{}", format!("Audit log for repository '{}' (newest first):", audit.repo).bold());
+    for entry in audit.entries {
+        let mut line = format!("  {} {}", entry.timestamp.to_string().dimmed(), entry.action.cyan());
+        if let Some(role) = &entry.role {
+            line.push_str(&format!(" {}", role));
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to revoke admin role: {}", e).red());
-            std::process::exit(1);
+        if let Some(actor) = &entry.actor {
+            line.push_str(&format!(" by {}", actor));
+        }
+        if let Some(signer) = &entry.unverified_signer {
+            line.push_str(&format!(" (claims signer {}, unverified)", signer).yellow().to_string());
+        }
+        if let Some(target) = &entry.target {
+            line.push_str(&format!(" -> {}", target));
+        }
+        if let Some(tx_hash) = &entry.tx_hash {
+            line.push_str(&format!(" (tx {})", tx_hash.dimmed()));
         }
+        if let Some(costs) = &entry.costs {
+            line.push_str(&format!(" [{}]", costs));
+        }
+        for (key, value) in &entry.push_options {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        println!("{}", line);
     }
 
     Ok(())
 }
 
-async fn check_pusher_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    match client.check_pusher_role(repo, address).await {
-        Ok(has_role) => {
-            if has_role {
-                println!("{}", format!("✓ {} has pusher role for repository '{}'", address, repo).green());
-            } else {
-                println!("{}", format!("✗ {} does not have pusher role for repository '{}'", address, repo).yellow());
-            }
+async fn show_history(client: DaemonClient, repo: &str, since: Option<u64>, to_block: Option<u64>, output: Output) -> Result<()> {
+    let history = match progress::wait(output, "Reading events from the chain...", client.repo_history(repo, since, to_block)).await {
+        Ok(history) => history,
+        Err(e) => fail_request(output, e),
+    };
+    if output.is_json() {
+        output.result(&history, || {});
+        return Ok(());
+    }
+
+    if history.changes.is_empty() {
+        println!("{}", format!(
+            "No pushes to repository '{}' in blocks {}..={}",
+            history.repo, history.from_block, history.to_block,
+        ).yellow());
+        return Ok(());
+    }
+
+    println!("{}", format!(
+        "History of repository '{}' in blocks {}..={} (oldest first):",
+        history.repo, history.from_block, history.to_block,
+    ).bold());
+
+    // One transaction anchors one push, or one batch of a large push.
+    let mut pushes: Vec<(&str, Vec<&HistoryEntry>)> = Vec::new();
+    for change in &history.changes {
+        match pushes.last_mut() {
+            Some((tx_hash, changes)) if *tx_hash == change.tx_hash => changes.push(change),
+            _ => pushes.push((&change.tx_hash, vec![change])),
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to check pusher role: {}", e).red());
-            std::process::exit(1);
+    }
+
+    for (tx_hash, changes) in pushes {
+        let first = changes[0];
+        let objects = changes.iter().filter(|change| change.kind == "object").count();
+        println!(
+            "  block {} by {} ({} objects, tx {})",
+            first.block_number.to_string().cyan(), first.pusher, objects, tx_hash.dimmed(),
+        );
+        for change in changes.iter().filter(|change| change.kind == "ref") {
+            println!("    {} -> {}", change.name, change.target);
         }
     }
 
     Ok(())
 }
 
-async fn check_admin_role(client: DaemonClient, repo: &str, address: &str) -> Result<()> {
-    match client.check_admin_role(repo, address).await {
-        Ok(has_role) => {
-            if has_role {
-                println!("{}", format!("✓ {} has admin role for repository '{}'", address, repo).green());
-            } else {
-                println!("{}", format!("✗ {} does not have admin role for repository '{}'", address, repo).yellow());
-            }
+async fn anchoring_status(client: DaemonClient, repo: &str, output: Output) -> Result<()> {
+    let status = match client.anchoring_status(repo).await {
+        Ok(status) => status,
+        Err(e) => output.fail_with(e.exit_code(), format!("Failed to fetch anchoring status: {}", e), e.hint()),
+    };
+    if output.is_json() {
+        output.result(&status, || {});
+        return Ok(());
+    }
+
+    if !status.enabled {
+        println!("{}", "The daemon anchors pushes before answering them (DGIT_ASYNC_ANCHORING is off)".yellow());
+    }
+    if status.jobs.is_empty() {
+        println!("{}", format!("No anchoring jobs for repository '{}'", status.repo).yellow());
+        return Ok(());
+    }
+
+    println!("{}", format!("Anchoring jobs for repository '{}' (newest first):", status.repo).bold());
+    for job in status.jobs {
+        let state = match job.status.as_str() {
+            "confirmed" => job.status.green(),
+            "failed" => job.status.red(),
+            _ => job.status.yellow(),
+        };
+        let mut line = format!(
+            "  job {} {} {} ({} objects, {} refs, {} attempts)",
+            job.id, state, job.updated.to_string().dimmed(), job.object_count, job.ref_count, job.attempts
+        );
+        if let Some(tx_hash) = job.tx_hashes.last() {
+            line.push_str(&format!(" (tx {})", tx_hash.dimmed()));
         }
-        Err(e) => {
-            eprintln!("{}", format!("✗ Failed to check admin role: {}", e).red());
-            std::process::exit(1);
+        if job.costs.txs > 0 {
+            line.push_str(&format!(" [{}]", job.costs));
+        }
+        println!("{}", line);
+        if let Some(error) = &job.error {
+            println!("    {}", error.red());
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
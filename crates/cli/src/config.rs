@@ -1,14 +1,48 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use ::daemon::auth::{auth_origin, issue_token, Credentials};
 use dirs::config_dir;
+use onchain::ens;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::output::OutputFormat;
+
+/// Daemon URL used when neither `--daemon-url`, `DGIT_DAEMON_URL` nor the
+/// `daemon_url` setting gives one.
+pub const DEFAULT_DAEMON_URL: &str = "http://localhost:3000";
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
     pub accounts: HashMap<String, Account>,
     pub active_account: Option<String>,
+    /// Chain each repository lives on, keyed by repository name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub repos: HashMap<String, ChainSettings>,
+    #[serde(default, skip_serializing_if = "Settings::is_empty")]
+    pub settings: Settings,
+    /// Names for addresses, usable wherever a role command takes one.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub contacts: HashMap<String, String>,
+}
+
+/// Defaults for every command, managed with `dgit config`. Flags and
+/// environment variables take precedence over them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daemon_url: Option<String>,
+    /// Chain for repositories without their own or an account's chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+    /// Mainnet RPC URL to resolve `.eth` names with; they are not resolved
+    /// without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ens_rpc_url: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,6 +50,147 @@ pub struct Account {
     pub name: String,
     pub private_key: String,
     pub address: String,
+    /// Chain used for repositories without their own settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc_url: Option<String>,
+}
+
+/// Which chain a repository is addressed on. Either field may be missing;
+/// a chain id can be looked up from the RPC URL.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ChainSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc_url: Option<String>,
+}
+
+impl ChainSettings {
+    pub fn is_empty(&self) -> bool {
+        self.chain_id.is_none() && self.rpc_url.is_none()
+    }
+
+    /// Fields set in `self`, falling back to `other` for the rest.
+    pub fn or(self, other: &ChainSettings) -> ChainSettings {
+        ChainSettings {
+            chain_id: self.chain_id.or(other.chain_id),
+            rpc_url: self.rpc_url.or_else(|| other.rpc_url.clone()),
+        }
+    }
+}
+
+impl Settings {
+    pub const KEYS: [&'static str; 4] = ["daemon_url", "chain_id", "output", "ens_rpc_url"];
+
+    pub fn is_empty(&self) -> bool {
+        *self == Settings::default()
+    }
+
+    /// The daemon URL to use: `given` (from `--daemon-url` or
+    /// `DGIT_DAEMON_URL`), then the setting, then `DEFAULT_DAEMON_URL`.
+    pub fn daemon_url(&self, given: Option<String>) -> String {
+        given
+            .or_else(|| self.daemon_url.clone())
+            .unwrap_or_else(|| DEFAULT_DAEMON_URL.to_string())
+    }
+
+    /// The output format to use: `given` (from `--output`), then the
+    /// setting, then text.
+    pub fn output(&self, given: Option<OutputFormat>) -> OutputFormat {
+        given.or(self.output).unwrap_or_default()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(match key {
+            "daemon_url" => self.daemon_url.clone(),
+            "chain_id" => self.chain_id.map(|chain_id| chain_id.to_string()),
+            "output" => self.output.map(|format| format_name(format).to_string()),
+            "ens_rpc_url" => self.ens_rpc_url.clone(),
+            _ => unknown_key(key)?,
+        })
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "daemon_url" => {
+                if !value.contains("://") && !value.starts_with("unix:") {
+                    bail!("Invalid daemon_url '{}': expected a URL such as {}", value, DEFAULT_DAEMON_URL);
+                }
+                self.daemon_url = Some(value.to_string());
+            }
+            "chain_id" => {
+                let chain_id = value.parse().with_context(|| format!("Invalid chain_id '{}'", value))?;
+                self.chain_id = Some(chain_id);
+            }
+            "output" => {
+                let format = OutputFormat::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("Invalid output '{}': expected text or json", value))?;
+                self.output = Some(format);
+            }
+            "ens_rpc_url" => {
+                if !value.contains("://") {
+                    bail!("Invalid ens_rpc_url '{}': expected a URL", value);
+                }
+                self.ens_rpc_url = Some(value.to_string());
+            }
+            _ => unknown_key(key)?,
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        match key {
+            "daemon_url" => self.daemon_url = None,
+            "chain_id" => self.chain_id = None,
+            "output" => self.output = None,
+            "ens_rpc_url" => self.ens_rpc_url = None,
+            _ => unknown_key(key)?,
+        }
+        Ok(())
+    }
+
+    fn chain(&self) -> ChainSettings {
+        ChainSettings {
+            chain_id: self.chain_id,
+            rpc_url: None,
+        }
+    }
+}
+
+fn format_name(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Text => "text",
+        OutputFormat::Json => "json",
+    }
+}
+
+/// Whether `value` is a `0x`-prefixed 20-byte hex address.
+pub fn is_address(value: &str) -> bool {
+    value
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn unknown_key<T>(key: &str) -> Result<T> {
+    bail!("Unknown setting '{}', expected one of: {}", key, Settings::KEYS.join(", "))
+}
+
+impl Account {
+    /// Signs an auth token for this account that the daemon at `daemon_url`
+    /// accepts for `ttl_secs`.
+    pub fn credentials(&self, daemon_url: &str, ttl_secs: u64) -> Result<Credentials> {
+        issue_token(&self.private_key, &auth_origin(daemon_url), ttl_secs)
+            .with_context(|| format!("Failed to sign auth token for account '{}'", self.name))
+    }
+
+    pub fn chain(&self) -> ChainSettings {
+        ChainSettings {
+            chain_id: self.chain_id,
+            rpc_url: self.rpc_url.clone(),
+        }
+    }
 }
 
 impl Config {
@@ -34,6 +209,20 @@ impl Config {
         toml::from_str(&content).context("Failed to parse config file")
     }
 
+    /// The settings, for resolving global options before a command runs.
+    /// Unlike `load`, a missing config file is not created.
+    pub fn load_settings() -> Result<Settings> {
+        let config_path = Self::config_path()?;
+        if !config_path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let content = fs::read_to_string(&config_path)
+            .context("Failed to read config file")?;
+        let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
+        Ok(config.settings)
+    }
+
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
 
@@ -59,11 +248,21 @@ impl Config {
         self.save()
     }
 
+    /// Account that becomes active if `name` is removed, or `None` when no
+    /// accounts would remain.
+    pub fn active_after_removal(&self, name: &str) -> Option<&String> {
+        if self.active_account.as_deref() != Some(name) {
+            return self.active_account.as_ref();
+        }
+
+        self.accounts.keys().filter(|other| *other != name).min()
+    }
+
     pub fn remove_account(&mut self, name: &str) -> Result<()> {
         self.accounts.remove(name);
 
         if self.active_account.as_ref() == Some(&name.to_string()) {
-            self.active_account = self.accounts.keys().next().cloned();
+            self.active_account = self.accounts.keys().min().cloned();
         }
 
         self.save()
@@ -94,10 +293,103 @@ impl Config {
             .collect()
     }
 
-    fn config_path() -> Result<PathBuf> {
+    /// Chain settings for `repo`: explicit `overrides` first, then the
+    /// repository's saved settings, then the active account's, then the
+    /// `chain_id` setting.
+    pub fn chain_for(&self, repo: &str, overrides: ChainSettings) -> ChainSettings {
+        let repo_settings = self.repos.get(repo).cloned().unwrap_or_default();
+        let account_settings = self.get_active_account().map(Account::chain).unwrap_or_default();
+
+        overrides.or(&repo_settings).or(&account_settings).or(&self.settings.chain())
+    }
+
+    pub fn set_repo_chain(&mut self, repo: &str, settings: ChainSettings) -> Result<()> {
+        if settings.is_empty() {
+            self.repos.remove(repo);
+        } else {
+            self.repos.insert(repo.to_string(), settings);
+        }
+        self.save()
+    }
+
+    pub fn add_contact(&mut self, name: &str, address: &str) -> Result<()> {
+        if is_address(name) {
+            bail!("Contact name '{}' is an address; pick a name", name);
+        }
+        if ens::is_ens_name(name) {
+            bail!("Contact name '{}' would hide the ENS name; pick another", name);
+        }
+        if !is_address(address) {
+            bail!("'{}' is not an address", address);
+        }
+        if self.contacts.contains_key(name) {
+            bail!("Contact '{}' already exists", name);
+        }
+
+        self.contacts.insert(name.to_string(), address.to_string());
+        self.save()
+    }
+
+    pub fn remove_contact(&mut self, name: &str) -> Result<()> {
+        if self.contacts.remove(name).is_none() {
+            bail!("Contact '{}' not found", name);
+        }
+        self.save()
+    }
+
+    /// The address `input` names: itself if it is an address, else a
+    /// contact's or an account's, else an ENS name's when `ens_rpc_url` is
+    /// set. A name that is both a contact and an account with different
+    /// addresses is an error rather than a guess.
+    pub async fn resolve_address(&self, input: &str) -> Result<String> {
+        if is_address(input) {
+            return Ok(input.to_string());
+        }
+
+        let contact = self.contacts.get(input);
+        let account = self.accounts.get(input).map(|account| &account.address);
+        match (contact, account) {
+            (Some(contact), Some(account)) if !contact.eq_ignore_ascii_case(account) => bail!(
+                "'{}' is both a contact ({}) and an account ({}). Use the address, or rename one with 'dgit contact remove' and 'dgit contact add'",
+                input, contact, account,
+            ),
+            (Some(address), _) | (None, Some(address)) => return Ok(address.clone()),
+            (None, None) => {}
+        }
+
+        if ens::is_ens_name(input) {
+            let Some(rpc_url) = &self.settings.ens_rpc_url else {
+                bail!("'{}' is an ENS name; set a mainnet RPC URL to resolve it with 'dgit config set ens_rpc_url <url>'", input);
+            };
+            let address = ens::resolve(rpc_url, input).await?;
+            return Ok(format!("{:?}", address));
+        }
+
+        bail!("'{}' is not an address, a contact or an account. Add it with 'dgit contact add {} <address>'", input, input)
+    }
+
+    /// The contact or account name of `address`, if it has one.
+    pub fn alias_for(&self, address: &str) -> Option<&str> {
+        let contact = self.contacts.iter()
+            .filter(|(_, contact)| contact.eq_ignore_ascii_case(address))
+            .map(|(name, _)| name)
+            .min();
+        let account = self.accounts.values()
+            .filter(|account| account.address.eq_ignore_ascii_case(address))
+            .map(|account| &account.name)
+            .min();
+        contact.or(account).map(String::as_str)
+    }
+
+    /// Directory dgit keeps its config and daemon state in.
+    pub fn dir() -> Result<PathBuf> {
         let config_dir = config_dir()
             .context("Failed to determine config directory")?;
 
-        Ok(config_dir.join("dgit").join("config.toml"))
+        Ok(config_dir.join("dgit"))
+    }
+
+    fn config_path() -> Result<PathBuf> {
+        Ok(Self::dir()?.join("config.toml"))
     }
 }
\ No newline at end of file
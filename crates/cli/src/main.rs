@@ -1,14 +1,22 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use colored::*;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
 mod client;
 mod commands;
 mod config;
+mod output;
+mod progress;
 
-use commands::{account, daemon, repo};
+use commands::{account, config as config_cmd, contact, daemon, init, repo};
+use ::daemon::config::DaemonConfig;
+use ::daemon::logging::LogConfig;
+use output::{Output, OutputFormat};
 
 #[derive(Parser)]
 #[command(
@@ -21,9 +29,21 @@ struct Cli {
     #[arg(short, long, global = true, action = clap::ArgAction::Count)]
     verbose: u8,
 
-    /// Daemon URL (can also be set via DGIT_DAEMON_URL env var)
-    #[arg(long, global = true, env = "DGIT_DAEMON_URL", default_value = "http://localhost:3000")]
-    daemon_url: String,
+    /// Daemon URL (can also be set via DGIT_DAEMON_URL env var or `dgit config set daemon_url`) [default: http://localhost:3000]
+    #[arg(long, global = true, env = "DGIT_DAEMON_URL")]
+    daemon_url: Option<String>,
+
+    /// Seconds to wait for each daemon request; 0 waits as long as it takes
+    #[arg(long, global = true, env = "DGIT_TIMEOUT", default_value_t = client::DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
+
+    /// Fail instead of prompting for missing input and auto-confirm prompts (for scripts and CI)
+    #[arg(short = 'y', long, global = true, visible_alias = "yes")]
+    non_interactive: bool,
+
+    /// Print results as colored text or as JSON for scripts (default: `dgit config set output`, else text)
+    #[arg(long, global = true, value_enum)]
+    output: Option<OutputFormat>,
 
     #[command(subcommand)]
     command: Commands,
@@ -31,23 +51,59 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Start the daemon
+    /// Start the daemon, or manage one started with `daemon start --detach`
     Daemon {
+        #[command(subcommand)]
+        command: Option<daemon::DaemonCommands>,
+
         /// Port to run the daemon on
-        #[arg(short, long, default_value = "3000")]
+        #[arg(short, long, global = true, default_value = "3000")]
         port: u16,
+
+        /// Instead of starting a daemon, create, push and clone a scratch repository on the one at --daemon-url
+        #[arg(long)]
+        selftest: bool,
     },
 
+    /// Set up the git repository in the current directory to push to the daemon
+    Init(init::InitArgs),
+
     /// Repository management commands
     #[command(subcommand)]
-    Repo(repo::RepoCommands),
+    Repo(repo::RepoArgs),
 
     /// Account management commands
     #[command(subcommand)]
     Account(account::AccountCommands),
 
+    /// Names for addresses, usable in role commands
+    #[command(subcommand)]
+    Contact(contact::ContactCommands),
+
+    /// View and change saved defaults such as the daemon URL
+    #[command(subcommand)]
+    Config(config_cmd::ConfigCommands),
+
     /// Check daemon health
     Health,
+
+    /// Print version details: contract ABI hash, git version and daemon URL
+    Version,
+}
+
+impl Cli {
+    /// For a daemon started in this process, the `--log-file` it was given.
+    fn daemon_log_file(&self) -> Option<Option<PathBuf>> {
+        match &self.command {
+            Commands::Daemon { command: None, selftest: false, .. } => Some(None),
+            Commands::Daemon {
+                command: Some(daemon::DaemonCommands::Start { detach: false, log_file }),
+                selftest: false,
+                ..
+            } => Some(log_file.clone()),
+            _ => None,
+        }
+    }
 }
 
 #[tokio::main]
@@ -64,33 +120,154 @@ async fn main() -> Result<()> {
         _ => Level::TRACE,
     };
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    // A daemon started here logs like the standalone one, with -v setting
+    // the terminal's level only.
+    let _log = match cli.daemon_log_file() {
+        Some(file) => Some(::daemon::logging::init(LogConfig {
+            stderr_level: log_level,
+            file: file.or_else(DaemonConfig::log_file),
+            ..LogConfig::from_env()
+        })?),
+        None => {
+            let subscriber = FmtSubscriber::builder()
+                .with_max_level(log_level)
+                .finish();
+            tracing::subscriber::set_global_default(subscriber)?;
+            None
+        }
+    };
+
+    // Flags and environment variables win over saved settings. A config
+    // file that cannot be read only costs the defaults it would set.
+    let settings = config::Config::load_settings().unwrap_or_else(|e| {
+        eprintln!("{}", format!("Warning: ignoring saved settings: {:#}", e).yellow());
+        config::Settings::default()
+    });
+    let output = Output::new(settings.output(cli.output));
+    let daemon_url = settings.daemon_url(cli.daemon_url.clone());
+    match run(cli, daemon_url, output).await {
+        Err(e) => match e.chain().find_map(|cause| cause.downcast_ref::<client::DaemonError>()) {
+            Some(daemon_error) => output.fail_with(daemon_error.exit_code(), format!("{:#}", e), daemon_error.hint()),
+            None if output.is_json() => output.fail(format!("{:#}", e)),
+            None => Err(e),
+        },
+        Ok(()) => Ok(()),
+    }
+}
 
+async fn run(cli: Cli, daemon_url: String, output: Output) -> Result<()> {
+    let timeout = Some(Duration::from_secs(cli.timeout)).filter(|timeout| !timeout.is_zero());
     match cli.command {
-        Commands::Daemon { port } => {
-            daemon::start_daemon(port).await?;
+        Commands::Daemon { selftest: true, .. } => {
+            let credentials = repo::active_credentials(&config::Config::load()?, &daemon_url);
+            let client = client::DaemonClient::new(daemon_url).with_timeout(timeout).with_credentials(credentials);
+            daemon::selftest(client, output).await?;
+        }
+        Commands::Daemon { command, port, .. } => {
+            let client = client::DaemonClient::new(daemon_url).with_timeout(timeout);
+            daemon::handle_command(command, port, client, output).await?;
+        }
+        Commands::Init(args) => {
+            let daemon_url_given = cli.daemon_url.is_some();
+            init::run(args, daemon_url, daemon_url_given, timeout, cli.non_interactive, output).await?;
         }
         Commands::Repo(cmd) => {
-            let client = client::DaemonClient::new(cli.daemon_url);
-            repo::handle_command(cmd, client).await?;
+            let client = client::DaemonClient::new(daemon_url).with_timeout(timeout);
+            repo::handle_command(cmd, client, cli.non_interactive, output).await?;
         }
         Commands::Account(cmd) => {
-            account::handle_command(cmd).await?;
+            account::handle_command(cmd, &daemon_url, cli.non_interactive, output).await?;
+        }
+        Commands::Contact(cmd) => {
+            contact::handle_command(cmd, output)?;
+        }
+        Commands::Config(cmd) => {
+            config_cmd::handle_command(cmd, output)?;
         }
         Commands::Health => {
-            let client = client::DaemonClient::new(cli.daemon_url);
-            match client.health_check().await {
-                Ok(_) => println!("{}", "✓ Daemon is healthy".green()),
-                Err(e) => {
-                    eprintln!("{}", format!("✗ Daemon health check failed: {}", e).red());
-                    std::process::exit(1);
-                }
+            let client = client::DaemonClient::new(daemon_url.clone()).with_timeout(timeout);
+            if let Err(e) = client.health_check().await {
+                output.fail_with(e.exit_code(), format!("Daemon health check failed: {}", e), e.hint());
             }
+
+            // Health does not depend on it, so a version that cannot be
+            // read is reported like that of a daemon without `/version`.
+            let daemon_version = client.version().await.unwrap_or_else(|e| {
+                eprintln!("{}", format!("Warning: {}", e).yellow());
+                None
+            });
+            let cli_level = ::daemon::handlers::API_LEVEL;
+            let json = serde_json::json!({
+                "daemon_url": daemon_url,
+                "healthy": true,
+                "cli": { "version": env!("CARGO_PKG_VERSION"), "api_level": cli_level },
+                "daemon": daemon_version,
+            });
+            output.result(&json, || {
+                println!("{}", "✓ Daemon is healthy".green());
+                println!("  CLI:    {} (API level {})", env!("CARGO_PKG_VERSION"), cli_level);
+                match &daemon_version {
+                    Some(version) => {
+                        let commit = version.commit.as_deref().map(|commit| format!(", commit {}", commit)).unwrap_or_default();
+                        println!("  Daemon: {} (API level {}{})", version.version, version.api_level, commit);
+                        if !version.features.is_empty() {
+                            println!("  Features: {}", version.features.join(", "));
+                        }
+                        if version.api_level < cli_level {
+                            println!("{}", "The daemon is older than this CLI; commands that need a newer daemon will be refused".yellow());
+                        }
+                    }
+                    None => {
+                        println!("  Daemon: unknown (no /version endpoint)");
+                        println!("{}", "The daemon is older than this CLI; some commands may fail".yellow());
+                    }
+                }
+            });
+        }
+        Commands::Version => {
+            let version = VersionInfo::collect(&daemon_url);
+            output.result(&version, || println!("{}", version));
         }
     }
 
     Ok(())
 }
+
+/// What `dgit version` prints: enough to tell which build, contract
+/// interface and git a bug report came from.
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    contract_abi: String,
+    git: String,
+    daemon_url: String,
+}
+
+impl VersionInfo {
+    fn collect(daemon_url: &str) -> Self {
+        let contract_abi = onchain::contract_interaction::abi_hash()
+            .unwrap_or_else(|e| format!("unknown ({})", e));
+        let git = match std::process::Command::new("git").arg("--version").output() {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            Ok(output) => format!("unknown ({})", String::from_utf8_lossy(&output.stderr).trim()),
+            Err(e) => format!("not found ({})", e),
+        };
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            contract_abi,
+            git,
+            daemon_url: daemon_url.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "dgit {}\ncontract ABI: {}\ngit: {}\ndaemon: {}",
+            self.version, self.contract_abi, self.git, self.daemon_url,
+        )
+    }
+}
@@ -0,0 +1,75 @@
+use clap::ValueEnum;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Colored messages for people
+    #[default]
+    Text,
+    /// One JSON value on stdout per command, errors as JSON on stderr
+    Json,
+}
+
+/// How a command reports what it did.
+///
+/// In text mode commands print their usual colored messages. In JSON mode
+/// the progress and decoration is dropped: a command prints exactly one
+/// JSON value with its result to stdout, or `{"error": "..."}` to stderr
+/// before exiting non-zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Output {
+    format: OutputFormat,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format }
+    }
+
+    pub fn is_json(self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Prints a line meant only for people: progress, hints, decoration.
+    pub fn message(self, message: impl Display) {
+        if !self.is_json() {
+            println!("{}", message);
+        }
+    }
+
+    /// Prints the command's result: `value` in JSON mode, whatever `text`
+    /// prints otherwise.
+    pub fn result<T: Serialize + ?Sized>(self, value: &T, text: impl FnOnce()) {
+        if !self.is_json() {
+            text();
+            return;
+        }
+
+        match serde_json::to_string_pretty(value) {
+            Ok(json) => println!("{}", json),
+            Err(e) => self.fail(format!("Failed to serialize output: {}", e)),
+        }
+    }
+
+    /// Reports why the command failed and exits non-zero.
+    pub fn fail(self, message: impl Display) -> ! {
+        self.fail_with(1, message, None)
+    }
+
+    /// Like `fail`, exiting with `code`, and telling people what to try
+    /// next if there is a `hint`.
+    pub fn fail_with(self, code: i32, message: impl Display, hint: Option<&str>) -> ! {
+        if self.is_json() {
+            eprintln!("{}", serde_json::json!({ "error": message.to_string() }));
+        } else {
+            eprintln!("{}", format!("✗ {}", message).red());
+            if let Some(hint) = hint {
+                eprintln!("{}", hint.yellow());
+            }
+        }
+        std::process::exit(code);
+    }
+}
@@ -0,0 +1,78 @@
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::future::Future;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use crate::output::Output;
+
+/// Whether progress is drawn: only for someone watching a terminal. JSON
+/// output gets none, and redirected output gets each step as a plain line.
+pub fn should_draw(json: bool, stdout_is_terminal: bool) -> bool {
+    !json && stdout_is_terminal
+}
+
+fn draws(output: Output) -> bool {
+    should_draw(output.is_json(), std::io::stdout().is_terminal())
+}
+
+/// Awaits `future`, a single request the daemon may take a while to answer
+/// (a contract deployment, a role change waiting for its transaction),
+/// showing `message` with a spinner and the time spent so far.
+pub async fn wait<F: Future>(output: Output, message: impl Into<String>, future: F) -> F::Output {
+    let spinner = Spinner::start(output, String::new(), message.into());
+    let result = future.await;
+    spinner.clear();
+    result
+}
+
+/// A command made of several requests, each shown as `[step/total]` with a
+/// spinner while it runs. Nothing is left drawn between steps, so a step
+/// that fails can report it like any other command.
+pub struct Steps {
+    output: Output,
+    total: usize,
+    done: usize,
+}
+
+impl Steps {
+    pub fn new(output: Output, total: usize) -> Self {
+        Self { output, total, done: 0 }
+    }
+
+    /// Runs the next step, `future`, described by `message`.
+    pub async fn run<F: Future>(&mut self, message: impl Into<String>, future: F) -> F::Output {
+        self.done = (self.done + 1).min(self.total);
+        let prefix = format!("[{}/{}] ", self.done, self.total);
+        let spinner = Spinner::start(self.output, prefix, message.into());
+        let result = future.await;
+        spinner.clear();
+        result
+    }
+}
+
+struct Spinner(Option<ProgressBar>);
+
+impl Spinner {
+    fn start(output: Output, prefix: String, message: String) -> Self {
+        if !draws(output) {
+            output.message(format!("{}{}", prefix, message).yellow());
+            return Self(None);
+        }
+
+        let bar = ProgressBar::new_spinner()
+            .with_prefix(prefix)
+            .with_message(message);
+        if let Ok(style) = ProgressStyle::with_template("{spinner:.yellow} {prefix}{msg} [{elapsed}]") {
+            bar.set_style(style);
+        }
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self(Some(bar))
+    }
+
+    fn clear(self) {
+        if let Some(bar) = self.0 {
+            bar.finish_and_clear();
+        }
+    }
+}
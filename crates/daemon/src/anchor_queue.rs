@@ -0,0 +1,331 @@
+use anyhow::{anyhow, bail, Context, Result};
+use onchain::contract_interaction::Costs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Notify};
+use tracing::{debug, error, info, warn, Instrument, Span};
+
+use crate::{audit::{AuditAction, AuditEntry}, auth::unix_now, config::DaemonConfig, state::ContractState, validation::normalize_repo_name, webhooks::{self, PushEvent, RefUpdate, ZERO_ID}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobStatus {
+    Pending,
+    Confirmed,
+    Failed,
+}
+
+/// A push whose objects are on IPFS but not yet anchored on chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchorJob {
+    pub id: u64,
+    pub repo: String,
+    /// Contract the push was made against; the job fails rather than
+    /// anchor into another one.
+    pub address: String,
+    pub status: JobStatus,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created: u64,
+    pub updated: u64,
+    pub object_count: usize,
+    pub ref_count: usize,
+    #[serde(default)]
+    pub tx_hashes: Vec<String>,
+    #[serde(default)]
+    pub costs: Costs,
+    /// What to anchor: object hashes with their IPFS hashes, and ref names
+    /// with their values, all zeros for deleted refs. Dropped once the job
+    /// is confirmed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refs: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pusher: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unverified_signer: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ref_updates: Vec<RefUpdate>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub push_options: BTreeMap<String, String>,
+}
+
+/// Pushes waiting to be anchored when `DGIT_ASYNC_ANCHORING` is on, so git
+/// gets its answer once the objects are on IPFS instead of after every
+/// transaction is confirmed.
+///
+/// Jobs are journaled to `ANCHOR_QUEUE_PATH` as JSON lines, one per change,
+/// and picked up again after a restart. A single worker anchors them in
+/// order with the usual transaction handling; since it checks which objects
+/// are already on chain first, re-running a job that was cut short sends
+/// only what is missing.
+#[derive(Debug, Clone)]
+pub struct AnchorQueue {
+    enabled: bool,
+    path: Arc<PathBuf>,
+    jobs: Arc<Mutex<Vec<AnchorJob>>>,
+    wake: Arc<Notify>,
+}
+
+impl AnchorQueue {
+    pub fn load(path: impl Into<PathBuf>, enabled: bool) -> Self {
+        let path = path.into();
+        let mut jobs: BTreeMap<u64, AnchorJob> = BTreeMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                // Later lines are newer states of the same job.
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str::<AnchorJob>(line) {
+                        Ok(job) => {
+                            jobs.insert(job.id, job);
+                        },
+                        Err(e) => warn!("Skipping unreadable line in anchor queue {:?}: {}", path, e),
+                    }
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => error!("Failed to read anchor queue {:?}, starting empty: {}", path, e),
+        }
+
+        let pending = jobs.values().filter(|job| job.status == JobStatus::Pending).count();
+        if pending > 0 {
+            info!("Resuming {} queued anchoring jobs from {:?}", pending, path);
+        }
+
+        Self {
+            enabled,
+            path: Arc::new(path),
+            jobs: Arc::new(Mutex::new(jobs.into_values().collect())),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::load(DaemonConfig::anchor_queue_path(), DaemonConfig::async_anchoring())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Queues a push and returns its job id once the job is on disk.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn enqueue(
+        &self,
+        repo: &str,
+        address: String,
+        objects: Vec<(String, String)>,
+        refs: Vec<(String, String)>,
+        pusher: Option<String>,
+        unverified_signer: Option<String>,
+        ref_updates: Vec<RefUpdate>,
+        push_options: BTreeMap<String, String>,
+    ) -> Result<u64> {
+        let mut jobs = self.jobs.lock().await;
+        let now = unix_now();
+        let job = AnchorJob {
+            id: jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1,
+            repo: repo.to_string(),
+            address,
+            status: JobStatus::Pending,
+            attempts: 0,
+            error: None,
+            created: now,
+            updated: now,
+            object_count: objects.len(),
+            ref_count: refs.len(),
+            tx_hashes: Vec::new(),
+            costs: Costs::default(),
+            objects,
+            refs,
+            pusher,
+            unverified_signer,
+            ref_updates,
+            push_options,
+        };
+
+        self.append(&job).await?;
+        let id = job.id;
+        jobs.push(job);
+        drop(jobs);
+
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// Jobs of `repo`, newest first.
+    pub async fn jobs(&self, repo: &str) -> Vec<AnchorJob> {
+        let key = normalize_repo_name(repo);
+        let jobs = self.jobs.lock().await;
+        jobs.iter().rev().filter(|job| normalize_repo_name(&job.repo) == key).cloned().collect()
+    }
+
+    /// Runs the worker that drains the queue, if async anchoring is on.
+    pub fn spawn_worker(&self, contract_state: ContractState) {
+        if !self.enabled {
+            return;
+        }
+
+        let queue = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let Some(job) = queue.next_pending().await else {
+                    queue.wake.notified().await;
+                    continue;
+                };
+                queue.run(&contract_state, job).await;
+            }
+        }.instrument(Span::current()));
+    }
+
+    async fn next_pending(&self) -> Option<AnchorJob> {
+        let jobs = self.jobs.lock().await;
+        jobs.iter().find(|job| job.status == JobStatus::Pending).cloned()
+    }
+
+    async fn run(&self, contract_state: &ContractState, mut job: AnchorJob) {
+        info!("Anchoring job {} of {} ({} objects, {} refs, attempt {})", job.id, job.repo, job.object_count, job.ref_count, job.attempts + 1);
+        job.attempts += 1;
+
+        match anchor(contract_state, &mut job).await {
+            Ok(()) => {
+                info!("Anchored job {} of {}: {}", job.id, job.repo, job.costs);
+                job.status = JobStatus::Confirmed;
+                job.error = None;
+                job.objects.clear();
+                job.refs.clear();
+                finish(contract_state, &job).await;
+            },
+            Err(e) => {
+                let max_attempts = DaemonConfig::anchor_max_attempts();
+                error!("Anchoring job {} of {} failed (attempt {}/{}): {:#}", job.id, job.repo, job.attempts, max_attempts, e);
+                job.error = Some(format!("{:#}", e));
+                if job.attempts >= max_attempts {
+                    job.status = JobStatus::Failed;
+                }
+            },
+        }
+
+        job.updated = unix_now();
+        if let Err(e) = self.update(&job).await {
+            error!("Failed to record state of anchoring job {}: {:#}", job.id, e);
+        }
+
+        if job.status == JobStatus::Pending {
+            // Jobs run in order, so the next one waits too: it may build on
+            // this push's objects.
+            let delay = DaemonConfig::anchor_retry_delay_secs() * u64::from(job.attempts);
+            debug!("Retrying job {} in {}s", job.id, delay);
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+        }
+    }
+
+    async fn update(&self, job: &AnchorJob) -> Result<()> {
+        let mut jobs = self.jobs.lock().await;
+        self.append(job).await?;
+        if let Some(existing) = jobs.iter_mut().find(|existing| existing.id == job.id) {
+            *existing = job.clone();
+        }
+        Ok(())
+    }
+
+    async fn append(&self, job: &AnchorJob) -> Result<()> {
+        let mut line = serde_json::to_string(job)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.as_path())
+            .await
+            .with_context(|| format!("Failed to open anchor queue {:?}", self.path))?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
+
+/// Anchors what of `job` is not on chain yet, recording the transactions
+/// on the job as they confirm.
+async fn anchor(contract_state: &ContractState, job: &mut AnchorJob) -> Result<()> {
+    let contract = contract_state.get_contract(&job.repo).await
+        .ok_or_else(|| anyhow!("Repository {} not found", job.repo))?;
+    if contract.address() != job.address {
+        bail!("Repository {} now lives at {}, not {}", job.repo, contract.address(), job.address);
+    }
+
+    let hashes: Vec<String> = job.objects.iter().map(|(hash, _)| hash.clone()).collect();
+    let exists = contract_state.object_cache().check(&job.repo, &contract, &hashes).await?;
+    let missing: Vec<&(String, String)> = job.objects
+        .iter()
+        .zip(exists)
+        .filter(|(_, exists)| !exists)
+        .map(|(object, _)| object)
+        .collect();
+    if missing.len() < job.objects.len() {
+        debug!("{} of {} objects of job {} are already on chain", job.objects.len() - missing.len(), job.objects.len(), job.id);
+    }
+
+    for batch in missing.chunks(DaemonConfig::object_batch_size()) {
+        let hashes: Vec<String> = batch.iter().map(|(hash, _)| hash.clone()).collect();
+        let ipfs_urls = batch.iter().map(|(_, url)| url.as_bytes().to_vec()).collect();
+        let confirmed = contract.add_objects(hashes.clone(), ipfs_urls).await?;
+        job.tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+        job.costs.add(confirmed.costs);
+        contract_state.object_cache().record(&job.repo, &contract, hashes).await;
+    }
+
+    if !job.refs.is_empty() {
+        let (deleted, updated): (Vec<_>, Vec<_>) = job.refs.iter().partition(|(_, data)| data == ZERO_ID);
+        if !updated.is_empty() {
+            let names = updated.iter().map(|(name, _)| name.clone()).collect();
+            let data = updated.iter().map(|(_, data)| data.as_bytes().to_vec()).collect();
+            let confirmed = contract.add_refs(names, data).await?;
+            job.tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+            job.costs.add(confirmed.costs);
+        }
+        if !deleted.is_empty() {
+            let names = deleted.iter().map(|(name, _)| name.clone()).collect();
+            let confirmed = contract.deactivate_refs(names).await?;
+            job.tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+            job.costs.add(confirmed.costs);
+        }
+
+        contract_state.ref_cache().invalidate(&job.repo).await;
+        contract_state.snapshots().invalidate(&job.repo, &contract).await;
+    }
+
+    Ok(())
+}
+
+/// Records a confirmed job the way a synchronous push is recorded.
+async fn finish(contract_state: &ContractState, job: &AnchorJob) {
+    contract_state.audit().record(AuditEntry {
+        actor: job.pusher.clone(),
+        unverified_signer: job.unverified_signer.clone(),
+        tx_hash: job.tx_hashes.last().cloned(),
+        costs: (job.costs.txs > 0).then_some(job.costs),
+        push_options: job.push_options.clone(),
+        ..AuditEntry::new(AuditAction::Push, &job.repo)
+    }).await;
+
+    if !job.ref_updates.is_empty() {
+        webhooks::dispatch(contract_state.webhooks().list(&job.repo).await, PushEvent {
+            repo: job.repo.clone(),
+            pusher: job.pusher.clone(),
+            unverified_signer: job.unverified_signer.clone(),
+            refs: job.ref_updates.clone(),
+            object_count: job.object_count,
+            tx_hashes: job.tx_hashes.clone(),
+            costs: job.costs,
+            push_options: job.push_options.clone(),
+        });
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use onchain::contract_interaction::Costs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, error};
+
+use crate::{auth::unix_now, config::DaemonConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditAction {
+    Grant,
+    Revoke,
+    Push,
+    CreateRepo,
+    Fork,
+    Gc,
+    Unpin,
+}
+
+/// One line of the audit log. Addresses and hashes are kept as strings so
+/// the file stays readable with plain `jq`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub action: AuditAction,
+    pub repo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// Address a push named with `-o dgit-signer` that did not match the
+    /// authenticated caller. Client-supplied and not proven.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unverified_signer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    /// Gas and fees of every transaction the action sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub costs: Option<Costs>,
+    /// `git push -o key=value` options of a push.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub push_options: BTreeMap<String, String>,
+}
+
+impl AuditEntry {
+    pub fn new(action: AuditAction, repo: &str) -> Self {
+        Self {
+            timestamp: unix_now(),
+            action,
+            repo: repo.to_string(),
+            actor: None,
+            unverified_signer: None,
+            target: None,
+            role: None,
+            tx_hash: None,
+            costs: None,
+            push_options: BTreeMap::new(),
+        }
+    }
+}
+
+/// Append-only JSON-lines log of role changes, pushes and repo creation.
+///
+/// Writes go through a single mutex so concurrent handlers never interleave
+/// partial lines.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: Arc<PathBuf>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Arc::new(path.into()),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::audit_log_path())
+    }
+
+    /// Appends `entry`. A failed write is logged rather than returned: the
+    /// on-chain change it describes has already happened.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.append(&entry).await {
+            error!("Failed to write audit entry {:?}: {:#}", entry, e);
+        }
+    }
+
+    async fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.as_path())
+            .await
+            .with_context(|| format!("Failed to open audit log {:?}", self.path))?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+
+        debug!("Recorded audit entry: {}", line.trim_end());
+        Ok(())
+    }
+
+    /// Most recent entries for `repo`, newest first.
+    pub async fn entries(&self, repo: &str, limit: usize) -> Result<Vec<AuditEntry>> {
+        let content = {
+            let _guard = self.lock.lock().await;
+            match fs::read_to_string(self.path.as_path()).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e).with_context(|| format!("Failed to read audit log {:?}", self.path)),
+            }
+        };
+
+        Ok(content
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+            .filter(|entry| entry.repo == repo)
+            .take(limit)
+            .collect())
+    }
+
+    /// Recorded costs of `repo`, summed per action.
+    pub async fn costs(&self, repo: &str) -> Result<BTreeMap<AuditAction, Costs>> {
+        let mut costs = BTreeMap::<AuditAction, Costs>::new();
+        for entry in self.entries(repo, usize::MAX).await? {
+            if let Some(entry_costs) = entry.costs {
+                costs.entry(entry.action).or_default().add(entry_costs);
+            }
+        }
+        Ok(costs)
+    }
+}
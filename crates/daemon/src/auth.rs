@@ -0,0 +1,430 @@
+use anyhow::{anyhow, Result};
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethcontract::web3::signing::{self, Key, SecretKey, SecretKeyRef};
+use ethcontract::Address;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::config::DaemonConfig;
+use onchain::{contract_interaction::ContractInteraction, signer::{recover_signer, Signer}};
+
+/// Realm advertised in `WWW-Authenticate` so git prompts for credentials.
+pub const AUTH_REALM: &str = "dgit";
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// No credentials were supplied.
+    Missing,
+    /// Credentials were supplied but could not be verified.
+    Invalid(String),
+    /// The caller is authenticated but lacks the required role.
+    Forbidden(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "Authentication required"),
+            AuthError::Invalid(reason) => write!(f, "Invalid credentials: {}", reason),
+            AuthError::Forbidden(reason) => write!(f, "Forbidden: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Address and signed token of an account, sent as HTTP Basic credentials
+/// (address as user name, token as password) so plain git clients can use
+/// them too.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub address: String,
+    pub token: String,
+}
+
+impl Credentials {
+    pub fn basic_header(&self) -> String {
+        format!("Basic {}", STANDARD.encode(format!("{}:{}", self.address, self.token)))
+    }
+}
+
+/// Message an account signs (EIP-191) to prove control of `address` to the
+/// daemon at `origin` until `expires_at`. Naming the daemon keeps a token
+/// handed to one daemon from being replayed against another.
+pub fn auth_message(origin: &str, address: &str, expires_at: u64) -> String {
+    format!("dgit-auth:{}:{}:{}", origin, address.to_ascii_lowercase(), expires_at)
+}
+
+/// The daemon a token is for, as named in [`auth_message`]: the lowercase
+/// host and port of an `http(s)://` daemon URL, or `unix:<path>` for a
+/// daemon on a socket.
+pub fn auth_origin(daemon_url: &str) -> String {
+    let daemon_url = daemon_url.trim();
+    if let Some(path) = daemon_url.strip_prefix("unix:") {
+        return format!("unix:{}", path.strip_prefix("//").unwrap_or(path));
+    }
+
+    let rest = daemon_url.split_once("://").map_or(daemon_url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    host.to_ascii_lowercase()
+}
+
+fn secret_key(private_key: &str) -> Result<SecretKey> {
+    SecretKey::from_str(private_key.trim().trim_start_matches("0x"))
+        .map_err(|e| anyhow!("Invalid private key: {}", e))
+}
+
+/// Address of the account `private_key` controls, as `0x` and lowercase hex.
+pub fn key_address(private_key: &str) -> Result<String> {
+    Ok(format!("{:?}", SecretKeyRef::new(&secret_key(private_key)?).address()))
+}
+
+/// Signs a token for `private_key` that the daemon at `origin` (see
+/// [`auth_origin`]) accepts for `ttl_secs`.
+pub fn issue_token(private_key: &str, origin: &str, ttl_secs: u64) -> Result<Credentials> {
+    let key = secret_key(private_key)?;
+    let key = SecretKeyRef::new(&key);
+
+    let address = format!("{:?}", key.address());
+    let expires_at = unix_now() + ttl_secs;
+    let hash = signing::hash_message(auth_message(origin, &address, expires_at).as_bytes());
+
+    let signature = key
+        .sign_message(hash.as_bytes())
+        .map_err(|e| anyhow!("Failed to sign auth token: {:?}", e))?;
+
+    let mut bytes = Vec::with_capacity(65);
+    bytes.extend_from_slice(signature.r.as_bytes());
+    bytes.extend_from_slice(signature.s.as_bytes());
+    bytes.push(signature.v as u8);
+
+    Ok(Credentials {
+        address,
+        token: format!("{}.{}", expires_at, encode_hex(&bytes)),
+    })
+}
+
+/// Resolves the caller's address from the `Authorization` header.
+pub fn authenticate(headers: &HeaderMap) -> Result<Address, AuthError> {
+    let header = headers
+        .get(AUTHORIZATION)
+        .ok_or(AuthError::Missing)?
+        .to_str()
+        .map_err(|_| AuthError::Invalid("malformed Authorization header".to_string()))?;
+
+    let encoded = header
+        .strip_prefix("Basic ")
+        .ok_or_else(|| AuthError::Invalid("expected Basic credentials".to_string()))?;
+    let decoded = STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| AuthError::Invalid("malformed Basic credentials".to_string()))?;
+    let decoded = String::from_utf8(decoded)
+        .map_err(|_| AuthError::Invalid("malformed Basic credentials".to_string()))?;
+
+    let (address, token) = decoded
+        .split_once(':')
+        .ok_or_else(|| AuthError::Invalid("expected address:token".to_string()))?;
+
+    verify_token(address, token)
+}
+
+/// Like [`authenticate`], but anonymous requests resolve to `None` instead
+/// of an error. Credentials that are present must still be valid.
+pub fn authenticate_optional(headers: &HeaderMap) -> Result<Option<Address>, AuthError> {
+    match authenticate(headers) {
+        Ok(address) => Ok(Some(address)),
+        Err(AuthError::Missing) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn verify_token(address: &str, token: &str) -> Result<Address, AuthError> {
+    check_token(address, token, &DaemonConfig::auth_origins(), DaemonConfig::max_token_ttl_secs(), unix_now())
+}
+
+/// Checks that `token` was signed by `address` for one of `origins`, and is
+/// neither expired nor valid for longer than `max_ttl_secs` (0 for no limit).
+fn check_token(address: &str, token: &str, origins: &[String], max_ttl_secs: u64, now: u64) -> Result<Address, AuthError> {
+    let claimed = Address::from_str(address)
+        .map_err(|_| AuthError::Invalid("invalid address".to_string()))?;
+
+    let (expires_at, signature) = token
+        .split_once('.')
+        .ok_or_else(|| AuthError::Invalid("malformed token".to_string()))?;
+    let expires_at: u64 = expires_at
+        .parse()
+        .map_err(|_| AuthError::Invalid("malformed token expiry".to_string()))?;
+
+    if expires_at < now {
+        return Err(AuthError::Invalid("token has expired".to_string()));
+    }
+    if max_ttl_secs > 0 && expires_at - now > max_ttl_secs {
+        return Err(AuthError::Invalid(format!("token is valid for longer than this daemon's limit of {}s", max_ttl_secs)));
+    }
+
+    let signature = decode_hex(signature)
+        .filter(|bytes| bytes.len() == 65)
+        .ok_or_else(|| AuthError::Invalid("malformed token signature".to_string()))?;
+
+    let address = format!("{:?}", claimed);
+    for origin in origins {
+        let message = auth_message(origin, &address, expires_at);
+        let recovered = recover_signer(message.as_bytes(), &signature)
+            .map_err(|_| AuthError::Invalid("signature recovery failed".to_string()))?;
+        if recovered == claimed {
+            debug!("Authenticated request from {:?} for {}", claimed, origin);
+            return Ok(claimed);
+        }
+    }
+
+    Err(AuthError::Invalid("signature does not match address for this daemon".to_string()))
+}
+
+/// Enforces a repository's visibility. Public repositories are readable by
+/// anyone; private ones need a reader, pusher or admin.
+pub async fn ensure_read_access(contract: &ContractInteraction, headers: &HeaderMap) -> Result<()> {
+    let config = contract.get_repo_config().await?;
+    if !config.is_private() {
+        return Ok(());
+    }
+
+    let address = authenticate(headers)?;
+    if contract.has_reader_role(address).await?
+        || contract.has_pusher_role(address).await?
+        || contract.has_admin_role(address).await?
+    {
+        return Ok(());
+    }
+
+    Err(AuthError::Forbidden(format!("{:?} cannot read this repository", address)).into())
+}
+
+/// Requires `caller` to pass the daemon's own push lists and then to hold
+/// the pusher or admin role on the repository. Anonymous pushes are refused.
+pub async fn ensure_pusher(contract: &ContractInteraction, repo: &str, caller: Option<Address>) -> Result<Address> {
+    ensure_push_allowed(repo, caller)?;
+    check_push_role(repo, caller, |address| async move {
+        Ok(contract.has_pusher_role(address).await? || contract.has_admin_role(address).await?)
+    })
+    .await
+}
+
+/// Applies the daemon's own push lists to `caller`, before the contract is
+/// asked about roles. Once either list is configured, pushes must be
+/// authenticated, so a denylisted account cannot slip through anonymously.
+fn ensure_push_allowed(repo: &str, caller: Option<Address>) -> Result<()> {
+    let allowlist = DaemonConfig::push_allowlist()?;
+    let denylist = DaemonConfig::push_denylist()?;
+    check_push_lists(repo, caller, allowlist.as_deref(), denylist.as_deref())?;
+    Ok(())
+}
+
+/// The role half of [`ensure_pusher`]; `may_push` asks the contract whether
+/// an address holds the pusher or admin role.
+async fn check_push_role<F, Fut>(repo: &str, caller: Option<Address>, may_push: F) -> Result<Address>
+where
+    F: FnOnce(Address) -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let Some(caller) = caller else {
+        warn!("Refused unauthenticated push to {}", repo);
+        return Err(AuthError::Missing.into());
+    };
+
+    if !may_push(caller).await? {
+        warn!("Refused push to {} from {:?}: not a pusher or admin", repo, caller);
+        return Err(AuthError::Forbidden(format!("{:?} cannot push to this repository", caller)).into());
+    }
+
+    Ok(caller)
+}
+
+fn check_push_lists(
+    repo: &str,
+    caller: Option<Address>,
+    allowlist: Option<&[Address]>,
+    denylist: Option<&[Address]>,
+) -> Result<(), AuthError> {
+    if allowlist.is_none() && denylist.is_none() {
+        return Ok(());
+    }
+
+    let Some(caller) = caller else {
+        warn!("Refused unauthenticated push to {}: the daemon has a push list", repo);
+        return Err(AuthError::Missing);
+    };
+
+    if denylist.is_some_and(|denylist| denylist.contains(&caller)) {
+        warn!("Refused push to {} from {:?}: on the daemon's push denylist", repo, caller);
+        return Err(AuthError::Forbidden(format!("{:?} may not push to this daemon", caller)));
+    }
+    if allowlist.is_some_and(|allowlist| !allowlist.contains(&caller)) {
+        warn!("Refused push to {} from {:?}: not on the daemon's push allowlist", repo, caller);
+        return Err(AuthError::Forbidden(format!("{:?} may not push to this daemon", caller)));
+    }
+
+    Ok(())
+}
+
+/// Requires the caller to be the daemon's own signer, for endpoints that act
+/// on the daemon rather than on one repository.
+pub fn ensure_daemon_signer(headers: &HeaderMap) -> Result<Address> {
+    let caller = authenticate(headers)?;
+    if Signer::shared()?.address() != Some(caller) {
+        return Err(AuthError::Forbidden(format!("{:?} is not the daemon's signer", caller)).into());
+    }
+    Ok(caller)
+}
+
+/// Requires the caller to hold the admin role on the repository.
+pub async fn ensure_admin(contract: &ContractInteraction, headers: &HeaderMap) -> Result<Address> {
+    let address = authenticate(headers)?;
+    if contract.has_admin_role(address).await? {
+        return Ok(address);
+    }
+
+    Err(AuthError::Forbidden(format!("{:?} is not an admin of this repository", address)).into())
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = "0x".to_string();
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+pub(crate) fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_start_matches("0x");
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(byte: u8) -> Address {
+        Address::from([byte; 20])
+    }
+
+    #[test]
+    fn denylisted_caller_is_forbidden() {
+        let denylist = [address(1)];
+        let result = check_push_lists("repo", Some(address(1)), None, Some(&denylist));
+        assert!(matches!(result, Err(AuthError::Forbidden(_))));
+        assert!(check_push_lists("repo", Some(address(2)), None, Some(&denylist)).is_ok());
+    }
+
+    #[test]
+    fn anonymous_push_is_refused_with_a_denylist() {
+        let denylist = [address(1)];
+        let result = check_push_lists("repo", None, None, Some(&denylist));
+        assert!(matches!(result, Err(AuthError::Missing)));
+    }
+
+    #[test]
+    fn allowlisted_caller_may_push() {
+        let allowlist = [address(1)];
+        assert!(check_push_lists("repo", Some(address(1)), Some(&allowlist), None).is_ok());
+        assert!(matches!(
+            check_push_lists("repo", None, Some(&allowlist), None),
+            Err(AuthError::Missing)
+        ));
+    }
+
+    #[test]
+    fn caller_missing_from_allowlist_is_forbidden() {
+        let allowlist = [address(1)];
+        let result = check_push_lists("repo", Some(address(2)), Some(&allowlist), None);
+        assert!(matches!(result, Err(AuthError::Forbidden(_))));
+    }
+
+    #[test]
+    fn anonymous_push_is_allowed_without_lists() {
+        assert!(check_push_lists("repo", None, None, None).is_ok());
+    }
+
+    const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn origins() -> Vec<String> {
+        vec![auth_origin("http://localhost:3000"), auth_origin("https://dgit.example.com/")]
+    }
+
+    #[test]
+    fn token_is_accepted_by_the_daemon_it_was_issued_for() {
+        let credentials = issue_token(KEY, &auth_origin("https://DGIT.example.com"), 600).unwrap();
+        let address = check_token(&credentials.address, &credentials.token, &origins(), 3600, unix_now()).unwrap();
+        assert_eq!(format!("{:?}", address), credentials.address);
+    }
+
+    #[test]
+    fn token_for_another_daemon_is_refused() {
+        let credentials = issue_token(KEY, &auth_origin("https://other.example.com"), 600).unwrap();
+        let result = check_token(&credentials.address, &credentials.token, &origins(), 3600, unix_now());
+        assert!(matches!(result, Err(AuthError::Invalid(_))));
+    }
+
+    #[test]
+    fn token_valid_beyond_the_maximum_ttl_is_refused() {
+        let credentials = issue_token(KEY, &auth_origin("http://localhost:3000"), 7200).unwrap();
+        let result = check_token(&credentials.address, &credentials.token, &origins(), 3600, unix_now());
+        assert!(matches!(result, Err(AuthError::Invalid(_))));
+        assert!(check_token(&credentials.address, &credentials.token, &origins(), 0, unix_now()).is_ok());
+    }
+
+    #[test]
+    fn expired_token_is_refused() {
+        let credentials = issue_token(KEY, &auth_origin("http://localhost:3000"), 60).unwrap();
+        let result = check_token(&credentials.address, &credentials.token, &origins(), 3600, unix_now() + 120);
+        assert!(matches!(result, Err(AuthError::Invalid(_))));
+    }
+
+    #[test]
+    fn origin_is_the_host_and_port_of_the_daemon_url() {
+        assert_eq!(auth_origin("http://LocalHost:3000/"), "localhost:3000");
+        assert_eq!(auth_origin("https://user@dgit.example.com/repo?x=1"), "dgit.example.com");
+        assert_eq!(auth_origin("unix:///run/dgit.sock"), "unix:/run/dgit.sock");
+        assert_eq!(auth_origin("unix:/run/dgit.sock"), "unix:/run/dgit.sock");
+    }
+
+    fn auth_error(result: Result<Address>) -> AuthError {
+        result.unwrap_err().downcast::<AuthError>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn authenticated_non_pusher_is_forbidden() {
+        let result = check_push_role("repo", Some(address(1)), |_| async { Ok(false) }).await;
+        assert!(matches!(auth_error(result), AuthError::Forbidden(_)));
+    }
+
+    #[tokio::test]
+    async fn anonymous_push_is_refused_without_asking_the_contract() {
+        let result = check_push_role("repo", None, |_| async { panic!("roles looked up for an anonymous push") }).await;
+        assert!(matches!(auth_error(result), AuthError::Missing));
+    }
+
+    #[tokio::test]
+    async fn pusher_may_push() {
+        let result = check_push_role("repo", Some(address(1)), |candidate| async move { Ok(candidate == address(1)) }).await;
+        assert_eq!(result.unwrap(), address(1));
+    }
+}
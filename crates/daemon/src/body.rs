@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use axum::{body::Body, http::{header::CONTENT_ENCODING, HeaderMap}};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::fmt;
+use std::io::Read;
+use tracing::debug;
+
+#[derive(Debug)]
+pub struct UnsupportedEncoding(pub String);
+
+impl fmt::Display for UnsupportedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsupported Content-Encoding '{}', expected gzip, deflate or identity", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedEncoding {}
+
+/// Reads a git smart-HTTP request body, undoing any `Content-Encoding`.
+///
+/// Git gzips large upload-pack and receive-pack requests; the git child
+/// process only understands the raw pkt-line stream.
+pub async fn read_git_body(headers: &HeaderMap, body: Body) -> Result<Vec<u8>> {
+    let bytes = axum::body::to_bytes(body, usize::MAX).await?;
+
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let mut decoded = Vec::new();
+    match encoding.as_str() {
+        "" | "identity" => return Ok(bytes.to_vec()),
+        "gzip" | "x-gzip" => {
+            GzDecoder::new(bytes.as_ref())
+                .read_to_end(&mut decoded)
+                .context("Failed to decompress gzip request body")?;
+        },
+        "deflate" => {
+            ZlibDecoder::new(bytes.as_ref())
+                .read_to_end(&mut decoded)
+                .context("Failed to decompress deflate request body")?;
+        },
+        other => return Err(UnsupportedEncoding(other.to_string()).into()),
+    }
+
+    debug!("Decompressed {} request body: {} -> {} bytes", encoding, bytes.len(), decoded.len());
+    Ok(decoded)
+}
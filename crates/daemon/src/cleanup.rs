@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, error};
+
+use crate::{auth::unix_now, config::DaemonConfig};
+
+/// Objects anchored on chain by a push whose refs never were, so nothing
+/// references them yet. Kept for later reconciliation, e.g. by `gc`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanedObjects {
+    pub timestamp: u64,
+    pub repo: String,
+    pub objects: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    pub reason: String,
+}
+
+/// Append-only JSON-lines list of objects left behind by failed pushes.
+#[derive(Debug, Clone)]
+pub struct PendingCleanup {
+    path: Arc<PathBuf>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl PendingCleanup {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Arc::new(path.into()),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::pending_cleanup_path())
+    }
+
+    /// Records `objects` of `repo` as orphaned. Best effort: a failed write
+    /// is logged, the push has already failed for a better reason.
+    pub async fn record(&self, repo: &str, objects: Vec<String>, tx_hash: Option<String>, reason: &str) {
+        let entry = OrphanedObjects {
+            timestamp: unix_now(),
+            repo: repo.to_string(),
+            objects,
+            tx_hash,
+            reason: reason.to_string(),
+        };
+
+        if let Err(e) = self.append(&entry).await {
+            error!("Failed to record orphaned objects {:?}: {:#}", entry, e);
+        }
+    }
+
+    async fn append(&self, entry: &OrphanedObjects) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.as_path())
+            .await
+            .with_context(|| format!("Failed to open pending cleanup list {:?}", self.path))?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+
+        debug!("Recorded orphaned objects: {}", line.trim_end());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn orphans_are_appended_one_line_per_failed_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let cleanup = PendingCleanup::new(dir.path().join("pending.jsonl"));
+
+        cleanup.record("project", vec!["aa".to_string(), "bb".to_string()], Some("0x01".to_string()), "chain tx failed: reverted").await;
+        cleanup.record("other", vec!["cc".to_string()], None, "chain tx failed: out of gas").await;
+
+        let contents = std::fs::read_to_string(dir.path().join("pending.jsonl")).unwrap();
+        let entries: Vec<OrphanedObjects> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].repo, "project");
+        assert_eq!(entries[0].objects, ["aa", "bb"]);
+        assert_eq!(entries[0].tx_hash.as_deref(), Some("0x01"));
+        assert_eq!(entries[1].reason, "chain tx failed: out of gas");
+        assert!(!contents.lines().nth(1).unwrap().contains("tx_hash"));
+    }
+
+    #[tokio::test]
+    async fn unwritable_lists_are_only_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let cleanup = PendingCleanup::new(dir.path().join("missing").join("pending.jsonl"));
+        cleanup.record("project", vec!["aa".to_string()], None, "chain tx failed").await;
+        assert!(!dir.path().join("missing").exists());
+    }
+}
@@ -0,0 +1,427 @@
+use anyhow::{anyhow, Context, Result};
+use ethcontract::Address;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::{debug, warn, Level};
+
+use crate::auth::auth_origin;
+use crate::logging::{LogFormat, LogRotation};
+
+/// Daemon-only settings; chain and IPFS settings live in `onchain::config`.
+pub struct DaemonConfig;
+
+impl DaemonConfig {
+    pub fn audit_log_path() -> String {
+        match dotenv::var("AUDIT_LOG_PATH") {
+            Ok(path) => {
+                debug!("Loaded audit log path: {}", path);
+                path
+            },
+            Err(_) => "dgit-audit.jsonl".to_string(),
+        }
+    }
+
+    pub fn webhooks_path() -> String {
+        dotenv::var("WEBHOOKS_PATH").unwrap_or_else(|_| "dgit-webhooks.json".to_string())
+    }
+
+    /// Let webhooks point at loopback, private and link-local addresses,
+    /// for daemons whose receivers live on the same network.
+    pub fn webhook_allow_private() -> bool {
+        Self::number("DGIT_WEBHOOK_ALLOW_PRIVATE", false)
+    }
+
+    /// Directory the synced view of each repository contract is saved in.
+    pub fn sync_dir() -> String {
+        dotenv::var("DGIT_SYNC_DIR").unwrap_or_else(|_| "dgit-sync".to_string())
+    }
+
+    pub fn pending_cleanup_path() -> String {
+        dotenv::var("PENDING_CLEANUP_PATH").unwrap_or_else(|_| "dgit-pending-cleanup.jsonl".to_string())
+    }
+
+    /// Journal of pushes waiting to be anchored with `DGIT_ASYNC_ANCHORING`.
+    pub fn anchor_queue_path() -> String {
+        dotenv::var("ANCHOR_QUEUE_PATH").unwrap_or_else(|_| "dgit-anchor-queue.jsonl".to_string())
+    }
+
+    /// Journal of the IPFS hashes of objects pushed to any repository, so
+    /// pushing one again elsewhere reuses the upload; unset disables it.
+    pub fn object_index_path() -> Option<PathBuf> {
+        dotenv::var("DGIT_OBJECT_INDEX_PATH").ok().filter(|path| !path.is_empty()).map(PathBuf::from)
+    }
+
+    /// Journal of which IPFS CIDs each repository's objects are pinned under.
+    pub fn pin_index_path() -> String {
+        dotenv::var("DGIT_PIN_INDEX_PATH").unwrap_or_else(|_| "dgit-pins.jsonl".to_string())
+    }
+
+    /// Where scratch repositories are created; defaults to `~/.dgit/work`,
+    /// owned by the daemon so orphans can be swept without touching anything
+    /// else.
+    pub fn tmp_dir() -> PathBuf {
+        if let Ok(dir) = dotenv::var("DGIT_TMP_DIR") {
+            return PathBuf::from(dir);
+        }
+        match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".dgit").join("work"),
+            None => std::env::temp_dir().join("dgit-work"),
+        }
+    }
+
+    /// Age after which a scratch directory of another process is swept even
+    /// if its pid is alive; 0 sweeps only those of dead processes.
+    pub fn work_dir_ttl_secs() -> u64 {
+        Self::number("DGIT_WORK_DIR_TTL_SECS", 24 * 60 * 60)
+    }
+
+    /// How often orphaned scratch directories are swept; 0 sweeps only at
+    /// startup and on `POST /admin/gc`.
+    pub fn work_dir_sweep_secs() -> u64 {
+        Self::number("DGIT_WORK_DIR_SWEEP_SECS", 60 * 60)
+    }
+
+    /// Idle scratch repositories kept per repository for the next request;
+    /// 0 creates a fresh one every time.
+    pub fn workspaces_per_repo() -> usize {
+        Self::number("DGIT_WORKSPACES_PER_REPO", 2)
+    }
+
+    /// Disk all idle scratch repositories may use together; 0 for no limit.
+    pub fn workspace_max_bytes() -> u64 {
+        Self::number("DGIT_WORKSPACE_MAX_BYTES", 2 * 1024 * 1024 * 1024)
+    }
+
+    /// Where snapshot repositories are kept for serving clones and fetches
+    /// without rebuilding them from the chain; unset disables snapshots.
+    pub fn snapshot_dir() -> Option<PathBuf> {
+        dotenv::var("DGIT_SNAPSHOT_DIR").ok().filter(|dir| !dir.is_empty()).map(PathBuf::from)
+    }
+
+    /// How often every known repository's synced view is brought up to
+    /// date in the background; 0 syncs only when a request reads it.
+    pub fn indexer_interval_secs() -> u64 {
+        Self::number("DGIT_INDEXER_INTERVAL_SECS", 0)
+    }
+
+    /// Age after which a snapshot is rebuilt; 0 keeps it until a push.
+    pub fn snapshot_max_age_secs() -> u64 {
+        Self::number("DGIT_SNAPSHOT_MAX_AGE_SECS", 300)
+    }
+
+    /// Rebuild a repository's snapshot right after a push rather than on
+    /// the next read.
+    pub fn snapshot_rebuild_on_push() -> bool {
+        Self::number("DGIT_SNAPSHOT_REBUILD_ON_PUSH", true)
+    }
+
+    /// Answer pushes once their objects are on IPFS and anchor them on
+    /// chain in the background.
+    pub fn async_anchoring() -> bool {
+        Self::number("DGIT_ASYNC_ANCHORING", false)
+    }
+
+    /// Attempts at anchoring a queued push before it is marked failed.
+    pub fn anchor_max_attempts() -> u32 {
+        Self::number("DGIT_ANCHOR_MAX_ATTEMPTS", 5)
+    }
+
+    /// Wait before retrying a queued push, multiplied by its attempts so far.
+    pub fn anchor_retry_delay_secs() -> u64 {
+        Self::number("DGIT_ANCHOR_RETRY_DELAY_SECS", 30)
+    }
+
+    pub fn port() -> u16 {
+        Self::number("PORT", 3000)
+    }
+
+    /// File to log to besides stderr; unset logs to stderr only.
+    pub fn log_file() -> Option<PathBuf> {
+        dotenv::var("DGIT_LOG_FILE").ok().filter(|path| !path.is_empty()).map(PathBuf::from)
+    }
+
+    pub fn log_format() -> LogFormat {
+        Self::number("DGIT_LOG_FORMAT", LogFormat::Full)
+    }
+
+    pub fn log_file_format() -> LogFormat {
+        Self::number("DGIT_LOG_FILE_FORMAT", LogFormat::Json)
+    }
+
+    pub fn log_file_level() -> Level {
+        Self::number("DGIT_LOG_FILE_LEVEL", Level::INFO)
+    }
+
+    pub fn log_rotation() -> LogRotation {
+        Self::number("DGIT_LOG_ROTATION", LogRotation::Size)
+    }
+
+    /// Size at which the log file is rotated with `DGIT_LOG_ROTATION=size`;
+    /// 0 never rotates.
+    pub fn log_max_bytes() -> u64 {
+        Self::number("DGIT_LOG_MAX_BYTES", 10 * 1024 * 1024)
+    }
+
+    /// Unix domain socket to serve on, besides or instead of TCP.
+    pub fn uds_path() -> Option<PathBuf> {
+        Self::listen_unix().or_else(|| {
+            dotenv::var("DGIT_UDS_PATH").ok().filter(|path| !path.is_empty()).map(PathBuf::from)
+        })
+    }
+
+    /// Whether to listen on `PORT`; off serves only on `DGIT_UDS_PATH`.
+    pub fn listen_tcp() -> bool {
+        Self::listen_unix().is_none() && Self::number("DGIT_LISTEN_TCP", true)
+    }
+
+    /// Permissions of the socket file, in octal.
+    pub fn uds_mode() -> u32 {
+        match dotenv::var("DGIT_UDS_MODE") {
+            Ok(value) => u32::from_str_radix(value.trim_start_matches("0o"), 8)
+                .ok()
+                .filter(|mode| *mode <= 0o777)
+                .unwrap_or_else(|| {
+                    warn!("Invalid DGIT_UDS_MODE '{}', using default: 600", value);
+                    0o600
+                }),
+            Err(_) => 0o600,
+        }
+    }
+
+    /// `DGIT_LISTEN=unix:/path/to/dgit.sock`, shorthand for serving only on
+    /// that socket. `tcp`, the default, leaves it to the other settings.
+    fn listen_unix() -> Option<PathBuf> {
+        let value = dotenv::var("DGIT_LISTEN").ok()?;
+        if let Some(path) = value.strip_prefix("unix:") {
+            // `unix:///path` as the CLI writes it, or `unix:/path`.
+            let path = path.strip_prefix("//").unwrap_or(path);
+            if !path.is_empty() {
+                return Some(PathBuf::from(path));
+            }
+        }
+        if !value.is_empty() && value != "tcp" {
+            warn!("Ignoring invalid DGIT_LISTEN '{}': expected tcp or unix:/path", value);
+        }
+        None
+    }
+
+    /// How long a request other than a fetch or push may take to answer.
+    /// Longer than `TX_CONFIRM_TIMEOUT_SECS`, so a transaction is not cut
+    /// off while it confirms.
+    pub fn request_timeout_secs() -> u64 {
+        Self::number("DGIT_REQUEST_TIMEOUT_SECS", 360)
+    }
+
+    /// How long `git-upload-pack` and `git-receive-pack` may take to answer.
+    pub fn pack_timeout_secs() -> u64 {
+        Self::number("DGIT_PACK_TIMEOUT_SECS", 1800)
+    }
+
+    /// The git binary the daemon runs.
+    pub fn git_bin() -> String {
+        dotenv::var("DGIT_GIT_BIN").ok().filter(|bin| !bin.is_empty()).unwrap_or_else(|| "git".to_string())
+    }
+
+    /// Have git generate `info/refs` advertisements in a scratch repository
+    /// instead of building them from the chain's refs.
+    pub fn git_advertise() -> bool {
+        Self::number("DGIT_GIT_ADVERTISE", false)
+    }
+
+    /// Refuse to start when `DGIT_GIT_BIN` is missing or too old; with
+    /// false that is only logged and reported on `/ready`.
+    pub fn git_strict() -> bool {
+        Self::number("DGIT_GIT_STRICT", true)
+    }
+
+    /// How long one git subprocess may run before it is killed; 0 for no
+    /// limit. Shorter than `DGIT_PACK_TIMEOUT_SECS`, so a hung git is
+    /// reported as such rather than as a slow request.
+    pub fn git_timeout_secs() -> u64 {
+        Self::number("DGIT_GIT_TIMEOUT_SECS", 600)
+    }
+
+    /// Fetches and pushes handled at once; 0 for no limit.
+    pub fn pack_concurrency() -> usize {
+        Self::number("DGIT_PACK_CONCURRENCY", 4)
+    }
+
+    /// Fetches and pushes waiting for a slot before more are refused.
+    pub fn pack_queue() -> u64 {
+        Self::number("DGIT_PACK_QUEUE", 16)
+    }
+
+    pub fn push_rate_per_minute() -> u32 {
+        Self::number("PUSH_RATE_PER_MINUTE", 30)
+    }
+
+    pub fn object_rate_per_hour() -> u32 {
+        Self::number("OBJECT_RATE_PER_HOUR", 50_000)
+    }
+
+    pub fn max_object_bytes() -> u64 {
+        Self::number("DGIT_MAX_OBJECT_BYTES", 100 * 1024 * 1024)
+    }
+
+    pub fn max_push_bytes() -> u64 {
+        Self::number("DGIT_MAX_PUSH_BYTES", 1024 * 1024 * 1024)
+    }
+
+    /// Objects a repository may hold on chain in total.
+    pub fn max_repo_objects() -> u64 {
+        Self::number("DGIT_MAX_REPO_OBJECTS", 0)
+    }
+
+    /// Objects uploaded and anchored per `add_objects` transaction. Each
+    /// batch is committed before the next starts, so a failed push keeps
+    /// the batches before it.
+    pub fn object_batch_size() -> usize {
+        Self::number("DGIT_OBJECT_BATCH_SIZE", 500).max(1)
+    }
+
+    /// Ref namespaces pushes may write to, each ending in `/`. Updates to
+    /// other refs are rejected rather than silently dropped.
+    pub fn ref_namespaces() -> Vec<String> {
+        let value = dotenv::var("DGIT_REF_NAMESPACES").unwrap_or_else(|_| "refs/heads/,refs/tags/".to_string());
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|namespace| !namespace.is_empty())
+            .filter_map(|namespace| {
+                if !namespace.starts_with("refs/") {
+                    warn!("Ignoring ref namespace '{}' outside refs/", namespace);
+                    return None;
+                }
+                Some(format!("{}/", namespace.trim_end_matches('/')))
+            })
+            .collect()
+    }
+
+    /// Addresses allowed to push to any repository on this daemon, listed
+    /// comma-separated in `DGIT_PUSH_ALLOWLIST` and one per line in
+    /// `DGIT_PUSH_ALLOWLIST_FILE`. `None` when neither is set, so pushes
+    /// only need what the contract asks for.
+    pub fn push_allowlist() -> Result<Option<Vec<Address>>> {
+        Self::address_list("DGIT_PUSH_ALLOWLIST", "DGIT_PUSH_ALLOWLIST_FILE")
+    }
+
+    /// Addresses refused pushes to any repository on this daemon, whatever
+    /// roles they hold, from `DGIT_PUSH_DENYLIST` and `DGIT_PUSH_DENYLIST_FILE`.
+    /// `None` when neither is set.
+    pub fn push_denylist() -> Result<Option<Vec<Address>>> {
+        Self::address_list("DGIT_PUSH_DENYLIST", "DGIT_PUSH_DENYLIST_FILE")
+    }
+
+    /// Daemon URLs clients reach this daemon at, comma-separated in
+    /// `DGIT_AUTH_ORIGINS`; only tokens issued for one of them are accepted.
+    /// Defaults to `localhost` and `127.0.0.1` on `PORT`, and the socket when
+    /// serving on one.
+    pub fn auth_origins() -> Vec<String> {
+        let configured: Vec<String> = dotenv::var("DGIT_AUTH_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(auth_origin)
+            .collect();
+        if !configured.is_empty() {
+            return configured;
+        }
+
+        let mut origins = vec![format!("localhost:{}", Self::port()), format!("127.0.0.1:{}", Self::port())];
+        if let Some(path) = Self::uds_path() {
+            origins.push(format!("unix:{}", path.display()));
+        }
+        origins
+    }
+
+    /// Longest a token may still be valid for when presented, so a leaked
+    /// one cannot be used indefinitely; 0 for no limit.
+    pub fn max_token_ttl_secs() -> u64 {
+        Self::number("DGIT_MAX_TOKEN_TTL_SECS", 86_400)
+    }
+
+    /// Origins browsers may call the daemon from, or `*` for any; CORS
+    /// headers are only sent when this is set.
+    pub fn cors_origins() -> Vec<String> {
+        dotenv::var("DGIT_CORS_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/'))
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Object hashes known to be on chain kept in memory per repository;
+    /// 0 for no limit.
+    pub fn object_cache_max_per_repo() -> usize {
+        Self::number("DGIT_OBJECT_CACHE_MAX_PER_REPO", 100_000)
+    }
+
+    /// How long a name the registry did not resolve is answered as unknown
+    /// without asking it again; 0 asks every time.
+    pub fn registry_miss_ttl_secs() -> u64 {
+        Self::number("DGIT_REGISTRY_MISS_TTL_SECS", 60)
+    }
+
+    /// How long a repository's collapsed ref set is served from memory.
+    pub fn ref_cache_ttl_secs() -> u64 {
+        Self::number("REF_CACHE_TTL_SECS", 10)
+    }
+
+    /// How long create-repo waits for a new contract to answer reads.
+    pub fn create_ready_timeout_secs() -> u64 {
+        Self::number("CREATE_READY_TIMEOUT_SECS", 30)
+    }
+
+    pub fn create_ready_poll_ms() -> u64 {
+        Self::number("CREATE_READY_POLL_MS", 500)
+    }
+
+    /// Addresses from the comma-separated `name` and the file `file_name`
+    /// points to, which has one per line and `#` comments. The file is read
+    /// on every call, so edits apply without a restart. An entry that is
+    /// not an address is an error rather than skipped, since a list that
+    /// silently lost an entry would let through someone it was meant to stop.
+    fn address_list(name: &str, file_name: &str) -> Result<Option<Vec<Address>>> {
+        let mut entries = Vec::new();
+        let value = dotenv::var(name).unwrap_or_default();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            entries.push((name.to_string(), entry.to_string()));
+        }
+        let file = dotenv::var(file_name).ok().filter(|file| !file.is_empty());
+        if let Some(file) = &file {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("Failed to read {} {}", file_name, file))?;
+            for (index, line) in content.lines().enumerate() {
+                let entry = line.split('#').next().unwrap_or_default().trim();
+                if !entry.is_empty() {
+                    entries.push((format!("{} line {}", file, index + 1), entry.to_string()));
+                }
+            }
+        }
+
+        if entries.is_empty() && file.is_none() {
+            return Ok(None);
+        }
+        entries
+            .into_iter()
+            .map(|(source, entry)| {
+                Address::from_str(&entry).map_err(|_| anyhow!("Invalid address '{}' in {}", entry, source))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    fn number<T: FromStr + Display>(name: &str, default: T) -> T {
+        match dotenv::var(name) {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                warn!("Invalid {} '{}', using default: {}", name, value, default);
+                default
+            }),
+            Err(_) => default,
+        }
+    }
+}
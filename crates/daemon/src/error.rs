@@ -0,0 +1,105 @@
+use axum::{http::{header::{RETRY_AFTER, WWW_AUTHENTICATE}, StatusCode}, response::{IntoResponse, Response}};
+use onchain::{ipfs::IpfsError, transport::WrongChain};
+use std::fmt;
+
+use crate::{auth::{AuthError, AUTH_REALM}, body::UnsupportedEncoding, git::GitTimedOut, handlers::ContractNotReady, rate_limit::RateLimited, request_limits::{Overloaded, TimedOut}, validation::InvalidRepoName};
+
+/// A requested resource that does not exist, answered with 404 so git's dumb
+/// HTTP walker can tell a missing file from a failure.
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// Wraps handler errors and picks the HTTP status from the underlying cause.
+#[derive(Debug)]
+pub struct ApiError(anyhow::Error);
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        if self.0.downcast_ref::<InvalidRepoName>().is_some() {
+            return StatusCode::UNPROCESSABLE_ENTITY;
+        }
+
+        if self.0.downcast_ref::<NotFound>().is_some() {
+            return StatusCode::NOT_FOUND;
+        }
+
+        if self.0.downcast_ref::<RateLimited>().is_some() {
+            return StatusCode::TOO_MANY_REQUESTS;
+        }
+
+        if self.0.downcast_ref::<UnsupportedEncoding>().is_some() {
+            return StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        }
+
+        if self.0.downcast_ref::<Overloaded>().is_some() {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+
+        if self.0.downcast_ref::<TimedOut>().is_some() || self.0.downcast_ref::<GitTimedOut>().is_some() {
+            return StatusCode::GATEWAY_TIMEOUT;
+        }
+
+        if self.0.downcast_ref::<WrongChain>().is_some() {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+
+        if self.0.downcast_ref::<ContractNotReady>().is_some() {
+            return StatusCode::GATEWAY_TIMEOUT;
+        }
+
+        if let Some(err) = self.0.downcast_ref::<AuthError>() {
+            return match err {
+                AuthError::Missing | AuthError::Invalid(_) => StatusCode::UNAUTHORIZED,
+                AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            };
+        }
+
+        if let Some(err) = self.0.downcast_ref::<IpfsError>() {
+            return match err {
+                IpfsError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                IpfsError::ConnectionRefused { .. }
+                | IpfsError::Unauthorized { .. }
+                | IpfsError::BadResponse { .. }
+                | IpfsError::VerificationFailed { .. } => StatusCode::BAD_GATEWAY,
+                IpfsError::Io { .. } | IpfsError::Misconfigured { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+
+        StatusCode::BAD_REQUEST
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        Self(err.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        // `{:#}` keeps the root cause next to any context added on the way up.
+        let mut response = (status, format!("{:#}", self.0)).into_response();
+
+        if status == StatusCode::UNAUTHORIZED {
+            let challenge = format!("Basic realm=\"{}\"", AUTH_REALM);
+            response.headers_mut().insert(WWW_AUTHENTICATE, challenge.parse().unwrap());
+        }
+
+        let retry_after = self.0.downcast_ref::<RateLimited>().map(|limited| limited.retry_after)
+            .or_else(|| self.0.downcast_ref::<Overloaded>().map(|overloaded| overloaded.retry_after));
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, retry_after.as_secs().max(1).to_string().parse().unwrap());
+        }
+
+        response
+    }
+}
@@ -0,0 +1,71 @@
+use serde::Deserialize;
+
+/// Path parameters of routes addressing a repository, either by a flat name
+/// (`/{repo}/...`) or namespaced under an owner (`/{owner}/{repo}/...`).
+#[derive(Debug, Deserialize)]
+pub struct RepoPath {
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub repo: String,
+}
+
+impl RepoPath {
+    /// Key the repository is stored under in `ContractState`.
+    pub fn name(&self) -> String {
+        repo_key(self.owner.as_deref(), &self.repo)
+    }
+}
+
+/// Path parameters of role routes, which also carry the target address.
+#[derive(Debug, Deserialize)]
+pub struct RepoAddressPath {
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub repo: String,
+    pub address: String,
+}
+
+impl RepoAddressPath {
+    pub fn name(&self) -> String {
+        repo_key(self.owner.as_deref(), &self.repo)
+    }
+}
+
+/// Path parameters of the fork route, which also name the new repository.
+#[derive(Debug, Deserialize)]
+pub struct ForkPath {
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub repo: String,
+    pub new_name: String,
+}
+
+impl ForkPath {
+    pub fn name(&self) -> String {
+        repo_key(self.owner.as_deref(), &self.repo)
+    }
+}
+
+/// Path parameters of dumb-protocol loose object routes
+/// (`/{repo}/objects/{prefix}/{suffix}`).
+#[derive(Debug, Deserialize)]
+pub struct ObjectPath {
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub repo: String,
+    pub prefix: String,
+    pub suffix: String,
+}
+
+impl ObjectPath {
+    pub fn name(&self) -> String {
+        repo_key(self.owner.as_deref(), &self.repo)
+    }
+}
+
+fn repo_key(owner: Option<&str>, repo: &str) -> String {
+    match owner {
+        Some(owner) => format!("{}/{}", owner, repo),
+        None => repo.to_string(),
+    }
+}
@@ -0,0 +1,290 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::future::Future;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::{config::DaemonConfig, work_dirs};
+
+/// Oldest git the daemon runs on: protocol v2, which fetches use when the
+/// client offers it, arrived in 2.18.
+pub const MIN_VERSION: (u32, u32, u32) = (2, 18, 0);
+
+static PROBE: OnceLock<GitProbe> = OnceLock::new();
+
+/// What the startup check found out about `DGIT_GIT_BIN`.
+#[derive(Debug, Clone, Serialize)]
+pub struct GitProbe {
+    pub bin: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A command running the git binary in `DGIT_GIT_BIN`.
+pub fn command() -> Command {
+    Command::new(DaemonConfig::git_bin())
+}
+
+/// A `git upload-pack` command, set up to serve partial clones. Every
+/// object is on disk by the time git runs, so git applies a client's
+/// `filter` (`--filter=blob:none` and the like) itself. The promisor
+/// fetches that later fill in filtered objects want them by id, which v0
+/// clients may only do for objects reachable from a ref.
+pub fn upload_pack() -> Command {
+    let mut cmd = command();
+    cmd.args([
+        "-c", "uploadpack.allowFilter=true",
+        "-c", "uploadpack.allowReachableSHA1InWant=true",
+        "upload-pack",
+    ]);
+    cmd
+}
+
+/// Runs `git --version` and checks the version is at least `MIN_VERSION`.
+/// The first result is kept for `probed`.
+pub async fn probe() -> GitProbe {
+    let bin = DaemonConfig::git_bin();
+    let mut cmd = command();
+    cmd.arg("--version");
+
+    let (version, error) = match output(cmd).await {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let error = match parse_version(&version) {
+                Some(parsed) if parsed >= MIN_VERSION => None,
+                Some(_) => Some(format!("{} is {}, older than the {}.{}.{} the daemon needs", bin, version, MIN_VERSION.0, MIN_VERSION.1, MIN_VERSION.2)),
+                None => Some(format!("Cannot tell the version of {} from '{}'", bin, version)),
+            };
+            (Some(version), error)
+        },
+        Ok(output) => (None, Some(format!("{} --version failed: {}", bin, String::from_utf8_lossy(&output.stderr).trim()))),
+        Err(e) => (None, Some(format!("Cannot run {}: {:#}", bin, e))),
+    };
+
+    let probe = GitProbe { bin, version, ok: error.is_none(), error };
+    PROBE.get_or_init(|| probe.clone());
+    probe
+}
+
+/// The result of the startup check, once it ran.
+pub fn probed() -> Option<&'static GitProbe> {
+    PROBE.get()
+}
+
+/// Version of the git the startup check found, or `MIN_VERSION` before it
+/// ran.
+pub fn probed_version() -> (u32, u32, u32) {
+    probed()
+        .and_then(|probe| probe.version.as_deref())
+        .and_then(parse_version)
+        .unwrap_or(MIN_VERSION)
+}
+
+/// `(major, minor, patch)` from `git version 2.39.2`, including suffixes
+/// like `.windows.1` or ` (Apple Git-143)`.
+pub(crate) fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let number = version.strip_prefix("git version ")?.split_whitespace().next()?;
+    let mut parts = number.split('.').map(|part| part.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = parts.next().and_then(|part| part.ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// A git subprocess ran past `DGIT_GIT_TIMEOUT_SECS` and was killed.
+#[derive(Debug)]
+pub struct GitTimedOut {
+    pub command: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for GitTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "git {} did not finish within {}s and was killed", self.command, self.after.as_secs())
+    }
+}
+
+impl std::error::Error for GitTimedOut {}
+
+/// Runs `cmd` with `input` on its stdin and collects stdout and stderr.
+///
+/// Writing and reading happen concurrently: git starts answering before it
+/// has read the whole request (large pushes, multi-ack negotiation), and
+/// writing everything first would deadlock once both pipe buffers fill.
+/// Stdin is closed as soon as the input is written so git sees EOF.
+pub async fn run_with_input(cmd: Command, input: Vec<u8>) -> Result<Output> {
+    run_with_input_within(cmd, input, configured_timeout()).await
+}
+
+async fn run_with_input_within(mut cmd: Command, input: Vec<u8>, timeout: Duration) -> Result<Output> {
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let name = subcommand(&cmd);
+    let mut child = cmd.spawn()?;
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("Failed to open git stdin"))?;
+
+    let writer = tokio::spawn(async move {
+        let result = stdin.write_all(&input).await;
+        drop(stdin);
+        result
+    });
+
+    let output = match within(timeout, &name, child.wait_with_output()).await {
+        Ok(output) => output,
+        Err(e) => {
+            writer.abort();
+            return Err(e);
+        },
+    };
+
+    // git may exit without reading everything, e.g. when it rejects the
+    // request; its stderr says more than the broken pipe would.
+    match writer.await? {
+        Err(e) if output.status.success() => return Err(e.into()),
+        Err(e) => debug!("git exited before reading all input: {}", e),
+        Ok(()) => {},
+    }
+
+    Ok(output)
+}
+
+/// Runs `cmd` to completion like `Command::output`, killing it if it runs
+/// past `DGIT_GIT_TIMEOUT_SECS`.
+pub async fn output(cmd: Command) -> Result<Output> {
+    output_within(cmd, configured_timeout()).await
+}
+
+async fn output_within(mut cmd: Command, timeout: Duration) -> Result<Output> {
+    cmd.kill_on_drop(true);
+    let name = subcommand(&cmd);
+    within(timeout, &name, cmd.output()).await
+}
+
+fn configured_timeout() -> Duration {
+    Duration::from_secs(DaemonConfig::git_timeout_secs())
+}
+
+/// Awaits a git subprocess for at most `timeout`, zero meaning no limit.
+/// The child is spawned with `kill_on_drop`, so dropping `wait` on expiry
+/// kills it.
+async fn within<T>(timeout: Duration, name: &str, wait: impl Future<Output = std::io::Result<T>>) -> Result<T> {
+    if timeout.is_zero() {
+        return Ok(wait.await?);
+    }
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            warn!("Killing git {} after {}s", name, timeout.as_secs());
+            Err(GitTimedOut { command: name.to_string(), after: timeout }.into())
+        },
+    }
+}
+
+/// The git subcommand `cmd` runs, skipping `-c` settings, for messages.
+fn subcommand(cmd: &Command) -> String {
+    let mut args = cmd.as_std().get_args();
+    while let Some(arg) = args.next() {
+        if arg == "-c" {
+            args.next();
+            continue;
+        }
+        return arg.to_string_lossy().into_owned();
+    }
+    "(no subcommand)".to_string()
+}
+
+/// A scratch directory under `DGIT_TMP_DIR`, removed when dropped. Its
+/// name records this process and the time, for the orphan sweep.
+pub fn tempdir() -> Result<TempDir> {
+    let root = DaemonConfig::tmp_dir();
+    tempfile::Builder::new()
+        .prefix(&work_dirs::tag())
+        .tempdir_in(&root)
+        .with_context(|| format!("Failed to create a temporary directory in {}", root.display()))
+}
+
+/// Checks at startup that scratch directories can be created, so a bad
+/// `DGIT_TMP_DIR` fails there rather than in the middle of a push. The
+/// directory is created if missing.
+pub fn check_tmp_dir() -> Result<PathBuf> {
+    let root = DaemonConfig::tmp_dir();
+    std::fs::create_dir_all(&root)
+        .with_context(|| format!("Failed to create DGIT_TMP_DIR {}", root.display()))?;
+    if !root.is_dir() {
+        bail!("DGIT_TMP_DIR {} is not a directory", root.display());
+    }
+    tempdir()?;
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn kills_a_child_that_runs_past_the_timeout() {
+        let timeout = Duration::from_secs(1);
+        let started = Instant::now();
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        let err = output_within(cmd, timeout).await.unwrap_err();
+        assert!(err.downcast_ref::<GitTimedOut>().is_some(), "unexpected error: {:#}", err);
+
+        let mut cmd = Command::new("sleep");
+        cmd.arg("30");
+        let err = run_with_input_within(cmd, b"never read".to_vec(), timeout).await.unwrap_err();
+        assert!(err.downcast_ref::<GitTimedOut>().is_some(), "unexpected error: {:#}", err);
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn feeds_stdin_while_the_child_is_already_answering() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cmd = command();
+        cmd.args(["init", "--quiet"]).current_dir(dir.path());
+        assert!(output(cmd).await.unwrap().status.success());
+        let mut cmd = command();
+        cmd.args(["hash-object", "-w", "--stdin"]).current_dir(dir.path());
+        let blob = run_with_input(cmd, vec![b'x'; 4096]).await.unwrap();
+        let blob = String::from_utf8(blob.stdout).unwrap();
+
+        // cat-file answers each line as it reads it, so both pipe buffers
+        // fill long before the input is written.
+        let requests = 20_000;
+        let input = blob.repeat(requests).into_bytes();
+        assert!(input.len() > 64 * 1024);
+        let mut cmd = command();
+        cmd.args(["cat-file", "--batch"]).current_dir(dir.path());
+        let answer = tokio::time::timeout(Duration::from_secs(60), run_with_input(cmd, input))
+            .await
+            .expect("writing the input does not deadlock")
+            .unwrap();
+
+        assert!(answer.status.success());
+        assert!(answer.stdout.len() > requests * 4096);
+    }
+
+    #[test]
+    fn parses_git_versions() {
+        assert_eq!(parse_version("git version 2.39.2"), Some((2, 39, 2)));
+        assert_eq!(parse_version("git version 2.45.1.windows.1"), Some((2, 45, 1)));
+        assert_eq!(parse_version("git version 2.39.3 (Apple Git-146)"), Some((2, 39, 3)));
+        assert_eq!(parse_version("git version 2.18"), Some((2, 18, 0)));
+        assert_eq!(parse_version("not git"), None);
+    }
+}
@@ -0,0 +1,61 @@
+use axum::{extract::State, http::HeaderMap, response::{IntoResponse, Response}, Json};
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{auth::ensure_daemon_signer, error::ApiError, rpc::{self, RpcSwitch}, state::ContractState, work_dirs::{DiskUsage, SweepReport}};
+
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    /// New endpoints, in order of preference.
+    pub urls: Vec<String>,
+}
+
+/// Disk used by scratch directories under `DGIT_TMP_DIR`, and how much of
+/// it is orphaned. Only the daemon's own signer may look, since paths and
+/// sizes say what the daemon is busy with.
+pub async fn disk_usage(State(contract_state): State<ContractState>, headers: HeaderMap) -> Response {
+    match handle_disk_usage(contract_state, &headers).await {
+        Ok(usage) => Json(usage).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_disk_usage(contract_state: ContractState, headers: &HeaderMap) -> Result<DiskUsage> {
+    ensure_daemon_signer(headers)?;
+    contract_state.work_dirs().usage().await
+}
+
+/// Sweeps orphaned scratch directories now rather than at the next
+/// `DGIT_WORK_DIR_SWEEP_SECS` tick. Only directories of processes that are
+/// gone or past the TTL are touched, so this is safe at any time, but it
+/// is still left to the daemon's own signer.
+pub async fn sweep_work_dirs(State(contract_state): State<ContractState>, headers: HeaderMap) -> Response {
+    match handle_sweep_work_dirs(contract_state, &headers).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_sweep_work_dirs(contract_state: ContractState, headers: &HeaderMap) -> Result<SweepReport> {
+    ensure_daemon_signer(headers)?;
+    contract_state.work_dirs().sweep().await
+}
+
+/// Switches the daemon to other RPC endpoints without a restart. Only the
+/// daemon's own signer may, since whoever picks the endpoints decides what
+/// the daemon believes is on chain.
+pub async fn switch_rpc(
+    State(contract_state): State<ContractState>,
+    headers: HeaderMap,
+    Json(request): Json<RpcRequest>,
+) -> Response {
+    match handle_switch_rpc(contract_state, &headers, request).await {
+        Ok(switched) => Json(switched).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_switch_rpc(contract_state: ContractState, headers: &HeaderMap, request: RpcRequest) -> Result<RpcSwitch> {
+    ensure_daemon_signer(headers)?;
+    rpc::switch(&contract_state, request.urls).await
+}
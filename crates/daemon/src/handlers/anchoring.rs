@@ -0,0 +1,46 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::Serialize;
+use anyhow::Result;
+
+use crate::{anchor_queue::AnchorJob, auth::ensure_read_access, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+#[derive(Debug, Serialize)]
+pub struct AnchoringStatusResponse {
+    pub repo: String,
+    /// Whether this daemon queues pushes at all.
+    pub enabled: bool,
+    pub jobs: Vec<AnchorJob>,
+}
+
+/// Queued anchoring jobs of a repository, newest first, without the objects
+/// and refs they carry.
+pub async fn anchoring_status(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_anchoring_status(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_anchoring_status(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<AnchoringStatusResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let queue = contract_state.anchor_queue();
+    let jobs = queue.jobs(&repo).await
+        .into_iter()
+        .map(|job| AnchorJob { objects: Vec::new(), refs: Vec::new(), ref_updates: Vec::new(), ..job })
+        .collect();
+
+    Ok(AnchoringStatusResponse { repo, enabled: queue.enabled(), jobs })
+}
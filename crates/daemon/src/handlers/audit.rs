@@ -0,0 +1,90 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use onchain::contract_interaction::Costs;
+use std::collections::BTreeMap;
+
+use crate::{audit::{AuditAction, AuditEntry}, auth::ensure_read_access, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+const MAX_AUDIT_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    pub repo: String,
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Gas and fees spent on a repository, from the costs the audit log recorded.
+#[derive(Debug, Serialize)]
+pub struct CostsResponse {
+    pub repo: String,
+    pub total: Costs,
+    pub by_action: BTreeMap<AuditAction, Costs>,
+}
+
+pub async fn get_audit_log(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<AuditQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_get_audit_log(contract_state, path.name(), query, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_get_audit_log(
+    contract_state: ContractState,
+    repo: String,
+    query: AuditQuery,
+    headers: &HeaderMap,
+) -> Result<AuditResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT).min(MAX_AUDIT_LIMIT);
+    let entries = contract_state.audit().entries(&repo, limit).await?;
+
+    Ok(AuditResponse { repo, entries })
+}
+
+pub async fn get_costs(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_get_costs(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_get_costs(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<CostsResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let by_action = contract_state.audit().costs(&repo).await?;
+    let mut total = Costs::default();
+    for costs in by_action.values() {
+        total.add(*costs);
+    }
+
+    Ok(CostsResponse { repo, total, by_action })
+}
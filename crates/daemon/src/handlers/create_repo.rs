@@ -1,37 +1,209 @@
-use axum::{extract::{Path, State}, response::IntoResponse, Json};
-use onchain::contract_interaction::ContractInteraction;
-use serde::Serialize;
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::{config::Config, contract_interaction::ContractInteraction, transport::{current_chain_id, WrongChain}};
+use serde::{Deserialize, Serialize};
 use anyhow::Result;
+use ethcontract::Address;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
 
-use crate::state::ContractState;
+use crate::{audit::{AuditAction, AuditEntry}, auth::authenticate_optional, config::DaemonConfig, error::ApiError, extract::RepoPath, state::ContractState, validation::{split_chain, validate_repo_name}};
+
+/// Header clients may set so a retried create returns the original result.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRepoQuery {
+    /// Answer only once the new contract serves reads; `wait=false` skips it.
+    #[serde(default = "default_wait")]
+    pub wait: bool,
+}
+
+fn default_wait() -> bool {
+    true
+}
+
+/// A deployed contract that still did not answer reads when the wait ran out.
+#[derive(Debug)]
+pub struct ContractNotReady {
+    pub address: String,
+    pub waited: Duration,
+}
+
+impl fmt::Display for ContractNotReady {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Repository contract {} was deployed but is not queryable after {}s", self.address, self.waited.as_secs())
+    }
+}
+
+impl std::error::Error for ContractNotReady {}
 
 #[derive(Debug, Serialize)]
 pub struct CreateRepoResponse {
     pub repo: String,
     pub address: String,
+    /// Deployment transaction, for looking the contract up on an explorer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Chain the contract lives on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// Gas used by the deployment and the creator's role grants.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<u64>,
+    /// What that gas cost, in wei.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_wei: Option<u128>,
+}
+
+impl CreateRepoResponse {
+    pub(crate) fn new(repo: String, contract: &ContractInteraction) -> Self {
+        Self {
+            repo,
+            address: contract.address(),
+            tx_hash: contract.deployment.map(|deployment| format!("{:?}", deployment.tx_hash)),
+            block_number: contract.deployment.and_then(|deployment| deployment.block_number),
+            chain_id: contract.chain_id,
+            gas_used: contract.deployment.map(|deployment| deployment.costs.gas_used),
+            fee_wei: contract.deployment.map(|deployment| deployment.costs.fee_wei),
+        }
+    }
 }
 
 pub async fn create_repo(
     State(contract_state): State<ContractState>,
-    Path(repo): Path<String>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<CreateRepoQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    match handle_create_repo(contract_state, repo).await {
+    match handle_create_repo(contract_state, path.name(), query.wait, &headers).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
 async fn handle_create_repo(
     contract_state: ContractState,
     repo: String,
+    wait: bool,
+    headers: &HeaderMap,
 ) -> Result<CreateRepoResponse> {
-    let contract = contract_state.get_contract(&repo).await;
-    if contract.is_some() {
+    let repo = validate_repo_name(&repo)?;
+    let creator = authenticate_optional(headers)?;
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    if let Some(existing) = contract_state.get_contract(&repo).await {
+        // A retry of a create that already succeeded gets the original result.
+        if idempotency_key.is_some() && contract_state.idempotency_key(&repo).await == idempotency_key {
+            info!("Replaying create of {} for idempotency key {:?}", repo, idempotency_key);
+            // The original may have timed out waiting; the retry waits again.
+            if wait {
+                wait_until_queryable(&existing).await?;
+            }
+            return Ok(CreateRepoResponse::new(repo, &existing));
+        }
+        return Err(anyhow::anyhow!("Repository already exists"));
+    }
+
+    let slot = contract_state.creation_slot(&repo).await;
+    let result = slot
+        .get_or_try_init(|| deploy_repo(&contract_state, &repo, creator, idempotency_key))
+        .await
+        .cloned();
+    contract_state.finish_creation(&repo, &slot).await;
+
+    let contract = result?;
+    if wait {
+        wait_until_queryable(&contract).await?;
+    }
+    Ok(CreateRepoResponse::new(repo, &contract))
+}
+
+/// Polls a cheap view call until the contract answers, since RPC nodes
+/// behind a load balancer can lag the one that mined the deployment.
+async fn wait_until_queryable(contract: &ContractInteraction) -> Result<()> {
+    let timeout = Duration::from_secs(DaemonConfig::create_ready_timeout_secs());
+    let interval = Duration::from_millis(DaemonConfig::create_ready_poll_ms());
+    let started = Instant::now();
+
+    loop {
+        match contract.get_refs_length().await {
+            Ok(_) => {
+                debug!("Contract {} queryable after {:?}", contract.address(), started.elapsed());
+                return Ok(());
+            }
+            Err(e) if started.elapsed() + interval > timeout => {
+                debug!("Contract {} still not queryable: {}", contract.address(), e);
+                return Err(ContractNotReady { address: contract.address(), waited: started.elapsed() }.into());
+            }
+            Err(e) => debug!("Contract {} not queryable yet: {}", contract.address(), e),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+pub(crate) async fn deploy_repo(
+    contract_state: &ContractState,
+    repo: &str,
+    creator: Option<Address>,
+    idempotency_key: Option<String>,
+) -> Result<ContractInteraction> {
+    // Re-check under the slot: a create that finished between our lookup
+    // and taking the slot must not be deployed twice.
+    if contract_state.get_contract(repo).await.is_some() {
         return Err(anyhow::anyhow!("Repository already exists"));
     }
 
-    let contract = ContractInteraction::deploy().await?;
-    contract_state.insert_contract(repo.clone(), contract.clone()).await;
+    // `name@<chain id>` keeps repositories on different chains apart, so
+    // it must not be deployed anywhere else.
+    if let Some(expected) = split_chain(repo).1.and_then(|chain| chain.parse().ok()) {
+        let actual = current_chain_id().await?;
+        if actual != expected {
+            return Err(WrongChain { expected, actual }.into());
+        }
+    }
+
+    // With a registry, the contract is created there under the name without
+    // the chain suffix, so every daemon on the chain finds it.
+    let mut contract = if Config::registry_address().is_some() {
+        ContractInteraction::deploy_registered(split_chain(repo).0).await?
+    } else {
+        ContractInteraction::deploy().await?
+    };
+
+    // The daemon's key deploys the contract and so is its first admin. Hand
+    // the same roles to an authenticated creator so they can manage the repo
+    // without the daemon operator.
+    if let Some(creator) = creator {
+        if Some(creator) != contract.signer.address() {
+            let admin = contract.grant_admin_role(creator).await?;
+            let pusher = contract.grant_pusher_role(creator).await?;
+            if let Some(deployment) = contract.deployment.as_mut() {
+                deployment.costs.add(admin.costs);
+                deployment.costs.add(pusher.costs);
+            }
+            info!("Granted admin and pusher roles on {} to creator {:?}", repo, creator);
+        }
+    }
+
+    contract_state.insert_contract(repo.to_string(), contract.clone()).await;
+    if let Some(key) = idempotency_key {
+        contract_state.set_idempotency_key(repo, key).await;
+    }
+
+    let actor = creator.or(contract.signer.address());
+    contract_state.audit().record(AuditEntry {
+        actor: actor.map(|address| format!("{:?}", address)),
+        target: Some(contract.address()),
+        tx_hash: contract.deployment.map(|deployment| format!("{:?}", deployment.tx_hash)),
+        costs: contract.deployment.map(|deployment| deployment.costs),
+        ..AuditEntry::new(AuditAction::CreateRepo, repo)
+    }).await;
 
-    Ok(CreateRepoResponse { repo, address: contract.address() })
+    Ok(contract)
 }
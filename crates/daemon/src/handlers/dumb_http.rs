@@ -0,0 +1,193 @@
+use axum::{extract::{Path, State}, http::{header, HeaderMap}, response::IntoResponse};
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use onchain::{contract_interaction::Ref, storage};
+
+use crate::{auth::ensure_read_access, error::{ApiError, NotFound}, git, extract::{ObjectPath, RepoPath}, state::ContractState, validation::validate_repo_name};
+
+/// Branches `HEAD` points at for dumb clients, in order of preference. The
+/// contract does not record a symbolic `HEAD`.
+const DEFAULT_BRANCHES: &[&str] = &["refs/heads/main", "refs/heads/master"];
+
+pub async fn head_ref(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let repo = path.name();
+    info!("Dumb HEAD requested for repo: {}", repo);
+
+    match handle_head_ref(contract_state, repo, &headers).await {
+        Ok(head) => ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], head).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_head_ref(contract_state: ContractState, repo: String, headers: &HeaderMap) -> Result<String> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let refs = contract_state.ref_cache().active_refs(&repo, &contract).await?;
+    let config = contract.get_repo_config().await?;
+    let head = default_branch(&refs, config.default_branch.as_deref()).ok_or_else(|| NotFound("Repository has no branches".to_string()))?;
+
+    Ok(format!("ref: {}\n", head))
+}
+
+pub async fn loose_object(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<ObjectPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let repo = path.name();
+    debug!("Dumb object {}/{} requested for repo: {}", path.prefix, path.suffix, repo);
+
+    match handle_loose_object(contract_state, repo, &path.prefix, &path.suffix, &headers).await {
+        Ok(object) => (
+            [
+                (header::CONTENT_TYPE, "application/x-git-loose-object"),
+                // Objects are content-addressed and never change.
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            object,
+        ).into_response(),
+        Err(e) => {
+            warn!("Error serving loose object: {:?}", e);
+            ApiError::from(e).into_response()
+        },
+    }
+}
+
+async fn handle_loose_object(
+    contract_state: ContractState,
+    repo: String,
+    prefix: &str,
+    suffix: &str,
+    headers: &HeaderMap,
+) -> Result<Vec<u8>> {
+    let repo = validate_repo_name(&repo)?;
+
+    // Dumb clients also probe `objects/info/packs` and
+    // `objects/info/alternates`; a 404 tells them there are none.
+    let is_hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    if prefix.len() != 2 || suffix.len() != 38 || !is_hex(prefix) || !is_hex(suffix) {
+        return Err(NotFound(format!("objects/{}/{}", prefix, suffix)).into());
+    }
+    let hash = format!("{}{}", prefix, suffix).to_ascii_lowercase();
+
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    if !contract.is_object_exist(hash.clone()).await? {
+        return Err(NotFound(format!("Object {}", hash)).into());
+    }
+
+    let object = contract.get_object(hash.clone()).await?;
+    let cid = String::from_utf8(object.ipfs_url)?;
+
+    let temp_dir = git::tempdir()?;
+    let local_path = temp_dir.path().join(&hash);
+    storage::download(&cid, &local_path).await?;
+
+    let contents = tokio::fs::read(&local_path).await?;
+    debug!("Serving object {} ({} bytes)", hash, contents.len());
+
+    Ok(contents)
+}
+
+/// Points the scratch repository's `HEAD` at the branch clients should check
+/// out. A repository without branches gets an unborn `HEAD` on the
+/// configured or preferred default branch, which clones adopt as their
+/// initial branch instead of the daemon host's `init.defaultBranch`.
+pub(crate) async fn write_head(repo_path: &std::path::Path, refs: &[Ref], configured: Option<&str>) -> Result<()> {
+    let head = default_branch(refs, configured)
+        .or(configured)
+        .unwrap_or(DEFAULT_BRANCHES[0]);
+    tokio::fs::write(repo_path.join("HEAD"), format!("ref: {}\n", head)).await?;
+    Ok(())
+}
+
+/// The repository config's default branch if it exists, else the first of
+/// [`DEFAULT_BRANCHES`] that does, else the first branch by name.
+pub(crate) fn default_branch<'a>(refs: &'a [Ref], configured: Option<&'a str>) -> Option<&'a str> {
+    let heads: Vec<&str> = refs
+        .iter()
+        .filter(|r| r.is_active && r.name.starts_with("refs/heads/"))
+        .map(|r| r.name.as_str())
+        .collect();
+
+    configured
+        .into_iter()
+        .chain(DEFAULT_BRANCHES.iter().copied())
+        .find(|branch| heads.contains(branch))
+        .or_else(|| heads.into_iter().min())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use ethcontract::Address;
+    use std::path::Path;
+
+    fn branch(name: &str) -> Ref {
+        Ref { name: name.to_string(), data: Vec::new(), is_active: true, pusher: Address::zero() }
+    }
+
+    /// Clones `bare` over `file://`, so git reads `HEAD` from the ref
+    /// advertisement as a remote clone would, and returns the branch the
+    /// clone checked out.
+    fn cloned_head(bare: &Path, into: &Path) -> String {
+        let url = format!("file://{}", bare.display());
+        git(bare, &["clone", "--quiet", &url, into.to_str().unwrap()]);
+        git(into, &["symbolic-ref", "HEAD"])
+    }
+
+    #[test]
+    fn prefers_the_configured_then_main_then_master_then_the_first_branch() {
+        let refs = [branch("refs/heads/develop"), branch("refs/heads/main"), branch("refs/heads/master")];
+        assert_eq!(default_branch(&refs, Some("refs/heads/develop")), Some("refs/heads/develop"));
+        assert_eq!(default_branch(&refs, Some("refs/heads/gone")), Some("refs/heads/main"));
+        assert_eq!(default_branch(&refs[2..], None), Some("refs/heads/master"));
+        assert_eq!(default_branch(&refs[..1], None), Some("refs/heads/develop"));
+        assert_eq!(default_branch(&[], Some("refs/heads/develop")), None);
+    }
+
+    #[tokio::test]
+    async fn clones_check_out_the_configured_default_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        git(&source, &["init", "--quiet"]);
+        git(&source, &["commit", "--quiet", "--allow-empty", "-m", "first"]);
+        git(&source, &["branch", "-M", "main"]);
+        git(&source, &["branch", "develop"]);
+
+        let bare = dir.path().join("bare.git");
+        git(dir.path(), &["clone", "--quiet", "--bare", source.to_str().unwrap(), bare.to_str().unwrap()]);
+        let refs = [branch("refs/heads/develop"), branch("refs/heads/main")];
+
+        write_head(&bare, &refs, Some("refs/heads/develop")).await.unwrap();
+        assert_eq!(cloned_head(&bare, &dir.path().join("configured")), "refs/heads/develop");
+
+        write_head(&bare, &refs, None).await.unwrap();
+        assert_eq!(cloned_head(&bare, &dir.path().join("fallback")), "refs/heads/main");
+    }
+
+    /// Needs git 2.31 or later on both ends, which tells clones of an empty
+    /// repository about its unborn `HEAD`.
+    #[tokio::test]
+    async fn clones_of_an_empty_repository_start_on_the_configured_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let bare = dir.path().join("empty.git");
+        git(dir.path(), &["init", "--quiet", "--bare", bare.to_str().unwrap()]);
+
+        write_head(&bare, &[], Some("refs/heads/trunk")).await.unwrap();
+        assert_eq!(cloned_head(&bare, &dir.path().join("clone")), "refs/heads/trunk");
+    }
+}
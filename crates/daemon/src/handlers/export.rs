@@ -0,0 +1,142 @@
+use axum::{extract::{Path, State}, http::{header, HeaderMap, HeaderValue}, response::IntoResponse};
+use anyhow::{anyhow, bail, Result};
+use tracing::{debug, error, info};
+
+use crate::{auth::ensure_read_access, error::ApiError, git, extract::RepoPath, handlers::{default_branch, get_object_path}, object_store::ObjectStore, state::ContractState, validation::validate_repo_name};
+
+/// Missing hashes listed in the error of an incomplete export; the rest are counted.
+const MISSING_LISTED: usize = 50;
+
+pub async fn export_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let repo = path.name();
+    info!("Export requested for repo: {}", repo);
+    match handle_export_repo(contract_state, repo.clone(), &headers).await {
+        Ok(bundle) => {
+            info!("Exported {} as a {} byte bundle", repo, bundle.len());
+
+            let filename = format!("attachment; filename=\"{}.bundle\"", repo.replace('/', "-"));
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-git-bundle"));
+            if let Ok(value) = HeaderValue::from_str(&filename) {
+                headers.insert(header::CONTENT_DISPOSITION, value);
+            }
+
+            (headers, bundle).into_response()
+        },
+        Err(e) => {
+            error!("Error in export_repo: {:?}", e);
+            ApiError::from(e).into_response()
+        }
+    }
+}
+
+/// Rebuilds the repository from chain and IPFS and packs every active ref
+/// into a `git bundle`. Fails, naming the missing objects, rather than
+/// returning a bundle that would not clone.
+async fn handle_export_repo(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<Vec<u8>> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let refs = contract_state.ref_cache().active_refs(&repo, &contract).await?;
+    if refs.is_empty() {
+        bail!("Repository has no refs");
+    }
+    let config = contract.get_repo_config().await?;
+    let head = default_branch(&refs, config.default_branch.as_deref());
+
+    let temp_dir = git::tempdir()?;
+    let temp_path = temp_dir.path();
+    debug!("Created temporary directory: {:?}", temp_path);
+
+    run_git(temp_path, &["init", "--bare"]).await?;
+
+    let mut tips = Vec::new();
+    for ref_data in &refs {
+        let sha1 = String::from_utf8(ref_data.data.clone())?.trim().to_string();
+        let ref_file_path = temp_path.join(&ref_data.name);
+        if let Some(parent) = ref_file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&ref_file_path, format!("{}\n", sha1)).await?;
+        tips.push(sha1);
+    }
+
+    if let Some(head) = head {
+        tokio::fs::write(temp_path.join("HEAD"), format!("ref: {}\n", head)).await?;
+    }
+
+    let objects = contract_state.views().objects(&contract).await?;
+    let mut store = ObjectStore::new(temp_path, objects)?;
+    store.download_all().await?;
+    info!("Downloaded {} objects from IPFS", store.download_count());
+
+    let mut missing: Vec<String> = tips
+        .into_iter()
+        .filter(|tip| !get_object_path(temp_path, tip).exists())
+        .collect();
+    // rev-list cannot start a walk at a missing tip, so only look deeper
+    // once every tip is there.
+    if missing.is_empty() {
+        missing = missing_objects(temp_path).await?;
+    }
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        let mut listed = missing.iter().take(MISSING_LISTED).cloned().collect::<Vec<_>>().join(", ");
+        if missing.len() > MISSING_LISTED {
+            listed.push_str(&format!(" and {} more", missing.len() - MISSING_LISTED));
+        }
+        bail!("Repository is incomplete, {} objects are not stored on-chain: {}", missing.len(), listed);
+    }
+
+    let bundle_path = temp_path.join("export.bundle");
+    let bundle_path_str = bundle_path.to_string_lossy();
+    let mut args = vec!["bundle", "create", &bundle_path_str, "--all"];
+    if head.is_some() {
+        args.push("HEAD");
+    }
+    run_git(temp_path, &args).await?;
+    run_git(temp_path, &["bundle", "verify", &bundle_path_str]).await?;
+
+    Ok(tokio::fs::read(&bundle_path).await?)
+}
+
+/// Objects some ref reaches that are not in the repository.
+async fn missing_objects(repo_path: &std::path::Path) -> Result<Vec<String>> {
+    let mut cmd = git::command();
+    cmd.args(["rev-list", "--objects", "--all", "--missing=print"]).current_dir(repo_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        bail!("git rev-list failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix('?'))
+        .map(|hash| hash.to_string())
+        .collect())
+}
+
+async fn run_git(repo_path: &std::path::Path, args: &[&str]) -> Result<()> {
+    let mut cmd = git::command();
+    cmd.args(args).current_dir(repo_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use anyhow::{anyhow, Result};
+use onchain::contract_interaction::{ContractInteraction, Costs, Object, Ref};
+use serde::Serialize;
+use tracing::info;
+
+use crate::{audit::{AuditAction, AuditEntry}, auth::{authenticate, ensure_read_access}, error::ApiError, extract::ForkPath, handlers::{deploy_repo, CreateRepoResponse}, ref_cache, state::ContractState, validation::validate_repo_name};
+
+/// Objects and refs copied per transaction, to stay well under the block gas limit.
+const COPY_BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Serialize)]
+pub struct ForkResponse {
+    #[serde(flatten)]
+    pub created: CreateRepoResponse,
+    pub source: String,
+    pub objects_copied: usize,
+    pub refs_copied: usize,
+    /// Transactions that copied the objects, refs and config.
+    pub tx_hashes: Vec<String>,
+}
+
+pub async fn fork_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<ForkPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_fork_repo(contract_state, path.name(), path.new_name, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Deploys a new contract and copies the source's object records, active
+/// refs and config into it. The objects' IPFS CIDs are reused as they are,
+/// so forking never touches IPFS. The daemon's signer pays for the copy, so
+/// the requester must be authenticated and the copy is charged to the
+/// source's rate limits like a push of everything it holds.
+async fn handle_fork_repo(
+    contract_state: ContractState,
+    repo: String,
+    new_name: String,
+    headers: &HeaderMap,
+) -> Result<ForkResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let new_name = validate_repo_name(&new_name)?;
+
+    let source = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow!("Repository not found"))?;
+
+    let requester = authenticate(headers)?;
+    ensure_read_access(&source, headers).await?;
+
+    if contract_state.get_contract(&new_name).await.is_some() {
+        return Err(anyhow!("Repository already exists"));
+    }
+
+    // Read everything before deploying, so a failing source leaves no
+    // half-made fork behind.
+    let objects = contract_state.views().objects(&source).await?;
+    let refs = ref_cache::collapse(&contract_state.views().refs(&source).await?);
+    let config = source.get_config().await?;
+
+    contract_state.rate_limiter().check_push(&repo).await?;
+    contract_state.rate_limiter().check_objects(&repo, objects.len()).await?;
+
+    let slot = contract_state.creation_slot(&new_name).await;
+    let result = slot
+        .get_or_try_init(|| deploy_repo(&contract_state, &new_name, Some(requester), None))
+        .await
+        .cloned();
+    contract_state.finish_creation(&new_name, &slot).await;
+    let fork = result?;
+    info!("Deployed fork {} of {} at {}", new_name, repo, fork.address());
+
+    let (tx_hashes, costs) = copy_into(&fork, &objects, &refs, config).await?;
+    info!("Copied {} objects and {} refs from {} to {}", objects.len(), refs.len(), repo, new_name);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", requester)),
+        target: Some(repo.clone()),
+        tx_hash: tx_hashes.last().cloned(),
+        costs: Some(costs),
+        ..AuditEntry::new(AuditAction::Fork, &new_name)
+    }).await;
+
+    Ok(ForkResponse {
+        created: CreateRepoResponse::new(new_name, &fork),
+        source: repo,
+        objects_copied: objects.len(),
+        refs_copied: refs.len(),
+        tx_hashes,
+    })
+}
+
+async fn copy_into(
+    fork: &ContractInteraction,
+    objects: &[Object],
+    refs: &[Ref],
+    config: Vec<u8>,
+) -> Result<(Vec<String>, Costs)> {
+    let mut tx_hashes = Vec::new();
+    let mut costs = Costs::default();
+
+    for batch in objects.chunks(COPY_BATCH_SIZE) {
+        let hashes = batch.iter().map(|object| object.hash.clone()).collect();
+        let ipfs_urls = batch.iter().map(|object| object.ipfs_url.clone()).collect();
+        let confirmed = fork.add_objects(hashes, ipfs_urls).await?;
+        tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+        costs.add(confirmed.costs);
+    }
+
+    // Refs go in after the objects they point to.
+    for batch in refs.chunks(COPY_BATCH_SIZE) {
+        let names = batch.iter().map(|r| r.name.clone()).collect();
+        let data = batch.iter().map(|r| r.data.clone()).collect();
+        let confirmed = fork.add_refs(names, data).await?;
+        tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+        costs.add(confirmed.costs);
+    }
+
+    if !config.is_empty() {
+        fork.update_config(config).await?;
+    }
+
+    Ok((tx_hashes, costs))
+}
@@ -0,0 +1,470 @@
+use axum::{body::Body, extract::{Path, State}, http::{header, HeaderMap, HeaderValue}, response::IntoResponse};
+use anyhow::Result;
+use futures_util::stream;
+use onchain::{contract_interaction::{ContractInteraction, Object, Ref}, ipfs::{self, IpfsBackend}, storage};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn, Instrument, Span};
+
+use crate::{auth::ensure_read_access, error::{ApiError, NotFound}, git, extract::RepoPath, handlers::is_object_id, object_store::{header_values, inflate, parse_object, tree_entries}, repo_views::RepoViews, state::ContractState, validation::validate_repo_name};
+
+/// Objects downloaded and checked at once.
+const FSCK_CONCURRENCY: usize = 16;
+/// How long an availability check waits for the storage backend.
+const AVAILABILITY_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How far the check goes with each recorded object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CheckDepth {
+    /// Only confirm each CID is retrievable, without downloading it. Covers
+    /// packfiles too, and follows no links.
+    Availability,
+    /// Download each object, check it hashes to its name and follow its links.
+    Content,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// The object's CID could not be fetched from IPFS.
+    MissingObject,
+    /// The content behind the CID hashes to something else.
+    HashMismatch,
+    /// The content is not a loose git object.
+    Undecodable,
+    /// An active ref names an object that is not recorded on-chain.
+    DanglingRef,
+    /// An active ref names an object that is recorded but not retrievable.
+    UnreachableRef,
+    /// A commit, tree or tag points at an object that is not recorded
+    /// on-chain. An error when an active ref reaches it, unless a recorded
+    /// packfile might hold it.
+    BrokenLink,
+}
+
+/// Problems with objects that may sit inside a recorded packfile, which
+/// fsck does not unpack, are only warnings.
+fn unrecorded_severity(has_packs: bool, reachable: bool) -> Severity {
+    if reachable && !has_packs { Severity::Error } else { Severity::Warning }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsckProblem {
+    pub severity: Severity,
+    pub kind: ProblemKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub ref_name: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FsckSummary {
+    pub repo: String,
+    pub objects_checked: usize,
+    pub refs_checked: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub missing_objects: usize,
+    pub hash_mismatches: usize,
+    pub undecodable: usize,
+    pub dangling_refs: usize,
+    pub unreachable_refs: usize,
+    pub broken_links: usize,
+}
+
+impl FsckSummary {
+    fn count(&mut self, problem: &FsckProblem) {
+        match problem.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+        }
+        match problem.kind {
+            ProblemKind::MissingObject => self.missing_objects += 1,
+            ProblemKind::HashMismatch => self.hash_mismatches += 1,
+            ProblemKind::Undecodable => self.undecodable += 1,
+            ProblemKind::DanglingRef => self.dangling_refs += 1,
+            ProblemKind::UnreachableRef => self.unreachable_refs += 1,
+            ProblemKind::BrokenLink => self.broken_links += 1,
+        }
+    }
+}
+
+/// One line of the NDJSON response: each problem as it is found, then the
+/// summary, or an error if the check could not finish.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FsckEvent {
+    Problem(FsckProblem),
+    Summary(FsckSummary),
+    Error { message: String },
+}
+
+pub async fn fsck_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let repo = path.name();
+    info!("Fsck requested for repo: {}", repo);
+
+    // Lookup and access problems still get a plain HTTP error; only the
+    // check itself streams.
+    let (repo, contract) = match open_repo(&contract_state, &repo, &headers).await {
+        Ok(opened) => opened,
+        Err(e) => return ApiError::from(e).into_response(),
+    };
+
+    let views = contract_state.views().clone();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        if let Err(e) = run_fsck(&repo, &contract, &views, CheckDepth::Content, &sender).await {
+            error!("Error in fsck of {}: {:?}", repo, e);
+            let _ = sender.send(FsckEvent::Error { message: format!("{:#}", e) });
+        }
+    }.instrument(Span::current()));
+
+    let body = Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        let mut line = serde_json::to_vec(&event).unwrap_or_default();
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), receiver))
+    }));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    (headers, body).into_response()
+}
+
+/// Looks up a repository the caller may read.
+pub(crate) async fn open_repo(contract_state: &ContractState, repo: &str, headers: &HeaderMap) -> Result<(String, ContractInteraction)> {
+    let repo = validate_repo_name(repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    ensure_read_access(&contract, headers).await?;
+    Ok((repo, contract))
+}
+
+/// What checking one object found: the problem, if any, and the objects it
+/// links to.
+struct ObjectCheck {
+    hash: String,
+    problem: Option<FsckProblem>,
+    links: Vec<String>,
+}
+
+/// Where object checks get what they need: a client for availability
+/// checks, or a directory to download objects into.
+enum Checker {
+    Availability { backend: IpfsBackend, client: reqwest::Client },
+    Content { dir: TempDir },
+}
+
+impl Checker {
+    fn new(depth: CheckDepth) -> Result<Self> {
+        Ok(match depth {
+            CheckDepth::Availability => Checker::Availability {
+                backend: IpfsBackend::from_env()?,
+                client: reqwest::Client::builder().timeout(AVAILABILITY_TIMEOUT).build()?,
+            },
+            CheckDepth::Content => Checker::Content { dir: git::tempdir()? },
+        })
+    }
+
+    async fn check(&self, object: Object) -> ObjectCheck {
+        match self {
+            Checker::Availability { backend, client } => check_available(object, backend, client).await,
+            Checker::Content { dir } => check_object(object, dir.path()).await,
+        }
+    }
+}
+
+/// Checks every object and active ref of `repo`, sending each problem as it
+/// is found and the summary at the end.
+pub(crate) async fn run_fsck(
+    repo: &str,
+    contract: &ContractInteraction,
+    views: &RepoViews,
+    depth: CheckDepth,
+    sender: &mpsc::UnboundedSender<FsckEvent>,
+) -> Result<()> {
+    let mut summary = FsckSummary { repo: repo.to_string(), ..FsckSummary::default() };
+    let report = |problem: FsckProblem, summary: &mut FsckSummary| {
+        summary.count(&problem);
+        let _ = sender.send(FsckEvent::Problem(problem));
+    };
+
+    // Packfiles and other non-object entries have no git hash to check.
+    let (objects, other): (Vec<Object>, Vec<Object>) = views.objects(contract).await?
+        .into_iter()
+        .partition(|object| is_object_id(&object.hash));
+    let has_packs = !other.is_empty();
+    let refs: Vec<Ref> = views.refs(contract).await?.into_iter().filter(|r| r.is_active).collect();
+    let stored: HashSet<String> = objects.iter().map(|object| object.hash.clone()).collect();
+
+    let mut checked = objects;
+    if depth == CheckDepth::Availability {
+        checked.extend(other);
+    }
+    summary.objects_checked = checked.len();
+    summary.refs_checked = refs.len();
+    info!("Checking {} objects and {} refs of {}", checked.len(), refs.len(), repo);
+
+    let checker = Arc::new(Checker::new(depth)?);
+    let semaphore = Arc::new(Semaphore::new(FSCK_CONCURRENCY));
+    let mut checks = JoinSet::new();
+    for object in checked {
+        let checker = Arc::clone(&checker);
+        let semaphore = Arc::clone(&semaphore);
+        checks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            checker.check(object).await
+        }.instrument(Span::current()));
+    }
+
+    let mut links: HashMap<String, Vec<String>> = HashMap::new();
+    let mut missing = HashSet::new();
+    while let Some(joined) = checks.join_next().await {
+        let check = joined?;
+        if let Some(problem) = check.problem {
+            warn!("fsck {}: {}", repo, problem.detail);
+            if problem.kind == ProblemKind::MissingObject {
+                missing.insert(check.hash.clone());
+            }
+            report(problem, &mut summary);
+        }
+        links.insert(check.hash, check.links);
+    }
+
+    let (tips, problems) = ref_problems(&refs, &stored, &missing, has_packs);
+    for problem in problems {
+        report(problem, &mut summary);
+    }
+
+    // Missing links reachable from a ref break clones; others only affect
+    // objects nothing uses any more.
+    let reachable = walk(&tips, &links);
+    let mut broken: Vec<(&String, &String)> = links
+        .iter()
+        .flat_map(|(from, targets)| targets.iter().map(move |target| (from, target)))
+        .filter(|(_, target)| !stored.contains(*target))
+        .collect();
+    broken.sort();
+    for (from, target) in broken {
+        let severity = unrecorded_severity(has_packs, reachable.contains(from));
+        report(FsckProblem {
+            severity,
+            kind: ProblemKind::BrokenLink,
+            object: Some(from.clone()),
+            cid: None,
+            ref_name: None,
+            detail: format!("{} links to {}, which is not recorded on-chain", from, target),
+        }, &mut summary);
+    }
+
+    info!("Fsck of {} found {} errors and {} warnings", repo, summary.errors, summary.warnings);
+    let _ = sender.send(FsckEvent::Summary(summary));
+    Ok(())
+}
+
+/// The targets of `refs` the walk starts from, and the refs whose target is
+/// not recorded on-chain or, per `missing`, not retrievable.
+fn ref_problems(refs: &[Ref], stored: &HashSet<String>, missing: &HashSet<String>, has_packs: bool) -> (Vec<String>, Vec<FsckProblem>) {
+    let mut tips = Vec::new();
+    let mut problems = Vec::new();
+    for r in refs {
+        let target = String::from_utf8_lossy(&r.data).trim().to_string();
+        if !stored.contains(&target) {
+            problems.push(FsckProblem {
+                severity: unrecorded_severity(has_packs, true),
+                kind: ProblemKind::DanglingRef,
+                object: Some(target.clone()),
+                cid: None,
+                ref_name: Some(r.name.clone()),
+                detail: format!("{} points at {}, which is not recorded on-chain", r.name, target),
+            });
+            continue;
+        }
+
+        if missing.contains(&target) {
+            problems.push(FsckProblem {
+                severity: Severity::Error,
+                kind: ProblemKind::UnreachableRef,
+                object: Some(target.clone()),
+                cid: None,
+                ref_name: Some(r.name.clone()),
+                detail: format!("{} points at {}, which is not retrievable", r.name, target),
+            });
+        }
+        tips.push(target);
+    }
+    (tips, problems)
+}
+
+/// Confirms `object`'s CID is retrievable without downloading it.
+async fn check_available(object: Object, backend: &IpfsBackend, client: &reqwest::Client) -> ObjectCheck {
+    let hash = object.hash;
+    let cid = String::from_utf8_lossy(&object.ipfs_url).to_string();
+
+    // Objects kept outside IPFS have no cheaper check than a read.
+    let result = if storage::is_ipfs(&cid) {
+        ipfs::check_available(backend, client, &cid).await.map_err(anyhow::Error::from)
+    } else {
+        match storage::for_url(&cid) {
+            Ok(store) => store.get(&cid).await.map(|_| ()),
+            Err(e) => Err(e),
+        }
+    };
+
+    let problem = result.err().map(|e| object_problem(
+        ProblemKind::MissingObject,
+        &hash,
+        &cid,
+        format!("{} (CID {}) is not retrievable: {:#}", hash, cid, e),
+    ));
+    ObjectCheck { hash, problem, links: Vec::new() }
+}
+
+fn object_problem(kind: ProblemKind, hash: &str, cid: &str, detail: String) -> FsckProblem {
+    FsckProblem {
+        severity: Severity::Error,
+        kind,
+        object: Some(hash.to_string()),
+        cid: Some(cid.to_string()),
+        ref_name: None,
+        detail,
+    }
+}
+
+/// Downloads `object`, checks its content hashes to its name and collects
+/// the objects it links to.
+async fn check_object(object: Object, dir: &std::path::Path) -> ObjectCheck {
+    let hash = object.hash;
+    let cid = String::from_utf8_lossy(&object.ipfs_url).to_string();
+    let problem = |kind, detail: String| Some(object_problem(kind, &hash, &cid, detail));
+
+    let path = dir.join(&hash);
+    if let Err(e) = storage::download(&cid, &path).await {
+        let problem = problem(ProblemKind::MissingObject, format!("{} (CID {}) is not retrievable: {:#}", hash, cid, e));
+        return ObjectCheck { hash, problem, links: Vec::new() };
+    }
+
+    let decoded = tokio::fs::read(&path).await
+        .map_err(anyhow::Error::from)
+        .and_then(|compressed| inflate(&compressed));
+    let _ = tokio::fs::remove_file(&path).await;
+    let raw = match decoded {
+        Ok(raw) => raw,
+        Err(e) => {
+            let problem = problem(ProblemKind::Undecodable, format!("{} (CID {}) is not a git object: {}", hash, cid, e));
+            return ObjectCheck { hash, problem, links: Vec::new() };
+        }
+    };
+
+    let actual: String = Sha1::digest(&raw).iter().map(|b| format!("{:02x}", b)).collect();
+    if actual != hash {
+        let problem = problem(ProblemKind::HashMismatch, format!("{} (CID {}) hashes to {}", hash, cid, actual));
+        return ObjectCheck { hash, problem, links: Vec::new() };
+    }
+
+    match object_links(&raw) {
+        Ok(links) => ObjectCheck { hash, problem: None, links },
+        Err(e) => {
+            let problem = problem(ProblemKind::Undecodable, format!("{} (CID {}) cannot be parsed: {}", hash, cid, e));
+            ObjectCheck { hash, problem, links: Vec::new() }
+        }
+    }
+}
+
+/// Objects a commit, tree or tag points at; submodule commits are skipped
+/// since they live in another repository.
+fn object_links(raw: &[u8]) -> Result<Vec<String>> {
+    let object = parse_object(raw)?;
+    Ok(match object.kind.as_str() {
+        "commit" => header_values(&object.data, "tree")
+            .into_iter()
+            .chain(header_values(&object.data, "parent"))
+            .collect(),
+        "tag" => header_values(&object.data, "object"),
+        "tree" => tree_entries(&object.data)?
+            .into_iter()
+            .filter(|(mode, _)| mode != "160000")
+            .map(|(_, hash)| hash)
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+fn walk(tips: &[String], links: &HashMap<String, Vec<String>>) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut queue: VecDeque<&String> = tips.iter().collect();
+
+    while let Some(hash) = queue.pop_front() {
+        if !reachable.insert(hash.clone()) {
+            continue;
+        }
+        if let Some(targets) = links.get(hash) {
+            queue.extend(targets);
+        }
+    }
+
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethcontract::Address;
+
+    fn active(name: &str, target: &str) -> Ref {
+        Ref { name: name.to_string(), data: target.as_bytes().to_vec(), is_active: true, pusher: Address::zero() }
+    }
+
+    fn hashes(hashes: &[&str]) -> HashSet<String> {
+        hashes.iter().map(|hash| hash.to_string()).collect()
+    }
+
+    #[test]
+    fn refs_at_missing_objects_are_unreachable() {
+        let refs = [active("refs/heads/main", "aa"), active("refs/heads/dev", "bb"), active("refs/tags/v1", "cc")];
+        let (tips, problems) = ref_problems(&refs, &hashes(&["aa", "bb"]), &hashes(&["bb"]), false);
+
+        assert_eq!(tips, vec!["aa", "bb"]);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].kind, ProblemKind::UnreachableRef);
+        assert_eq!(problems[0].severity, Severity::Error);
+        assert_eq!(problems[0].ref_name.as_deref(), Some("refs/heads/dev"));
+        assert_eq!(problems[1].kind, ProblemKind::DanglingRef);
+        assert_eq!(problems[1].severity, Severity::Error);
+        assert_eq!(problems[1].object.as_deref(), Some("cc"));
+    }
+
+    #[test]
+    fn dangling_refs_are_warnings_when_a_pack_might_hold_the_target() {
+        let refs = [active("refs/heads/main", "aa")];
+        let (tips, problems) = ref_problems(&refs, &HashSet::new(), &HashSet::new(), true);
+
+        assert!(tips.is_empty());
+        assert_eq!(problems[0].kind, ProblemKind::DanglingRef);
+        assert_eq!(problems[0].severity, Severity::Warning);
+    }
+}
@@ -0,0 +1,167 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use anyhow::{anyhow, bail, Result};
+use onchain::contract_interaction::Ref;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{debug, info};
+
+use crate::{audit::{AuditAction, AuditEntry}, auth::{ensure_admin, ensure_read_access}, error::ApiError, git, extract::RepoPath, object_store::ObjectStore, state::ContractState, validation::validate_repo_name};
+
+/// Objects deactivated per transaction, to stay well under the block gas limit.
+const DEACTIVATE_BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct GcQuery {
+    /// Only report; deactivating needs an explicit `dry_run=false`.
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    pub repo: String,
+    pub dry_run: bool,
+    pub objects_checked: usize,
+    pub reachable: usize,
+    /// Stored objects no active ref reaches.
+    pub unreferenced: Vec<String>,
+    pub tx_hashes: Vec<String>,
+}
+
+pub async fn gc_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<GcQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_gc_repo(contract_state, path.name(), query.dry_run, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_gc_repo(
+    contract_state: ContractState,
+    repo: String,
+    dry_run: bool,
+    headers: &HeaderMap,
+) -> Result<GcReport> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow!("Repository not found"))?;
+
+    let admin = if dry_run {
+        ensure_read_access(&contract, headers).await?;
+        None
+    } else {
+        Some(ensure_admin(&contract, headers).await?)
+    };
+
+    let temp_dir = git::tempdir()?;
+    let temp_path = temp_dir.path();
+
+    let mut cmd = git::command();
+    cmd.args(["init", "--bare"]).current_dir(temp_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Failed to initialize git repo: {}", stderr));
+    }
+
+    let refs = active_refs(&contract_state.views().refs(&contract).await?)?;
+    for (name, target) in &refs {
+        let ref_path = temp_path.join(name);
+        if let Some(parent) = ref_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&ref_path, format!("{}\n", target)).await?;
+    }
+
+    let objects = contract_state.views().objects(&contract).await?;
+    let stored: Vec<String> = objects.iter().map(|object| object.hash.clone()).collect();
+    info!("Computing reachability of {} objects from {} refs of {}", stored.len(), refs.len(), repo);
+
+    let mut store = ObjectStore::new(temp_path, objects)?;
+    store.download_all().await?;
+
+    // A missing object makes rev-list fail instead of silently shrinking the
+    // reachable set, so nothing reachable is ever reported.
+    let mut cmd = git::command();
+    cmd.args(["rev-list", "--objects", "--all"]).current_dir(temp_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to compute reachable objects: {}", stderr.trim());
+    }
+
+    let reachable: HashSet<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|hash| hash.to_string())
+        .collect();
+
+    // Only loose objects are candidates; anything else stored on-chain (such
+    // as packfiles) is never reported.
+    let unreferenced: Vec<String> = stored
+        .iter()
+        .filter(|hash| is_object_id(hash) && !reachable.contains(hash.as_str()))
+        .cloned()
+        .collect();
+    info!("{} of {} objects in {} are unreferenced", unreferenced.len(), stored.len(), repo);
+
+    let mut tx_hashes = Vec::new();
+    if let Some(admin) = admin.filter(|_| !unreferenced.is_empty()) {
+        // A push that landed while we walked could have made some of these
+        // reachable again.
+        if active_refs(&contract_state.views().refs(&contract).await?)? != refs {
+            bail!("Refs changed during garbage collection; run it again");
+        }
+
+        for batch in unreferenced.chunks(DEACTIVATE_BATCH_SIZE) {
+            let tx_hash = contract.deactivate_objects(batch.to_vec()).await?;
+            debug!("Deactivated {} objects in {:?}", batch.len(), tx_hash);
+            tx_hashes.push(format!("{:?}", tx_hash));
+        }
+
+        for tx_hash in &tx_hashes {
+            contract_state.audit().record(AuditEntry {
+                actor: Some(format!("{:?}", admin)),
+                tx_hash: Some(tx_hash.clone()),
+                ..AuditEntry::new(AuditAction::Gc, &repo)
+            }).await;
+        }
+    }
+
+    Ok(GcReport {
+        repo,
+        dry_run,
+        objects_checked: stored.len(),
+        reachable: reachable.len(),
+        unreferenced,
+        tx_hashes,
+    })
+}
+
+/// Active refs as sorted `(name, target)` pairs.
+fn active_refs(refs: &[Ref]) -> Result<Vec<(String, String)>> {
+    let mut active = Vec::new();
+    for r in refs.iter().filter(|r| r.is_active) {
+        let target = String::from_utf8(r.data.clone())?.trim().to_string();
+        if !is_object_id(&target) || !r.name.starts_with("refs/") {
+            bail!("Malformed ref {}: {}", r.name, target);
+        }
+        active.push((r.name.clone(), target));
+    }
+    active.sort();
+    Ok(active)
+}
+
+pub(crate) fn is_object_id(hash: &str) -> bool {
+    hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
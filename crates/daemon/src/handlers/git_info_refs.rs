@@ -1,11 +1,14 @@
-use axum::{extract::{Path, State, Query}, response::IntoResponse};
+use axum::{extract::{Path, State, Query}, http::HeaderMap, response::IntoResponse};
 use anyhow::{anyhow, bail, Result};
 use tracing::{debug, info, warn};
 use serde::Deserialize;
-use tokio::process::Command;
-use tempfile;
+use std::collections::HashMap;
 use std::process::Stdio;
-use crate::state::ContractState;
+use onchain::{contract_interaction::{ContractInteraction, Ref}, storage};
+use crate::{auth::{authenticate_optional, ensure_pusher, ensure_read_access}, config::DaemonConfig, protocol::{self, FLUSH_PKT}, error::{ApiError, NotFound}, extract::RepoPath, git, handlers::{default_branch, write_head}, object_store::{header_values, inflate, parse_object}, ref_cache, state::ContractState, validation::validate_repo_name};
+
+/// Tags pointing at tags are followed this far.
+const MAX_TAG_CHAIN: usize = 8;
 
 #[derive(Debug, Deserialize)]
 pub struct InfoRefsQuery {
@@ -15,19 +18,22 @@ pub struct InfoRefsQuery {
 pub async fn info_refs(
     Query(query): Query<InfoRefsQuery>,
     State(contract_state): State<ContractState>,
-    Path(repo): Path<String>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
+    let repo = path.name();
     let service = query.service.unwrap_or_default();
     info!("Git info_refs called for repo: {} with service: {}", repo, service);
 
-    match handle_info_refs(contract_state, repo, &service).await {
+    match handle_info_refs(contract_state, repo, &service, &headers).await {
         Ok(response) => {
             let content_type = if service == "git-upload-pack" {
                 "application/x-git-upload-pack-advertisement"
             } else if service == "git-receive-pack" {
                 "application/x-git-receive-pack-advertisement"
             } else {
-                "text/plain"
+                // Dumb protocol: the plain `info/refs` listing.
+                "text/plain; charset=utf-8"
             };
 
             let mut headers = axum::http::HeaderMap::new();
@@ -39,7 +45,7 @@ pub async fn info_refs(
         },
         Err(e) => {
             warn!("Error in info_refs: {:?}", e);
-            (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            ApiError::from(e).into_response()
         },
     }
 }
@@ -48,43 +54,52 @@ async fn handle_info_refs(
     contract_state: ContractState,
     repo: String,
     service: &str,
+    headers: &HeaderMap,
 ) -> Result<Vec<u8>> {
+    let repo = validate_repo_name(&repo)?;
+
     // First, verify that the repository exists
     info!("Looking up contract for repo: {}", repo);
     let contract = contract_state.get_contract(&repo).await
-        .ok_or_else(|| anyhow!("Repository not found"))?;
-
-    let temp_dir = tempfile::tempdir()?;
-    let temp_path = temp_dir.path();
-
-    debug!("Created temporary directory: {:?}", temp_path);
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    // Pushers and admins may read any repository, so the push advertisement
+    // only needs the push check, which also makes git send credentials before
+    // it uploads the pack.
+    if service == "git-receive-pack" {
+        ensure_pusher(&contract, &repo, authenticate_optional(headers)?).await?;
+    } else {
+        ensure_read_access(&contract, headers).await?;
+    }
 
-    let output = Command::new("git")
-        .args(["init", "--bare"])
-        .current_dir(temp_path)
-        .output()
-        .await?;
+    // A v2 capability advertisement lists no refs, but must match the git
+    // that answers the fetch, so git still writes it.
+    let v2 = service == "git-upload-pack" && protocol::is_v2(protocol::git_protocol(headers).as_deref());
+    if !v2 && !DaemonConfig::git_advertise() {
+        return advertise_from_chain(&contract_state, &repo, &contract, service).await;
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to initialize git repo: {}", stderr));
+    // A push must see the refs as they are on chain right now.
+    if service != "git-receive-pack" {
+        if let Some(snapshot) = contract_state.snapshots().get(&repo, &contract).await {
+            return advertise(snapshot.path(), service, headers, snapshot.refs()).await;
+        }
     }
 
+    let workspace = contract_state.workspaces().acquire(&repo, &contract).await?;
+    let temp_path = workspace.path();
+
     info!("Fetching refs from blockchain for repo: {}", repo);
-    let refs = contract.get_refs().await?;
+    let refs = if service == "git-receive-pack" {
+        ref_cache::collapse(&contract_state.views().refs(&contract).await?)
+    } else {
+        contract_state.ref_cache().active_refs(&repo, &contract).await?
+    };
 
     info!("Found {} refs for repo {}", refs.len(), repo);
     debug!("Setting up {} refs in the repository", refs.len());
 
-    let refs_dir = temp_path.join("refs");
-    let heads_dir = refs_dir.join("heads");
-    tokio::fs::create_dir_all(&heads_dir).await?;
-
-    let tags_dir = refs_dir.join("tags");
-    tokio::fs::create_dir_all(&tags_dir).await?;
-
-    let objects_dir = temp_path.join("objects");
-    tokio::fs::create_dir_all(&objects_dir).await?;
+    workspace.clear_refs().await?;
 
     for ref_data in &refs {
         if ref_data.is_active {
@@ -111,33 +126,154 @@ async fn handle_info_refs(
         }
     }
 
-    let update_server_info = Command::new("git")
-        .args(["update-server-info"])
-        .current_dir(temp_path)
-        .output()
-        .await?;
+    // With no refs git advertises a bare `capabilities^{}` line, which
+    // clients take as an empty repository.
+    let config = contract.get_repo_config().await?;
+    write_head(temp_path, &refs, config.default_branch.as_deref()).await?;
+
+    let mut cmd = git::command();
+    cmd.args(["update-server-info"]).current_dir(temp_path);
+    let update_server_info = git::output(cmd).await?;
 
     if !update_server_info.status.success() {
         let stderr = String::from_utf8_lossy(&update_server_info.stderr);
         warn!("Failed to update server info: {}", stderr);
     }
 
+    let response = advertise(temp_path, service, headers, &refs).await?;
+    contract_state.workspaces().release(workspace).await;
+    Ok(response)
+}
+
+/// The advertisement for `service` built from the chain's refs alone, so
+/// no scratch repository is set up and no object is downloaded except the
+/// annotated tags not peeled before.
+async fn advertise_from_chain(contract_state: &ContractState, repo: &str, contract: &ContractInteraction, service: &str) -> Result<Vec<u8>> {
+    // A push must see the refs as they are on chain right now.
+    let refs = if service == "git-receive-pack" {
+        ref_cache::collapse(&contract_state.views().refs(contract).await?)
+    } else {
+        contract_state.ref_cache().active_refs(repo, contract).await?
+    };
+
+    let mut lines = Vec::with_capacity(refs.len());
+    for r in refs.iter().filter(|r| r.is_active) {
+        let oid = String::from_utf8_lossy(&r.data).trim().to_string();
+        if oid.len() != 40 || !r.name.starts_with("refs/") {
+            bail!("Malformed ref {}: {}", r.name, oid);
+        }
+        lines.push((r.name.clone(), oid));
+    }
+    lines.sort();
+    let peeled = peel_tags(contract_state, repo, contract, &lines).await;
+
+    let response = match service {
+        "git-upload-pack" | "git-receive-pack" => {
+            let config = contract.get_repo_config().await?;
+            let head = default_branch(&refs, config.default_branch.as_deref());
+
+            let mut response = protocol::encode(format!("# service={}\n", service).as_bytes());
+            response.extend_from_slice(FLUSH_PKT);
+            response.extend_from_slice(&protocol::ref_advertisement(service, &lines, head, &peeled, git::probed_version()));
+            response
+        },
+        "" => dumb_info_refs(&refs, &peeled),
+        _ => bail!("Unknown service: {}", service),
+    };
+    debug!("Built refs advertisement of {} bytes for {} refs of {}", response.len(), lines.len(), repo);
+    Ok(response)
+}
+
+/// What the annotated tags among `refs` point to, keyed by tag object id.
+/// A tag that cannot be read is left unpeeled rather than failing the
+/// advertisement; clients then only miss tag auto-following for it.
+async fn peel_tags(contract_state: &ContractState, repo: &str, contract: &ContractInteraction, refs: &[(String, String)]) -> HashMap<String, String> {
+    let tags: Vec<&str> = refs.iter()
+        .filter(|(name, _)| name.starts_with("refs/tags/"))
+        .map(|(_, oid)| oid.as_str())
+        .collect();
+    let mut peeled = contract_state.ref_cache().peeled(repo).await;
+    let unknown: Vec<&str> = tags.iter().copied().filter(|oid| !peeled.contains_key(*oid)).collect();
+
+    if !unknown.is_empty() {
+        match contract_state.views().objects(contract).await {
+            Ok(objects) => {
+                let urls: HashMap<String, String> = objects.into_iter()
+                    .map(|object| (object.hash, String::from_utf8_lossy(&object.ipfs_url).into_owned()))
+                    .collect();
+                for oid in unknown {
+                    match peel(&urls, oid).await {
+                        Ok(target) => {
+                            peeled.insert(oid.to_string(), target);
+                        },
+                        Err(e) => warn!("Not peeling tag {}: {:#}", oid, e),
+                    }
+                }
+            },
+            Err(e) => warn!("Not peeling tags: {:#}", e),
+        }
+    }
+
+    let result = tags.iter()
+        .filter_map(|oid| Some((oid.to_string(), peeled.get(*oid)?.clone()?)))
+        .collect();
+    contract_state.ref_cache().store_peeled(repo, peeled, &tags).await;
+    result
+}
+
+/// The first object that is not a tag along the chain starting at `oid`,
+/// or `None` when `oid` is not a tag. A tag names its target's type, so
+/// only tags are downloaded.
+async fn peel(urls: &HashMap<String, String>, oid: &str) -> Result<Option<String>> {
+    let mut current = oid.to_string();
+    for _ in 0..MAX_TAG_CHAIN {
+        let url = urls.get(&current).ok_or_else(|| anyhow!("object {} is not recorded on chain", current))?;
+        let object = parse_object(&inflate(&storage::for_url(url)?.get(url).await?)?)?;
+        if object.kind != "tag" {
+            return Ok(None);
+        }
+        let header = |key| header_values(&object.data, key).into_iter().next();
+        let target = header("object").ok_or_else(|| anyhow!("tag {} names no object", current))?;
+        if header("type").as_deref() != Some("tag") {
+            return Ok(Some(target));
+        }
+        current = target;
+    }
+    bail!("tag {} is nested more than {} deep", oid, MAX_TAG_CHAIN)
+}
+
+/// The advertisement for `service` of the bare repository at `repo_path`,
+/// whose active refs are `refs`.
+async fn advertise(repo_path: &std::path::Path, service: &str, headers: &HeaderMap, refs: &[Ref]) -> Result<Vec<u8>> {
     match service {
         "git-upload-pack" | "git-receive-pack" => {
-            // Use Git's built-in command to generate the advertisement
-            let git_command = if service == "git-upload-pack" {
-                "upload-pack"
+            // Only upload-pack speaks protocol v2; receive-pack stays on v0.
+            let git_protocol = protocol::git_protocol(headers).filter(|_| service == "git-upload-pack");
+            let v2 = protocol::is_v2(git_protocol.as_deref());
+
+            // The advertisement comes from the same configuration as the
+            // request it announces, so `filter` is offered only because
+            // upload-pack honors it.
+            let mut cmd = if service == "git-upload-pack" {
+                git::upload_pack()
             } else {
-                "receive-pack"
+                let mut cmd = git::command();
+                // All refs of a push are anchored in one transaction, so
+                // `git push --atomic` can be honored. Push options carry
+                // dgit metadata such as `dgit-signer`.
+                cmd.args(["-c", "receive.advertiseAtomic=true", "-c", "receive.advertisePushOptions=true", "receive-pack"]);
+                cmd
             };
-
-            let mut cmd = Command::new("git");
-            cmd.args([git_command, "--advertise-refs", "."])
-                .current_dir(temp_path)
+            cmd.args(["--stateless-rpc", "--advertise-refs", "."])
+                .current_dir(repo_path)
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped());
+            if let Some(git_protocol) = &git_protocol {
+                debug!("Advertising with GIT_PROTOCOL={}", git_protocol);
+                cmd.env("GIT_PROTOCOL", git_protocol);
+            }
 
-            let output = cmd.output().await?;
+            let output = git::output(cmd).await?;
 
             if !output.status.success() {
                 let stderr = String::from_utf8_lossy(&output.stderr);
@@ -146,22 +282,55 @@ async fn handle_info_refs(
 
             let mut response = Vec::new();
 
-            let service_announcement = format!("# service={}\n", service);
-            let pkt_len = 4 + service_announcement.len();
-            let pkt_header = format!("{:04x}", pkt_len);
-
-            response.extend_from_slice(pkt_header.as_bytes());
-            response.extend_from_slice(service_announcement.as_bytes());
-
-            response.extend_from_slice(b"0000");
+            // A v2 capability advertisement stands alone, like git-http-backend
+            // sends it; v0 clients expect the service announcement first.
+            if !v2 {
+                let service_announcement = format!("# service={}\n", service);
+                response.extend_from_slice(&protocol::encode(service_announcement.as_bytes()));
+                response.extend_from_slice(FLUSH_PKT);
+            }
             response.extend_from_slice(&output.stdout);
 
             debug!("Generated refs advertisement of size {} bytes", response.len());
 
             Ok(response)
         },
+        // Clients without smart HTTP (or with `http.smartHttp=false`) read
+        // the file `update-server-info` generated, then fetch loose objects.
+        "" => {
+            let info_refs = match tokio::fs::read(repo_path.join("info").join("refs")).await {
+                Ok(contents) => contents,
+                Err(e) => {
+                    warn!("Failed to read generated info/refs, listing refs directly: {}", e);
+                    dumb_info_refs(refs, &HashMap::new())
+                },
+            };
+
+            debug!("Serving dumb info/refs of size {} bytes", info_refs.len());
+            Ok(info_refs)
+        },
         _ => {
             Err(anyhow!("Unknown service: {}", service))
         }
     }
+}
+
+/// `info/refs` in the format `git update-server-info` writes it, with the
+/// targets of the annotated tags in `peeled` on a `^{}` line after each.
+fn dumb_info_refs(refs: &[Ref], peeled: &HashMap<String, String>) -> Vec<u8> {
+    let mut refs: Vec<(&str, String)> = refs
+        .iter()
+        .filter(|r| r.is_active)
+        .map(|r| (r.name.as_str(), String::from_utf8_lossy(&r.data).trim().to_string()))
+        .collect();
+    refs.sort();
+
+    let mut lines = String::new();
+    for (name, oid) in refs {
+        lines.push_str(&format!("{}\t{}\n", oid, name));
+        if let Some(target) = peeled.get(&oid) {
+            lines.push_str(&format!("{}\t{}^{{}}\n", target, name));
+        }
+    }
+    lines.into_bytes()
 }
\ No newline at end of file
@@ -1,73 +1,156 @@
-use axum::{extract::{Path, State}, response::IntoResponse};
+use axum::{body::Body, extract::{Path, State}, http::HeaderMap, response::IntoResponse};
 use anyhow::{anyhow, Result};
-use tokio::process::Command;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use ethcontract::Address;
+use futures_util::stream;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::convert::Infallible;
 use tokio::fs;
-use tracing::{info, error, debug};
-use tempfile::tempdir;
+use tokio::sync::mpsc;
+use tracing::{info, error, debug, warn, Instrument, Span};
 use walkdir::WalkDir;
-use std::process::Stdio;
-use onchain::ipfs;
-use crate::{handlers::get_object_path, state::ContractState};
+use onchain::{contract_interaction::{ContractInteraction, Costs, Ref}, repo_config::{RepoConfig, SigningKeys}, storage};
+use crate::{audit::{AuditAction, AuditEntry}, auth::{authenticate_optional, ensure_pusher}, body::read_git_body, config::DaemonConfig, error::{ApiError, NotFound}, git, extract::RepoPath, handlers::is_object_id, object_store::ObjectStore, protocol::{self, Packet, RefCommand}, rate_limit::RateLimited, sideband::{self, Progress, Sideband}, signing::Keyring, size_limit::SizeLimits, state::ContractState, validation::validate_repo_name, webhooks::{self, PushEvent, RefUpdate, ZERO_ID}, workspaces::Workspace};
+
+/// Push option naming the address a push should be attributed to.
+const SIGNER_OPTION: &str = "dgit-signer";
+
+/// Settings git receive-pack runs with in the scratch repository. Pushed
+/// objects are checked, filtered and stored one by one, so git must unpack
+/// them loose however many there are, and must not pack them up afterwards;
+/// a kept pack would be taken for a single object.
+const RECEIVE_PACK_CONFIG: [&str; 6] = [
+    "-c", "receive.advertisePushOptions=true",
+    "-c", "receive.unpackLimit=2147483647",
+    "-c", "receive.autogc=false",
+];
 
 pub async fn receive_pack(
     State(contract_state): State<ContractState>,
-    Path(repo): Path<String>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
     req_body: axum::body::Body,
 ) -> impl IntoResponse {
+    let repo = path.name();
     info!("Git receive-pack called for repo: {}", repo);
-    match handle_receive_pack(contract_state, repo, req_body).await {
-        Ok(response) => {
-            info!("Successfully processed receive-pack request, response size: {} bytes", response.len());
-
-            let mut headers = axum::http::HeaderMap::new();
-            headers.insert(axum::http::header::CONTENT_TYPE, "application/x-git-receive-pack-result".parse().unwrap());
-            headers.insert(axum::http::header::CACHE_CONTROL, "no-cache".parse().unwrap());
-            headers.insert(axum::http::header::CONNECTION, "keep-alive".parse().unwrap());
 
-            (headers, response).into_response()
-        },
+    let push = match prepare_push(contract_state, repo, &headers, req_body).await {
+        Ok(push) => push,
         Err(e) => {
             error!("Error in receive_pack: {:?}", e);
-            (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            return ApiError::from(e).into_response();
         }
-    }
+    };
+
+    let body = match push.sideband {
+        Some(sideband) => stream_push(push, sideband),
+        None => match finish_push(push, &Progress::disabled()).await {
+            Ok(response) => {
+                info!("Successfully processed receive-pack request, response size: {} bytes", response.len());
+                Body::from(response)
+            },
+            Err(e) => {
+                error!("Error in receive_pack: {:?}", e);
+                return ApiError::from(e).into_response();
+            }
+        },
+    };
+
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert(axum::http::header::CONTENT_TYPE, "application/x-git-receive-pack-result".parse().unwrap());
+    headers.insert(axum::http::header::CACHE_CONTROL, "no-cache".parse().unwrap());
+    headers.insert(axum::http::header::CONNECTION, "keep-alive".parse().unwrap());
+
+    (headers, body).into_response()
+}
+
+/// A push git has accepted into the scratch repository but that is not yet
+/// stored on IPFS and anchored on chain.
+struct PendingPush {
+    contract_state: ContractState,
+    contract: ContractInteraction,
+    repo: String,
+    caller: Address,
+    workspace: Workspace,
+    existing_refs: Vec<Ref>,
+    /// git's own response, held back until the push is anchored.
+    response: Vec<u8>,
+    /// The ref updates the client asked for.
+    commands: Vec<RefCommand>,
+    pushed_refs: Vec<String>,
+    sideband: Option<Sideband>,
+    /// The client asked for `atomic`: either every ref is reported stored or none.
+    atomic: bool,
+    /// `git push -o key=value` options, recorded with the push.
+    push_options: BTreeMap<String, String>,
+    /// The daemon's size limits lowered to the repository's quota.
+    limits: SizeLimits,
+    /// Refs the protection rules turned down, with why. They are already
+    /// reset in the scratch repository.
+    rejected: HashMap<String, String>,
+    /// Object files in the scratch repository before git received the
+    /// push. They came from the chain, or from earlier pushes that were all
+    /// anchored, so only the rest need checking and uploading.
+    preexisting: HashSet<PathBuf>,
+    /// With some refs rejected, the objects only the accepted ones bring
+    /// in; git wrote the rest too, but nothing on chain will reach them.
+    accepted_objects: Option<HashSet<String>>,
+}
+
+/// Streams progress on the sideband while the push is finished, then the
+/// report-status; a report rejecting every ref if even that cannot be built.
+fn stream_push(push: PendingPush, sideband: Sideband) -> Body {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let progress = Progress::new(sideband, sender.clone());
+
+    tokio::spawn(async move {
+        let pushed_refs = push.pushed_refs.clone();
+        let tail = match finish_push(push, &progress).await {
+            Ok(response) => {
+                info!("Successfully processed receive-pack request, response size: {} bytes", response.len());
+                response
+            },
+            Err(e) => {
+                error!("Error in receive_pack: {:?}", e);
+                let reason = format!("{:#}", e);
+                progress.message(&format!("error: {}", reason));
+                sideband.failure_report(&pushed_refs, &reason)
+            }
+        };
+        // Dropping the last sender ends the response body.
+        let _ = sender.send(tail);
+    }.instrument(Span::current()));
+
+    Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (Ok::<_, Infallible>(chunk), receiver))
+    }))
 }
 
-async fn handle_receive_pack(
+async fn prepare_push(
     contract_state: ContractState,
     repo: String,
+    headers: &HeaderMap,
     req_body: axum::body::Body,
-) -> Result<Vec<u8>> {
+) -> Result<PendingPush> {
+    let repo = validate_repo_name(&repo)?;
+    let caller = authenticate_optional(headers)?;
+
     info!("Looking up contract for repo: {}", repo);
     let contract = contract_state.get_contract(&repo).await
-        .ok_or_else(|| anyhow!("Repository not found"))?;
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+    let caller = ensure_pusher(&contract, &repo, caller).await?;
 
-    let temp_dir = tempdir()?;
-    let temp_path = temp_dir.path();
-    debug!("Created temporary directory: {:?}", temp_path);
+    contract_state.rate_limiter().check_push(&repo).await?;
 
-    let output = Command::new("git")
-        .args(["init", "--bare"])
-        .current_dir(temp_path)
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to initialize git repo: {}", stderr));
-    }
+    let workspace = contract_state.workspaces().acquire(&repo, &contract).await?;
+    let temp_path = workspace.path();
 
     info!("Fetching existing refs from blockchain for repo: {}", repo);
-    let existing_refs = contract.get_refs().await?;
+    let existing_refs = contract_state.views().refs(&contract).await?;
     info!("Found {} existing refs for repo {}", existing_refs.len(), repo);
 
-    let refs_dir = temp_path.join("refs");
-    let heads_dir = refs_dir.join("heads");
-    tokio::fs::create_dir_all(&heads_dir).await?;
-
-    let tags_dir = refs_dir.join("tags");
-    tokio::fs::create_dir_all(&tags_dir).await?;
+    workspace.clear_refs().await?;
 
     for ref_data in &existing_refs {
         let ref_name = &ref_data.name;
@@ -83,184 +166,1089 @@ async fn handle_receive_pack(
         tokio::fs::write(&ref_file_path, format!("{}\n", sha1)).await?;
     }
 
-    let objects_dir = temp_path.join("objects");
-    tokio::fs::create_dir_all(&objects_dir).await?;
-
-    let objects = contract.get_objects().await?;
-    for object in objects {
-        let object_hash = object.hash;
-        let ipfs_url = String::from_utf8(object.ipfs_url)?;
-        let object_path = get_object_path(temp_path, &object_hash);
-        let local_path = objects_dir.join(object_path);
-        let local_path_str = local_path.to_string_lossy();
-        ipfs::download_from_ipfs(&ipfs_url, &local_path_str).await?;
-    }
+    let objects = contract_state.views().objects(&contract).await?;
+    contract_state.object_cache().record(&repo, &contract, objects.iter().map(|object| object.hash.clone())).await;
+    let mut store = ObjectStore::new(temp_path, objects)?;
+    store.download_all().await?;
+    debug!("Downloaded {} objects from IPFS", store.download_count());
 
-    let body_bytes = axum::body::to_bytes(req_body, usize::MAX).await?;
+    let body_bytes = read_git_body(headers, req_body).await?;
     debug!("Client request size: {} bytes", body_bytes.len());
 
+    let packets = protocol::parse(&body_bytes)?;
+    let commands = protocol::ref_commands(&packets);
+    let pushed_refs: Vec<String> = commands.iter().map(|command| command.name.clone()).collect();
+    let sideband = Sideband::requested(&packets);
+    let atomic = protocol::capabilities(&packets).iter().any(|capability| capability == "atomic");
+    let push_options = parse_push_options(&packets);
+    debug!("Client pushes {:?}, sideband: {:?}, atomic: {}, options: {:?}", pushed_refs, sideband, atomic, push_options);
+
+    let preexisting: HashSet<PathBuf> = object_files(temp_path)
+        .into_iter()
+        .map(|object| object.path)
+        .collect();
+
     debug!("Running git receive-pack command");
-    let mut cmd = Command::new("git");
-    cmd.args(["receive-pack", "--stateless-rpc", "."])
-        .current_dir(temp_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    let mut cmd = git::command();
+    cmd.args(RECEIVE_PACK_CONFIG)
+        .args(["receive-pack", "--stateless-rpc", "."])
+        .current_dir(temp_path);
+
+    let output = git::run_with_input(cmd, body_bytes).await?;
 
-    let mut child = cmd.spawn()?;
+    if !output.status.success() {
+        let err_str = String::from_utf8_lossy(&output.stderr);
+        error!("git receive-pack failed: {}", err_str);
+        return Err(anyhow!("git receive-pack failed: {}", err_str));
+    }
+
+    let config = contract.get_repo_config().await?;
+    let namespaces = allowed_namespaces(&DaemonConfig::ref_namespaces(), &config.ref_namespaces);
+    let mut rejected = enforce_namespaces(&namespaces, temp_path, &commands).await?;
 
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(&body_bytes).await?;
+    let protected = enforce_protection(&contract, &config, caller, temp_path, &commands, &rejected).await?;
+    rejected.extend(protected);
+    if config.require_signed_commits {
+        let unsigned = enforce_signatures(&config.signing_keys, temp_path, &commands, &existing_refs, &rejected).await?;
+        rejected.extend(unsigned);
     }
+    let accepted_objects = if rejected.is_empty() {
+        None
+    } else {
+        info!("Rejected {:?} in push to {}", rejected.keys().collect::<Vec<_>>(), repo);
+        Some(accepted_objects(temp_path, &commands, &rejected, &existing_refs).await?)
+    };
+    let limits = contract_state.size_limits().for_repo(&config.quota);
 
-    let mut response = Vec::new();
-    if let Some(mut stdout) = child.stdout.take() {
-        stdout.read_to_end(&mut response).await?;
+    Ok(PendingPush {
+        contract_state,
+        contract,
+        repo,
+        caller,
+        workspace,
+        existing_refs,
+        response: output.stdout,
+        commands,
+        pushed_refs,
+        sideband,
+        atomic,
+        push_options,
+        limits,
+        rejected,
+        preexisting,
+        accepted_objects,
+    })
+}
+
+/// The daemon's namespaces narrowed to the repository's: where one of each
+/// contains the other, the narrower one.
+fn allowed_namespaces(daemon: &[String], repo: &[String]) -> Vec<String> {
+    if repo.is_empty() {
+        return daemon.to_vec();
     }
 
-    let status = child.wait().await?;
-    if !status.success() {
-        let mut err_msg = Vec::new();
-        if let Some(mut stderr) = child.stderr.take() {
-            stderr.read_to_end(&mut err_msg).await?;
+    let mut allowed: Vec<String> = Vec::new();
+    for outer in daemon {
+        for inner in repo {
+            let narrower = if inner.starts_with(outer.as_str()) {
+                inner
+            } else if outer.starts_with(inner.as_str()) {
+                outer
+            } else {
+                continue;
+            };
+            if !allowed.contains(narrower) {
+                allowed.push(narrower.clone());
+            }
         }
-        let err_str = String::from_utf8_lossy(&err_msg);
-        error!("git receive-pack failed: {}", err_str);
-        return Err(anyhow!("git receive-pack failed: {}", err_str));
     }
+    allowed
+}
 
-    let objects_dir = temp_path.join("objects");
+/// Resets and rejects updates to refs outside `namespaces`, which would
+/// otherwise never be anchored.
+async fn enforce_namespaces(
+    namespaces: &[String],
+    repo_path: &std::path::Path,
+    commands: &[RefCommand],
+) -> Result<HashMap<String, String>> {
+    let mut rejected = HashMap::new();
 
-    info!("Scanning for new objects to upload to IPFS");
-    let mut objects_to_upload = Vec::new();
-    for entry in WalkDir::new(&objects_dir)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file()) {
+    for command in commands {
+        if namespaces.iter().any(|namespace| command.name.starts_with(namespace.as_str())) {
+            continue;
+        }
+
+        let reason = if namespaces.is_empty() {
+            "the repository's ref_namespaces share no namespace with the daemon's DGIT_REF_NAMESPACES".to_string()
+        } else {
+            format!(
+                "refs outside {} are not stored for this repository (see DGIT_REF_NAMESPACES and the repository's ref_namespaces)",
+                namespaces.join(", ")
+            )
+        };
+        debug!("Rejecting update of {}: {}", command.name, reason);
+        reset_ref(repo_path, command).await?;
+        rejected.insert(command.name.clone(), reason);
+    }
+
+    Ok(rejected)
+}
 
-        let object_path = entry.path();
-        let obj_dir_name = object_path.parent()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
+/// Checks each ref update against the repository's protection rules and
+/// resets the refs that break one to their previous value, so neither they
+/// nor the objects only they reach are anchored. Admins are exempt, and
+/// refs in `skip` were already rejected.
+async fn enforce_protection(
+    contract: &ContractInteraction,
+    config: &RepoConfig,
+    caller: Address,
+    repo_path: &std::path::Path,
+    commands: &[RefCommand],
+    skip: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    if config.protected_refs.is_empty() {
+        return Ok(HashMap::new());
+    }
 
-        let obj_file_name = entry.file_name().to_str().unwrap_or("");
-        let obj_hash = format!("{}{}", obj_dir_name, obj_file_name);
+    if contract.has_admin_role(caller).await? {
+        debug!("{:?} is an admin, skipping protection rules", caller);
+        return Ok(HashMap::new());
+    }
 
-        debug!("Checking if object {} exists in blockchain", obj_hash);
+    enforce_rules(config, repo_path, commands, skip).await
+}
 
-        match contract.is_object_exist(obj_hash.clone()).await {
-            Ok(true) => {
-                debug!("Object {} already exists in blockchain, skipping", obj_hash);
-            },
-            _ => {
-                debug!("Found new object to upload: {}", obj_hash);
-                objects_to_upload.push((obj_hash, object_path.to_path_buf()));
+/// The part of [`enforce_protection`] that applies to non-admins.
+async fn enforce_rules(
+    config: &RepoConfig,
+    repo_path: &std::path::Path,
+    commands: &[RefCommand],
+    skip: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut rejected = HashMap::new();
+
+    for command in commands.iter().filter(|command| !skip.contains_key(&command.name)) {
+        let rules: Vec<_> = config.protection_for(&command.name).collect();
+        if rules.is_empty() {
+            continue;
+        }
+
+        let reason = if rules.iter().any(|rule| rule.admins_only) {
+            Some("protected ref, only admins may update it")
+        } else if command.is_delete() && rules.iter().any(|rule| rule.deny_delete) {
+            Some("protected ref, deletion is not allowed")
+        } else if !command.is_create()
+            && !command.is_delete()
+            && rules.iter().any(|rule| rule.deny_force_push)
+            && !is_ancestor(repo_path, &command.old, &command.new).await?
+        {
+            Some("protected ref, force push is not allowed")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            debug!("Rejecting update of {}: {}", command.name, reason);
+            reset_ref(repo_path, command).await?;
+            rejected.insert(command.name.clone(), reason.to_string());
+        }
+    }
+
+    Ok(rejected)
+}
+
+/// Checks that every new tip, and every commit it brings in that no ref
+/// already on chain reaches, is signed by one of `keys`. Refs with an
+/// unsigned one are reset. Refs in `skip` were already rejected.
+async fn enforce_signatures(
+    keys: &SigningKeys,
+    repo_path: &std::path::Path,
+    commands: &[RefCommand],
+    existing_refs: &[Ref],
+    skip: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut rejected = HashMap::new();
+    let keyring = Keyring::materialize(keys).await?;
+    let known: Vec<String> = existing_refs
+        .iter()
+        .filter(|r| r.is_active)
+        .map(|r| String::from_utf8_lossy(&r.data).trim().to_string())
+        .collect();
+
+    for command in commands.iter().filter(|command| !command.is_delete() && !skip.contains_key(&command.name)) {
+        let mut reason = keyring.verify(repo_path, &command.new).await?;
+        if reason.is_none() {
+            for commit in new_commits(repo_path, &command.new, &known).await? {
+                reason = keyring.verify(repo_path, &commit).await?;
+                if reason.is_some() {
+                    break;
+                }
             }
         }
+
+        if let Some(reason) = reason {
+            debug!("Rejecting update of {}: {}", command.name, reason);
+            reset_ref(repo_path, command).await?;
+            rejected.insert(command.name.clone(), reason);
+        }
     }
 
-    info!("Found {} new objects to upload", objects_to_upload.len());
+    Ok(rejected)
+}
 
-    let mut object_hashes = Vec::new();
-    let mut ipfs_urls = Vec::new();
+/// Objects reachable from the new tips of the commands not in `rejected`
+/// and from no ref that was on chain before the push.
+async fn accepted_objects(
+    repo_path: &std::path::Path,
+    commands: &[RefCommand],
+    rejected: &HashMap<String, String>,
+    existing_refs: &[Ref],
+) -> Result<HashSet<String>> {
+    let tips: Vec<&str> = commands
+        .iter()
+        .filter(|command| !command.is_delete() && !rejected.contains_key(&command.name))
+        .map(|command| command.new.as_str())
+        .collect();
+    if tips.is_empty() {
+        return Ok(HashSet::new());
+    }
 
-    for (obj_hash, obj_path) in objects_to_upload {
-        let path_str = obj_path.to_string_lossy();
+    let mut revisions: Vec<String> = tips.iter().map(|tip| tip.to_string()).collect();
+    revisions.extend(
+        existing_refs
+            .iter()
+            .filter(|r| r.is_active)
+            .map(|r| format!("^{}", String::from_utf8_lossy(&r.data).trim())),
+    );
 
-        debug!("Uploading object {} to IPFS", obj_hash);
-        match ipfs::load_to_ipfs(&path_str).await {
-            Ok(ipfs_hash) => {
-                debug!("Object {} uploaded to IPFS with hash {}", obj_hash, ipfs_hash);
-                object_hashes.push(obj_hash);
-                ipfs_urls.push(ipfs_hash.as_bytes().to_vec());
-            },
-            Err(e) => {
-                error!("Failed to upload object {} to IPFS: {}", obj_hash, e);
-                return Err(anyhow!("Failed to upload object to IPFS: {}", e));
-            }
+    let mut cmd = git::command();
+    cmd.args(["rev-list", "--objects", "--stdin"]).current_dir(repo_path);
+    let output = git::run_with_input(cmd, format!("{}\n", revisions.join("\n")).into_bytes()).await?;
+    if !output.status.success() {
+        return Err(anyhow!("git rev-list failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|hash| hash.to_string())
+        .collect())
+}
+
+/// Commits reachable from `tip` but from none of `known`, newest first.
+async fn new_commits(repo_path: &std::path::Path, tip: &str, known: &[String]) -> Result<Vec<String>> {
+    let mut cmd = git::command();
+    cmd.args(["rev-list", tip, "--not"]).args(known).current_dir(repo_path);
+
+    let output = git::output(cmd).await?;
+    if !output.status.success() {
+        return Err(anyhow!("git rev-list failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(|line| line.to_string()).collect())
+}
+
+/// Puts a ref of the scratch repository back to its value before the push.
+async fn reset_ref(repo_path: &std::path::Path, command: &RefCommand) -> Result<()> {
+    let ref_path = repo_path.join(&command.name);
+    if command.is_create() {
+        let _ = fs::remove_file(&ref_path).await;
+    } else {
+        if let Some(parent) = ref_path.parent() {
+            fs::create_dir_all(parent).await?;
         }
+        fs::write(&ref_path, format!("{}\n", command.old)).await?;
     }
+    Ok(())
+}
+
+async fn is_ancestor(repo_path: &std::path::Path, ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut cmd = git::command();
+    cmd.args(["merge-base", "--is-ancestor", ancestor, descendant]).current_dir(repo_path);
+    let output = git::output(cmd).await?;
+
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(anyhow!("git merge-base failed: {}", String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
 
-    if !object_hashes.is_empty() {
-        info!("Storing {} object hashes in blockchain", object_hashes.len());
-        match contract.add_objects(object_hashes.clone(), ipfs_urls).await {
-            Ok(_) => debug!("Successfully stored object hashes in blockchain"),
+/// Under `atomic`, one failed ref fails them all; the others are reported
+/// with a reason of their own.
+fn fail_atomically(failures: &mut HashMap<String, String>, pushed_refs: &[String], atomic: bool) {
+    if !atomic || failures.is_empty() {
+        return;
+    }
+    for name in pushed_refs {
+        failures.entry(name.clone()).or_insert_with(|| "atomic push failed".to_string());
+    }
+}
+
+/// Anchors the push and returns git's report-status, rewritten so every ref
+/// that did not make it on chain is reported `ng` with the reason.
+async fn finish_push(push: PendingPush, progress: &Progress) -> Result<Vec<u8>> {
+    let mut failures = push.rejected.clone();
+    fail_atomically(&mut failures, &push.pushed_refs, push.atomic);
+
+    // Anchor only when some ref survived the protection rules.
+    if push.pushed_refs.iter().any(|name| !failures.contains_key(name)) {
+        let anchored = anchor_push(&push, progress).await;
+        // Even a failed anchor may have written some refs.
+        push.contract_state.ref_cache().invalidate(&push.repo).await;
+        push.contract_state.snapshots().invalidate(&push.repo, &push.contract).await;
+        match anchored {
+            Ok(anchor_failures) => failures.extend(anchor_failures),
+            // Nothing was stored yet; let the client see the 429 and retry.
+            Err(e) if e.is::<RateLimited>() => return Err(e),
             Err(e) => {
-                error!("Failed to store object hashes in blockchain: {}", e);
-                return Err(anyhow!("Failed to store object hashes in blockchain: {}", e));
-            }
+                error!("Failed to anchor push to {}: {:?}", push.repo, e);
+                let reason = format!("{:#}", e);
+                for name in &push.pushed_refs {
+                    failures.entry(name.clone()).or_insert_with(|| reason.clone());
+                }
+            },
         }
     }
 
-    info!("Collecting updated refs");
-    let mut updated_refs = Vec::new();
-    let mut ref_data = Vec::new();
+    for (name, reason) in &failures {
+        progress.message(&format!("error: {} not stored: {}", name, reason));
+    }
 
-    for entry in WalkDir::new(heads_dir)
-        .min_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file()) {
+    let report = sideband::rewrite_report(&push.response, push.sideband, &failures)?;
+    // Only once every object in the workspace is on chain may the next push
+    // start from it; otherwise it would take them for its own.
+    if failures.is_empty() && !push.contract_state.anchor_queue().enabled() {
+        push.contract_state.workspaces().release(push.workspace).await;
+    }
+    Ok(report)
+}
 
-        let ref_path = entry.path();
-        let ref_content = fs::read_to_string(ref_path).await?;
-        let ref_content = ref_content.trim();
+/// Uploads the pushed objects to IPFS and anchors them and the updated refs
+/// on chain. Returns the refs that failed verification afterwards, with why.
+///
+/// With `DGIT_ASYNC_ANCHORING` only the upload happens here; the anchoring
+/// is queued and the push reported stored right away.
+async fn anchor_push(push: &PendingPush, progress: &Progress) -> Result<HashMap<String, String>> {
+    let PendingPush { contract_state, contract, repo, caller, workspace, existing_refs, commands, pushed_refs, atomic, push_options, limits, rejected, preexisting, accepted_objects, .. } = push;
+    let temp_path = workspace.path();
 
-        let heads_rel_path = ref_path.strip_prefix(temp_path)?;
-        let ref_name = heads_rel_path.to_string_lossy().to_string();
+    // Everything the push writes is gathered before the first IPFS upload
+    // or transaction, so a failure here leaves nothing behind.
+    info!("Collecting updated refs");
+    let ref_changes = ref_changes(temp_path, commands, rejected).await?;
+    let (deleted, updated): (Vec<_>, Vec<_>) = ref_changes.iter().partition(|(_, value)| value == ZERO_ID);
+    let updated_refs: Vec<String> = updated.iter().map(|(name, _)| name.clone()).collect();
+    let ref_data: Vec<Vec<u8>> = updated.iter().map(|(_, value)| value.as_bytes().to_vec()).collect();
+    let deleted_refs: Vec<String> = deleted.iter().map(|(name, _)| name.clone()).collect();
 
-        debug!("Found updated ref: {} -> {}", ref_name, ref_content);
-        updated_refs.push(ref_name);
-        ref_data.push(ref_content.as_bytes().to_vec());
+    info!("Scanning for objects written by the push");
+    let mut candidates = Vec::new();
+    let mut skipped = 0;
+    let mut unreachable = 0;
+    for object in object_files(temp_path) {
+        if preexisting.contains(&object.path) {
+            skipped += 1;
+            continue;
+        }
+        // Sizes, counts and the rejected-ref filter all go object by object.
+        if !is_object_id(&object.hash) {
+            return Err(anyhow!("git left {} in the scratch repository instead of loose objects", object.path.display()));
+        }
+        // Objects only a rejected ref reaches would be anchored for nothing.
+        if accepted_objects.as_ref().is_some_and(|accepted| !accepted.contains(&object.hash)) {
+            unreachable += 1;
+            continue;
+        }
+        candidates.push((object.hash, object.path, object.size));
     }
+    debug!("{} objects were written by the push, {} were already there, {} only reach rejected refs", candidates.len(), skipped, unreachable);
 
-    for entry in WalkDir::new(tags_dir)
-        .min_depth(1)
+    // Only hashes the cache has not seen on chain cost a contract read.
+    let hashes: Vec<String> = candidates.iter().map(|(hash, _, _)| hash.clone()).collect();
+    let exists = contract_state.object_cache().check(repo, contract, &hashes).await?;
+    let objects_to_upload: Vec<_> = candidates
         .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file()) {
+        .zip(exists)
+        .filter(|(_, exists)| !exists)
+        .map(|(candidate, _)| candidate)
+        .collect();
+
+    info!("Found {} new objects to upload", objects_to_upload.len());
+    let stored: u64 = if limits.limits_repo_objects() {
+        contract.get_objects_length().await?.try_into().unwrap_or(u64::MAX)
+    } else {
+        0
+    };
+    limits.check(objects_to_upload.iter().map(|(hash, _, size)| (hash.as_str(), *size)), stored)?;
+    contract_state.rate_limiter().check_objects(repo, objects_to_upload.len()).await?;
+
+    let mut object_hashes = Vec::new();
+    let mut tx_hashes = Vec::new();
+    let mut costs = Costs::default();
+    let mut failures = HashMap::new();
+    let queue = contract_state.anchor_queue();
+    let mut queued_objects = Vec::new();
+
+    // Objects are uploaded and anchored batch by batch, so a failure late in
+    // a large push keeps what came before: a retried push finds those
+    // objects on chain and skips them.
+    let storage = storage::from_env()?;
+    let upload_count = objects_to_upload.len();
+    let batch_size = DaemonConfig::object_batch_size();
+    let batch_count = upload_count.div_ceil(batch_size);
+    for (batch, objects) in objects_to_upload.chunks(batch_size).enumerate() {
+        let mut batch_hashes = Vec::with_capacity(objects.len());
+        let mut ipfs_urls = Vec::with_capacity(objects.len());
+
+        for (obj_hash, obj_path, _) in objects {
+            progress.update(&format!("Uploading objects to {}: {}/{}", storage.name(), object_hashes.len() + batch_hashes.len() + 1, upload_count));
 
-        let ref_path = entry.path();
-        let ref_content = fs::read_to_string(ref_path).await?;
-        let ref_content = ref_content.trim();
+            // Another repository may have pushed the same object already.
+            if let Some(ipfs_hash) = contract_state.object_index().cid(obj_hash).await {
+                debug!("Object {} is already stored as {}", obj_hash, ipfs_hash);
+                batch_hashes.push(obj_hash.clone());
+                ipfs_urls.push(ipfs_hash.into_bytes());
+                continue;
+            }
+
+            debug!("Uploading object {} to {}", obj_hash, storage.name());
+            let uploaded = match fs::read(obj_path).await {
+                Ok(content) => storage.put(obj_hash, content).await,
+                Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to read object {}", obj_hash))),
+            };
+            match uploaded {
+                Ok(ipfs_hash) => {
+                    debug!("Object {} stored as {}", obj_hash, ipfs_hash);
+                    batch_hashes.push(obj_hash.clone());
+                    ipfs_urls.push(ipfs_hash.into_bytes());
+                },
+                Err(e) => {
+                    error!("Failed to upload object {} to {}: {:#}", obj_hash, storage.name(), e);
+                    record_orphans(contract_state, repo, &object_hashes, &tx_hashes, "object upload failed").await;
+                    return Err(e.context("object upload failed"));
+                }
+            }
+        }
 
-        let tags_rel_path = ref_path.strip_prefix(temp_path)?;
-        let ref_name = tags_rel_path.to_string_lossy().to_string();
+        let urls = ipfs_urls.iter().map(|url| String::from_utf8_lossy(url).into_owned());
+        contract_state.object_index().record(batch_hashes.iter().cloned().zip(urls)).await;
+        let urls = ipfs_urls.iter().map(|url| String::from_utf8_lossy(url).into_owned());
+        contract_state.pin_index().record(repo, batch_hashes.iter().cloned().zip(urls)).await;
 
-        debug!("Found updated tag: {} -> {}", ref_name, ref_content);
-        updated_refs.push(ref_name);
-        ref_data.push(ref_content.as_bytes().to_vec());
+        if queue.enabled() {
+            let urls = ipfs_urls.iter().map(|url| String::from_utf8_lossy(url).into_owned());
+            queued_objects.extend(batch_hashes.into_iter().zip(urls));
+            continue;
+        }
+
+        info!("Storing object batch {}/{} ({} objects) in blockchain", batch + 1, batch_count, batch_hashes.len());
+        progress.message(&format!("Anchoring objects on chain: batch {}/{}...", batch + 1, batch_count));
+        match contract.add_objects(batch_hashes.clone(), ipfs_urls).await {
+            Ok(confirmed) => {
+                debug!("Successfully stored object batch {}/{} in blockchain", batch + 1, batch_count);
+                tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+                costs.add(confirmed.costs);
+                contract_state.object_cache().record(repo, contract, batch_hashes.iter().cloned()).await;
+                object_hashes.extend(batch_hashes);
+                info!("Anchored {}/{} objects (tx {:?})", object_hashes.len(), upload_count, confirmed.tx_hash);
+            },
+            Err(e) => {
+                error!("Failed to store object batch {}/{} in blockchain: {}", batch + 1, batch_count, e);
+                let reason = format!("anchoring object batch {}/{} failed: {}", batch + 1, batch_count, e);
+                record_orphans(contract_state, repo, &object_hashes, &tx_hashes, &reason).await;
+                return Err(anyhow!(reason));
+            }
+        }
+    }
+
+    if queue.enabled() {
+        let mut push_options = push_options.clone();
+        let (actor, unverified_signer) = push_actor(&mut push_options, Some(*caller), contract.signer.address(), repo);
+        let ref_updates = ref_updates(&ref_changes, existing_refs, &failures);
+        let job = queue.enqueue(repo, contract.address(), queued_objects, ref_changes, actor, unverified_signer, ref_updates, push_options).await?;
+        info!("Queued anchoring of push to {} as job {}", repo, job);
+        progress.message(&format!("anchoring queued (job {})", job));
+        return Ok(failures);
     }
 
+    if upload_count > 0 {
+        progress.message(&format!(
+            "Anchored {} objects on chain in {} transaction(s), last tx {}",
+            upload_count,
+            batch_count,
+            tx_hashes.last().map(String::as_str).unwrap_or_default()
+        ));
+    }
+
+    // Refs go on chain only once every object they reach is there.
     if !updated_refs.is_empty() {
         info!("Storing {} updated refs in blockchain", updated_refs.len());
+        progress.message(&format!("Anchoring {} refs on chain...", updated_refs.len()));
         match contract.add_refs(updated_refs.clone(), ref_data).await {
-            Ok(_) => debug!("Successfully stored updated refs in blockchain"),
+            Ok(confirmed) => {
+                debug!("Successfully stored updated refs in blockchain");
+                progress.message(&format!("Anchored refs on chain (tx {:?})", confirmed.tx_hash));
+                tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+                costs.add(confirmed.costs);
+            },
             Err(e) => {
                 error!("Failed to store refs in blockchain: {}", e);
-                return Err(anyhow!("Failed to store refs in blockchain: {}", e));
+                let reason = format!("chain tx failed: {}", e);
+                record_orphans(contract_state, repo, &object_hashes, &tx_hashes, &reason).await;
+                return Err(anyhow!(reason));
             }
         }
+    }
 
-        for ref_name in updated_refs.iter() {
-            debug!("Verifying ref {} was properly stored", ref_name);
-            let mut found = false;
-
-            for blockchain_ref in contract.get_refs().await? {
-                if blockchain_ref.name == *ref_name && blockchain_ref.is_active {
-                    found = true;
-                    break;
-                }
+    if !deleted_refs.is_empty() {
+        info!("Deactivating {} deleted refs in blockchain", deleted_refs.len());
+        progress.message(&format!("Deleting {} refs on chain...", deleted_refs.len()));
+        match contract.deactivate_refs(deleted_refs.clone()).await {
+            Ok(confirmed) => {
+                progress.message(&format!("Deleted refs on chain (tx {:?})", confirmed.tx_hash));
+                tx_hashes.push(format!("{:?}", confirmed.tx_hash));
+                costs.add(confirmed.costs);
+            },
+            Err(e) => {
+                error!("Failed to deactivate refs in blockchain: {}", e);
+                let reason = format!("chain tx failed: {}", e);
+                record_orphans(contract_state, repo, &object_hashes, &tx_hashes, &reason).await;
+                return Err(anyhow!(reason));
             }
+        }
+    }
 
-            if !found {
+    if !ref_changes.is_empty() {
+        let anchored_refs = contract.get_all_refs().await?;
+        for (ref_name, value) in &ref_changes {
+            debug!("Verifying ref {} was properly stored", ref_name);
+            let active = anchored_refs
+                .iter()
+                .any(|anchored| anchored.name == *ref_name && anchored.is_active);
+
+            if value == ZERO_ID && active {
+                error!("Failed to verify ref {} was deleted in blockchain", ref_name);
+                failures.insert(ref_name.clone(), "ref still active on chain after deleting it".to_string());
+            } else if value != ZERO_ID && !active {
                 error!("Failed to verify ref {} was stored in blockchain", ref_name);
-                return Err(anyhow!("Failed to verify ref was stored in blockchain: {}", ref_name));
+                failures.insert(ref_name.clone(), "ref not found on chain after anchoring".to_string());
             }
         }
+
+        fail_atomically(&mut failures, pushed_refs, *atomic);
+    }
+
+    if costs.txs > 0 {
+        info!("Push to {} cost {}", repo, costs);
+        progress.message(&format!("anchored {} objects, {} refs — {}", object_hashes.len(), ref_changes.len(), costs));
+    }
+
+    let mut push_options = push_options.clone();
+    let (actor, unverified_signer) = push_actor(&mut push_options, Some(*caller), contract.signer.address(), repo);
+    contract_state.audit().record(AuditEntry {
+        actor: actor.clone(),
+        unverified_signer: unverified_signer.clone(),
+        tx_hash: tx_hashes.last().cloned(),
+        costs: (costs.txs > 0).then_some(costs),
+        push_options: push_options.clone(),
+        ..AuditEntry::new(AuditAction::Push, repo)
+    }).await;
+
+    let ref_updates = ref_updates(&ref_changes, existing_refs, &failures);
+    if !ref_updates.is_empty() {
+        webhooks::dispatch(contract_state.webhooks().list(repo).await, PushEvent {
+            repo: repo.clone(),
+            pusher: actor,
+            unverified_signer,
+            refs: ref_updates,
+            object_count: object_hashes.len(),
+            tx_hashes,
+            costs,
+            push_options,
+        });
     }
 
     info!("Push operation completed successfully");
-    Ok(response)
+    Ok(failures)
+}
+
+/// Who a push is recorded as, and the signer the client named with
+/// `-o dgit-signer=<address>` if that could not be verified. The contract
+/// records the daemon's key as pusher, so the actor is the authenticated
+/// caller, falling back to the daemon's key. The option is taken out of
+/// `push_options` and only counts when it names the caller; anything else
+/// is kept apart so a client cannot pick who a push is attributed to.
+fn push_actor(push_options: &mut BTreeMap<String, String>, caller: Option<Address>, daemon: Option<Address>, repo: &str) -> (Option<String>, Option<String>) {
+    let signer = push_options.remove(SIGNER_OPTION).and_then(|signer| match Address::from_str(&signer) {
+        Ok(address) => Some(address),
+        Err(_) => {
+            warn!("Ignoring invalid {} option: {}", SIGNER_OPTION, signer);
+            None
+        },
+    });
+    let unverified = signer.filter(|signer| Some(*signer) != caller);
+    if let Some(signer) = unverified {
+        warn!("Push to {} names signer {:?} but is authenticated as {:?}; recording it as unverified", repo, signer, caller);
+    }
+
+    let actor = caller.or(daemon).map(|address| format!("{:?}", address));
+    (actor, unverified.map(|address| format!("{:?}", address)))
+}
+
+/// The refs the accepted `commands` change, with their new values, or
+/// [`ZERO_ID`] for refs they delete. Commands git did not carry out, and
+/// ones that leave a ref as it was, are left out, so neither is anchored.
+async fn ref_changes(repo_path: &std::path::Path, commands: &[RefCommand], rejected: &HashMap<String, String>) -> Result<Vec<(String, String)>> {
+    let mut changes = Vec::new();
+    for command in commands.iter().filter(|command| !rejected.contains_key(&command.name)) {
+        if command.old == command.new {
+            continue;
+        }
+
+        let value = match fs::read_to_string(repo_path.join(&command.name)).await {
+            Ok(value) => Some(value.trim().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        match value {
+            None if command.is_delete() => changes.push((command.name.clone(), ZERO_ID.to_string())),
+            Some(value) if value == command.new => changes.push((command.name.clone(), value)),
+            _ => {
+                warn!("git did not apply {} {} -> {}; not anchoring it", command.name, command.old, command.new);
+                continue;
+            },
+        }
+        debug!("Found updated ref: {} -> {}", command.name, command.new);
+    }
+    Ok(changes)
+}
+
+/// The refs a push changed, as reported to webhooks.
+fn ref_updates(ref_changes: &[(String, String)], existing_refs: &[Ref], failures: &HashMap<String, String>) -> Vec<RefUpdate> {
+    ref_changes
+        .iter()
+        .filter(|(name, _)| !failures.contains_key(name))
+        .filter_map(|(name, new)| {
+            let old = existing_refs
+                .iter()
+                .find(|r| r.name == *name && r.is_active)
+                .map(|r| String::from_utf8_lossy(&r.data).trim().to_string())
+                .unwrap_or_else(|| ZERO_ID.to_string());
+            (old != *new).then(|| RefUpdate { name: name.clone(), old, new: new.clone() })
+        })
+        .collect()
+}
+
+struct ObjectFile {
+    /// The name objects are recorded under on chain: the directory and file
+    /// name run together, like the object's hash for a loose object.
+    hash: String,
+    path: PathBuf,
+    size: u64,
+}
+
+/// Every file under the `objects` directory of the repository at
+/// `repo_path`. Pushes arrive as loose objects (see [`RECEIVE_PACK_CONFIG`]);
+/// anything else is named by its directory and file name too.
+fn object_files(repo_path: &std::path::Path) -> Vec<ObjectFile> {
+    WalkDir::new(repo_path.join("objects"))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|entry| {
+            let object_path = entry.path();
+            let obj_dir_name = object_path.parent()
+                .and_then(|p| p.file_name())
+                .and_then(|n| n.to_str())
+                .unwrap_or("");
+            let obj_file_name = entry.file_name().to_str().unwrap_or("");
+
+            ObjectFile {
+                hash: format!("{}{}", obj_dir_name, obj_file_name),
+                path: object_path.to_path_buf(),
+                size: entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+/// Records objects a failed push anchored without anchoring any ref to them.
+async fn record_orphans(contract_state: &ContractState, repo: &str, objects: &[String], tx_hashes: &[String], reason: &str) {
+    if !objects.is_empty() {
+        contract_state.pending_cleanup()
+            .record(repo, objects.to_vec(), tx_hashes.last().cloned(), reason)
+            .await;
+    }
+}
+
+/// `key=value` push options; anything else is ignored, as git itself only
+/// forwards them to hooks.
+fn parse_push_options(packets: &[Packet]) -> BTreeMap<String, String> {
+    protocol::push_options(packets)
+        .into_iter()
+        .filter_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use onchain::repo_config::ProtectionRule;
+    use std::path::Path;
+
+    fn commit_file(work: &Path, name: &str) -> String {
+        std::fs::write(work.join(name), name).unwrap();
+        git(work, &["add", name]);
+        git(work, &["commit", "--quiet", "-m", name]);
+        git(work, &["rev-parse", "HEAD"])
+    }
+
+    fn update(name: &str, old: &str, new: &str) -> RefCommand {
+        RefCommand { old: old.to_string(), new: new.to_string(), name: name.to_string() }
+    }
+
+    fn on_chain(name: &str, oid: &str) -> Ref {
+        Ref { name: name.to_string(), data: oid.as_bytes().to_vec(), is_active: true, pusher: Address::zero() }
+    }
+
+    /// A bare repository holding `main` at a first commit, as the scratch
+    /// repository does once the chain's refs and objects are in, and a
+    /// working copy to push from.
+    struct Fixture {
+        dir: tempfile::TempDir,
+        base: String,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let work = dir.path().join("work");
+            std::fs::create_dir(&work).unwrap();
+            git(&work, &["init", "--quiet"]);
+            git(&work, &["checkout", "--quiet", "-b", "main"]);
+            let base = commit_file(&work, "base.txt");
+            git(dir.path(), &["clone", "--quiet", "--bare", work.to_str().unwrap(), "bare.git"]);
+            Self { dir, base }
+        }
+
+        fn work(&self) -> PathBuf {
+            self.dir.path().join("work")
+        }
+
+        fn bare(&self) -> PathBuf {
+            self.dir.path().join("bare.git")
+        }
+
+        fn push(&self, refspecs: &[&str]) {
+            let mut args = vec!["push", "--quiet", "--force", "../bare.git"];
+            args.extend_from_slice(refspecs);
+            git(&self.work(), &args);
+        }
+
+        /// Like [`push`](Self::push), with receive-pack configured as the
+        /// daemon runs it.
+        fn push_as_daemon(&self, refspecs: &[&str]) {
+            let receive_pack = format!("git {} receive-pack", RECEIVE_PACK_CONFIG.join(" "));
+            let mut args = vec!["push", "--quiet", "--force", "--receive-pack", &receive_pack, "../bare.git"];
+            args.extend_from_slice(refspecs);
+            git(&self.work(), &args);
+        }
+
+        /// Commits `count` new files of 200 barely compressible bytes on the
+        /// working copy's branch at once.
+        fn commit_many(&self, count: usize) -> String {
+            for i in 0..count {
+                let content: String = (0..25u32).map(|j| format!("{:08x}", (i as u32 * 31 + j).wrapping_mul(2_654_435_761))).collect();
+                std::fs::write(self.work().join(format!("file-{}.txt", i)), content).unwrap();
+            }
+            git(&self.work(), &["add", "."]);
+            git(&self.work(), &["commit", "--quiet", "-m", "many files"]);
+            git(&self.work(), &["rev-parse", "HEAD"])
+        }
+
+        fn object_paths(&self) -> HashSet<PathBuf> {
+            object_files(&self.bare()).into_iter().map(|object| object.path).collect()
+        }
+
+        fn objects(&self, tip: &str) -> HashSet<String> {
+            git(&self.bare(), &["rev-list", "--objects", tip, "--not", &self.base])
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .map(|hash| hash.to_string())
+                .collect()
+        }
+    }
+
+    fn namespaces(namespaces: &[&str]) -> Vec<String> {
+        namespaces.iter().map(|namespace| namespace.to_string()).collect()
+    }
+
+    #[test]
+    fn repository_namespaces_narrow_the_daemons() {
+        let daemon = namespaces(&["refs/heads/", "refs/tags/"]);
+        assert_eq!(allowed_namespaces(&daemon, &[]), daemon);
+        assert_eq!(allowed_namespaces(&daemon, &namespaces(&["refs/heads/release/"])), namespaces(&["refs/heads/release/"]));
+        assert_eq!(allowed_namespaces(&namespaces(&["refs/"]), &daemon), daemon);
+        assert_eq!(allowed_namespaces(&daemon, &namespaces(&["refs/", "refs/heads/"])), daemon);
+        assert!(allowed_namespaces(&daemon, &namespaces(&["refs/notes/"])).is_empty());
+    }
+
+    #[tokio::test]
+    async fn refs_outside_the_namespaces_are_rejected_and_reset() {
+        let fixture = Fixture::new();
+        git(&fixture.work(), &["checkout", "--quiet", "-b", "notes"]);
+        let note = commit_file(&fixture.work(), "note.txt");
+        fixture.push(&["notes:refs/notes/commits", "notes:refs/heads/feature"]);
+
+        let commands = [update("refs/notes/commits", ZERO_ID, &note), update("refs/heads/feature", ZERO_ID, &note)];
+        let rejected = enforce_namespaces(&namespaces(&["refs/heads/"]), &fixture.bare(), &commands).await.unwrap();
+
+        assert_eq!(rejected.keys().collect::<Vec<_>>(), vec!["refs/notes/commits"]);
+        assert!(!fixture.bare().join("refs/notes/commits").exists());
+        assert!(fixture.bare().join("refs/heads/feature").exists());
+
+        let rejected = enforce_namespaces(&[], &fixture.bare(), &commands[1..]).await.unwrap();
+        assert!(rejected["refs/heads/feature"].contains("share no namespace"));
+    }
+
+    #[tokio::test]
+    async fn only_refs_the_accepted_commands_changed_are_anchored() {
+        let fixture = Fixture::new();
+        fixture.push(&["main:refs/heads/old", "main:refs/heads/same"]);
+        let main = commit_file(&fixture.work(), "main.txt");
+        fixture.push(&["main:refs/heads/main", "main:refs/heads/rejected", ":refs/heads/old"]);
+
+        let commands = [
+            update("refs/heads/main", &fixture.base, &main),
+            update("refs/heads/old", &fixture.base, ZERO_ID),
+            update("refs/heads/same", &fixture.base, &fixture.base),
+            update("refs/heads/rejected", ZERO_ID, &main),
+            // git turned this one down, so the ref was never written.
+            update("refs/heads/refused", ZERO_ID, &main),
+        ];
+        let rejected = HashMap::from([("refs/heads/rejected".to_string(), "protected ref".to_string())]);
+        let changes = ref_changes(&fixture.bare(), &commands, &rejected).await.unwrap();
+
+        assert_eq!(changes, vec![
+            ("refs/heads/main".to_string(), main.clone()),
+            ("refs/heads/old".to_string(), ZERO_ID.to_string()),
+        ]);
+
+        let existing = [on_chain("refs/heads/main", &fixture.base), on_chain("refs/heads/old", &fixture.base)];
+        let updates = ref_updates(&changes, &existing, &HashMap::new());
+        assert_eq!(updates.len(), 2);
+        assert_eq!((updates[1].old.as_str(), updates[1].new.as_str()), (fixture.base.as_str(), ZERO_ID));
+    }
+
+    #[tokio::test]
+    async fn large_pushes_arrive_loose_so_rejecting_a_ref_keeps_the_rest() {
+        let fixture = Fixture::new();
+        let preexisting = fixture.object_paths();
+        let main = fixture.commit_many(120);
+        git(&fixture.work(), &["checkout", "--quiet", "-b", "notes", &fixture.base]);
+        let note = commit_file(&fixture.work(), "note.txt");
+        fixture.push_as_daemon(&["main:refs/heads/main", "notes:refs/notes/commits"]);
+
+        let commands = [update("refs/heads/main", &fixture.base, &main), update("refs/notes/commits", ZERO_ID, &note)];
+        let rejected = HashMap::from([("refs/notes/commits".to_string(), "outside the namespaces".to_string())]);
+        let existing = [on_chain("refs/heads/main", &fixture.base)];
+        let accepted = accepted_objects(&fixture.bare(), &commands, &rejected, &existing).await.unwrap();
+
+        let anchored: HashSet<String> = object_files(&fixture.bare())
+            .into_iter()
+            .filter(|object| !preexisting.contains(&object.path))
+            .map(|object| object.hash)
+            .filter(|hash| accepted.contains(hash))
+            .collect();
+        assert!(fixture.objects(&main).len() > 100);
+        assert_eq!(anchored, fixture.objects(&main));
+    }
+
+    #[tokio::test]
+    async fn large_pushes_are_measured_object_by_object() {
+        let fixture = Fixture::new();
+        let preexisting = fixture.object_paths();
+        let main = fixture.commit_many(120);
+        fixture.push_as_daemon(&["main:refs/heads/main"]);
+
+        let pushed: Vec<ObjectFile> = object_files(&fixture.bare())
+            .into_iter()
+            .filter(|object| !preexisting.contains(&object.path))
+            .collect();
+        assert_eq!(pushed.len(), fixture.objects(&main).len());
+        assert!(pushed.iter().all(|object| is_object_id(&object.hash)));
+
+        // Each object fits, but all of them packed together would not.
+        let limits = SizeLimits::new(8192, 0, pushed.len() as u64);
+        let sizes = || pushed.iter().map(|object| (object.hash.as_str(), object.size));
+        assert!(limits.check(sizes(), 0).is_ok());
+        assert!(limits.check(sizes(), 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn objects_only_rejected_refs_reach_are_not_anchored() {
+        let fixture = Fixture::new();
+        let main = commit_file(&fixture.work(), "main.txt");
+        git(&fixture.work(), &["checkout", "--quiet", "-b", "notes", &fixture.base]);
+        let note = commit_file(&fixture.work(), "note.txt");
+        fixture.push(&["main:refs/heads/main", "notes:refs/notes/commits"]);
+
+        let commands = [update("refs/heads/main", &fixture.base, &main), update("refs/notes/commits", ZERO_ID, &note)];
+        let rejected = HashMap::from([("refs/notes/commits".to_string(), "outside the namespaces".to_string())]);
+        let existing = [on_chain("refs/heads/main", &fixture.base)];
+        let accepted = accepted_objects(&fixture.bare(), &commands, &rejected, &existing).await.unwrap();
+
+        assert_eq!(accepted, fixture.objects(&main));
+        assert!(fixture.objects(&note).is_disjoint(&accepted));
+        assert!(!accepted.contains(&fixture.base));
+
+        let everything = HashMap::from([
+            ("refs/heads/main".to_string(), "rejected".to_string()),
+            ("refs/notes/commits".to_string(), "rejected".to_string()),
+        ]);
+        assert!(accepted_objects(&fixture.bare(), &commands, &everything, &existing).await.unwrap().is_empty());
+    }
+
+    fn protecting(pattern: &str, rule: ProtectionRule) -> RepoConfig {
+        RepoConfig {
+            protected_refs: vec![ProtectionRule { pattern: pattern.to_string(), ..rule }],
+            ..RepoConfig::default()
+        }
+    }
+
+    fn ref_value(bare: &Path, name: &str) -> String {
+        std::fs::read_to_string(bare.join(name)).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn force_pushes_to_protected_refs_are_rejected() {
+        let fixture = Fixture::new();
+        let forward = commit_file(&fixture.work(), "forward.txt");
+        git(&fixture.work(), &["checkout", "--quiet", "-b", "rewrite", &fixture.base]);
+        let rewrite = commit_file(&fixture.work(), "rewrite.txt");
+        let config = protecting("refs/heads/*", ProtectionRule { deny_force_push: true, ..ProtectionRule::default() });
+
+        fixture.push(&["main:refs/heads/main"]);
+        let commands = [update("refs/heads/main", &fixture.base, &forward)];
+        assert!(enforce_rules(&config, &fixture.bare(), &commands, &HashMap::new()).await.unwrap().is_empty());
+        assert_eq!(ref_value(&fixture.bare(), "refs/heads/main"), forward);
+
+        git(&fixture.work(), &["checkout", "--quiet", "-b", "feature", &fixture.base]);
+        let feature = commit_file(&fixture.work(), "feature.txt");
+        fixture.push(&["rewrite:refs/heads/main", "feature:refs/heads/feature"]);
+        let commands = [update("refs/heads/main", &forward, &rewrite), update("refs/heads/feature", ZERO_ID, &feature)];
+        let rejected = enforce_rules(&config, &fixture.bare(), &commands, &HashMap::new()).await.unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected["refs/heads/main"], "protected ref, force push is not allowed");
+        assert_eq!(ref_value(&fixture.bare(), "refs/heads/main"), forward);
+
+        let existing = [on_chain("refs/heads/main", &forward)];
+        let accepted = accepted_objects(&fixture.bare(), &commands, &rejected, &existing).await.unwrap();
+        assert_eq!(accepted, fixture.objects(&feature));
+        assert!(fixture.objects(&rewrite).is_disjoint(&accepted));
+    }
+
+    #[tokio::test]
+    async fn deleting_protected_refs_is_rejected() {
+        let fixture = Fixture::new();
+        git(&fixture.bare(), &["branch", "release", &fixture.base]);
+        git(&fixture.bare(), &["branch", "scratch", &fixture.base]);
+        let config = protecting("refs/heads/release", ProtectionRule { deny_delete: true, ..ProtectionRule::default() });
+        git(&fixture.bare(), &["branch", "-D", "release", "scratch"]);
+
+        let commands = [update("refs/heads/release", &fixture.base, ZERO_ID), update("refs/heads/scratch", &fixture.base, ZERO_ID)];
+        let rejected = enforce_rules(&config, &fixture.bare(), &commands, &HashMap::new()).await.unwrap();
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected["refs/heads/release"], "protected ref, deletion is not allowed");
+        assert_eq!(ref_value(&fixture.bare(), "refs/heads/release"), fixture.base);
+        assert!(!fixture.bare().join("refs/heads/scratch").exists());
+    }
+
+    #[tokio::test]
+    async fn admins_only_refs_reject_every_update_and_skip_earlier_rejections() {
+        let fixture = Fixture::new();
+        let config = protecting("refs/tags/*", ProtectionRule { admins_only: true, ..ProtectionRule::default() });
+        git(&fixture.bare(), &["tag", "v1", &fixture.base]);
+
+        let commands = [update("refs/tags/v1", ZERO_ID, &fixture.base), update("refs/heads/main", &fixture.base, &fixture.base)];
+        let rejected = enforce_rules(&config, &fixture.bare(), &commands, &HashMap::new()).await.unwrap();
+        assert_eq!(rejected.keys().collect::<Vec<_>>(), vec!["refs/tags/v1"]);
+        assert!(!fixture.bare().join("refs/tags/v1").exists());
+
+        let skip = HashMap::from([("refs/tags/v1".to_string(), "outside the namespaces".to_string())]);
+        assert!(enforce_rules(&config, &fixture.bare(), &commands, &skip).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn atomic_pushes_fail_every_ref_with_the_first() {
+        let pushed = ["refs/heads/main", "refs/heads/dev", "refs/tags/v1"].map(str::to_string);
+        let mut failures = HashMap::from([("refs/heads/dev".to_string(), "chain tx failed: reverted".to_string())]);
+
+        fail_atomically(&mut failures, &pushed, false);
+        assert_eq!(failures.len(), 1);
+
+        fail_atomically(&mut failures, &pushed, true);
+        assert_eq!(failures.len(), 3);
+        assert_eq!(failures["refs/heads/dev"], "chain tx failed: reverted");
+        assert_eq!(failures["refs/heads/main"], "atomic push failed");
+        assert_eq!(failures["refs/tags/v1"], "atomic push failed");
+
+        let mut none = HashMap::new();
+        fail_atomically(&mut none, &pushed, true);
+        assert!(none.is_empty());
+    }
+
+    fn push_with_options(options: &[&str]) -> Vec<Packet> {
+        let mut body = protocol::encode(format!("{} {} refs/heads/main\0report-status push-options\n", ZERO_ID, "1".repeat(40)).as_bytes());
+        body.extend_from_slice(b"0000");
+        for option in options {
+            body.extend(protocol::encode(format!("{}\n", option).as_bytes()));
+        }
+        body.extend_from_slice(b"0000");
+        body.extend_from_slice(b"PACK");
+        protocol::parse(&body).unwrap()
+    }
+
+    #[test]
+    fn push_options_are_parsed_as_key_value_pairs() {
+        let options = parse_push_options(&push_with_options(&["dgit-signer=0xabc", "ci.skip", "message=a=b"]));
+        assert_eq!(options, BTreeMap::from([
+            ("dgit-signer".to_string(), "0xabc".to_string()),
+            ("message".to_string(), "a=b".to_string()),
+        ]));
+
+        // Options only follow the commands when the client asked for them.
+        let mut packets = push_with_options(&["dgit-signer=0xabc"]);
+        packets[0] = Packet::Data(format!("{} {} refs/heads/main\0report-status\n", ZERO_ID, "1".repeat(40)).into_bytes());
+        assert!(parse_push_options(&packets).is_empty());
+    }
+
+    #[test]
+    fn the_signer_option_only_counts_when_it_names_the_caller() {
+        let caller = Address::from_low_u64_be(1);
+        let other = Address::from_low_u64_be(2);
+        let daemon = Address::from_low_u64_be(3);
+        let options = |signer: &str| BTreeMap::from([(SIGNER_OPTION.to_string(), signer.to_string()), ("ci".to_string(), "1".to_string())]);
+        let name = |address: Address| Some(format!("{:?}", address));
+
+        let mut matching = options(&format!("{:?}", caller));
+        assert_eq!(push_actor(&mut matching, Some(caller), Some(daemon), "project"), (name(caller), None));
+        assert!(!matching.contains_key(SIGNER_OPTION) && matching.contains_key("ci"));
+
+        let mut claimed = options(&format!("{:?}", other));
+        assert_eq!(push_actor(&mut claimed, Some(caller), Some(daemon), "project"), (name(caller), name(other)));
+
+        let mut anonymous = options(&format!("{:?}", other));
+        assert_eq!(push_actor(&mut anonymous, None, Some(daemon), "project"), (name(daemon), name(other)));
+
+        let mut invalid = options("not an address");
+        assert_eq!(push_actor(&mut invalid, None, None, "project"), (None, None));
+    }
 }
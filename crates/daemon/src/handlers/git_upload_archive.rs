@@ -0,0 +1,162 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse};
+use anyhow::{anyhow, Result};
+use onchain::contract_interaction::Ref;
+use tracing::{info, error, debug};
+use crate::{auth::ensure_read_access, body::read_git_body, git, handlers::default_branch, object_store::ObjectStore, protocol::{self, Packet}, error::{ApiError, NotFound}, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+pub async fn upload_archive(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    req_body: axum::body::Body,
+) -> impl IntoResponse {
+    let repo = path.name();
+    info!("Git upload-archive called for repo: {}", repo);
+    match handle_upload_archive(contract_state, repo, &headers, req_body).await {
+        Ok(response) => {
+            info!("Successfully processed upload-archive request, response size: {} bytes", response.len());
+
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(axum::http::header::CONTENT_TYPE, "application/x-git-upload-archive-result".parse().unwrap());
+            headers.insert(axum::http::header::CACHE_CONTROL, "no-cache".parse().unwrap());
+            headers.insert(axum::http::header::CONNECTION, "keep-alive".parse().unwrap());
+
+            (headers, response).into_response()
+        },
+        Err(e) => {
+            error!("Error in upload_archive: {:?}", e);
+            ApiError::from(e).into_response()
+        }
+    }
+}
+
+async fn handle_upload_archive(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    req_body: axum::body::Body,
+) -> Result<Vec<u8>> {
+    let repo = validate_repo_name(&repo)?;
+
+    info!("Looking up contract for repo: {}", repo);
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let body_bytes = read_git_body(headers, req_body).await?;
+    let packets = protocol::parse(&body_bytes)?;
+    let tree_ish = requested_tree_ish(&packets)
+        .ok_or_else(|| anyhow!("upload-archive: no tree-ish given"))?;
+    debug!("Client requested archive of {}", tree_ish);
+
+    info!("Fetching refs from blockchain for repo: {}", repo);
+    let refs = contract_state.ref_cache().active_refs(&repo, &contract).await?;
+    if refs.is_empty() {
+        return Err(anyhow!("Repository has no refs"));
+    }
+
+    let config = contract.get_repo_config().await?;
+    let head = default_branch(&refs, config.default_branch.as_deref());
+
+    let (hash, needs_history) = resolve_tree_ish(tree_ish, &refs, head)
+        .ok_or_else(|| anyhow!("upload-archive: unknown tree-ish {}", tree_ish))?;
+
+    if !contract.is_object_exist(hash.clone()).await? {
+        error!("Object {} not found in blockchain", hash);
+        return Err(anyhow!("upload-archive: {} is not stored on-chain", hash));
+    }
+
+    let temp_dir = git::tempdir()?;
+    let temp_path = temp_dir.path();
+    debug!("Created temporary directory: {:?}", temp_path);
+
+    let mut cmd = git::command();
+    cmd.args(["init", "--bare"]).current_dir(temp_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Failed to initialize git repo: {}", stderr));
+    }
+
+    for ref_data in refs.iter().filter(|r| r.is_active) {
+        let sha1 = String::from_utf8(ref_data.data.clone())?;
+        let ref_file_path = temp_path.join(&ref_data.name);
+        if let Some(parent) = ref_file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&ref_file_path, format!("{}\n", sha1.trim())).await?;
+    }
+
+    if let Some(head) = head {
+        tokio::fs::write(temp_path.join("HEAD"), format!("ref: {}\n", head)).await?;
+    }
+
+    let objects = contract_state.views().objects(&contract).await?;
+    let mut store = ObjectStore::new(temp_path, objects)?;
+
+    // An archive only needs the one tree, unless the tree-ish walks history
+    // (`main~2`) to find it.
+    if needs_history {
+        store.download_all().await?;
+    } else if !store.download_snapshot(&hash).await? {
+        return Err(anyhow!("upload-archive: tree of {} is not fully stored on-chain", tree_ish));
+    }
+    info!("Downloaded {} objects from IPFS", store.download_count());
+
+    // upload-archive has no --stateless-rpc: the client sends its arguments
+    // and a flush, and the archive comes back in one response anyway.
+    debug!("Running git upload-archive command");
+    let mut cmd = git::command();
+    cmd.args(["upload-archive", "."])
+        .current_dir(temp_path);
+
+    let output = git::run_with_input(cmd, body_bytes).await?;
+    let response = output.stdout;
+
+    if !output.status.success() {
+        let err_str = String::from_utf8_lossy(&output.stderr);
+        error!("git upload-archive stderr: {}", err_str);
+
+        if response.is_empty() {
+            return Err(anyhow!("git upload-archive failed: {}", err_str));
+        }
+    }
+
+    Ok(response)
+}
+
+/// The first non-option `argument` line; any later ones are paths.
+fn requested_tree_ish(packets: &[Packet]) -> Option<&str> {
+    packets
+        .iter()
+        .filter_map(Packet::text)
+        .filter_map(|line| line.strip_prefix("argument "))
+        .find(|argument| !argument.starts_with('-'))
+}
+
+/// Resolves the revision part of `tree_ish` to a hash through the on-chain
+/// refs, like git does for `<name>`, `refs/tags/<name>` and
+/// `refs/heads/<name>`. The flag is set when a `~`/`^` suffix needs history.
+fn resolve_tree_ish(tree_ish: &str, refs: &[Ref], head: Option<&str>) -> Option<(String, bool)> {
+    let rev = tree_ish.split(':').next().unwrap_or_default();
+    let end = rev.find(['~', '^']).unwrap_or(rev.len());
+    let (base, suffix) = rev.split_at(end);
+
+    let hash = if base.len() == 40 && base.chars().all(|c| c.is_ascii_hexdigit()) {
+        base.to_ascii_lowercase()
+    } else {
+        let name = match base {
+            "" | "HEAD" => head?.to_string(),
+            name => name.to_string(),
+        };
+        let candidates = [name.clone(), format!("refs/{}", name), format!("refs/tags/{}", name), format!("refs/heads/{}", name)];
+        let found = candidates
+            .iter()
+            .find_map(|candidate| refs.iter().find(|r| r.is_active && r.name == *candidate))?;
+        String::from_utf8_lossy(&found.data).trim().to_string()
+    };
+
+    Some((hash, !suffix.is_empty()))
+}
@@ -1,21 +1,18 @@
-use axum::{extract::{Path, State}, response::IntoResponse};
-use anyhow::{anyhow, Result};
-use tokio::process::Command;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::{info, error, debug};
-use tempfile::tempdir;
-use crate::state::ContractState;
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse};
+use anyhow::{anyhow, bail, Result};
+use tracing::{info, error, debug, warn};
+use crate::{auth::ensure_read_access, body::read_git_body, git, object_store::{parse_depth_request, ObjectStore}, protocol::{self, Packet}, error::{ApiError, NotFound}, extract::RepoPath, handlers::write_head, state::ContractState, validation::validate_repo_name};
 use std::path::PathBuf;
-use std::process::Stdio;
-use onchain::ipfs;
 
 pub async fn upload_pack(
     State(contract_state): State<ContractState>,
-    Path(repo): Path<String>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
     req_body: axum::body::Body,
 ) -> impl IntoResponse {
+    let repo = path.name();
     info!("Git upload-pack called for repo: {}", repo);
-    match handle_upload_pack(contract_state, repo, req_body).await {
+    match handle_upload_pack(contract_state, repo, &headers, req_body).await {
         Ok(response) => {
             info!("Successfully processed upload-pack request, response size: {} bytes", response.len());
 
@@ -28,7 +25,7 @@ pub async fn upload_pack(
         },
         Err(e) => {
             error!("Error in upload_pack: {:?}", e);
-            (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            ApiError::from(e).into_response()
         }
     }
 }
@@ -36,47 +33,43 @@ pub async fn upload_pack(
 async fn handle_upload_pack(
     contract_state: ContractState,
     repo: String,
+    headers: &HeaderMap,
     req_body: axum::body::Body,
 ) -> Result<Vec<u8>> {
+    let repo = validate_repo_name(&repo)?;
+
     info!("Looking up contract for repo: {}", repo);
     let contract = contract_state.get_contract(&repo).await
-        .ok_or_else(|| anyhow!("Repository not found"))?;
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
 
-    let temp_dir = tempdir()?;
-    let temp_path = temp_dir.path();
-    debug!("Created temporary directory: {:?}", temp_path);
+    ensure_read_access(&contract, headers).await?;
 
-    let output = Command::new("git")
-        .args(["init", "--bare"])
-        .current_dir(temp_path)
-        .output()
-        .await?;
+    let body_bytes = read_git_body(headers, req_body).await?;
+    debug!("Client request size: {} bytes", body_bytes.len());
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to initialize git repo: {}", stderr));
+    let packets = protocol::parse_complete(&body_bytes)
+        .map_err(|e| anyhow!("Malformed upload-pack request: {:#}", e))?;
+    if !wants_anything(&packets)? {
+        info!("Upload-pack request wants nothing, answering without downloading objects");
+        return Ok(Vec::new());
     }
+    let command = v2_command(&packets);
+    let wanted_commits = parse_wanted_objects(&packets);
 
-    info!("Fetching refs from blockchain for repo: {}", repo);
-    let refs = contract.get_refs().await?;
-    info!("Found {} refs for repo {}", refs.len(), repo);
-
-    if refs.is_empty() {
-        return Err(anyhow!("Repository has no refs"));
+    // Everything in a snapshot came from the chain, so git can answer on
+    // its own, including rejecting wants that are not ours.
+    if let Some(snapshot) = contract_state.snapshots().get(&repo, &contract).await {
+        return run_upload_pack(snapshot.path(), headers, body_bytes).await;
     }
 
-    let refs_dir = temp_path.join("refs");
-    let heads_dir = refs_dir.join("heads");
-    tokio::fs::create_dir_all(&heads_dir).await?;
+    let workspace = contract_state.workspaces().acquire(&repo, &contract).await?;
+    let temp_path = workspace.path();
 
-    let tags_dir = refs_dir.join("tags");
-    tokio::fs::create_dir_all(&tags_dir).await?;
+    info!("Fetching refs from blockchain for repo: {}", repo);
+    let refs = contract_state.ref_cache().active_refs(&repo, &contract).await?;
+    info!("Found {} refs for repo {}", refs.len(), repo);
 
-    let objects_dir = temp_path.join("objects");
-    let objects_info_dir = objects_dir.join("info");
-    let objects_pack_dir = objects_dir.join("pack");
-    tokio::fs::create_dir_all(&objects_info_dir).await?;
-    tokio::fs::create_dir_all(&objects_pack_dir).await?;
+    workspace.clear_refs().await?;
 
     for ref_data in &refs {
         if ref_data.is_active {
@@ -94,14 +87,34 @@ async fn handle_upload_pack(
         }
     }
 
-    let body_bytes = axum::body::to_bytes(req_body, usize::MAX).await?;
-    debug!("Client request size: {} bytes", body_bytes.len());
+    // An empty repository is served as one, so `ls-refs` of a clone lists
+    // nothing rather than failing.
+    let config = contract.get_repo_config().await?;
+    write_head(temp_path, &refs, config.default_branch.as_deref()).await?;
+
+    let git_protocol = protocol::git_protocol(headers);
+    debug!("Git protocol: {:?}, v2 command: {:?}", git_protocol, command);
+
+    // v0 clients choose `side-band-64k`, `no-progress` and the like on their
+    // first want. git upload-pack reads them from the body, which it gets
+    // untouched, and frames its response to match.
+    debug!("Client capabilities: {:?}", protocol::want_capabilities(&packets));
 
-    let wanted_commits = parse_wanted_objects(&body_bytes)?;
     info!("Client wants {} commits", wanted_commits.len());
+    if let Some(filter) = parse_filter(&packets) {
+        // git upload-pack leaves out what the filter excludes; everything
+        // is still downloaded so it has the objects to decide with.
+        info!("Partial clone requested with filter {}", filter);
+    }
 
     if !wanted_commits.is_empty() {
-        for commit_hash in &wanted_commits {
+        // The synced view answers for most wants; the contract is asked only
+        // about the rest, which may have been pushed since the last sync.
+        let indexed = contract_state.views().has_objects(&contract, &wanted_commits).await.unwrap_or_else(|e| {
+            warn!("Failed to check wants against the synced view: {:#}", e);
+            vec![false; wanted_commits.len()]
+        });
+        for (commit_hash, _) in wanted_commits.iter().zip(indexed).filter(|(_, indexed)| !indexed) {
             debug!("Checking if commit {} exists in contract", commit_hash);
             match contract.is_object_exist(commit_hash.clone()).await {
                 Ok(true) => {
@@ -119,46 +132,56 @@ async fn handle_upload_pack(
         }
     }
 
-    let objects = contract.get_objects().await?;
-    info!("Fetched {} objects from blockchain", objects.len());
-
-    for object in objects {
-        let object_hash = object.hash;
-        let ipfs_url = String::from_utf8(object.ipfs_url)?;
-        let object_path = get_object_path(temp_path, &object_hash);
-
-        let local_path = objects_dir.join(object_path);
-        let local_path_str = local_path.to_string_lossy();
-
-        ipfs::download_from_ipfs(&ipfs_url, &local_path_str).await?;
+    // v2 `ls-refs` (and other non-fetch commands) only need the refs.
+    if command.is_none_or(|command| command == "fetch") {
+        let objects = contract_state.views().objects(&contract).await?;
+        info!("Fetched {} objects from blockchain", objects.len());
+
+        let mut store = ObjectStore::new(temp_path, objects)?;
+        let depth = parse_depth_request(packets.iter().filter_map(Packet::text));
+
+        // A shallow fetch only needs the history it asked for; git
+        // upload-pack computes the shallow boundary from what is on disk.
+        let complete = match &depth {
+            Some(depth) if !wanted_commits.is_empty() => {
+                info!("Shallow fetch requested: {:?}", depth);
+                store.download_shallow(&wanted_commits, depth).await?
+            },
+            _ => false,
+        };
+
+        if !complete {
+            store.download_all().await?;
+        }
+        info!("Downloaded {} objects from IPFS", store.download_count());
     }
 
-    debug!("Running git upload-pack command");
-    let mut cmd = Command::new("git");
-    cmd.args(["upload-pack", "--stateless-rpc", "."])
-        .current_dir(temp_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    let mut child = cmd.spawn()?;
+    let response = run_upload_pack(temp_path, headers, body_bytes).await?;
+    contract_state.workspaces().release(workspace).await;
+    Ok(response)
+}
 
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(&body_bytes).await?;
+/// Answers the request in `body_bytes` with `git upload-pack` run in the
+/// bare repository at `repo_path`.
+///
+/// The response goes back byte for byte, so sideband frames and progress
+/// (or its absence under `no-progress`) are exactly what git produced.
+/// git's stderr is only logged: mixing it into the response would corrupt
+/// the pkt-line stream.
+async fn run_upload_pack(repo_path: &std::path::Path, headers: &HeaderMap, body_bytes: Vec<u8>) -> Result<Vec<u8>> {
+    debug!("Running git upload-pack command");
+    let mut cmd = git::upload_pack();
+    cmd.args(["--stateless-rpc", "."])
+        .current_dir(repo_path);
+    if let Some(git_protocol) = protocol::git_protocol(headers) {
+        cmd.env("GIT_PROTOCOL", git_protocol);
     }
 
-    let mut response = Vec::new();
-    if let Some(mut stdout) = child.stdout.take() {
-        stdout.read_to_end(&mut response).await?;
-    }
+    let output = git::run_with_input(cmd, body_bytes).await?;
+    let response = output.stdout;
 
-    let status = child.wait().await?;
-    if !status.success() {
-        let mut err_msg = Vec::new();
-        if let Some(mut stderr) = child.stderr.take() {
-            stderr.read_to_end(&mut err_msg).await?;
-        }
-        let err_str = String::from_utf8_lossy(&err_msg);
+    if !output.status.success() {
+        let err_str = String::from_utf8_lossy(&output.stderr);
         error!("git upload-pack stderr: {}", err_str);
 
         if response.is_empty() {
@@ -170,20 +193,57 @@ async fn handle_upload_pack(
     Ok(response)
 }
 
-fn parse_wanted_objects(body: &[u8]) -> Result<Vec<String>> {
-    let body_str = std::str::from_utf8(body)?;
-    let mut wanted = Vec::new();
+fn parse_wanted_objects(packets: &[Packet]) -> Vec<String> {
+    packets
+        .iter()
+        .filter_map(Packet::text)
+        .filter_map(|line| line.strip_prefix("want "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(|hash| hash.to_string())
+        .collect()
+}
 
-    for line in body_str.lines() {
-        if line.starts_with("want ") {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                wanted.push(parts[1].to_string());
-            }
+/// Whether git has to be set up to answer the request. A v0 request of
+/// nothing but a flush wants nothing, and git upload-pack answers it with
+/// nothing; any other request that wants nothing is malformed.
+fn wants_anything(packets: &[Packet]) -> Result<bool> {
+    let command = v2_command(packets);
+    let wants = !parse_wanted_objects(packets).is_empty();
+    if command.is_none() && !wants {
+        if is_empty_request(packets) {
+            return Ok(false);
         }
+        bail!("Malformed upload-pack request: no want lines");
+    }
+    if command == Some("fetch") && !wants && !wants_refs(packets) {
+        bail!("Malformed upload-pack request: fetch without wants");
     }
+    Ok(true)
+}
+
+/// Whether a v0 request is a bare flush: a client that wants nothing.
+fn is_empty_request(packets: &[Packet]) -> bool {
+    !packets.is_empty() && packets.iter().all(|packet| *packet == Packet::Flush)
+}
 
-    Ok(wanted)
+/// Whether a v2 fetch names refs with `want-ref` rather than objects.
+fn wants_refs(packets: &[Packet]) -> bool {
+    packets.iter().filter_map(Packet::text).any(|line| line.starts_with("want-ref "))
+}
+
+/// The object filter of a partial clone or fetch, sent as a `filter <spec>`
+/// line in both v0 and v2 requests.
+fn parse_filter(packets: &[Packet]) -> Option<&str> {
+    packets
+        .iter()
+        .filter_map(Packet::text)
+        .find_map(|line| line.strip_prefix("filter "))
+        .map(str::trim)
+}
+
+/// The `command=` of a protocol v2 request, if the body is one.
+fn v2_command(packets: &[Packet]) -> Option<&str> {
+    packets.first()?.text()?.strip_prefix("command=")
 }
 
 pub fn get_object_path(repo_path: &std::path::Path, hash: &str) -> PathBuf {
@@ -195,3 +255,138 @@ pub fn get_object_path(repo_path: &std::path::Path, hash: &str) -> PathBuf {
     let file = &hash[2..];
     repo_path.join("objects").join(dir).join(file)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use std::path::Path;
+
+    /// A v0 request for `want`, with `filter` if given.
+    fn request(want: &str, filter: Option<&str>) -> Vec<u8> {
+        let mut body = protocol::encode(format!("want {} filter no-progress\n", want).as_bytes());
+        if let Some(filter) = filter {
+            body.extend(protocol::encode(format!("filter {}\n", filter).as_bytes()));
+        }
+        body.extend_from_slice(b"0000");
+        body.extend(protocol::encode(b"done\n"));
+        body
+    }
+
+    /// How many objects of each type the pack in an upload-pack response
+    /// holds.
+    fn pack_contents(dir: &Path, response: &[u8]) -> Vec<String> {
+        let start = response.windows(4).position(|window| window == b"PACK").expect("response carries a pack");
+        let pack = dir.join("response.pack");
+        std::fs::write(&pack, &response[start..]).unwrap();
+
+        let index = dir.join("index");
+        git(dir, &["init", "--quiet", "--bare", index.to_str().unwrap()]);
+        git(&index, &["index-pack", pack.to_str().unwrap()]);
+        git(&index, &["verify-pack", "--verbose", pack.to_str().unwrap()])
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .filter(|kind| ["commit", "tree", "blob", "tag"].contains(kind))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn filters_are_read_from_v0_and_v2_requests() {
+        let v0 = protocol::parse_complete(&request("aa", Some("blob:none"))).unwrap();
+        assert_eq!(parse_filter(&v0), Some("blob:none"));
+
+        let mut v2 = protocol::encode(b"command=fetch\n");
+        v2.extend_from_slice(b"0001");
+        v2.extend(protocol::encode(b"want aa\n"));
+        v2.extend(protocol::encode(b"filter tree:0\n"));
+        v2.extend_from_slice(b"0000");
+        assert_eq!(parse_filter(&protocol::parse_complete(&v2).unwrap()), Some("tree:0"));
+
+        assert_eq!(parse_filter(&protocol::parse_complete(&request("aa", None)).unwrap()), None);
+    }
+
+    #[tokio::test]
+    async fn partial_clones_leave_out_what_the_filter_excludes() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = dir.path().join("work");
+        std::fs::create_dir_all(work.join("docs")).unwrap();
+        std::fs::write(work.join("README"), "readme").unwrap();
+        std::fs::write(work.join("docs/guide.md"), "guide").unwrap();
+        git(&work, &["init", "--quiet"]);
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "--quiet", "-m", "docs"]);
+        let head = git(&work, &["rev-parse", "HEAD"]);
+        git(dir.path(), &["clone", "--quiet", "--bare", work.to_str().unwrap(), "bare.git"]);
+        let bare = dir.path().join("bare.git");
+
+        let full = run_upload_pack(&bare, &HeaderMap::new(), request(&head, None)).await.unwrap();
+        let mut kinds = pack_contents(dir.path(), &full);
+        kinds.sort();
+        assert_eq!(kinds, ["blob", "blob", "commit", "tree", "tree"]);
+
+        std::fs::remove_dir_all(dir.path().join("index")).unwrap();
+        let partial = run_upload_pack(&bare, &HeaderMap::new(), request(&head, Some("blob:none"))).await.unwrap();
+        let mut kinds = pack_contents(dir.path(), &partial);
+        kinds.sort();
+        assert_eq!(kinds, ["commit", "tree", "tree"]);
+    }
+
+    fn request_packets(body: &[u8]) -> Vec<Packet> {
+        protocol::parse_complete(body).unwrap()
+    }
+
+    #[test]
+    fn requests_must_be_nothing_but_pkt_lines() {
+        let body = request("aa", None);
+        assert_eq!(request_packets(&body).len(), 3);
+        assert!(protocol::parse_complete(&[body.as_slice(), &b"PACK"[..]].concat()).is_err());
+        assert!(protocol::parse_complete(b"0032want aa").is_err());
+        assert!(protocol::parse_complete(b"0003").is_err());
+        assert_eq!(protocol::parse(&[body.as_slice(), &b"PACK"[..]].concat()).unwrap(), request_packets(&body));
+    }
+
+    #[test]
+    fn a_bare_flush_wants_nothing() {
+        assert!(!wants_anything(&request_packets(b"0000")).unwrap());
+        assert!(!wants_anything(&request_packets(b"00000000")).unwrap());
+    }
+
+    #[test]
+    fn requests_without_wants_are_malformed() {
+        let mut haves = protocol::encode(format!("have {}\n", "1".repeat(40)).as_bytes());
+        haves.extend_from_slice(b"0000");
+        haves.extend(protocol::encode(b"done\n"));
+        assert!(wants_anything(&request_packets(&haves)).is_err());
+        assert!(wants_anything(&[]).is_err());
+
+        let mut fetch = protocol::encode(b"command=fetch\n");
+        fetch.extend_from_slice(b"0001");
+        fetch.extend(protocol::encode(b"done\n"));
+        fetch.extend_from_slice(b"0000");
+        assert!(wants_anything(&request_packets(&fetch)).is_err());
+    }
+
+    #[test]
+    fn requests_with_wants_need_git() {
+        assert!(wants_anything(&request_packets(&request("aa", None))).unwrap());
+
+        let v2 = |lines: &[&str]| {
+            let mut body = Vec::new();
+            for line in lines {
+                body.extend(protocol::encode(format!("{}\n", line).as_bytes()));
+            }
+            body.extend_from_slice(b"0000");
+            request_packets(&body)
+        };
+        assert!(wants_anything(&v2(&["command=fetch", "want-ref refs/heads/main"])).unwrap());
+        assert!(wants_anything(&v2(&["command=ls-refs", "peel"])).unwrap());
+    }
+
+    #[tokio::test]
+    async fn git_also_answers_a_bare_flush_with_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet", "--bare"]);
+        assert!(run_upload_pack(dir.path(), &HeaderMap::new(), b"0000".to_vec()).await.unwrap().is_empty());
+    }
+}
@@ -1,5 +1,33 @@
-use axum::response::IntoResponse;
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use onchain::{config::Config, transport::{rpc_health, EndpointHealth}};
+use serde::Serialize;
+
+use crate::git::{self, GitProbe};
 
 pub async fn health_check() -> impl IntoResponse {
     "ok"
 }
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    /// `CHAIN_ID`, which every endpoint must be on if set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    pub rpc: Vec<EndpointHealth>,
+    /// The git binary found at startup; not ready when it is unusable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitProbe>,
+}
+
+/// Ready while at least one RPC endpoint is not cooling down after a
+/// failure and, with `CHAIN_ID` set, is not on another chain, and git
+/// passed the startup check; 503 otherwise, with each endpoint's state
+/// and the git check either way.
+pub async fn ready_check() -> impl IntoResponse {
+    let rpc = rpc_health();
+    let git = git::probed().cloned();
+    let ready = rpc.iter().any(|endpoint| endpoint.healthy) && git.as_ref().is_none_or(|git| git.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadyResponse { ready, chain_id: Config::chain_id(), rpc, git }))
+}
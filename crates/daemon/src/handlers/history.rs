@@ -0,0 +1,121 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Result};
+use onchain::contract_interaction::{RepoChange, RepoEvent};
+
+use crate::{auth::ensure_read_access, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name, webhooks::ZERO_ID};
+
+/// Widest block range one history request may cover. Without `from_block`,
+/// the history starts this many blocks before `to_block`.
+pub const MAX_HISTORY_BLOCKS: u64 = 1_000_000;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from_block: Option<u64>,
+    /// Defaults to the latest block.
+    pub to_block: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Object,
+    Ref,
+}
+
+/// One object or ref recorded on chain.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub block_number: u64,
+    pub tx_hash: String,
+    pub pusher: String,
+    pub kind: ChangeKind,
+    /// The object's hash or the ref's name.
+    pub name: String,
+    /// Where the object is stored, or the commit the ref points to (all
+    /// zeros once it is deleted).
+    pub target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub repo: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub changes: Vec<HistoryEntry>,
+}
+
+pub async fn repo_history(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<HistoryQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_repo_history(contract_state, path.name(), query, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_repo_history(
+    contract_state: ContractState,
+    repo: String,
+    query: HistoryQuery,
+    headers: &HeaderMap,
+) -> Result<HistoryResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let to_block = match query.to_block {
+        Some(block) => block,
+        None => contract.block_number().await?,
+    };
+    let from_block = query.from_block.unwrap_or_else(|| to_block.saturating_sub(MAX_HISTORY_BLOCKS - 1));
+    if from_block > to_block {
+        bail!("from_block {} is after to_block {}", from_block, to_block);
+    }
+    if to_block - from_block >= MAX_HISTORY_BLOCKS {
+        bail!(
+            "Block range {}..={} is too wide (at most {} blocks per request)",
+            from_block, to_block, MAX_HISTORY_BLOCKS,
+        );
+    }
+
+    let changes = contract.get_repo_changes(from_block, to_block).await?
+        .into_iter()
+        .map(history_entry)
+        .collect();
+
+    Ok(HistoryResponse { repo, from_block, to_block, changes })
+}
+
+fn history_entry(change: RepoChange) -> HistoryEntry {
+    let (kind, name, target, pusher) = match change.event {
+        RepoEvent::ObjectSaved(object) => (
+            ChangeKind::Object,
+            object.hash,
+            String::from_utf8_lossy(&object.ipfs_url).to_string(),
+            object.pusher,
+        ),
+        RepoEvent::RefAdded(r) => (
+            ChangeKind::Ref,
+            r.name,
+            String::from_utf8_lossy(&r.data).trim().to_string(),
+            r.pusher,
+        ),
+        // A deletion, as git writes it.
+        RepoEvent::RefDeactivated { name, pusher } => (ChangeKind::Ref, name, ZERO_ID.to_string(), pusher),
+    };
+
+    HistoryEntry {
+        block_number: change.block_number,
+        tx_hash: format!("{:?}", change.tx_hash),
+        pusher: format!("{:?}", pusher),
+        kind,
+        name,
+        target,
+    }
+}
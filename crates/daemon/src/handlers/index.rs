@@ -0,0 +1,98 @@
+use axum::{
+    extract::State,
+    http::{header::ACCEPT, HeaderMap},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{handlers::API_LEVEL, state::ContractState};
+
+/// What the landing page lists, as method, path and description.
+const ENDPOINTS: &[(&str, &str, &str)] = &[
+    ("GET", "/{repo}/info/refs", "Git smart HTTP: clone, fetch and push"),
+    ("POST", "/create-repo/{repo}", "Create a repository"),
+    ("GET", "/repo/{repo}/info", "Repository summary"),
+    ("GET", "/repo/{repo}/refs", "Refs and their values"),
+    ("GET", "/repo/{repo}/roles", "Admins, pushers and readers"),
+    ("GET", "/repo/{repo}/audit", "Audit log"),
+    ("GET", "/repo/{repo}/history", "Object and ref changes in a block range"),
+    ("GET", "/repo/{repo}/verify", "Check every object against IPFS"),
+    ("GET", "/repo/{repo}/pins", "IPFS CIDs pinned for the repository, with sizes"),
+    ("GET", "/health", "Liveness"),
+    ("GET", "/ready", "Readiness, with each RPC endpoint's state"),
+    ("GET", "/version", "Version and API level"),
+    ("POST", "/whoami", "Recover the signer of a message"),
+    ("GET", "/metrics", "Prometheus metrics"),
+    ("GET", "/admin/disk-usage", "Disk used by scratch directories"),
+    ("POST", "/admin/gc", "Remove orphaned scratch directories"),
+    ("POST", "/admin/rpc", "Switch to other RPC endpoints"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct IndexResponse {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub api_level: u32,
+    /// Repositories this daemon knows the contract of.
+    pub repositories: usize,
+    pub endpoints: Vec<Endpoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Endpoint {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub description: &'static str,
+}
+
+/// A landing page for people who open the daemon in a browser, or JSON for
+/// clients that ask for it with `Accept: application/json`.
+pub async fn index(State(contract_state): State<ContractState>, headers: HeaderMap) -> Response {
+    let index = IndexResponse {
+        name: "dgit-daemon",
+        version: env!("CARGO_PKG_VERSION"),
+        api_level: API_LEVEL,
+        repositories: contract_state.contracts().await.len(),
+        endpoints: ENDPOINTS
+            .iter()
+            .map(|&(method, path, description)| Endpoint { method, path, description })
+            .collect(),
+    };
+
+    let wants_json = headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    if wants_json {
+        return Json(index).into_response();
+    }
+
+    let rows: String = index
+        .endpoints
+        .iter()
+        .map(|endpoint| format!(
+            "<tr><td>{}</td><td><code>{}</code></td><td>{}</td></tr>\n",
+            endpoint.method, endpoint.path, endpoint.description
+        ))
+        .collect();
+    Html(format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head><meta charset=\"utf-8\"><title>{name}</title></head>\n\
+         <body>\n\
+         <h1>{name} {version}</h1>\n\
+         <p>API level {api_level}, serving {repositories} known repositories.</p>\n\
+         <table>\n\
+         <tr><th>Method</th><th>Path</th><th></th></tr>\n\
+         {rows}\
+         </table>\n\
+         </body>\n\
+         </html>\n",
+        name = index.name,
+        version = index.version,
+        api_level = index.api_level,
+        repositories = index.repositories,
+        rows = rows,
+    )).into_response()
+}
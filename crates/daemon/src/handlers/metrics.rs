@@ -0,0 +1,93 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+use tracing::warn;
+
+use crate::state::ContractState;
+
+/// Counters in the Prometheus text format.
+pub async fn metrics(State(contract_state): State<ContractState>) -> impl IntoResponse {
+    let cache = contract_state.object_cache();
+    let snapshots = contract_state.snapshots();
+    let limits = contract_state.request_limits();
+    let workspaces = contract_state.workspaces();
+    let work_dirs = contract_state.work_dirs();
+    // A root that cannot be read shows as empty; /admin/disk-usage says why.
+    let (work_bytes, orphaned_bytes) = match work_dirs.usage().await {
+        Ok(usage) => (usage.bytes, usage.orphaned_bytes),
+        Err(e) => {
+            warn!("Failed to measure {}: {:#}", work_dirs.root().display(), e);
+            (0, 0)
+        },
+    };
+    let body = format!(
+        "# HELP dgit_object_cache_hits_total Object existence checks answered from memory.\n\
+         # TYPE dgit_object_cache_hits_total counter\n\
+         dgit_object_cache_hits_total {}\n\
+         # HELP dgit_object_cache_misses_total Object existence checks sent to the contract.\n\
+         # TYPE dgit_object_cache_misses_total counter\n\
+         dgit_object_cache_misses_total {}\n\
+         # HELP dgit_object_cache_evictions_total Known objects dropped to stay within DGIT_OBJECT_CACHE_MAX_PER_REPO.\n\
+         # TYPE dgit_object_cache_evictions_total counter\n\
+         dgit_object_cache_evictions_total {}\n\
+         # HELP dgit_object_index_reused_total Objects not uploaded to IPFS again because another push had uploaded them.\n\
+         # TYPE dgit_object_index_reused_total counter\n\
+         dgit_object_index_reused_total {}\n\
+         # HELP dgit_snapshot_hits_total Clones and fetches served from a snapshot.\n\
+         # TYPE dgit_snapshot_hits_total counter\n\
+         dgit_snapshot_hits_total {}\n\
+         # HELP dgit_snapshot_misses_total Clones and fetches that rebuilt the repository.\n\
+         # TYPE dgit_snapshot_misses_total counter\n\
+         dgit_snapshot_misses_total {}\n\
+         # HELP dgit_workspaces_reused_total Git requests served from a workspace an earlier request prepared.\n\
+         # TYPE dgit_workspaces_reused_total counter\n\
+         dgit_workspaces_reused_total {}\n\
+         # HELP dgit_workspaces_created_total Git requests that created a workspace.\n\
+         # TYPE dgit_workspaces_created_total counter\n\
+         dgit_workspaces_created_total {}\n\
+         # HELP dgit_workspaces_idle_bytes Disk used by idle workspaces.\n\
+         # TYPE dgit_workspaces_idle_bytes gauge\n\
+         dgit_workspaces_idle_bytes {}\n\
+         # HELP dgit_work_dir_bytes Disk used by scratch directories under DGIT_TMP_DIR, idle workspaces included.\n\
+         # TYPE dgit_work_dir_bytes gauge\n\
+         dgit_work_dir_bytes {}\n\
+         # HELP dgit_work_dir_orphaned_bytes Disk used by scratch directories the next sweep removes.\n\
+         # TYPE dgit_work_dir_orphaned_bytes gauge\n\
+         dgit_work_dir_orphaned_bytes {}\n\
+         # HELP dgit_work_dirs_swept_total Orphaned scratch directories removed.\n\
+         # TYPE dgit_work_dirs_swept_total counter\n\
+         dgit_work_dirs_swept_total {}\n\
+         # HELP dgit_requests_in_flight Requests being handled.\n\
+         # TYPE dgit_requests_in_flight gauge\n\
+         dgit_requests_in_flight {}\n\
+         # HELP dgit_pack_operations_in_flight Fetches and pushes holding one of DGIT_PACK_CONCURRENCY slots.\n\
+         # TYPE dgit_pack_operations_in_flight gauge\n\
+         dgit_pack_operations_in_flight {}\n\
+         # HELP dgit_pack_operations_queued Fetches and pushes waiting for a slot.\n\
+         # TYPE dgit_pack_operations_queued gauge\n\
+         dgit_pack_operations_queued {}\n\
+         # HELP dgit_pack_operations_rejected_total Fetches and pushes refused with 503 because the queue was full.\n\
+         # TYPE dgit_pack_operations_rejected_total counter\n\
+         dgit_pack_operations_rejected_total {}\n\
+         # HELP dgit_requests_timed_out_total Requests answered with 504 after their timeout.\n\
+         # TYPE dgit_requests_timed_out_total counter\n\
+         dgit_requests_timed_out_total {}\n",
+        cache.hits(),
+        cache.misses(),
+        cache.evictions(),
+        contract_state.object_index().reused(),
+        snapshots.hits(),
+        snapshots.misses(),
+        workspaces.reused(),
+        workspaces.created(),
+        workspaces.idle_bytes(),
+        work_bytes,
+        orphaned_bytes,
+        work_dirs.swept(),
+        limits.in_flight(),
+        limits.packs_in_flight(),
+        limits.packs_queued(),
+        limits.packs_rejected(),
+        limits.timed_out(),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
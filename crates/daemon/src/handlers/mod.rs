@@ -1,13 +1,65 @@
+mod admin;
+mod anchoring;
+mod audit;
 mod git_receive_pack;
+mod git_upload_archive;
 mod git_upload_pack;
 mod health;
+mod history;
+mod metrics;
 mod create_repo;
+mod dumb_http;
+mod export;
+mod fork;
+mod fsck;
+mod gc;
 mod git_info_refs;
+mod index;
+mod objects;
+mod protection;
+mod prune;
+mod registry;
+mod pins;
+mod repin;
+mod repo_config;
+mod repo_info;
 mod role_management;
+mod signing_keys;
+mod verify;
+mod version;
+mod visibility;
+mod webhooks;
+mod whoami;
 
+pub use admin::*;
+pub use anchoring::*;
+pub use audit::*;
 pub use git_receive_pack::*;
+pub use git_upload_archive::*;
 pub use git_upload_pack::*;
 pub use health::*;
+pub use history::*;
+pub use metrics::*;
 pub use create_repo::*;
+pub use dumb_http::*;
+pub use export::*;
+pub use fork::*;
+pub use fsck::*;
+pub use gc::*;
 pub use git_info_refs::*;
-pub use role_management::*;
\ No newline at end of file
+pub use index::*;
+pub use objects::*;
+pub use protection::*;
+pub use prune::*;
+pub use registry::*;
+pub use pins::*;
+pub use repin::*;
+pub use repo_config::*;
+pub use repo_info::*;
+pub use role_management::*;
+pub use signing_keys::*;
+pub use verify::*;
+pub use version::*;
+pub use visibility::*;
+pub use webhooks::*;
+pub use whoami::*;
\ No newline at end of file
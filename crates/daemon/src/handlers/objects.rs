@@ -0,0 +1,99 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Result};
+
+use crate::{auth::ensure_read_access, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+/// Upper bound on hashes per `has-objects` call; the check is a single
+/// contract call and large batches can exceed the node's gas limit for calls.
+pub const MAX_HAS_OBJECTS: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct HasObjectsResponse {
+    pub repo: String,
+    pub hashes: Vec<String>,
+    pub exists: Vec<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefEntry {
+    pub name: String,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefsResponse {
+    pub repo: String,
+    pub refs: Vec<RefEntry>,
+}
+
+pub async fn has_objects(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(hashes): Json<Vec<String>>,
+) -> impl IntoResponse {
+    match handle_has_objects(contract_state, path.name(), &headers, hashes).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_has_objects(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    hashes: Vec<String>,
+) -> Result<HasObjectsResponse> {
+    let repo = validate_repo_name(&repo)?;
+
+    if hashes.len() > MAX_HAS_OBJECTS {
+        bail!("Too many hashes: {} (at most {} per request)", hashes.len(), MAX_HAS_OBJECTS);
+    }
+
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    if hashes.is_empty() {
+        return Ok(HasObjectsResponse { repo, hashes, exists: Vec::new() });
+    }
+
+    let exists = contract_state.object_cache().check(&repo, &contract, &hashes).await?;
+
+    Ok(HasObjectsResponse { repo, hashes, exists })
+}
+
+pub async fn list_refs(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_list_refs(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_list_refs(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<RefsResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let refs = contract_state.ref_cache().active_refs(&repo, &contract).await?
+        .into_iter()
+        .map(|r| RefEntry {
+            name: r.name,
+            target: String::from_utf8_lossy(&r.data).trim().to_string(),
+        })
+        .collect();
+
+    Ok(RefsResponse { repo, refs })
+}
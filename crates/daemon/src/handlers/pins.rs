@@ -0,0 +1,265 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::{contract_interaction::ContractInteraction, ipfs::{self, IpfsBackend}, storage};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::{audit::{AuditAction, AuditEntry}, auth::{ensure_admin, ensure_read_access, unix_now}, config::DaemonConfig, error::ApiError, extract::RepoPath, state::ContractState, validation::{normalize_repo_name, validate_repo_name}};
+
+/// Block sizes looked up at once.
+const STAT_CONCURRENCY: usize = 8;
+const PIN_API_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+pub struct PinInfo {
+    pub cid: String,
+    pub hash: String,
+    /// Unknown when the IPFS node could not say.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Whether the repository still records the object on chain.
+    pub referenced: bool,
+    /// Whether another repository stores an object under the same CID.
+    pub shared: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinsResponse {
+    pub repo: String,
+    pub pins: Vec<PinInfo>,
+    /// Sum of the sizes that are known.
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnpinQuery {
+    /// Unpin every CID of the repository, not only unreferenced ones.
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnpinFailure {
+    pub cid: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnpinReport {
+    pub repo: String,
+    pub all: bool,
+    pub dry_run: bool,
+    /// CIDs whose pins were removed, or would be on a dry run.
+    pub unpinned: Vec<String>,
+    /// CIDs left pinned because another repository stores them too.
+    pub shared: Vec<String>,
+    /// CIDs left pinned because a push still in progress may anchor them.
+    pub recent: Vec<String>,
+    pub failed: Vec<UnpinFailure>,
+}
+
+pub async fn list_pins(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_list_pins(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Lists the CIDs `repo`'s objects are pinned under, with their sizes from
+/// the IPFS node.
+async fn handle_list_pins(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<PinsResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let referenced = sync_pins(&contract_state, &repo, &contract).await?;
+    let pins = contract_state.pin_index().pinned(&repo).await;
+
+    // Sizes only come from Kubo; other backends list pins without them.
+    let backend = Arc::new(IpfsBackend::from_env()?);
+    let client = reqwest::Client::builder().timeout(PIN_API_TIMEOUT).build()?;
+    let semaphore = Arc::new(Semaphore::new(STAT_CONCURRENCY));
+    let mut stats = JoinSet::new();
+    for (index, pin) in pins.iter().enumerate() {
+        let backend = Arc::clone(&backend);
+        let client = client.clone();
+        let semaphore = Arc::clone(&semaphore);
+        let cid = pin.cid.clone();
+        stats.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (index, ipfs::block_size(&backend, &client, &cid).await)
+        });
+    }
+    let mut sizes = vec![None; pins.len()];
+    while let Some(joined) = stats.join_next().await {
+        match joined? {
+            (index, Ok(size)) => sizes[index] = Some(size),
+            (index, Err(e)) => debug!("No size for {}: {}", pins[index].cid, e),
+        }
+    }
+
+    let mut infos = Vec::with_capacity(pins.len());
+    for (pin, size) in pins.into_iter().zip(sizes) {
+        let shared = contract_state.pin_index().shared(&repo, &pin.cid).await;
+        infos.push(PinInfo {
+            referenced: referenced.contains(&pin.cid),
+            cid: pin.cid,
+            hash: pin.hash,
+            size,
+            shared,
+        });
+    }
+    let total_bytes = infos.iter().filter_map(|info| info.size).sum();
+
+    Ok(PinsResponse { repo, pins: infos, total_bytes })
+}
+
+pub async fn unpin_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<UnpinQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_unpin_repo(contract_state, path.name(), query, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Removes `repo`'s pins on CIDs its contract no longer records, or on
+/// every CID with `all`. A CID any other repository stores is never
+/// unpinned, so every repository the daemon has loaded or the registry
+/// lists is synced into the index first; if one cannot be read, nothing is
+/// unpinned.
+async fn handle_unpin_repo(
+    contract_state: ContractState,
+    repo: String,
+    query: UnpinQuery,
+    headers: &HeaderMap,
+) -> Result<UnpinReport> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let backend = IpfsBackend::from_env()?;
+    if !matches!(backend, IpfsBackend::Kubo { .. }) {
+        bail!("Unpinning needs IPFS_BACKEND=kubo; pinning services manage their own pins");
+    }
+    let client = reqwest::Client::builder().timeout(PIN_API_TIMEOUT).build()?;
+
+    let referenced = sync_pins(&contract_state, &repo, &contract).await?;
+    let key = normalize_repo_name(&repo);
+    let mut synced = HashSet::from([contract.address()]);
+    for (other, other_contract) in contract_state.contracts().await {
+        if normalize_repo_name(&other) != key && synced.insert(other_contract.address()) {
+            sync_pins(&contract_state, &other, &other_contract).await
+                .with_context(|| format!("Failed to read the objects of {}, which may share pins with {}", other, repo))?;
+        }
+    }
+    // Forks and repositories created through other daemons are only loaded
+    // once asked for, but may store the same objects.
+    let registered = ContractInteraction::registered().await
+        .with_context(|| format!("Failed to list the registry's repositories, which may share pins with {}", repo))?;
+    for (other, other_contract) in registered {
+        if normalize_repo_name(&other) != key && synced.insert(other_contract.address()) {
+            sync_pins(&contract_state, &other, &other_contract).await
+                .with_context(|| format!("Failed to read the objects of {}, which may share pins with {}", other, repo))?;
+        }
+    }
+
+    // A push uploads and pins objects before anchoring them, so a recent
+    // unreferenced pin may be one a push is still working on.
+    let recent_since = unix_now().saturating_sub(DaemonConfig::pack_timeout_secs());
+    let mut candidates = Vec::new();
+    let mut shared = Vec::new();
+    let mut recent = Vec::new();
+    for pin in contract_state.pin_index().pinned(&repo).await {
+        if !query.all && referenced.contains(&pin.cid) {
+            continue;
+        }
+        if contract_state.pin_index().shared(&repo, &pin.cid).await {
+            shared.push(pin.cid);
+        } else if pin.pinned_at > recent_since {
+            recent.push(pin.cid);
+        } else {
+            candidates.push(pin);
+        }
+    }
+    info!(
+        "Unpinning {} CIDs of {} ({} shared, {} recent kept){}",
+        candidates.len(), repo, shared.len(), recent.len(), if query.dry_run { " (dry run)" } else { "" }
+    );
+
+    if query.dry_run {
+        let unpinned = candidates.into_iter().map(|pin| pin.cid).collect();
+        return Ok(UnpinReport { repo, all: query.all, dry_run: true, unpinned, shared, recent, failed: Vec::new() });
+    }
+
+    let mut unpinned = Vec::new();
+    let mut hashes = Vec::new();
+    let mut failed = Vec::new();
+    for pin in candidates {
+        match ipfs::unpin(&backend, &client, &pin.cid).await {
+            Ok(()) => {
+                unpinned.push(pin.cid);
+                hashes.push(pin.hash);
+            },
+            Err(e) => {
+                warn!("Failed to unpin {} of {}: {}", pin.cid, repo, e);
+                failed.push(UnpinFailure { cid: pin.cid, error: e.to_string() });
+            },
+        }
+    }
+    contract_state.pin_index().mark_unpinned(&repo, &unpinned).await;
+    contract_state.object_index().forget(hashes).await;
+
+    if !unpinned.is_empty() {
+        contract_state.audit().record(AuditEntry {
+            actor: Some(format!("{:?}", admin)),
+            target: Some(format!("{} CIDs", unpinned.len())),
+            ..AuditEntry::new(AuditAction::Unpin, &repo)
+        }).await;
+    }
+
+    Ok(UnpinReport { repo, all: query.all, dry_run: false, unpinned, shared, recent, failed })
+}
+
+/// Adds the IPFS objects `contract` records to the pin index, and returns
+/// the CIDs `repo` still references: those on chain and those of pushes
+/// waiting to be anchored.
+async fn sync_pins(contract_state: &ContractState, repo: &str, contract: &ContractInteraction) -> Result<HashSet<String>> {
+    let objects: Vec<(String, String)> = contract_state.views().objects(contract).await?
+        .into_iter()
+        .map(|object| (object.hash, String::from_utf8_lossy(&object.ipfs_url).into_owned()))
+        .collect();
+    let queued: Vec<(String, String)> = contract_state.anchor_queue().jobs(repo).await
+        .into_iter()
+        .flat_map(|job| job.objects)
+        .collect();
+
+    let referenced = objects.iter().chain(&queued)
+        .filter(|(_, url)| storage::is_ipfs(url))
+        .map(|(_, url)| ipfs::normalize_cid(url))
+        .collect();
+    contract_state.pin_index().backfill(repo, objects).await;
+    Ok(referenced)
+}
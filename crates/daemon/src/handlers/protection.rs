@@ -0,0 +1,127 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::repo_config::ProtectionRule;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{auth::{ensure_admin, ensure_read_access}, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveProtectionQuery {
+    pub pattern: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtectionResponse {
+    pub repo: String,
+    pub rules: Vec<ProtectionRule>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemoveProtectionResponse {
+    pub repo: String,
+    pub pattern: String,
+    pub removed: bool,
+}
+
+pub async fn add_protection(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(rule): Json<ProtectionRule>,
+) -> impl IntoResponse {
+    match handle_add_protection(contract_state, path.name(), &headers, rule).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_add_protection(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    rule: ProtectionRule,
+) -> Result<ProtectionResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    if !rule.pattern.starts_with("refs/") {
+        bail!("Protection pattern must start with refs/");
+    }
+
+    // A pattern has at most one rule; adding it again replaces the flags.
+    let mut config = contract.get_repo_config().await?;
+    config.protected_refs.retain(|existing| existing.pattern != rule.pattern);
+    config.protected_refs.push(rule.clone());
+    contract.update_repo_config(&config).await?;
+
+    info!("{:?} protected {} in {}: {:?}", admin, rule.pattern, repo, rule);
+
+    Ok(ProtectionResponse { repo, rules: config.protected_refs })
+}
+
+pub async fn list_protection(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_list_protection(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_list_protection(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<ProtectionResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let config = contract.get_repo_config().await?;
+    Ok(ProtectionResponse { repo, rules: config.protected_refs })
+}
+
+pub async fn remove_protection(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<RemoveProtectionQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_remove_protection(contract_state, path.name(), &headers, query.pattern).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_remove_protection(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    pattern: String,
+) -> Result<RemoveProtectionResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let mut config = contract.get_repo_config().await?;
+    let before = config.protected_refs.len();
+    config.protected_refs.retain(|rule| rule.pattern != pattern);
+    let removed = config.protected_refs.len() != before;
+
+    if removed {
+        contract.update_repo_config(&config).await?;
+        info!("{:?} removed protection of {} from {}", admin, pattern, repo);
+    }
+
+    Ok(RemoveProtectionResponse { repo, pattern, removed })
+}
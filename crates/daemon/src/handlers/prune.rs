@@ -0,0 +1,114 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::{auth::{ensure_admin, ensure_read_access}, error::ApiError, extract::RepoPath, ref_cache::collapse, state::ContractState, validation::validate_repo_name};
+
+#[derive(Debug, Deserialize)]
+pub struct PruneQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrunedRef {
+    /// Position of the entry in the contract's ref list.
+    pub index: usize,
+    pub name: String,
+    pub target: String,
+    /// `superseded` by a later entry with the same name, or `inactive`.
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PruneReport {
+    pub repo: String,
+    pub dry_run: bool,
+    /// Entries in the contract's ref list.
+    pub entries: usize,
+    /// Refs left after collapsing to the latest active entry per name.
+    pub active: usize,
+    pub pruned: Vec<PrunedRef>,
+}
+
+pub async fn prune_refs(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<PruneQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_prune_refs(contract_state, path.name(), query.dry_run, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Collapses the on-chain ref list to the latest active entry per name and,
+/// unless `dry_run`, caches that set for the read handlers. Listing needs
+/// read access; refreshing the cache needs the admin role.
+///
+/// The contract rewrites a ref's entry in place and has no way to drop or
+/// deactivate entries, so nothing is changed on chain: pruned entries are
+/// only left out of what the daemon serves.
+async fn handle_prune_refs(
+    contract_state: ContractState,
+    repo: String,
+    dry_run: bool,
+    headers: &HeaderMap,
+) -> Result<PruneReport> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow!("Repository not found"))?;
+
+    if dry_run {
+        ensure_read_access(&contract, headers).await?;
+    } else {
+        ensure_admin(&contract, headers).await?;
+    }
+
+    let refs = contract_state.views().refs(&contract).await?;
+    let latest: HashMap<&str, usize> = refs
+        .iter()
+        .enumerate()
+        .map(|(index, r)| (r.name.as_str(), index))
+        .collect();
+
+    let pruned: Vec<PrunedRef> = refs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, r)| {
+            let reason = if latest.get(r.name.as_str()) != Some(&index) {
+                "superseded"
+            } else if !r.is_active {
+                "inactive"
+            } else {
+                return None;
+            };
+            Some(PrunedRef {
+                index,
+                name: r.name.clone(),
+                target: String::from_utf8_lossy(&r.data).trim().to_string(),
+                reason: reason.to_string(),
+            })
+        })
+        .collect();
+
+    let active = collapse(&refs);
+    info!("{} has {} ref entries, {} active after pruning {}", repo, refs.len(), active.len(), pruned.len());
+
+    let report = PruneReport {
+        repo: repo.clone(),
+        dry_run,
+        entries: refs.len(),
+        active: active.len(),
+        pruned,
+    };
+
+    if !dry_run {
+        contract_state.ref_cache().store(&repo, active).await;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,40 @@
+use axum::{extract::Path, response::IntoResponse, Json};
+use anyhow::Result;
+use onchain::{contract_interaction::ContractInteraction, registry};
+use serde::Serialize;
+
+use crate::{error::{ApiError, NotFound}, extract::RepoPath, validation::{split_chain, validate_repo_name}};
+
+#[derive(Debug, Serialize)]
+pub struct ResolveResponse {
+    pub repo: String,
+    pub registry: String,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+}
+
+/// Where the registry says `repo` lives. Registry entries are public on
+/// chain, so this needs no read access.
+pub async fn resolve_repo(Path(path): Path<RepoPath>) -> impl IntoResponse {
+    match handle_resolve_repo(path.name()).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_resolve_repo(repo: String) -> Result<ResolveResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let registry = registry::address()?
+        .ok_or_else(|| NotFound("No registry is configured".to_string()))?;
+
+    let contract = ContractInteraction::resolve(split_chain(&repo).0).await?
+        .ok_or_else(|| NotFound(format!("{} is not in the registry", repo)))?;
+
+    Ok(ResolveResponse {
+        repo,
+        registry: format!("{:?}", registry),
+        address: contract.address(),
+        chain_id: contract.chain_id,
+    })
+}
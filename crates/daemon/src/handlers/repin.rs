@@ -0,0 +1,101 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::{ipfs::{self, IpfsBackend, RepinOutcome}, storage};
+use serde::Serialize;
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::{auth::ensure_admin, error::ApiError, extract::RepoPath, handlers::MissingObject, state::ContractState, validation::validate_repo_name};
+
+/// Objects re-pinned at once; each may mean a download and an upload.
+const REPIN_CONCURRENCY: usize = 8;
+/// Generous, since Kubo may search the network for content before giving up.
+const REPIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize)]
+pub struct RepinReport {
+    pub repo: String,
+    pub objects_checked: usize,
+    pub already_pinned: usize,
+    pub repinned: usize,
+    /// Objects no source had, or whose content came back under another CID.
+    pub unrecoverable: Vec<MissingObject>,
+}
+
+pub async fn repin_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_repin_repo(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Pins every object recorded on chain for `repo` on the configured IPFS
+/// backend again, fetching what the backend lost from the network or a
+/// gateway.
+async fn handle_repin_repo(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<RepinReport> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_admin(&contract, headers).await?;
+
+    let backend = Arc::new(IpfsBackend::from_env()?);
+    let client = reqwest::Client::builder().timeout(REPIN_TIMEOUT).build()?;
+    let semaphore = Arc::new(Semaphore::new(REPIN_CONCURRENCY));
+
+    // Pinning means nothing to HTTP or S3 storage.
+    let (objects, elsewhere): (Vec<_>, Vec<_>) = contract_state.views().objects(&contract).await?
+        .into_iter()
+        .partition(|object| storage::is_ipfs(&String::from_utf8_lossy(&object.ipfs_url)));
+    if !elsewhere.is_empty() {
+        info!("Skipping {} objects of {} stored outside IPFS", elsewhere.len(), repo);
+    }
+    let objects_checked = objects.len();
+    info!("Re-pinning {} objects of {}", objects_checked, repo);
+
+    let mut repins = JoinSet::new();
+    for object in objects {
+        let backend = Arc::clone(&backend);
+        let client = client.clone();
+        let semaphore = Arc::clone(&semaphore);
+        repins.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let cid = ipfs::normalize_cid(&String::from_utf8_lossy(&object.ipfs_url));
+            let result = ipfs::repin(&backend, &client, &cid).await;
+            (object.hash, cid, result)
+        });
+    }
+
+    let mut already_pinned = 0;
+    let mut repinned = 0;
+    let mut unrecoverable = Vec::new();
+    while let Some(joined) = repins.join_next().await {
+        match joined? {
+            (_, _, Ok(RepinOutcome::Present)) => already_pinned += 1,
+            (_, _, Ok(RepinOutcome::Repinned)) => repinned += 1,
+            (hash, cid, Err(e)) => {
+                warn!("Object {} (CID {}) could not be re-pinned: {}", hash, cid, e);
+                unrecoverable.push(MissingObject { hash, cid, error: e.to_string() });
+            },
+        }
+    }
+    unrecoverable.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+    info!(
+        "Re-pinned {}: {} already pinned, {} re-pinned, {} unrecoverable",
+        repo, already_pinned, repinned, unrecoverable.len()
+    );
+
+    Ok(RepinReport { repo, objects_checked, already_pinned, repinned, unrecoverable })
+}
@@ -0,0 +1,148 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::repo_config::{RepoConfig, Visibility};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{auth::{ensure_admin, ensure_read_access}, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+/// Fields to change; anything left out keeps its value. An empty string
+/// clears `description`/`default_branch`, a 0 clears a quota limit and a
+/// `null` removes an `extra` key and an empty list clears `ref_namespaces`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigUpdate {
+    pub description: Option<String>,
+    pub default_branch: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub max_object_bytes: Option<u64>,
+    pub max_push_bytes: Option<u64>,
+    pub max_repo_objects: Option<u64>,
+    pub ref_namespaces: Option<Vec<String>>,
+    #[serde(default)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepoConfigResponse {
+    pub repo: String,
+    pub config: RepoConfig,
+}
+
+pub async fn get_repo_config(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_get_repo_config(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_get_repo_config(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<RepoConfigResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let config = contract.get_repo_config().await?;
+    Ok(RepoConfigResponse { repo, config })
+}
+
+pub async fn update_repo_config(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(update): Json<ConfigUpdate>,
+) -> impl IntoResponse {
+    match handle_update_repo_config(contract_state, path.name(), &headers, update).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_update_repo_config(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    update: ConfigUpdate,
+) -> Result<RepoConfigResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let mut config = contract.get_repo_config().await?;
+    apply_update(&mut config, update)?;
+    contract.update_repo_config(&config).await?;
+    // Snapshots carry the default branch in their HEAD.
+    contract_state.snapshots().invalidate(&repo, &contract).await;
+
+    info!("{:?} updated config of {}", admin, repo);
+
+    Ok(RepoConfigResponse { repo, config })
+}
+
+fn apply_update(config: &mut RepoConfig, update: ConfigUpdate) -> Result<()> {
+    if let Some(description) = update.description {
+        config.description = Some(description).filter(|description| !description.is_empty());
+    }
+
+    if let Some(branch) = update.default_branch {
+        config.default_branch = match branch.as_str() {
+            "" => None,
+            branch if branch.chars().any(|c| c.is_whitespace() || c.is_control()) => {
+                bail!("Invalid default branch '{}'", branch);
+            },
+            branch if branch.starts_with("refs/heads/") => Some(branch.to_string()),
+            branch => Some(format!("refs/heads/{}", branch)),
+        };
+    }
+
+    if let Some(visibility) = update.visibility {
+        config.visibility = visibility;
+    }
+
+    let clear_zero = |limit: u64| (limit > 0).then_some(limit);
+    if let Some(limit) = update.max_object_bytes {
+        config.quota.max_object_bytes = clear_zero(limit);
+    }
+    if let Some(limit) = update.max_push_bytes {
+        config.quota.max_push_bytes = clear_zero(limit);
+    }
+    if let Some(limit) = update.max_repo_objects {
+        config.quota.max_repo_objects = clear_zero(limit);
+    }
+
+    if let Some(namespaces) = update.ref_namespaces {
+        let mut normalized: Vec<String> = Vec::new();
+        for namespace in namespaces {
+            let namespace = namespace.trim();
+            if !namespace.starts_with("refs/") || namespace.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                bail!("Invalid ref namespace '{}': it must start with refs/", namespace);
+            }
+            let namespace = format!("{}/", namespace.trim_end_matches('/'));
+            if !normalized.contains(&namespace) {
+                normalized.push(namespace);
+            }
+        }
+        config.ref_namespaces = normalized;
+    }
+
+    for (key, value) in update.extra {
+        if value.is_null() {
+            config.extra.remove(&key);
+        } else {
+            config.extra.insert(key, value);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use anyhow::Result;
+
+use crate::{auth::ensure_read_access, error::{ApiError, NotFound}, extract::RepoPath, handlers::CreateRepoResponse, state::ContractState, validation::validate_repo_name};
+
+/// The repository's contract address and the chain it was deployed on.
+pub async fn repo_info(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_repo_info(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_repo_info(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<CreateRepoResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| NotFound("Repository not found".to_string()))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    Ok(CreateRepoResponse::new(repo, &contract))
+}
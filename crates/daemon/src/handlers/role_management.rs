@@ -1,10 +1,11 @@
-use axum::{extract::{Path, State}, response::IntoResponse, Json};
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
 use serde::Serialize;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use ethcontract::Address;
 use std::str::FromStr;
+use tracing::{info, warn};
 
-use crate::state::ContractState;
+use crate::{audit::{AuditAction, AuditEntry}, auth::ensure_admin, error::ApiError, extract::{RepoAddressPath, RepoPath}, state::ContractState, validation::validate_repo_name};
 
 #[derive(Debug, Serialize)]
 pub struct RoleResponse {
@@ -14,6 +15,34 @@ pub struct RoleResponse {
     pub granted: bool,
 }
 
+/// Upper bound on addresses per `grant-pushers`/`revoke-pushers` call;
+/// each one is its own transaction, sent one after another.
+pub const MAX_BATCH_ADDRESSES: usize = 100;
+
+#[derive(Debug, Serialize)]
+pub struct BatchRoleResult {
+    pub address: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchRoleResult {
+    fn failed(address: String, error: impl Into<String>) -> Self {
+        Self { address, ok: false, tx_hash: None, error: Some(error.into()) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchRoleResponse {
+    pub repo: String,
+    pub role: String,
+    pub granted: bool,
+    pub results: Vec<BatchRoleResult>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct RoleCheckResponse {
     pub repo: String,
@@ -22,13 +51,22 @@ pub struct RoleCheckResponse {
     pub has_role: bool,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RoleListResponse {
+    pub repo: String,
+    pub admins: Vec<String>,
+    pub pushers: Vec<String>,
+    pub readers: Vec<String>,
+}
+
 pub async fn grant_pusher_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    match handle_grant_pusher_role(contract_state, repo, address).await {
+    match handle_grant_pusher_role(contract_state, path.name(), path.address, &headers).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -36,14 +74,28 @@ async fn handle_grant_pusher_role(
     contract_state: ContractState,
     repo: String,
     address_str: String,
+    headers: &HeaderMap,
 ) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
+    let admin = ensure_admin(&contract, headers).await?;
+
     let address = Address::from_str(&address_str)
         .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
 
-    contract.grant_pusher_role(address).await?;
+    let confirmed = contract.grant_pusher_role(address).await?;
+    info!("{:?} granted pusher role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("pusher".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Grant, &repo)
+    }).await;
 
     Ok(RoleResponse {
         repo,
@@ -55,11 +107,12 @@ async fn handle_grant_pusher_role(
 
 pub async fn revoke_pusher_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    match handle_revoke_pusher_role(contract_state, repo, address).await {
+    match handle_revoke_pusher_role(contract_state, path.name(), path.address, &headers).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -67,14 +120,28 @@ async fn handle_revoke_pusher_role(
     contract_state: ContractState,
     repo: String,
     address_str: String,
+    headers: &HeaderMap,
 ) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
+    let admin = ensure_admin(&contract, headers).await?;
+
     let address = Address::from_str(&address_str)
         .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
 
-    contract.revoke_pusher_role(address).await?;
+    let confirmed = contract.revoke_pusher_role(address).await?;
+    info!("{:?} revoked pusher role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("pusher".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Revoke, &repo)
+    }).await;
 
     Ok(RoleResponse {
         repo,
@@ -84,13 +151,123 @@ async fn handle_revoke_pusher_role(
     })
 }
 
+pub async fn grant_pushers(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(addresses): Json<Vec<String>>,
+) -> impl IntoResponse {
+    match handle_batch_pusher_role(contract_state, path.name(), &headers, addresses, true).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+pub async fn revoke_pushers(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(addresses): Json<Vec<String>>,
+) -> impl IntoResponse {
+    match handle_batch_pusher_role(contract_state, path.name(), &headers, addresses, false).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Grants or revokes the pusher role for each of `addresses` in turn. The
+/// contract has no batch method, so every address is its own transaction;
+/// one failing does not stop the rest, and each gets its own result.
+async fn handle_batch_pusher_role(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    addresses: Vec<String>,
+    grant: bool,
+) -> Result<BatchRoleResponse> {
+    let repo = validate_repo_name(&repo)?;
+
+    if addresses.is_empty() {
+        bail!("No addresses given");
+    }
+    if addresses.len() > MAX_BATCH_ADDRESSES {
+        bail!("Too many addresses: {} (at most {} per request)", addresses.len(), MAX_BATCH_ADDRESSES);
+    }
+
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let mut seen = Vec::new();
+    let mut results = Vec::with_capacity(addresses.len());
+    for address_str in addresses {
+        let address = match Address::from_str(&address_str) {
+            Ok(address) if seen.contains(&address) => {
+                results.push(BatchRoleResult::failed(address_str, "Listed more than once"));
+                continue;
+            }
+            Ok(address) => address,
+            Err(_) => {
+                results.push(BatchRoleResult::failed(address_str, "Invalid address format"));
+                continue;
+            }
+        };
+        seen.push(address);
+
+        let confirmed = if grant {
+            contract.grant_pusher_role(address).await
+        } else {
+            contract.revoke_pusher_role(address).await
+        };
+        let confirmed = match confirmed {
+            Ok(confirmed) => confirmed,
+            Err(e) => {
+                warn!("{:?} failed to change pusher role for {:?} on {}: {:#}", admin, address, repo, e);
+                results.push(BatchRoleResult::failed(address_str, format!("{:#}", e)));
+                continue;
+            }
+        };
+        if grant {
+            info!("{:?} granted pusher role for {:?} on {}", admin, address, repo);
+        } else {
+            info!("{:?} revoked pusher role for {:?} on {}", admin, address, repo);
+        }
+
+        let action = if grant { AuditAction::Grant } else { AuditAction::Revoke };
+        contract_state.audit().record(AuditEntry {
+            actor: Some(format!("{:?}", admin)),
+            target: Some(format!("{:?}", address)),
+            role: Some("pusher".to_string()),
+            tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+            costs: Some(confirmed.costs),
+            ..AuditEntry::new(action, &repo)
+        }).await;
+
+        results.push(BatchRoleResult {
+            address: address_str,
+            ok: true,
+            tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+            error: None,
+        });
+    }
+
+    Ok(BatchRoleResponse {
+        repo,
+        role: "pusher".to_string(),
+        granted: grant,
+        results,
+    })
+}
+
 pub async fn grant_admin_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    match handle_grant_admin_role(contract_state, repo, address).await {
+    match handle_grant_admin_role(contract_state, path.name(), path.address, &headers).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -98,14 +275,28 @@ async fn handle_grant_admin_role(
     contract_state: ContractState,
     repo: String,
     address_str: String,
+    headers: &HeaderMap,
 ) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
+    let admin = ensure_admin(&contract, headers).await?;
+
     let address = Address::from_str(&address_str)
         .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
 
-    contract.grant_admin_role(address).await?;
+    let confirmed = contract.grant_admin_role(address).await?;
+    info!("{:?} granted admin role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("admin".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Grant, &repo)
+    }).await;
 
     Ok(RoleResponse {
         repo,
@@ -118,11 +309,12 @@ async fn handle_grant_admin_role(
 // Revoke admin role
 pub async fn revoke_admin_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    match handle_revoke_admin_role(contract_state, repo, address).await {
+    match handle_revoke_admin_role(contract_state, path.name(), path.address, &headers).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -130,14 +322,28 @@ async fn handle_revoke_admin_role(
     contract_state: ContractState,
     repo: String,
     address_str: String,
+    headers: &HeaderMap,
 ) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
+    let admin = ensure_admin(&contract, headers).await?;
+
     let address = Address::from_str(&address_str)
         .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
 
-    contract.revoke_admin_role(address).await?;
+    let confirmed = contract.revoke_admin_role(address).await?;
+    info!("{:?} revoked admin role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("admin".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Revoke, &repo)
+    }).await;
 
     Ok(RoleResponse {
         repo,
@@ -149,11 +355,11 @@ async fn handle_revoke_admin_role(
 
 pub async fn check_pusher_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
 ) -> impl IntoResponse {
-    match handle_check_pusher_role(contract_state, repo, address).await {
+    match handle_check_pusher_role(contract_state, path.name(), path.address).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -162,6 +368,7 @@ async fn handle_check_pusher_role(
     repo: String,
     address_str: String,
 ) -> Result<RoleCheckResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
@@ -180,11 +387,11 @@ async fn handle_check_pusher_role(
 
 pub async fn check_admin_role(
     State(contract_state): State<ContractState>,
-    Path((repo, address)): Path<(String, String)>,
+    Path(path): Path<RepoAddressPath>,
 ) -> impl IntoResponse {
-    match handle_check_admin_role(contract_state, repo, address).await {
+    match handle_check_admin_role(contract_state, path.name(), path.address).await {
         Ok(response) => Json(response).into_response(),
-        Err(e) => (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
     }
 }
 
@@ -193,6 +400,7 @@ async fn handle_check_admin_role(
     repo: String,
     address_str: String,
 ) -> Result<RoleCheckResponse> {
+    let repo = validate_repo_name(&repo)?;
     let contract = contract_state.get_contract(&repo).await
         .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
 
@@ -207,4 +415,157 @@ async fn handle_check_admin_role(
         role: "admin".to_string(),
         has_role,
     })
-}
\ No newline at end of file
+}
+pub async fn grant_reader_role(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_grant_reader_role(contract_state, path.name(), path.address, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_grant_reader_role(
+    contract_state: ContractState,
+    repo: String,
+    address_str: String,
+    headers: &HeaderMap,
+) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let address = Address::from_str(&address_str)
+        .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
+
+    let confirmed = contract.grant_reader_role(address).await?;
+    info!("{:?} granted reader role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("reader".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Grant, &repo)
+    }).await;
+
+    Ok(RoleResponse {
+        repo,
+        address: address_str,
+        role: "reader".to_string(),
+        granted: true,
+    })
+}
+
+pub async fn revoke_reader_role(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoAddressPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_revoke_reader_role(contract_state, path.name(), path.address, &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_revoke_reader_role(
+    contract_state: ContractState,
+    repo: String,
+    address_str: String,
+    headers: &HeaderMap,
+) -> Result<RoleResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let address = Address::from_str(&address_str)
+        .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
+
+    let confirmed = contract.revoke_reader_role(address).await?;
+    info!("{:?} revoked reader role for {:?} on {}", admin, address, repo);
+
+    contract_state.audit().record(AuditEntry {
+        actor: Some(format!("{:?}", admin)),
+        target: Some(format!("{:?}", address)),
+        role: Some("reader".to_string()),
+        tx_hash: Some(format!("{:?}", confirmed.tx_hash)),
+        costs: Some(confirmed.costs),
+        ..AuditEntry::new(AuditAction::Revoke, &repo)
+    }).await;
+
+    Ok(RoleResponse {
+        repo,
+        address: address_str,
+        role: "reader".to_string(),
+        granted: false,
+    })
+}
+
+pub async fn check_reader_role(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoAddressPath>,
+) -> impl IntoResponse {
+    match handle_check_reader_role(contract_state, path.name(), path.address).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_check_reader_role(
+    contract_state: ContractState,
+    repo: String,
+    address_str: String,
+) -> Result<RoleCheckResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let address = Address::from_str(&address_str)
+        .map_err(|_| anyhow::anyhow!("Invalid address format"))?;
+
+    let has_role = contract.has_reader_role(address).await?;
+
+    Ok(RoleCheckResponse {
+        repo,
+        address: address_str,
+        role: "reader".to_string(),
+        has_role,
+    })
+}
+
+pub async fn list_roles(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+) -> impl IntoResponse {
+    match handle_list_roles(contract_state, path.name()).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_list_roles(
+    contract_state: ContractState,
+    repo: String,
+) -> Result<RoleListResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let to_strings = |addresses: Vec<Address>| {
+        addresses.into_iter().map(|address| format!("{:?}", address)).collect()
+    };
+
+    Ok(RoleListResponse {
+        repo,
+        admins: to_strings(contract.get_admins().await?),
+        pushers: to_strings(contract.get_pushers().await?),
+        readers: to_strings(contract.get_readers().await?),
+    })
+}
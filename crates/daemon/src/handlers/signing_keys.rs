@@ -0,0 +1,103 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::repo_config::SigningKeys;
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{auth::{ensure_admin, ensure_read_access}, error::ApiError, extract::RepoPath, signing::Keyring, state::ContractState, validation::validate_repo_name};
+
+/// Fields left out keep their current value.
+#[derive(Debug, Deserialize)]
+pub struct SigningKeysRequest {
+    pub gpg: Option<String>,
+    pub ssh_allowed_signers: Option<String>,
+    pub require_signed_commits: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SigningKeysResponse {
+    pub repo: String,
+    pub require_signed_commits: bool,
+    pub signing_keys: SigningKeys,
+}
+
+pub async fn set_signing_keys(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(request): Json<SigningKeysRequest>,
+) -> impl IntoResponse {
+    match handle_set_signing_keys(contract_state, path.name(), &headers, request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_set_signing_keys(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    request: SigningKeysRequest,
+) -> Result<SigningKeysResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let mut config = contract.get_repo_config().await?;
+    if let Some(gpg) = request.gpg {
+        config.signing_keys.gpg = gpg;
+    }
+    if let Some(ssh_allowed_signers) = request.ssh_allowed_signers {
+        config.signing_keys.ssh_allowed_signers = ssh_allowed_signers;
+    }
+    if let Some(require_signed_commits) = request.require_signed_commits {
+        config.require_signed_commits = require_signed_commits;
+    }
+
+    if config.require_signed_commits && config.signing_keys.is_empty() {
+        bail!("Signed commits cannot be required without signing keys");
+    }
+    // Catch keys gpg cannot import now rather than on every push.
+    Keyring::materialize(&config.signing_keys).await?;
+
+    contract.update_repo_config(&config).await?;
+    info!("{:?} updated signing keys of {} (required: {})", admin, repo, config.require_signed_commits);
+
+    Ok(SigningKeysResponse {
+        repo,
+        require_signed_commits: config.require_signed_commits,
+        signing_keys: config.signing_keys,
+    })
+}
+
+pub async fn get_signing_keys(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_get_signing_keys(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_get_signing_keys(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<SigningKeysResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_read_access(&contract, headers).await?;
+
+    let config = contract.get_repo_config().await?;
+    Ok(SigningKeysResponse {
+        repo,
+        require_signed_commits: config.require_signed_commits,
+        signing_keys: config.signing_keys,
+    })
+}
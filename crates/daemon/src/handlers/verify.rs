@@ -0,0 +1,151 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::Serialize;
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+
+use crate::{error::ApiError, extract::RepoPath, handlers::{open_repo, run_fsck, CheckDepth, FsckEvent, ProblemKind}, state::ContractState};
+
+#[derive(Debug, Serialize)]
+pub struct MissingObject {
+    pub hash: String,
+    pub cid: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrokenRef {
+    pub name: String,
+    pub target: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub repo: String,
+    pub ok: bool,
+    pub objects_checked: usize,
+    pub refs_checked: usize,
+    pub missing_objects: Vec<MissingObject>,
+    pub broken_refs: Vec<BrokenRef>,
+}
+
+pub async fn verify_repo(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_verify_repo(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// Confirms every object recorded on-chain is retrievable and every active
+/// ref points at one: fsck's availability check, answered in one report.
+async fn handle_verify_repo(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<VerifyReport> {
+    let (repo, contract) = open_repo(&contract_state, &repo, headers).await?;
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    run_fsck(&repo, &contract, contract_state.views(), CheckDepth::Availability, &sender).await?;
+    drop(sender);
+
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(event);
+    }
+    verify_report(events)
+}
+
+fn verify_report(events: Vec<FsckEvent>) -> Result<VerifyReport> {
+    let mut summary = None;
+    let mut missing_objects = Vec::new();
+    let mut broken_refs = Vec::new();
+    for event in events {
+        match event {
+            FsckEvent::Problem(problem) => match problem.kind {
+                ProblemKind::MissingObject => missing_objects.push(MissingObject {
+                    hash: problem.object.unwrap_or_default(),
+                    cid: problem.cid.unwrap_or_default(),
+                    error: problem.detail,
+                }),
+                ProblemKind::DanglingRef | ProblemKind::UnreachableRef => broken_refs.push(BrokenRef {
+                    name: problem.ref_name.unwrap_or_default(),
+                    target: problem.object.unwrap_or_default(),
+                    reason: problem.detail,
+                }),
+                // Availability checks follow no links and read no content.
+                ProblemKind::HashMismatch | ProblemKind::Undecodable | ProblemKind::BrokenLink => {},
+            },
+            FsckEvent::Summary(done) => summary = Some(done),
+            FsckEvent::Error { message } => return Err(anyhow!(message)),
+        }
+    }
+
+    let summary = summary.ok_or_else(|| anyhow!("Check ended without a summary"))?;
+    missing_objects.sort_by(|a, b| a.hash.cmp(&b.hash));
+    Ok(VerifyReport {
+        repo: summary.repo,
+        ok: missing_objects.is_empty() && broken_refs.is_empty(),
+        objects_checked: summary.objects_checked,
+        refs_checked: summary.refs_checked,
+        missing_objects,
+        broken_refs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::{FsckProblem, FsckSummary, Severity};
+
+    fn problem(kind: ProblemKind, object: &str, ref_name: Option<&str>) -> FsckEvent {
+        FsckEvent::Problem(FsckProblem {
+            severity: Severity::Error,
+            kind,
+            object: Some(object.to_string()),
+            cid: ref_name.is_none().then(|| format!("Qm{}", object)),
+            ref_name: ref_name.map(str::to_string),
+            detail: format!("{} is broken", object),
+        })
+    }
+
+    fn summary() -> FsckEvent {
+        FsckEvent::Summary(FsckSummary { repo: "project".to_string(), objects_checked: 3, refs_checked: 1, ..FsckSummary::default() })
+    }
+
+    #[test]
+    fn a_missing_cid_and_the_ref_at_it_are_reported() {
+        let report = verify_report(vec![
+            problem(ProblemKind::MissingObject, "bb", None),
+            problem(ProblemKind::UnreachableRef, "bb", Some("refs/heads/main")),
+            summary(),
+        ]).unwrap();
+
+        assert!(!report.ok);
+        assert_eq!(report.repo, "project");
+        assert_eq!(report.objects_checked, 3);
+        assert_eq!(report.missing_objects.len(), 1);
+        assert_eq!(report.missing_objects[0].hash, "bb");
+        assert_eq!(report.missing_objects[0].cid, "Qmbb");
+        assert_eq!(report.broken_refs.len(), 1);
+        assert_eq!(report.broken_refs[0].name, "refs/heads/main");
+        assert_eq!(report.broken_refs[0].target, "bb");
+    }
+
+    #[test]
+    fn a_clean_check_is_ok() {
+        let report = verify_report(vec![summary()]).unwrap();
+        assert!(report.ok);
+        assert!(report.missing_objects.is_empty() && report.broken_refs.is_empty());
+    }
+
+    #[test]
+    fn an_unfinished_check_is_an_error() {
+        assert!(verify_report(vec![FsckEvent::Error { message: "node went away".to_string() }]).is_err());
+        assert!(verify_report(Vec::new()).is_err());
+    }
+}
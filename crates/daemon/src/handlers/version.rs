@@ -0,0 +1,52 @@
+use axum::{extract::State, Json};
+use onchain::config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::DaemonConfig, state::ContractState};
+
+/// Bumped whenever an endpoint is added or a response changes shape, so
+/// clients can tell whether this daemon has what a command needs.
+///
+/// 1: `/version` itself, `/repo/{repo}/anchoring-status`.
+/// 2: `/repo/{repo}/repin`.
+/// 3: `/`.
+/// 4: `/whoami`.
+/// 5: `/admin/disk-usage`, `/admin/gc`.
+/// 6: `/repo/{repo}/history`.
+/// 7: `/admin/rpc`.
+/// 8: `/repo/{repo}/grant-pushers`, `/repo/{repo}/revoke-pushers`.
+/// 9: `/repo/{repo}/pins`, `/repo/{repo}/unpin`.
+pub const API_LEVEL: u32 = 9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub version: String,
+    /// Commit the daemon was built from, if `DGIT_GIT_COMMIT` was set at
+    /// build time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub api_level: u32,
+    /// Optional behavior turned on in this daemon's configuration.
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+pub async fn version(State(contract_state): State<ContractState>) -> Json<VersionResponse> {
+    let features = [
+        ("registry", Config::registry_address().is_some()),
+        ("async-anchoring", contract_state.anchor_queue().enabled()),
+        ("snapshots", contract_state.snapshots().dir().is_some()),
+        ("indexer", DaemonConfig::indexer_interval_secs() > 0),
+    ];
+
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        commit: option_env!("DGIT_GIT_COMMIT").map(str::to_string),
+        api_level: API_LEVEL,
+        features: features
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    })
+}
@@ -0,0 +1,51 @@
+use axum::{extract::{Path, State}, http::HeaderMap, response::IntoResponse, Json};
+use onchain::repo_config::Visibility;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use tracing::info;
+
+use crate::{auth::ensure_admin, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisibilityRequest {
+    pub visibility: Visibility,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VisibilityResponse {
+    pub repo: String,
+    pub visibility: Visibility,
+}
+
+pub async fn set_visibility(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(request): Json<VisibilityRequest>,
+) -> impl IntoResponse {
+    match handle_set_visibility(contract_state, path.name(), &headers, request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_set_visibility(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    request: VisibilityRequest,
+) -> Result<VisibilityResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let mut config = contract.get_repo_config().await?;
+    config.visibility = request.visibility;
+    contract.update_repo_config(&config).await?;
+
+    info!("{:?} set visibility of {} to {:?}", admin, repo, request.visibility);
+
+    Ok(VisibilityResponse { repo, visibility: request.visibility })
+}
@@ -0,0 +1,142 @@
+use axum::{extract::{Path, Query, State}, http::HeaderMap, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use tracing::info;
+
+use crate::{auth::ensure_admin, error::ApiError, extract::RepoPath, state::ContractState, validation::validate_repo_name, webhooks::{resolve_public, Webhook}};
+
+#[derive(Debug, Deserialize)]
+pub struct AddWebhookRequest {
+    pub url: String,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveWebhookQuery {
+    pub url: String,
+}
+
+/// A webhook as shown to clients; the secret itself is never returned.
+#[derive(Debug, Serialize)]
+pub struct WebhookInfo {
+    pub url: String,
+    pub has_secret: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhooksResponse {
+    pub repo: String,
+    pub webhooks: Vec<WebhookInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemoveWebhookResponse {
+    pub repo: String,
+    pub url: String,
+    pub removed: bool,
+}
+
+impl From<Webhook> for WebhookInfo {
+    fn from(webhook: Webhook) -> Self {
+        Self { url: webhook.url, has_secret: webhook.secret.is_some() }
+    }
+}
+
+pub async fn add_webhook(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+    Json(request): Json<AddWebhookRequest>,
+) -> impl IntoResponse {
+    match handle_add_webhook(contract_state, path.name(), &headers, request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_add_webhook(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    request: AddWebhookRequest,
+) -> Result<WebhooksResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    resolve_public(&request.url).await?;
+
+    let secret = request.secret.filter(|secret| !secret.is_empty());
+    contract_state.webhooks().add(&repo, Webhook { url: request.url.clone(), secret }).await?;
+    info!("{:?} added webhook {} to {}", admin, request.url, repo);
+
+    list_response(&contract_state, repo).await
+}
+
+pub async fn list_webhooks(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_list_webhooks(contract_state, path.name(), &headers).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_list_webhooks(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+) -> Result<WebhooksResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    ensure_admin(&contract, headers).await?;
+
+    list_response(&contract_state, repo).await
+}
+
+pub async fn remove_webhook(
+    State(contract_state): State<ContractState>,
+    Path(path): Path<RepoPath>,
+    Query(query): Query<RemoveWebhookQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match handle_remove_webhook(contract_state, path.name(), &headers, query.url).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+async fn handle_remove_webhook(
+    contract_state: ContractState,
+    repo: String,
+    headers: &HeaderMap,
+    url: String,
+) -> Result<RemoveWebhookResponse> {
+    let repo = validate_repo_name(&repo)?;
+    let contract = contract_state.get_contract(&repo).await
+        .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+
+    let admin = ensure_admin(&contract, headers).await?;
+
+    let removed = contract_state.webhooks().remove(&repo, &url).await?;
+    if removed {
+        info!("{:?} removed webhook {} from {}", admin, url, repo);
+    }
+
+    Ok(RemoveWebhookResponse { repo, url, removed })
+}
+
+async fn list_response(contract_state: &ContractState, repo: String) -> Result<WebhooksResponse> {
+    let webhooks = contract_state.webhooks().list(&repo).await
+        .into_iter()
+        .map(WebhookInfo::from)
+        .collect();
+
+    Ok(WebhooksResponse { repo, webhooks })
+}
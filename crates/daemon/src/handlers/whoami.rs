@@ -0,0 +1,34 @@
+use anyhow::{anyhow, Result};
+use axum::{response::IntoResponse, Json};
+use onchain::signer::recover_signer;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::decode_hex, error::ApiError};
+
+#[derive(Debug, Deserialize)]
+pub struct WhoamiRequest {
+    /// Text that was signed, typically a nonce the client picked.
+    pub message: String,
+    /// EIP-191 signature of `message` as 65 bytes of hex.
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WhoamiResponse {
+    pub address: String,
+}
+
+/// Recovers who signed a message, so a client can check that its key
+/// signs the way the daemon verifies before relying on it for auth.
+pub async fn whoami(Json(request): Json<WhoamiRequest>) -> impl IntoResponse {
+    match handle_whoami(request) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+fn handle_whoami(request: WhoamiRequest) -> Result<WhoamiResponse> {
+    let signature = decode_hex(&request.signature).ok_or_else(|| anyhow!("Signature is not hex"))?;
+    let address = recover_signer(request.message.as_bytes(), &signature)?;
+    Ok(WhoamiResponse { address: format!("{:?}", address) })
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::state::ContractState;
+
+/// Keeps the synced view of every repository the daemon knows current in
+/// the background, following the contracts' events, so reads of refs and
+/// objects rarely wait on the chain. Enabled by `DGIT_INDEXER_INTERVAL_SECS`.
+pub fn spawn(contract_state: ContractState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            for (repo, contract) in contract_state.contracts().await {
+                // A failure only means the next read syncs on its own.
+                match contract_state.views().refresh(&contract).await {
+                    Ok(()) => debug!("Indexed {}", repo),
+                    Err(e) => warn!("Failed to index {}: {:#}", repo, e),
+                }
+            }
+        }
+    });
+}
@@ -1,2 +1,37 @@
+pub mod anchor_queue;
+pub mod audit;
+pub mod auth;
+pub mod body;
+pub mod cleanup;
+pub mod config;
+pub mod error;
+pub mod extract;
+pub mod git;
 pub mod handlers;
-pub mod state;
\ No newline at end of file
+pub mod indexer;
+pub mod logging;
+pub mod object_cache;
+pub mod object_index;
+pub mod object_store;
+pub mod pin_index;
+pub mod protocol;
+pub mod rate_limit;
+pub mod ref_cache;
+pub mod repo_views;
+pub mod request_id;
+pub mod request_limits;
+pub mod rpc;
+pub mod server;
+pub mod sideband;
+pub mod snapshots;
+pub mod signing;
+pub mod size_limit;
+pub mod state;
+#[cfg(test)]
+mod test_support;
+pub mod validation;
+pub mod webhooks;
+pub mod work_dirs;
+pub mod workspaces;
+
+pub use server::{run, ServerConfig};
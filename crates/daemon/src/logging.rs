@@ -0,0 +1,229 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{error, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{filter::LevelFilter, fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt, Layer};
+
+use crate::config::DaemonConfig;
+
+/// How log lines are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `tracing_subscriber`'s default single-line format.
+    Full,
+    Compact,
+    Pretty,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "compact" => Ok(Self::Compact),
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("expected full, compact, pretty or json")),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Full => "full",
+            Self::Compact => "compact",
+            Self::Pretty => "pretty",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// When the log file is started afresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    /// Moved to `<file>.1` once it reaches `DGIT_LOG_MAX_BYTES`.
+    Size,
+    /// A new `<file>.<date>` every day.
+    Daily,
+    /// A new `<file>.<date>-<hour>` every hour.
+    Hourly,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "size" => Ok(Self::Size),
+            "daily" => Ok(Self::Daily),
+            "hourly" => Ok(Self::Hourly),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow!("expected size, daily, hourly or never")),
+        }
+    }
+}
+
+impl fmt::Display for LogRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Size => "size",
+            Self::Daily => "daily",
+            Self::Hourly => "hourly",
+            Self::Never => "never",
+        })
+    }
+}
+
+/// Where and how the daemon logs: to stderr, and to a file if `file` is set.
+/// Each has its own level and format, e.g. JSON to the file for shipping
+/// and pretty lines to the terminal.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub stderr_level: Level,
+    pub stderr_format: LogFormat,
+    pub file: Option<PathBuf>,
+    pub file_level: Level,
+    pub file_format: LogFormat,
+    pub rotation: LogRotation,
+    pub max_bytes: u64,
+}
+
+impl LogConfig {
+    pub fn from_env() -> Self {
+        Self {
+            stderr_level: Level::INFO,
+            stderr_format: DaemonConfig::log_format(),
+            file: DaemonConfig::log_file(),
+            file_level: DaemonConfig::log_file_level(),
+            file_format: DaemonConfig::log_file_format(),
+            rotation: DaemonConfig::log_rotation(),
+            max_bytes: DaemonConfig::log_max_bytes(),
+        }
+    }
+}
+
+/// Flushes the log file when dropped; keep it for as long as the process
+/// logs.
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+/// Installs the global subscriber for `config`, and a panic hook so panics
+/// reach the log file too.
+pub fn init(config: LogConfig) -> Result<LogGuard> {
+    let stderr = layer(config.stderr_format, io::stderr, true, config.stderr_level);
+
+    let (file, guard) = match &config.file {
+        Some(path) => {
+            let (writer, guard) = match config.rotation {
+                LogRotation::Size => tracing_appender::non_blocking(
+                    RotatingLog::open(path, config.max_bytes)
+                        .with_context(|| format!("Failed to open log file {}", path.display()))?,
+                ),
+                rotation => tracing_appender::non_blocking(rolling(path, rotation)?),
+            };
+            (Some(layer(config.file_format, writer, false, config.file_level)), Some(guard))
+        },
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .context("Failed to install the log subscriber")?;
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!(target: "panic", "{}", info);
+        previous(info);
+    }));
+
+    Ok(LogGuard { _file: guard })
+}
+
+fn layer<S, W>(format: LogFormat, writer: W, ansi: bool, level: Level) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let level = LevelFilter::from_level(level);
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Full => layer.with_filter(level).boxed(),
+        LogFormat::Compact => layer.compact().with_filter(level).boxed(),
+        LogFormat::Pretty => layer.pretty().with_filter(level).boxed(),
+        LogFormat::Json => layer.json().with_filter(level).boxed(),
+    }
+}
+
+/// Time-based rotation, writing to `<file>.<date>` next to `path`.
+fn rolling(path: &Path, rotation: LogRotation) -> Result<tracing_appender::rolling::RollingFileAppender> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or_else(|| anyhow!("DGIT_LOG_FILE {} has no file name", path.display()))?;
+    fs::create_dir_all(dir)?;
+    Ok(match rotation {
+        LogRotation::Daily => tracing_appender::rolling::daily(dir, name),
+        LogRotation::Hourly => tracing_appender::rolling::hourly(dir, name),
+        LogRotation::Never | LogRotation::Size => tracing_appender::rolling::never(dir, name),
+    })
+}
+
+/// Append-only log file that is moved to `<path>.1` once it would grow
+/// past `max_bytes`, replacing the previous one, so a detached daemon does
+/// not fill the disk. A `max_bytes` of 0 never rotates.
+pub struct RotatingLog {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    len: u64,
+}
+
+impl RotatingLog {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = Self::append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, max_bytes, file, len })
+    }
+
+    fn append(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, &rotated)?;
+        self.file = Self::append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes > 0 && self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
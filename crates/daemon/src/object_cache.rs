@@ -0,0 +1,183 @@
+use anyhow::{bail, Result};
+use onchain::contract_interaction::ContractInteraction;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::{config::DaemonConfig, validation::normalize_repo_name};
+
+/// Hashes in the order they were last used, so the least recently used
+/// are dropped first once there are more than the capacity.
+#[derive(Debug, Default)]
+struct LruSet {
+    last_used: HashMap<String, u64>,
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl LruSet {
+    fn len(&self) -> usize {
+        self.last_used.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.last_used.is_empty()
+    }
+
+    /// Whether `hash` is in the set, marking it as just used if so.
+    fn touch(&mut self, hash: &str) -> bool {
+        let Some(last_used) = self.last_used.get_mut(hash) else {
+            return false;
+        };
+        self.clock += 1;
+        if let Some(hash) = self.by_use.remove(last_used) {
+            self.by_use.insert(self.clock, hash);
+        }
+        *last_used = self.clock;
+        true
+    }
+
+    /// Adds `hashes`, dropping the least recently used beyond `capacity`
+    /// (0 for no limit). Returns how many were dropped.
+    fn extend(&mut self, hashes: impl IntoIterator<Item = String>, capacity: usize) -> usize {
+        for hash in hashes {
+            if self.touch(&hash) {
+                continue;
+            }
+            self.clock += 1;
+            self.last_used.insert(hash.clone(), self.clock);
+            self.by_use.insert(self.clock, hash);
+        }
+
+        let mut evicted = 0;
+        while capacity > 0 && self.last_used.len() > capacity {
+            let Some((_, hash)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.last_used.remove(&hash);
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+/// Object hashes known to be recorded on a repository's contract.
+#[derive(Debug, Default)]
+struct KnownObjects {
+    /// Contract the hashes were seen on; a re-registered repository starts over.
+    address: String,
+    /// The contract's object count when last checked. Objects are never
+    /// removed, so a lower count means the chain was reset or reorganized.
+    length: u64,
+    hashes: LruSet,
+}
+
+/// Per-repository set of objects known to exist on chain, so pushes only ask
+/// the contract about hashes it has not confirmed before.
+///
+/// Only presence is cached: an object recorded through another daemon must
+/// still be found, so every hash not in the set is checked on chain. Since
+/// objects are never removed, entries stay valid until the set reaches
+/// `DGIT_OBJECT_CACHE_MAX_PER_REPO` and the least recently used make room.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectCache {
+    entries: Arc<Mutex<HashMap<String, KnownObjects>>>,
+    /// Hashes kept per repository; 0 for no limit.
+    capacity: usize,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    evictions: Arc<AtomicU64>,
+}
+
+impl ObjectCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, ..Self::default() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::object_cache_max_per_repo())
+    }
+
+    /// Hashes answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Hashes that had to be checked on chain.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Hashes dropped to keep a repository's set within the capacity.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Which of `hashes` are recorded on `contract`, in order. Only the ones
+    /// not already known are sent to the contract, in one `check_objects`.
+    pub async fn check(&self, repo: &str, contract: &ContractInteraction, hashes: &[String]) -> Result<Vec<bool>> {
+        let length: u64 = contract.get_objects_length().await?.try_into().unwrap_or(u64::MAX);
+        let key = normalize_repo_name(repo);
+
+        let unknown: Vec<String> = {
+            let mut entries = self.entries.lock().await;
+            let known = Self::entry(&mut entries, &key, &contract.address(), length);
+            hashes.iter().filter(|hash| !known.hashes.touch(hash)).cloned().collect()
+        };
+        let unknown_set: HashSet<String> = unknown.iter().cloned().collect();
+
+        let hits = (hashes.len() - unknown.len()) as u64;
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+        self.misses.fetch_add(unknown.len() as u64, Ordering::Relaxed);
+        debug!("Object cache of {}: {} hits, {} misses", repo, hits, unknown.len());
+
+        let found: HashSet<String> = if unknown.is_empty() {
+            HashSet::new()
+        } else {
+            let exists = contract.check_objects(unknown.clone()).await?;
+            if exists.len() != unknown.len() {
+                bail!("Contract returned {} results for {} hashes", exists.len(), unknown.len());
+            }
+            unknown.into_iter().zip(exists).filter(|(_, exists)| *exists).map(|(hash, _)| hash).collect()
+        };
+
+        // Answered from what was looked up, not the set: with a small
+        // capacity, part of it may already have been dropped again.
+        let result = hashes.iter().map(|hash| !unknown_set.contains(hash) || found.contains(hash)).collect();
+
+        let mut entries = self.entries.lock().await;
+        let known = Self::entry(&mut entries, &key, &contract.address(), length);
+        let evicted = known.hashes.extend(found, self.capacity);
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+        Ok(result)
+    }
+
+    /// Records `hashes` as present on `contract`, e.g. after `get_objects`
+    /// or a successful `add_objects`.
+    pub async fn record(&self, repo: &str, contract: &ContractInteraction, hashes: impl IntoIterator<Item = String>) {
+        let mut entries = self.entries.lock().await;
+        let known = entries.entry(normalize_repo_name(repo)).or_default();
+        if known.address != contract.address() {
+            *known = KnownObjects { address: contract.address(), ..KnownObjects::default() };
+        }
+        let evicted = known.hashes.extend(hashes, self.capacity);
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+        known.length = known.length.max(known.hashes.len() as u64);
+    }
+
+    /// The entry for `key`, emptied first if it belongs to another contract
+    /// or the contract's object count went down.
+    fn entry<'a>(entries: &'a mut HashMap<String, KnownObjects>, key: &str, address: &str, length: u64) -> &'a mut KnownObjects {
+        let known = entries.entry(key.to_string()).or_default();
+        if known.address != address || length < known.length {
+            if !known.hashes.is_empty() {
+                info!("Dropping {} cached objects of {}: contract or object count changed", known.hashes.len(), key);
+            }
+            *known = KnownObjects { address: address.to_string(), ..KnownObjects::default() };
+        }
+        known.length = length;
+        known
+    }
+}
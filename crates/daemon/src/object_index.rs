@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::config::DaemonConfig;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    hash: String,
+    cid: String,
+    /// The CID was unpinned, so the object must be uploaded again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    forgotten: bool,
+}
+
+/// IPFS hashes of git objects uploaded through this daemon, whatever
+/// repository they were pushed to, so the same object pushed to another
+/// repository reuses the upload instead of being added and pinned again.
+/// A git object's hash names its content, so any repository's CID for it
+/// serves every other; each repository still anchors it in its own contract.
+///
+/// Entries are journaled to `DGIT_OBJECT_INDEX_PATH` as JSON lines and
+/// loaded again after a restart. Unset disables the index.
+#[derive(Debug, Clone)]
+pub struct ObjectIndex {
+    path: Option<Arc<PathBuf>>,
+    cids: Arc<Mutex<HashMap<String, String>>>,
+    reused: Arc<AtomicU64>,
+}
+
+impl ObjectIndex {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut cids = HashMap::new();
+        if let Some(path) = &path {
+            match std::fs::read_to_string(path) {
+                Ok(content) => {
+                    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                        match serde_json::from_str::<IndexEntry>(line) {
+                            Ok(entry) if entry.forgotten => {
+                                cids.remove(&entry.hash);
+                            },
+                            Ok(entry) => {
+                                cids.insert(entry.hash, entry.cid);
+                            },
+                            Err(e) => warn!("Skipping unreadable line in object index {:?}: {}", path, e),
+                        }
+                    }
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => error!("Failed to read object index {:?}, starting empty: {}", path, e),
+            }
+            if !cids.is_empty() {
+                info!("Loaded {} known objects from {:?}", cids.len(), path);
+            }
+        }
+
+        Self {
+            path: path.map(Arc::new),
+            cids: Arc::new(Mutex::new(cids)),
+            reused: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::load(DaemonConfig::object_index_path())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Objects whose upload was skipped because their CID was known.
+    pub fn reused(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// The CID `hash` was uploaded under, counted as a reuse when known.
+    pub async fn cid(&self, hash: &str) -> Option<String> {
+        if !self.enabled() {
+            return None;
+        }
+        let cid = self.cids.lock().await.get(hash).cloned()?;
+        self.reused.fetch_add(1, Ordering::Relaxed);
+        Some(cid)
+    }
+
+    /// Remembers the CIDs of `objects`, given as object hash and CID. A
+    /// failure to write is logged; the objects are only uploaded again.
+    pub async fn record(&self, objects: impl IntoIterator<Item = (String, String)>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let mut cids = self.cids.lock().await;
+        let mut lines = String::new();
+        for (hash, cid) in objects {
+            if cids.get(&hash) == Some(&cid) {
+                continue;
+            }
+            match serde_json::to_string(&IndexEntry { hash: hash.clone(), cid: cid.clone(), forgotten: false }) {
+                Ok(line) => {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                },
+                Err(e) => {
+                    warn!("Failed to serialize object index entry for {}: {}", hash, e);
+                    continue;
+                },
+            }
+            cids.insert(hash, cid);
+        }
+
+        if !lines.is_empty() {
+            if let Err(e) = Self::append(path, &lines).await {
+                error!("Failed to write object index: {:#}", e);
+            }
+        }
+    }
+
+    /// Drops the objects named by `hashes`, whose pins were removed, so the
+    /// next push of one uploads and pins it again instead of reusing a CID
+    /// nothing keeps.
+    pub async fn forget(&self, hashes: impl IntoIterator<Item = String>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let mut cids = self.cids.lock().await;
+        let mut lines = String::new();
+        for hash in hashes {
+            let Some(cid) = cids.get(&hash).cloned() else {
+                continue;
+            };
+            match serde_json::to_string(&IndexEntry { hash: hash.clone(), cid, forgotten: true }) {
+                Ok(line) => {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                },
+                Err(e) => {
+                    warn!("Failed to serialize object index entry for {}: {}", hash, e);
+                    continue;
+                },
+            }
+            cids.remove(&hash);
+        }
+
+        if !lines.is_empty() {
+            if let Err(e) = Self::append(path, &lines).await {
+                error!("Failed to write object index: {:#}", e);
+            }
+        }
+    }
+
+    async fn append(path: &Path, lines: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open object index {:?}", path))?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
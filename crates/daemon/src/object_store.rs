@@ -0,0 +1,390 @@
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use onchain::{contract_interaction::Object, storage};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::handlers::get_object_path;
+
+/// Downloads a repository's loose objects from IPFS into a temporary bare
+/// repository, either all at once or only those a request needs.
+pub struct ObjectStore {
+    repo_path: PathBuf,
+    cids: HashMap<String, String>,
+    downloaded: HashSet<String>,
+    fetched: usize,
+}
+
+#[derive(Debug)]
+pub struct GitObject {
+    pub kind: String,
+    pub data: Vec<u8>,
+}
+
+/// History limits a fetch asked for with `deepen`/`shallow` lines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DepthRequest {
+    pub depth: u32,
+    /// `deepen-relative`: count `depth` from the client's shallow boundary.
+    pub relative: bool,
+    /// Commits the client's history is currently cut off at.
+    pub shallow: Vec<String>,
+}
+
+impl ObjectStore {
+    pub fn new(repo_path: &Path, objects: Vec<Object>) -> Result<Self> {
+        let cids = objects
+            .into_iter()
+            .map(|object| Ok((object.hash, String::from_utf8(object.ipfs_url)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(Self {
+            repo_path: repo_path.to_path_buf(),
+            cids,
+            downloaded: HashSet::new(),
+            fetched: 0,
+        })
+    }
+
+    /// Number of objects fetched from IPFS so far.
+    pub fn download_count(&self) -> usize {
+        self.fetched
+    }
+
+    pub async fn download_all(&mut self) -> Result<()> {
+        let hashes: Vec<String> = self.cids.keys().cloned().collect();
+        for hash in hashes {
+            self.download(&hash).await?;
+        }
+        Ok(())
+    }
+
+    /// Downloads `hash`, returning `false` if it is not recorded on-chain
+    /// (for example because it arrived inside a packfile).
+    pub async fn download(&mut self, hash: &str) -> Result<bool> {
+        if self.downloaded.contains(hash) {
+            return Ok(true);
+        }
+
+        let Some(cid) = self.cids.get(hash) else {
+            return Ok(false);
+        };
+
+        // A pooled workspace keeps what earlier requests downloaded.
+        let local_path = get_object_path(&self.repo_path, hash);
+        if !tokio::fs::try_exists(&local_path).await.unwrap_or(false) {
+            storage::download(cid, &local_path).await?;
+            self.fetched += 1;
+        }
+        self.downloaded.insert(hash.to_string());
+        Ok(true)
+    }
+
+    /// Downloads and decodes `hash`, or `None` if it is not recorded on-chain.
+    pub async fn read(&mut self, hash: &str) -> Result<Option<GitObject>> {
+        if !self.download(hash).await? {
+            return Ok(None);
+        }
+
+        let compressed = tokio::fs::read(get_object_path(&self.repo_path, hash)).await?;
+        decode_loose_object(&compressed)
+            .with_context(|| format!("Failed to decode object {}", hash))
+            .map(Some)
+    }
+
+    /// Downloads only what a shallow fetch of `wants` needs: the commits
+    /// within the requested depth and their trees and blobs.
+    ///
+    /// Returns `false` when some object along the way is not individually
+    /// recorded on-chain; the caller should then fall back to `download_all`.
+    pub async fn download_shallow(&mut self, wants: &[String], request: &DepthRequest) -> Result<bool> {
+        let shallow: HashSet<&str> = request.shallow.iter().map(String::as_str).collect();
+
+        // Each entry carries how many commits, itself included, may still be
+        // walked. `None` means unlimited: a relative deepen walks freely down
+        // to the client's boundary and starts counting there.
+        let mut queue: VecDeque<(String, Option<u32>)> = wants
+            .iter()
+            .map(|want| (want.clone(), if request.relative { None } else { Some(request.depth) }))
+            .collect();
+        let mut visited = HashSet::new();
+
+        while let Some((hash, budget)) = queue.pop_front() {
+            if !visited.insert(hash.clone()) {
+                continue;
+            }
+
+            let Some(object) = self.read(&hash).await? else {
+                debug!("Object {} is not stored individually, shallow download not possible", hash);
+                return Ok(false);
+            };
+
+            match object.kind.as_str() {
+                "tag" => {
+                    let target = header_value(&object.data, "object")
+                        .ok_or_else(|| anyhow!("Tag {} has no target", hash))?;
+                    queue.push_back((target, budget));
+                },
+                "commit" => {
+                    let tree = header_value(&object.data, "tree")
+                        .ok_or_else(|| anyhow!("Commit {} has no tree", hash))?;
+                    if !self.download_tree(&tree).await? {
+                        return Ok(false);
+                    }
+
+                    let budget = if request.relative && shallow.contains(hash.as_str()) {
+                        Some(request.depth + 1)
+                    } else {
+                        budget
+                    };
+
+                    let parent_budget = match budget {
+                        None => None,
+                        Some(n) if n > 1 => Some(n - 1),
+                        Some(_) => continue,
+                    };
+                    for parent in header_values(&object.data, "parent") {
+                        queue.push_back((parent, parent_budget));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        info!("Shallow download fetched {} of {} objects", self.download_count(), self.cids.len());
+        Ok(true)
+    }
+
+    /// Downloads the tree `hash` names, following a tag or commit to it, but
+    /// none of the history behind it.
+    ///
+    /// Returns `false` when some object of the tree is not recorded on-chain.
+    pub async fn download_snapshot(&mut self, hash: &str) -> Result<bool> {
+        let mut hash = hash.to_string();
+
+        loop {
+            let Some(object) = self.read(&hash).await? else {
+                return Ok(false);
+            };
+
+            let next = match object.kind.as_str() {
+                "tag" => header_value(&object.data, "object")
+                    .ok_or_else(|| anyhow!("Tag {} has no target", hash))?,
+                "commit" => header_value(&object.data, "tree")
+                    .ok_or_else(|| anyhow!("Commit {} has no tree", hash))?,
+                "tree" => return self.download_tree(&hash).await,
+                kind => bail!("Object {} is a {}, not a tree", hash, kind),
+            };
+            hash = next;
+        }
+    }
+
+    async fn download_tree(&mut self, root: &str) -> Result<bool> {
+        let mut stack = vec![root.to_string()];
+
+        while let Some(hash) = stack.pop() {
+            let Some(object) = self.read(&hash).await? else {
+                return Ok(false);
+            };
+
+            for (mode, entry) in tree_entries(&object.data)? {
+                match mode.as_str() {
+                    // Submodule commits live in another repository.
+                    "160000" => {},
+                    "40000" | "040000" => stack.push(entry),
+                    _ => {
+                        if !self.download(&entry).await? {
+                            return Ok(false);
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Parses `deepen`, `deepen-relative` and `shallow` lines. Returns `None`
+/// when the fetch is not depth-limited, or is limited in a way (by date or
+/// ref) that needs the full history to evaluate.
+pub fn parse_depth_request<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<DepthRequest> {
+    let mut request = DepthRequest::default();
+    let mut deepen = None;
+
+    for line in lines {
+        if let Some(depth) = line.strip_prefix("deepen ") {
+            deepen = depth.trim().parse::<u32>().ok();
+        } else if line == "deepen-relative" {
+            request.relative = true;
+        } else if let Some(hash) = line.strip_prefix("shallow ") {
+            request.shallow.push(hash.trim().to_string());
+        } else if line.starts_with("deepen-since ") || line.starts_with("deepen-not ") {
+            return None;
+        }
+    }
+
+    request.depth = deepen.filter(|depth| *depth > 0)?;
+    Some(request)
+}
+
+fn decode_loose_object(compressed: &[u8]) -> Result<GitObject> {
+    parse_object(&inflate(compressed)?)
+}
+
+/// The `<kind> <size>\0<data>` bytes a loose object's git hash is taken over.
+pub(crate) fn inflate(compressed: &[u8]) -> Result<Vec<u8>> {
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed).read_to_end(&mut raw)?;
+    Ok(raw)
+}
+
+pub(crate) fn parse_object(raw: &[u8]) -> Result<GitObject> {
+    let null_pos = raw
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow!("missing header terminator"))?;
+    let header = std::str::from_utf8(&raw[..null_pos])?;
+    let (kind, _size) = header
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("malformed header '{}'", header))?;
+
+    Ok(GitObject {
+        kind: kind.to_string(),
+        data: raw[null_pos + 1..].to_vec(),
+    })
+}
+
+/// Header lines (`tree`, `parent`, `object`, ...) of a commit or tag, which
+/// end at the first blank line.
+pub(crate) fn header_values(data: &[u8], key: &str) -> Vec<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+        .map(|value| value.trim().to_string())
+        .collect()
+}
+
+fn header_value(data: &[u8], key: &str) -> Option<String> {
+    header_values(data, key).into_iter().next()
+}
+
+/// `(mode, hex hash)` of each entry of a tree object.
+pub(crate) fn tree_entries(mut data: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ').ok_or_else(|| anyhow!("malformed tree entry"))?;
+        let null_pos = data.iter().position(|&b| b == 0).ok_or_else(|| anyhow!("malformed tree entry"))?;
+        if null_pos < space || data.len() < null_pos + 21 {
+            bail!("truncated tree entry");
+        }
+
+        let mode = std::str::from_utf8(&data[..space])?.to_string();
+        let hash = data[null_pos + 1..null_pos + 21]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        entries.push((mode, hash));
+
+        data = &data[null_pos + 21..];
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{self, Packet};
+    use crate::test_support::git;
+    use ethcontract::Address;
+
+    fn object_ids(listing: &str) -> HashSet<String> {
+        listing.lines().filter_map(|line| line.split_whitespace().next()).map(str::to_string).collect()
+    }
+
+    /// A repository of five commits, its loose objects already in place the
+    /// way a pooled workspace keeps them, and a store recording every one.
+    fn history() -> (tempfile::TempDir, ObjectStore) {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        for n in 1..=5 {
+            std::fs::write(dir.path().join(format!("file{}", n)), n.to_string()).unwrap();
+            git(dir.path(), &["add", "."]);
+            git(dir.path(), &["commit", "--quiet", "-m", &format!("commit {}", n)]);
+        }
+
+        let objects = object_ids(&git(dir.path(), &["rev-list", "--objects", "--all"]))
+            .into_iter()
+            .map(|hash| Object { ipfs_url: format!("bafy{}", hash).into_bytes(), hash, pusher: Address::zero() })
+            .collect();
+        let store = ObjectStore::new(&dir.path().join(".git"), objects).unwrap();
+        (dir, store)
+    }
+
+    fn depth_request(lines: &[&str]) -> Option<DepthRequest> {
+        let mut body = Vec::new();
+        for line in lines {
+            body.extend(protocol::encode(format!("{}\n", line).as_bytes()));
+        }
+        body.extend_from_slice(b"0000");
+        body.extend(protocol::encode(b"done\n"));
+
+        let packets = protocol::parse(&body).unwrap();
+        assert_eq!(packets.iter().filter(|packet| **packet == Packet::Flush).count(), 1);
+        parse_depth_request(packets.iter().filter_map(Packet::text))
+    }
+
+    #[test]
+    fn depth_requests_are_parsed_from_the_request_lines() {
+        let request = depth_request(&["want aa multi_ack", "shallow bb", "shallow cc", "deepen 3", "deepen-relative"]).unwrap();
+        assert_eq!(request, DepthRequest { depth: 3, relative: true, shallow: vec!["bb".to_string(), "cc".to_string()] });
+
+        assert_eq!(depth_request(&["want aa", "deepen 1"]).unwrap().depth, 1);
+        assert_eq!(depth_request(&["want aa"]), None);
+        assert_eq!(depth_request(&["want aa", "deepen 0"]), None);
+        assert_eq!(depth_request(&["want aa", "deepen 2", "deepen-since 1700000000"]), None);
+        assert_eq!(depth_request(&["want aa", "deepen-not refs/heads/old"]), None);
+    }
+
+    #[tokio::test]
+    async fn shallow_downloads_stop_at_the_requested_depth() {
+        let (dir, mut store) = history();
+        let tip = git(dir.path(), &["rev-parse", "HEAD"]);
+
+        let request = DepthRequest { depth: 1, ..DepthRequest::default() };
+        assert!(store.download_shallow(&[tip.clone()], &request).await.unwrap());
+        assert_eq!(store.downloaded, object_ids(&git(dir.path(), &["rev-list", "--objects", "--no-walk", "HEAD"])));
+
+        let request = DepthRequest { depth: 2, ..DepthRequest::default() };
+        assert!(store.download_shallow(&[tip], &request).await.unwrap());
+        assert_eq!(store.downloaded, object_ids(&git(dir.path(), &["rev-list", "--objects", "--no-walk", "HEAD", "HEAD~1"])));
+        assert!(store.downloaded.len() < store.cids.len());
+    }
+
+    #[tokio::test]
+    async fn relative_deepens_count_from_the_clients_boundary() {
+        let (dir, mut store) = history();
+        let tip = git(dir.path(), &["rev-parse", "HEAD"]);
+        let boundary = git(dir.path(), &["rev-parse", "HEAD~1"]);
+
+        let request = DepthRequest { depth: 1, relative: true, shallow: vec![boundary] };
+        assert!(store.download_shallow(&[tip], &request).await.unwrap());
+        assert_eq!(store.downloaded, object_ids(&git(dir.path(), &["rev-list", "--objects", "--no-walk", "HEAD", "HEAD~1", "HEAD~2"])));
+    }
+
+    #[tokio::test]
+    async fn shallow_downloads_give_up_on_unrecorded_objects() {
+        let (dir, mut store) = history();
+        let tip = git(dir.path(), &["rev-parse", "HEAD"]);
+        let blob = git(dir.path(), &["rev-parse", "HEAD:file5"]);
+        store.cids.remove(&blob);
+
+        let request = DepthRequest { depth: 1, ..DepthRequest::default() };
+        assert!(!store.download_shallow(&[tip], &request).await.unwrap());
+    }
+}
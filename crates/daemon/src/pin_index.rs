@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use onchain::{ipfs, storage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::{auth::unix_now, config::DaemonConfig, validation::normalize_repo_name};
+
+/// One line of the journal: the latest state of a repository's pin on a CID.
+#[derive(Debug, Serialize, Deserialize)]
+struct PinRecord {
+    repo: String,
+    cid: String,
+    hash: String,
+    #[serde(default)]
+    pinned_at: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unpinned: bool,
+}
+
+/// A CID one of a repository's objects is stored under.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    pub cid: String,
+    /// Git object hash.
+    pub hash: String,
+    /// When a push through this daemon pinned it; 0 when it was only
+    /// learned from the chain.
+    pub pinned_at: u64,
+    pub unpinned: bool,
+}
+
+/// Which IPFS CIDs each repository's objects are pinned under, so pins can
+/// be listed per repository and removed without touching another's. Filled
+/// in as pushes upload objects, and from the chain whenever a repository's
+/// pins are looked at. Objects kept outside IPFS are left out.
+///
+/// Journaled to `DGIT_PIN_INDEX_PATH` as JSON lines, the last line for a
+/// repository and CID winning.
+#[derive(Debug, Clone)]
+pub struct PinIndex {
+    path: Arc<PathBuf>,
+    repos: Arc<Mutex<HashMap<String, BTreeMap<String, Pin>>>>,
+}
+
+impl PinIndex {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut repos: HashMap<String, BTreeMap<String, Pin>> = HashMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str::<PinRecord>(line) {
+                        Ok(record) => {
+                            repos.entry(record.repo).or_default().insert(record.cid.clone(), Pin {
+                                cid: record.cid,
+                                hash: record.hash,
+                                pinned_at: record.pinned_at,
+                                unpinned: record.unpinned,
+                            });
+                        },
+                        Err(e) => warn!("Skipping unreadable line in pin index {:?}: {}", path, e),
+                    }
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => error!("Failed to read pin index {:?}, starting empty: {}", path, e),
+        }
+        if !repos.is_empty() {
+            info!("Loaded pins of {} repositories from {:?}", repos.len(), path);
+        }
+
+        Self {
+            path: Arc::new(path),
+            repos: Arc::new(Mutex::new(repos)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::load(DaemonConfig::pin_index_path())
+    }
+
+    /// Records that a push to `repo` stored `objects`, given as object hash
+    /// and the URL recorded on chain, pinned as of now.
+    pub async fn record(&self, repo: &str, objects: impl IntoIterator<Item = (String, String)>) {
+        self.update(repo, objects, Some(unix_now())).await;
+    }
+
+    /// Adds `objects` the chain records for `repo` that the index does not
+    /// know yet. Known CIDs, including unpinned ones, stay as they are.
+    pub async fn backfill(&self, repo: &str, objects: impl IntoIterator<Item = (String, String)>) {
+        self.update(repo, objects, None).await;
+    }
+
+    /// The CIDs `repo` still has pinned, in CID order.
+    pub async fn pinned(&self, repo: &str) -> Vec<Pin> {
+        let repos = self.repos.lock().await;
+        repos.get(&normalize_repo_name(repo))
+            .map(|pins| pins.values().filter(|pin| !pin.unpinned).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether any repository but `repo` stores an object under `cid`,
+    /// whether or not it still has it pinned.
+    pub async fn shared(&self, repo: &str, cid: &str) -> bool {
+        let key = normalize_repo_name(repo);
+        let repos = self.repos.lock().await;
+        repos.iter().any(|(other, pins)| *other != key && pins.contains_key(cid))
+    }
+
+    /// Records that the pins on `cids` were removed for `repo`.
+    pub async fn mark_unpinned(&self, repo: &str, cids: &[String]) {
+        let key = normalize_repo_name(repo);
+        let mut repos = self.repos.lock().await;
+        let Some(pins) = repos.get_mut(&key) else {
+            return;
+        };
+
+        let mut lines = String::new();
+        for cid in cids {
+            let Some(pin) = pins.get_mut(cid) else {
+                continue;
+            };
+            pin.unpinned = true;
+            push_line(&mut lines, &key, pin);
+        }
+        self.write(&lines).await;
+    }
+
+    /// Adds `objects` to `repo`'s pins: as pinned at `pinned_at`, or with
+    /// `None` only the ones not known yet.
+    async fn update(&self, repo: &str, objects: impl IntoIterator<Item = (String, String)>, pinned_at: Option<u64>) {
+        let key = normalize_repo_name(repo);
+        let mut repos = self.repos.lock().await;
+        let pins = repos.entry(key.clone()).or_default();
+
+        let mut lines = String::new();
+        for (hash, url) in objects {
+            if !storage::is_ipfs(&url) {
+                continue;
+            }
+            let cid = ipfs::normalize_cid(&url);
+            if pinned_at.is_none() && pins.contains_key(&cid) {
+                continue;
+            }
+            let pin = Pin { cid: cid.clone(), hash, pinned_at: pinned_at.unwrap_or_default(), unpinned: false };
+            push_line(&mut lines, &key, &pin);
+            pins.insert(cid, pin);
+        }
+        self.write(&lines).await;
+    }
+
+    /// Appends `lines` to the journal. A failure is logged; the pins are
+    /// only learned again from the chain.
+    async fn write(&self, lines: &str) {
+        if lines.is_empty() {
+            return;
+        }
+        if let Err(e) = Self::append(&self.path, lines).await {
+            error!("Failed to write pin index: {:#}", e);
+        }
+    }
+
+    async fn append(path: &Path, lines: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open pin index {:?}", path))?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
+
+fn push_line(lines: &mut String, repo: &str, pin: &Pin) {
+    let record = PinRecord {
+        repo: repo.to_string(),
+        cid: pin.cid.clone(),
+        hash: pin.hash.clone(),
+        pinned_at: pin.pinned_at,
+        unpinned: pin.unpinned,
+    };
+    match serde_json::to_string(&record) {
+        Ok(line) => {
+            lines.push_str(&line);
+            lines.push('\n');
+        },
+        Err(e) => warn!("Failed to serialize pin index entry for {}: {}", pin.cid, e),
+    }
+}
@@ -0,0 +1,417 @@
+use anyhow::{bail, Result};
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+
+/// Request header git uses to negotiate the wire protocol version
+/// (`version=2`), forwarded to the git child as `GIT_PROTOCOL`.
+pub const GIT_PROTOCOL_HEADER: &str = "Git-Protocol";
+
+pub const FLUSH_PKT: &[u8] = b"0000";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    /// `0000`: end of a message or section.
+    Flush,
+    /// `0001`: separates command arguments from capabilities (v2 only).
+    Delim,
+    /// `0002`: end of a stateless response (v2 only).
+    ResponseEnd,
+}
+
+impl Packet {
+    /// Payload as text without the trailing newline, if it is a data line.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Packet::Data(data) => std::str::from_utf8(data).ok().map(|s| s.trim_end_matches('\n')),
+            _ => None,
+        }
+    }
+}
+
+/// Frames `payload` as a single pkt-line.
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut pkt = format!("{:04x}", payload.len() + 4).into_bytes();
+    pkt.extend_from_slice(payload);
+    pkt
+}
+
+/// Splits a pkt-line stream into packets. Parsing stops at the first
+/// non-pkt-line byte, which is where a packfile starts in receive-pack.
+pub fn parse(data: &[u8]) -> Result<Vec<Packet>> {
+    Ok(split(data)?.0)
+}
+
+/// Like [`parse`], for requests that are nothing but pkt-lines: anything
+/// left over is an error.
+pub fn parse_complete(data: &[u8]) -> Result<Vec<Packet>> {
+    let (packets, rest) = split(data)?;
+    if !rest.is_empty() {
+        bail!("{} bytes after the last pkt-line", rest.len());
+    }
+    Ok(packets)
+}
+
+/// The packets at the start of `data`, and what follows them.
+fn split(mut data: &[u8]) -> Result<(Vec<Packet>, &[u8])> {
+    let mut packets = Vec::new();
+
+    while data.len() >= 4 {
+        let Some(len) = std::str::from_utf8(&data[..4]).ok().and_then(|hex| usize::from_str_radix(hex, 16).ok()) else {
+            break;
+        };
+
+        match len {
+            0 => packets.push(Packet::Flush),
+            1 => packets.push(Packet::Delim),
+            2 => packets.push(Packet::ResponseEnd),
+            3 => bail!("Invalid pkt-line length 3"),
+            _ => {
+                if len > data.len() {
+                    bail!("Truncated pkt-line: expected {} bytes, {} left", len, data.len());
+                }
+                packets.push(Packet::Data(data[4..len].to_vec()));
+                data = &data[len..];
+                continue;
+            }
+        }
+
+        data = &data[4..];
+    }
+
+    Ok((packets, data))
+}
+
+/// One `<old> <new> <ref>` command of a receive-pack request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefCommand {
+    pub old: String,
+    pub new: String,
+    pub name: String,
+}
+
+impl RefCommand {
+    pub fn is_create(&self) -> bool {
+        is_zero_id(&self.old)
+    }
+
+    pub fn is_delete(&self) -> bool {
+        is_zero_id(&self.new)
+    }
+}
+
+fn is_zero_id(id: &str) -> bool {
+    id.bytes().all(|b| b == b'0')
+}
+
+/// The ref update commands of a receive-pack request, which run up to the
+/// first flush.
+pub fn ref_commands(packets: &[Packet]) -> Vec<RefCommand> {
+    packets
+        .iter()
+        .take_while(|packet| matches!(packet, Packet::Data(_)))
+        .filter_map(|packet| match packet {
+            Packet::Data(line) => {
+                let line = line.split(|&b| b == 0).next().unwrap_or_default();
+                let line = String::from_utf8_lossy(line);
+                let mut fields = line.trim_end().split(' ');
+                Some(RefCommand {
+                    old: fields.next()?.to_string(),
+                    new: fields.next()?.to_string(),
+                    name: fields.next()?.to_string(),
+                })
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Capabilities a v0 request lists after a NUL on its first command line.
+pub fn capabilities(packets: &[Packet]) -> Vec<String> {
+    let Some(Packet::Data(line)) = packets.first() else {
+        return Vec::new();
+    };
+    let Some(nul) = line.iter().position(|&b| b == 0) else {
+        return Vec::new();
+    };
+
+    String::from_utf8_lossy(&line[nul + 1..])
+        .split_whitespace()
+        .map(|capability| capability.to_string())
+        .collect()
+}
+
+/// Capabilities a v0 fetch lists after the object id of its first `want`.
+pub fn want_capabilities(packets: &[Packet]) -> Vec<String> {
+    let Some(line) = packets.first().and_then(Packet::text) else {
+        return Vec::new();
+    };
+    let Some(rest) = line.strip_prefix("want ") else {
+        return Vec::new();
+    };
+
+    rest.split_whitespace()
+        .skip(1)
+        .map(|capability| capability.to_string())
+        .collect()
+}
+
+/// `git push -o` values, which follow the commands' flush when the client
+/// asked for `push-options`.
+pub fn push_options(packets: &[Packet]) -> Vec<String> {
+    if !capabilities(packets).iter().any(|capability| capability == "push-options") {
+        return Vec::new();
+    }
+
+    packets
+        .iter()
+        .skip_while(|packet| **packet != Packet::Flush)
+        .skip(1)
+        .take_while(|packet| matches!(packet, Packet::Data(_)))
+        .filter_map(Packet::text)
+        .map(|option| option.to_string())
+        .collect()
+}
+
+/// `Git-Protocol` header value, if present and safe to pass to git.
+pub fn git_protocol(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(GIT_PROTOCOL_HEADER)?.to_str().ok()?;
+    // Same character set git-http-backend accepts for this header.
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '=' | ':' | '-' | '_' | '.')) {
+        return None;
+    }
+    Some(value.to_string())
+}
+
+pub fn is_v2(git_protocol: Option<&str>) -> bool {
+    git_protocol.is_some_and(|value| value.split(':').any(|param| param == "version=2"))
+}
+
+/// Capabilities upload-pack advertises over protocol v0, in git's order.
+/// `allow-reachable-sha1-in-want` and `filter` match the settings
+/// [`crate::git::upload_pack`] runs with, and `no-done` is always on for
+/// stateless HTTP.
+const UPLOAD_PACK_CAPABILITIES: &str = "multi_ack thin-pack side-band side-band-64k ofs-delta shallow deepen-since deepen-not \
+    deepen-relative no-progress include-tag multi_ack_detailed allow-reachable-sha1-in-want no-done";
+
+/// Capabilities receive-pack advertises, in git's order, with `atomic` and
+/// `push-options` switched on as the push handler runs it.
+/// `report-status-v2` is spliced in after `report-status` for git 2.29 and
+/// later.
+const RECEIVE_PACK_CAPABILITIES: &str = "delete-refs side-band-64k quiet atomic ofs-delta push-options";
+
+/// First git to advertise `object-format`.
+const OBJECT_FORMAT_VERSION: (u32, u32, u32) = (2, 28, 0);
+/// First git to understand `report-status-v2`.
+const REPORT_STATUS_V2_VERSION: (u32, u32, u32) = (2, 29, 0);
+/// First git whose upload-pack announces capabilities for an empty
+/// repository; receive-pack always has.
+const EMPTY_UPLOAD_CAPABILITIES_VERSION: (u32, u32, u32) = (2, 41, 0);
+
+const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+/// The protocol v0 ref advertisement for `service`, byte for byte what
+/// `git <service> --stateless-rpc --advertise-refs` prints for a repository
+/// with `refs` (name and object id, sorted by name) apart from the agent.
+/// Upload-pack leads with `HEAD` when `head` names one of the refs, and
+/// follows each annotated tag with the object `peeled` says it points to.
+/// Capabilities are the ones `git_version` advertises, since that git is
+/// what serves the request that follows.
+pub fn ref_advertisement(
+    service: &str,
+    refs: &[(String, String)],
+    head: Option<&str>,
+    peeled: &HashMap<String, String>,
+    git_version: (u32, u32, u32),
+) -> Vec<u8> {
+    let upload = service == "git-upload-pack";
+    let head = head
+        .filter(|_| upload)
+        .and_then(|head| refs.iter().find(|(name, _)| name == head))
+        .map(|(name, oid)| (name.as_str(), oid.as_str()));
+
+    let mut capabilities = if upload {
+        let mut capabilities = UPLOAD_PACK_CAPABILITIES.to_string();
+        if let Some((branch, _)) = head {
+            capabilities.push_str(&format!(" symref=HEAD:{}", branch));
+        }
+        capabilities.push_str(" filter");
+        capabilities
+    } else if git_version >= REPORT_STATUS_V2_VERSION {
+        format!("report-status report-status-v2 {}", RECEIVE_PACK_CAPABILITIES)
+    } else {
+        format!("report-status {}", RECEIVE_PACK_CAPABILITIES)
+    };
+    if git_version >= OBJECT_FORMAT_VERSION {
+        capabilities.push_str(" object-format=sha1");
+    }
+    capabilities.push_str(&format!(" agent=dgit/{}", env!("CARGO_PKG_VERSION")));
+
+    let lines = head
+        .map(|(_, oid)| ("HEAD", oid))
+        .into_iter()
+        .chain(refs.iter().map(|(name, oid)| (name.as_str(), oid.as_str())));
+
+    let mut advertisement = Vec::new();
+    let mut first = true;
+    for (name, oid) in lines {
+        let line = if first {
+            format!("{} {}\0{}\n", oid, name, capabilities)
+        } else {
+            format!("{} {}\n", oid, name)
+        };
+        first = false;
+        advertisement.extend_from_slice(&encode(line.as_bytes()));
+        if let Some(target) = peeled.get(oid).filter(|_| upload) {
+            advertisement.extend_from_slice(&encode(format!("{} {}^{{}}\n", target, name).as_bytes()));
+        }
+    }
+    // An empty repository still has to announce what it supports.
+    if first && (!upload || git_version >= EMPTY_UPLOAD_CAPABILITIES_VERSION) {
+        advertisement.extend_from_slice(&encode(format!("{} capabilities^{{}}\0{}\n", NULL_OID, capabilities).as_bytes()));
+    }
+    advertisement.extend_from_slice(FLUSH_PKT);
+    advertisement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::parse_version;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", "user.name=dgit", "-c", "user.email=dgit@example.com"])
+            .args(args)
+            .output()
+            .expect("git runs");
+        assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).expect("git prints UTF-8")
+    }
+
+    fn init() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        dir
+    }
+
+    fn commit(repo: &Path, message: &str) {
+        git(repo, &["commit", "--quiet", "--allow-empty", "-m", message]);
+    }
+
+    /// The packets of an advertisement with the `agent=` capability left
+    /// out, the one place dgit and git are meant to differ.
+    fn without_agent(advertisement: &[u8]) -> Vec<Packet> {
+        parse_complete(advertisement)
+            .unwrap()
+            .into_iter()
+            .map(|packet| match packet {
+                Packet::Data(line) => {
+                    let line = String::from_utf8(line).unwrap();
+                    let line = match line.find(" agent=") {
+                        Some(start) => {
+                            let end = line[start + 1..].find([' ', '\n']).map_or(line.len(), |end| start + 1 + end);
+                            format!("{}{}", &line[..start], &line[end..])
+                        },
+                        None => line,
+                    };
+                    Packet::Data(line.into_bytes())
+                },
+                packet => packet,
+            })
+            .collect()
+    }
+
+    /// Checks `ref_advertisement` against what the installed git prints for
+    /// `repo`, run with the settings the daemon's handlers use.
+    fn assert_matches_git(repo: &Path, service: &str) {
+        let version = parse_version(git(repo, &["--version"]).trim()).expect("git version");
+
+        let refs: Vec<(String, String)> = git(repo, &["for-each-ref", "--format=%(refname) %(objectname)"])
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, oid)| (name.to_string(), oid.to_string()))
+            .collect();
+        let mut peeled = HashMap::new();
+        for (name, oid) in refs.iter().filter(|(name, _)| name.starts_with("refs/tags/")) {
+            let target = git(repo, &["rev-parse", &format!("{}^{{}}", name)]).trim().to_string();
+            if target != *oid {
+                peeled.insert(oid.clone(), target);
+            }
+        }
+        let head = git(repo, &["symbolic-ref", "HEAD"]).trim().to_string();
+
+        let args: &[&str] = match service {
+            "git-upload-pack" => &["-c", "uploadpack.allowFilter=true", "-c", "uploadpack.allowReachableSHA1InWant=true", "upload-pack"],
+            _ => &["-c", "receive.advertisePushOptions=true", "receive-pack"],
+        };
+        let expected = Command::new("git")
+            .args(args)
+            .args(["--stateless-rpc", "--advertise-refs"])
+            .arg(repo)
+            .output()
+            .expect("git runs")
+            .stdout;
+
+        let actual = ref_advertisement(service, &refs, Some(&head), &peeled, version);
+        assert_eq!(without_agent(&actual), without_agent(&expected));
+    }
+
+    #[test]
+    fn advertises_an_empty_repository_like_git() {
+        let repo = init();
+        assert_matches_git(repo.path(), "git-upload-pack");
+        assert_matches_git(repo.path(), "git-receive-pack");
+    }
+
+    #[test]
+    fn advertises_head_as_a_symref() {
+        let repo = init();
+        commit(repo.path(), "first");
+        git(repo.path(), &["branch", "feature"]);
+        commit(repo.path(), "second");
+        assert_matches_git(repo.path(), "git-upload-pack");
+    }
+
+    #[test]
+    fn peels_annotated_tags() {
+        let repo = init();
+        commit(repo.path(), "first");
+        git(repo.path(), &["tag", "light"]);
+        git(repo.path(), &["tag", "-a", "v1", "-m", "v1"]);
+        git(repo.path(), &["tag", "-a", "v1-signed-off", "-m", "tag of a tag", "v1"]);
+        assert_matches_git(repo.path(), "git-upload-pack");
+    }
+
+    #[test]
+    fn advertises_receive_pack_like_git() {
+        let repo = init();
+        commit(repo.path(), "first");
+        git(repo.path(), &["tag", "-a", "v1", "-m", "v1"]);
+        assert_matches_git(repo.path(), "git-receive-pack");
+    }
+
+    fn push_request(capabilities: &str) -> Vec<Packet> {
+        let mut body = encode(format!("{} {} refs/heads/main\0{}\n", NULL_OID, "1".repeat(40), capabilities).as_bytes());
+        body.extend(encode(format!("{} {} refs/heads/dev\n", NULL_OID, "2".repeat(40)).as_bytes()));
+        body.extend_from_slice(FLUSH_PKT);
+        body.extend_from_slice(b"PACK");
+        parse(&body).unwrap()
+    }
+
+    #[test]
+    fn capabilities_come_from_the_first_command() {
+        let packets = push_request("report-status atomic side-band-64k agent=git/2.43.0");
+        assert_eq!(capabilities(&packets), ["report-status", "atomic", "side-band-64k", "agent=git/2.43.0"]);
+        assert_eq!(ref_commands(&packets).len(), 2);
+        assert_eq!(ref_commands(&packets)[1].name, "refs/heads/dev");
+
+        assert!(!capabilities(&push_request("report-status")).iter().any(|capability| capability == "atomic"));
+        assert!(capabilities(&push_request("")).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::config::DaemonConfig;
+
+/// A write was refused because the repository used up its budget.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub reason: String,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit exceeded: {}, retry in {}s", self.reason, self.retry_after.as_secs().max(1))
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Token bucket holding up to `capacity` tokens, refilled evenly over `period`.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    capacity: f64,
+    period: Duration,
+}
+
+impl Limit {
+    fn per_second(&self) -> f64 {
+        self.capacity / self.period.as_secs_f64()
+    }
+}
+
+impl Bucket {
+    fn full(limit: Limit) -> Self {
+        Self { tokens: limit.capacity, updated: Instant::now() }
+    }
+
+    fn take(&mut self, limit: Limit, amount: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * limit.per_second();
+        self.tokens = (self.tokens + refill).min(limit.capacity);
+        self.updated = now;
+
+        if amount <= self.tokens {
+            self.tokens -= amount;
+            return Ok(());
+        }
+
+        // A request larger than the whole bucket can only pass once it is full.
+        let missing = amount.min(limit.capacity) - self.tokens;
+        Err(Duration::from_secs_f64(missing / limit.per_second()))
+    }
+}
+
+/// Per-repository limits on pushes and on objects written, which is what
+/// spends the signer's gas. Reads are never limited.
+///
+/// Configured with `PUSH_RATE_PER_MINUTE` and `OBJECT_RATE_PER_HOUR`; `0`
+/// disables a limit.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    pushes: Option<Limit>,
+    objects: Option<Limit>,
+    buckets: Arc<Mutex<HashMap<(String, &'static str), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(pushes_per_minute: u32, objects_per_hour: u32) -> Self {
+        let limit = |capacity: u32, period: Duration| {
+            (capacity > 0).then(|| Limit { capacity: capacity as f64, period })
+        };
+
+        Self {
+            pushes: limit(pushes_per_minute, Duration::from_secs(60)),
+            objects: limit(objects_per_hour, Duration::from_secs(3600)),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::push_rate_per_minute(), DaemonConfig::object_rate_per_hour())
+    }
+
+    /// Takes one push from `repo`'s budget.
+    pub async fn check_push(&self, repo: &str) -> Result<(), RateLimited> {
+        self.take(repo, "pushes", self.pushes, 1).await
+    }
+
+    /// Takes `count` written objects from `repo`'s budget.
+    pub async fn check_objects(&self, repo: &str, count: usize) -> Result<(), RateLimited> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.take(repo, "objects", self.objects, count).await
+    }
+
+    async fn take(&self, repo: &str, kind: &'static str, limit: Option<Limit>, amount: usize) -> Result<(), RateLimited> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .entry((repo.to_string(), kind))
+            .or_insert_with(|| Bucket::full(limit));
+
+        bucket.take(limit, amount as f64).map_err(|retry_after| {
+            warn!("Rate limited {} on {}: {} requested", kind, repo, amount);
+            RateLimited {
+                reason: format!("too many {} for repository '{}' (at most {} per {}s)", kind, repo, limit.capacity, limit.period.as_secs()),
+                retry_after,
+            }
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use onchain::contract_interaction::{ContractInteraction, Ref};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use crate::{config::DaemonConfig, repo_views::RepoViews, validation::normalize_repo_name};
+
+/// The latest active entry for each ref name, sorted by name. Later entries
+/// in the contract's ref list supersede earlier ones with the same name.
+pub fn collapse(refs: &[Ref]) -> Vec<Ref> {
+    let mut latest: HashMap<&str, &Ref> = HashMap::new();
+    for r in refs {
+        latest.insert(r.name.as_str(), r);
+    }
+
+    let mut active: Vec<Ref> = latest.into_values().filter(|r| r.is_active).cloned().collect();
+    active.sort_by(|a, b| a.name.cmp(&b.name));
+    active
+}
+
+/// Collapsed ref sets per repository, so read handlers do not fetch and
+/// collapse the whole on-chain list on every request.
+///
+/// Entries expire after `REF_CACHE_TTL_SECS` (`0` disables the cache), which
+/// bounds how long a write through another daemon goes unseen. Writes
+/// through this daemon invalidate the entry right away.
+///
+/// It also remembers what each repository's annotated tags peel to. Tag
+/// objects never change, so these do not expire; they are pruned to the
+/// tags a repository still has whenever they are stored.
+#[derive(Debug, Clone)]
+pub struct RefCache {
+    ttl: Duration,
+    views: RepoViews,
+    entries: Arc<Mutex<HashMap<String, (Instant, Vec<Ref>)>>>,
+    peeled: Arc<Mutex<HashMap<String, HashMap<String, Option<String>>>>>,
+}
+
+impl RefCache {
+    pub fn new(ttl: Duration, views: RepoViews) -> Self {
+        Self {
+            ttl,
+            views,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            peeled: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env(views: RepoViews) -> Self {
+        Self::new(Duration::from_secs(DaemonConfig::ref_cache_ttl_secs()), views)
+    }
+
+    /// The active refs of `repo`, from the cache while it is fresh.
+    pub async fn active_refs(&self, repo: &str, contract: &ContractInteraction) -> Result<Vec<Ref>> {
+        let key = normalize_repo_name(repo);
+        if let Some((cached_at, refs)) = self.entries.lock().await.get(&key) {
+            if cached_at.elapsed() < self.ttl {
+                debug!("Using {} cached refs of {}", refs.len(), repo);
+                return Ok(refs.clone());
+            }
+        }
+
+        let refs = collapse(&self.views.refs(contract).await?);
+        self.store(repo, refs.clone()).await;
+        Ok(refs)
+    }
+
+    pub async fn store(&self, repo: &str, refs: Vec<Ref>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries.lock().await.insert(normalize_repo_name(repo), (Instant::now(), refs));
+    }
+
+    pub async fn invalidate(&self, repo: &str) {
+        self.entries.lock().await.remove(&normalize_repo_name(repo));
+    }
+
+    /// What the tag objects of `repo` peeled so far point to, or `None` for
+    /// an object that turned out not to be a tag.
+    pub async fn peeled(&self, repo: &str) -> HashMap<String, Option<String>> {
+        self.peeled.lock().await.get(&normalize_repo_name(repo)).cloned().unwrap_or_default()
+    }
+
+    /// Replaces the peeled tags of `repo`, keeping only the object ids in
+    /// `tags` so entries for tags moved or deleted since are dropped.
+    pub async fn store_peeled(&self, repo: &str, mut peeled: HashMap<String, Option<String>>, tags: &[&str]) {
+        peeled.retain(|oid, _| tags.contains(&oid.as_str()));
+        let mut cache = self.peeled.lock().await;
+        if peeled.is_empty() {
+            cache.remove(&normalize_repo_name(repo));
+        } else {
+            cache.insert(normalize_repo_name(repo), peeled);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use onchain::contract_interaction::{ContractInteraction, Object, Ref};
+use onchain::sync::RepoView;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, error, warn};
+
+use crate::config::DaemonConfig;
+
+/// Synced views of each repository contract, so handlers read objects and
+/// refs without fetching the whole on-chain arrays.
+///
+/// Each view is saved under `DGIT_SYNC_DIR` as `<contract address>.json`
+/// together with the block it was synced to, so a restarted daemon only
+/// catches up on the blocks it missed.
+#[derive(Debug, Clone)]
+pub struct RepoViews {
+    dir: Arc<PathBuf>,
+    views: Arc<Mutex<HashMap<String, Arc<Mutex<RepoView>>>>>,
+}
+
+impl RepoViews {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Arc::new(dir.into()),
+            views: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::sync_dir())
+    }
+
+    /// Every object recorded on `contract`, in contract order.
+    pub async fn objects(&self, contract: &ContractInteraction) -> Result<Vec<Object>> {
+        self.synced(contract, |view| view.current().objects.clone()).await
+    }
+
+    /// Every ref entry of `contract`, in contract order.
+    pub async fn refs(&self, contract: &ContractInteraction) -> Result<Vec<Ref>> {
+        self.synced(contract, |view| view.current().refs.clone()).await
+    }
+
+    /// Which of `hashes` are recorded on `contract`, without a contract call
+    /// per hash.
+    pub async fn has_objects(&self, contract: &ContractInteraction, hashes: &[String]) -> Result<Vec<bool>> {
+        self.synced(contract, |view| hashes.iter().map(|hash| view.has_object(hash)).collect()).await
+    }
+
+    /// Syncs the view of `contract` without reading it, so the next read
+    /// finds it current.
+    pub async fn refresh(&self, contract: &ContractInteraction) -> Result<()> {
+        self.synced(contract, |_| ()).await
+    }
+
+    async fn synced<T>(&self, contract: &ContractInteraction, read: impl FnOnce(&RepoView) -> T) -> Result<T> {
+        let address = contract.address();
+        let view = {
+            let mut views = self.views.lock().await;
+            match views.get(&address) {
+                Some(view) => Arc::clone(view),
+                None => {
+                    let view = Arc::new(Mutex::new(self.load(&address).await));
+                    views.insert(address.clone(), Arc::clone(&view));
+                    view
+                },
+            }
+        };
+
+        // One sync per contract at a time; other repositories are not held up.
+        let mut view = view.lock().await;
+        let before = view.block;
+        view.sync(contract).await?;
+        if view.block != before {
+            if let Err(e) = self.persist(&address, &view).await {
+                warn!("Failed to save synced view of {}: {:#}", address, e);
+            }
+        }
+
+        Ok(read(&view))
+    }
+
+    async fn load(&self, address: &str) -> RepoView {
+        let path = self.path(address);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse synced view {:?}, syncing from scratch: {}", path, e);
+                RepoView::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RepoView::default(),
+            Err(e) => {
+                error!("Failed to read synced view {:?}, syncing from scratch: {}", path, e);
+                RepoView::default()
+            }
+        }
+    }
+
+    async fn persist(&self, address: &str, view: &RepoView) -> Result<()> {
+        tokio::fs::create_dir_all(self.dir.as_path()).await
+            .with_context(|| format!("Failed to create sync directory {:?}", self.dir))?;
+
+        let path = self.path(address);
+        let content = serde_json::to_vec(view)?;
+        // Write-then-rename so a crash never leaves a truncated file behind.
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, content).await
+            .with_context(|| format!("Failed to write synced view {:?}", tmp))?;
+        tokio::fs::rename(&tmp, &path).await
+            .with_context(|| format!("Failed to replace synced view {:?}", path))?;
+        debug!("Saved view of {} at block {:?}", address, view.block);
+        Ok(())
+    }
+
+    fn path(&self, address: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", address))
+    }
+}
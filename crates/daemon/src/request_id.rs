@@ -0,0 +1,79 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info_span, Instrument};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied ID that is kept; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Runs each request in a `request` span carrying its ID, method, path and
+/// repository, so logs from the handler, IPFS and contract layers can be
+/// told apart. The ID comes from `X-Request-Id` if the client sent a usable
+/// one and is echoed in the response either way.
+pub async fn trace_request(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate_id);
+
+    let repo = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|matched| repo_from_path(matched.as_str(), request.uri().path()))
+        .unwrap_or_default();
+
+    let span = info_span!("request", id = %id, method = %request.method(), path = %request.uri().path(), repo = %repo);
+    let mut response = next.run(request).instrument(span).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// IDs unique within this daemon run: the start time, then a counter.
+fn generate_id() -> String {
+    static STARTED: LazyLock<u64> = LazyLock::new(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+    });
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+
+    format!("{:x}-{:06x}", *STARTED, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// The `{repo}` (or `{owner}/{repo}`) segments of `path` according to the
+/// route template it matched.
+fn repo_from_path(template: &str, path: &str) -> Option<String> {
+    let mut owner = None;
+    let mut repo = None;
+    for (part, segment) in template.split('/').zip(path.split('/')) {
+        match part {
+            "{owner}" => owner = Some(segment),
+            "{repo}" => repo = Some(segment),
+            _ => {},
+        }
+    }
+
+    let repo = repo?;
+    Some(match owner {
+        Some(owner) => format!("{}/{}", owner, repo),
+        None => repo.to_string(),
+    })
+}
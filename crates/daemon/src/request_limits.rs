@@ -0,0 +1,214 @@
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use crate::{config::DaemonConfig, error::ApiError, state::ContractState};
+
+/// How long a client refused for lack of a pack slot is asked to wait.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// A pack operation was refused because every slot and queue place is taken.
+#[derive(Debug)]
+pub struct Overloaded {
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Too many pack operations in progress, retry in {}s", self.retry_after.as_secs().max(1))
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+/// A handler did not produce a response in time.
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request timed out after {}s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Bounds on how long a request may run and how many fetches and pushes
+/// run at once, so a hung gateway or RPC node cannot hold a task forever
+/// and a burst of pushes cannot fill the disk with temporary repositories.
+///
+/// `git-upload-pack` and `git-receive-pack` share `DGIT_PACK_CONCURRENCY`
+/// slots; up to `DGIT_PACK_QUEUE` more wait for one, and the rest are
+/// refused with 503. Timeouts start once a request has its slot. `0`
+/// disables a limit.
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    timeout: Duration,
+    pack_timeout: Duration,
+    pack_concurrency: usize,
+    pack_slots: Option<Arc<Semaphore>>,
+    max_queued: u64,
+    in_flight: Arc<AtomicU64>,
+    queued: Arc<AtomicU64>,
+    rejected: Arc<AtomicU64>,
+    timed_out: Arc<AtomicU64>,
+}
+
+impl RequestLimits {
+    pub fn new(timeout: Duration, pack_timeout: Duration, pack_concurrency: usize, max_queued: u64) -> Self {
+        Self {
+            timeout,
+            pack_timeout,
+            pack_concurrency,
+            pack_slots: (pack_concurrency > 0).then(|| Arc::new(Semaphore::new(pack_concurrency))),
+            max_queued,
+            in_flight: Arc::new(AtomicU64::new(0)),
+            queued: Arc::new(AtomicU64::new(0)),
+            rejected: Arc::new(AtomicU64::new(0)),
+            timed_out: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            Duration::from_secs(DaemonConfig::request_timeout_secs()),
+            Duration::from_secs(DaemonConfig::pack_timeout_secs()),
+            DaemonConfig::pack_concurrency(),
+            DaemonConfig::pack_queue(),
+        )
+    }
+
+    /// Requests being handled, pack operations included.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Pack operations holding a slot.
+    pub fn packs_in_flight(&self) -> u64 {
+        match &self.pack_slots {
+            Some(slots) => (self.pack_concurrency - slots.available_permits()) as u64,
+            None => 0,
+        }
+    }
+
+    /// Pack operations waiting for a slot.
+    pub fn packs_queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn packs_rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn timed_out(&self) -> u64 {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Waits for a pack slot, or refuses at once if the queue is full.
+    /// `None` when pack operations are not limited.
+    async fn acquire_pack_slot(&self) -> Result<Option<OwnedSemaphorePermit>, Overloaded> {
+        let Some(slots) = &self.pack_slots else {
+            return Ok(None);
+        };
+        if let Ok(permit) = slots.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+
+        let waiting = Counted::enter(&self.queued);
+        if waiting.value > self.max_queued {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(Overloaded { retry_after: RETRY_AFTER });
+        }
+        // The semaphore is never closed.
+        Ok(slots.clone().acquire_owned().await.ok())
+    }
+}
+
+/// Applies `RequestLimits` to every request: a slot for pack operations,
+/// then the timeout for the route's kind.
+pub async fn enforce_limits(State(contract_state): State<ContractState>, request: Request, next: Next) -> Response {
+    let limits = contract_state.request_limits().clone();
+    let in_flight = Counted::enter(&limits.in_flight);
+
+    let is_pack = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|matched| is_pack_route(matched.as_str()));
+
+    let slot = if is_pack {
+        match limits.acquire_pack_slot().await {
+            Ok(slot) => slot,
+            Err(e) => {
+                warn!("Refusing {}: {}", request.uri().path(), e);
+                return ApiError::from(e).into_response();
+            },
+        }
+    } else {
+        None
+    };
+
+    let timeout = if is_pack { limits.pack_timeout } else { limits.timeout };
+    let response = if timeout.is_zero() {
+        next.run(request).await
+    } else {
+        match tokio::time::timeout(timeout, next.run(request)).await {
+            Ok(response) => response,
+            Err(_) => {
+                limits.timed_out.fetch_add(1, Ordering::Relaxed);
+                warn!("Request timed out after {}s", timeout.as_secs());
+                return ApiError::from(TimedOut(timeout)).into_response();
+            },
+        }
+    };
+
+    // A fetch streams its pack after the handler returns; the slot is the
+    // fetch's until the last byte is sent.
+    match slot {
+        Some(slot) => hold_until_sent(response, (slot, in_flight)),
+        None => response,
+    }
+}
+
+fn is_pack_route(path: &str) -> bool {
+    path.ends_with("/git-upload-pack") || path.ends_with("/git-receive-pack")
+}
+
+fn hold_until_sent<T: Send + Sync + 'static>(response: Response, held: T) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &held;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Counts itself in `counter` while alive, so requests the client gives up
+/// on are counted out too.
+struct Counted {
+    counter: Arc<AtomicU64>,
+    /// The counter's value including this one.
+    value: u64,
+}
+
+impl Counted {
+    fn enter(counter: &Arc<AtomicU64>) -> Self {
+        let value = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        Self { counter: counter.clone(), value }
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
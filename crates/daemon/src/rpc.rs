@@ -0,0 +1,78 @@
+use anyhow::{bail, Context, Result};
+use ethcontract::web3::Web3;
+use onchain::{config::Config, signer::Signer, transport::{is_rpc_url, rpc_health, EndpointHealth, FailoverTransport}};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::state::ContractState;
+
+/// What switching RPC endpoints did.
+#[derive(Debug, Serialize)]
+pub struct RpcSwitch {
+    /// Chain the new endpoints are on.
+    pub chain_id: u64,
+    /// Repository contracts moved over to them.
+    pub contracts: usize,
+    pub endpoints: Vec<EndpointHealth>,
+}
+
+/// Moves every chain call over to `urls`, for changing RPC providers
+/// without a restart. The new endpoints must answer, and be on `CHAIN_ID`
+/// if it is set, before anything is switched; the repository-to-contract
+/// map is kept, only the connections behind it change.
+pub async fn switch(contract_state: &ContractState, urls: Vec<String>) -> Result<RpcSwitch> {
+    if urls.is_empty() {
+        bail!("No RPC endpoints given");
+    }
+    if let Some(url) = urls.iter().find(|url| !is_rpc_url(url)) {
+        bail!("Invalid RPC endpoint '{}': expected an http(s) or ws(s) URL", url);
+    }
+    if let Signer::Remote { .. } = Signer::shared()? {
+        bail!("Chain calls go through the remote signer; change REMOTE_SIGNER_URL and restart instead");
+    }
+
+    let client = Web3::new(FailoverTransport::from_env(&urls));
+    let chain_id = client.eth().chain_id().await
+        .context("The new RPC endpoints did not answer; keeping the current ones")?
+        .as_u64();
+
+    Config::set_rpc_urls(urls);
+    let contracts = contract_state.reconnect_contracts().await;
+    info!("Switched to {} RPC endpoints on chain {}, reconnecting {} contracts", Config::rpc_urls().len(), chain_id, contracts);
+
+    Ok(RpcSwitch { chain_id, contracts, endpoints: rpc_health() })
+}
+
+/// Switches to the endpoints in `.env` whenever the daemon gets SIGHUP, so
+/// editing `RPC_URLS` or `RPC_URL` there and signalling the process is
+/// enough to change providers.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(contract_state: ContractState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Failed to listen for SIGHUP, RPC endpoints can only be changed with POST /admin/rpc: {}", e);
+            return;
+        },
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let Some(urls) = Config::rpc_urls_from_env_file() else {
+                warn!("SIGHUP: .env sets neither RPC_URLS nor RPC_URL, keeping the current endpoints");
+                continue;
+            };
+            if urls == Config::rpc_urls() {
+                info!("SIGHUP: RPC endpoints unchanged");
+                continue;
+            }
+            if let Err(e) = switch(&contract_state, urls).await {
+                warn!("SIGHUP: failed to switch RPC endpoints: {:#}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_contract_state: ContractState) {}
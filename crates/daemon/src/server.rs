@@ -0,0 +1,346 @@
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use axum::{
+    http::{header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE}, HeaderName, HeaderValue, Method},
+    middleware,
+    routing::{get, post, put, MethodRouter},
+    Router,
+};
+use onchain::{config::Config, registry, signer::Signer, storage, transport::current_chain_id};
+use tokio::sync::watch;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::{error, info, warn};
+
+use crate::{handlers::{
+    create_repo, health_check, ready_check, receive_pack, upload_pack, upload_archive, info_refs,
+    grant_pusher_role, revoke_pusher_role, grant_pushers, revoke_pushers, grant_admin_role, revoke_admin_role,
+    check_pusher_role, check_admin_role, grant_reader_role, revoke_reader_role,
+    check_reader_role, list_roles, set_visibility, get_audit_log, get_costs, repo_history, anchoring_status, has_objects, list_refs,
+    add_webhook, list_webhooks, remove_webhook, verify_repo, head_ref, loose_object, gc_repo,
+    add_protection, list_protection, remove_protection, get_signing_keys, set_signing_keys,
+    get_repo_config, update_repo_config, repo_info, resolve_repo, fork_repo, export_repo, fsck_repo, prune_refs, repin_repo, list_pins, unpin_repo, metrics, version, index, whoami,
+    disk_usage, sweep_work_dirs, switch_rpc
+}, config::DaemonConfig, git, indexer, request_id::{trace_request, REQUEST_ID_HEADER}, request_limits::enforce_limits, rpc, state::ContractState};
+
+/// How long browsers may cache a preflight answer.
+const CORS_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// Where the daemon listens.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// Whether to listen on `port` at all.
+    pub tcp: bool,
+    /// Unix domain socket to listen on too.
+    pub uds_path: Option<PathBuf>,
+    /// Permissions of the socket file.
+    pub uds_mode: u32,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            port: DaemonConfig::port(),
+            tcp: DaemonConfig::listen_tcp(),
+            uds_path: DaemonConfig::uds_path(),
+            uds_mode: DaemonConfig::uds_mode(),
+        }
+    }
+}
+
+/// Registers a repository route under both the flat `{repo}` form and the
+/// namespaced `{owner}/{repo}` form.
+trait RepoRoutes {
+    fn repo_route(self, path: &str, method_router: MethodRouter<ContractState>) -> Self;
+}
+
+impl RepoRoutes for Router<ContractState> {
+    fn repo_route(self, path: &str, method_router: MethodRouter<ContractState>) -> Self {
+        let namespaced = path.replace("{repo}", "{owner}/{repo}");
+        self.route(path, method_router.clone())
+            .route(&namespaced, method_router)
+    }
+}
+
+/// Runs the daemon until Ctrl+C or, on Unix, SIGTERM. Requests in flight
+/// are finished before it returns.
+pub async fn run(config: ServerConfig) -> Result<()> {
+    if !config.tcp && config.uds_path.is_none() {
+        bail!("Nothing to listen on: DGIT_LISTEN_TCP is off and DGIT_UDS_PATH is unset");
+    }
+
+    let tmp_dir = git::check_tmp_dir()?;
+    info!("Temporary directories go under {}", tmp_dir.display());
+
+    // Without a usable git every clone and push would fail, with errors
+    // that do not say why.
+    let probe = git::probe().await;
+    match &probe.error {
+        None => info!("Using {} ({})", probe.bin, probe.version.as_deref().unwrap_or_default()),
+        Some(e) if DaemonConfig::git_strict() => bail!("{}; point DGIT_GIT_BIN at a newer git, or set DGIT_GIT_STRICT=false to start anyway", e),
+        Some(e) => warn!("{}; git requests will fail and /ready reports not ready", e),
+    }
+
+    // A keystore with the wrong password must stop the daemon here, not
+    // send transactions from some other account later.
+    let signer = Signer::shared().context("Failed to load the signer")?;
+    match signer.address() {
+        Some(address) => info!("Signing transactions as {:?}", address),
+        None => warn!("No signer configured; transactions are sent from the node's default account"),
+    }
+
+    // A mismatch is not fatal: `/ready` reports it, and nothing is sent to
+    // an endpoint on the wrong chain until one on the right chain answers.
+    match (current_chain_id().await, Config::chain_id()) {
+        (Ok(chain_id), _) => info!("RPC endpoints are on chain {}", chain_id),
+        (Err(e), Some(expected)) => error!("No RPC endpoint on chain {} (CHAIN_ID); refusing chain operations until one is: {:#}", expected, e),
+        (Err(e), None) => error!("Failed to read the chain id from the RPC endpoints: {:#}", e),
+    }
+
+    // An invalid address would otherwise only show up on the first create.
+    if let Some(address) = registry::address()? {
+        info!("Creating and resolving repositories through registry {:?}", address);
+    }
+
+    // Likewise a misconfigured OBJECT_STORE on the first push.
+    let storage = storage::from_env()?;
+    info!("Storing pushed objects in {}", storage.name());
+
+    let contract_state = ContractState::new();
+    // Whatever a crashed daemon left behind goes before it can fill the disk.
+    if let Err(e) = contract_state.work_dirs().sweep().await {
+        warn!("Failed to sweep {}: {:#}", tmp_dir.display(), e);
+    }
+    let sweep_interval = DaemonConfig::work_dir_sweep_secs();
+    if sweep_interval > 0 {
+        contract_state.work_dirs().spawn_sweeper(Duration::from_secs(sweep_interval));
+    }
+
+    if let Some(dir) = contract_state.snapshots().dir() {
+        contract_state.snapshots().clear().await?;
+        info!("Serving reads from snapshots under {}", dir.display());
+    }
+
+    if contract_state.anchor_queue().enabled() {
+        contract_state.anchor_queue().spawn_worker(contract_state.clone());
+        info!("Anchoring pushes in the background");
+    }
+
+    let indexer_interval = DaemonConfig::indexer_interval_secs();
+    if indexer_interval > 0 {
+        indexer::spawn(contract_state.clone(), Duration::from_secs(indexer_interval));
+        info!("Indexing repositories every {}s", indexer_interval);
+    }
+
+    rpc::spawn_reload_on_sighup(contract_state.clone());
+
+    let app = router(contract_state);
+
+    // Both listeners stop on the same signal.
+    let (stop, stopped) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = stop.send(true);
+    });
+    let until_stopped = || {
+        let mut stopped = stopped.clone();
+        async move {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+        }
+    };
+
+    let tcp = async {
+        if !config.tcp {
+            return Ok(());
+        }
+        let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Server listening on {}", listener.local_addr()?);
+        axum::serve(listener, app.clone())
+            .with_graceful_shutdown(until_stopped())
+            .await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    let uds = async {
+        match &config.uds_path {
+            Some(path) => serve_uds(path, config.uds_mode, app.clone(), until_stopped()).await,
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(tcp, uds)?;
+    info!("Server stopped");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_uds(path: &Path, mode: u32, app: Router, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+    // A socket left behind by a daemon that did not shut down cleanly
+    // would make the bind fail.
+    if let Ok(metadata) = tokio::fs::symlink_metadata(path).await {
+        if !metadata.file_type().is_socket() {
+            bail!("DGIT_UDS_PATH {} exists and is not a socket", path.display());
+        }
+        tokio::fs::remove_file(path).await
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind {}", path.display()))?;
+    // Set before the first connection is accepted.
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await
+        .with_context(|| format!("Failed to set permissions of {}", path.display()))?;
+    info!("Server listening on {} (mode {:o})", path.display(), mode);
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await;
+
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        warn!("Failed to remove socket {}: {}", path.display(), e);
+    }
+    served?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn serve_uds(_path: &Path, _mode: u32, _app: Router, _shutdown: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+    bail!("DGIT_UDS_PATH is only supported on Unix")
+}
+
+/// Every route the daemon serves.
+pub fn router(contract_state: ContractState) -> Router {
+    // What git itself talks to. Browsers have no business here, so these
+    // never get CORS headers.
+    let git = Router::new()
+        .repo_route("/{repo}/git-upload-pack", post(upload_pack))
+        .repo_route("/{repo}/git-receive-pack", post(receive_pack))
+        .repo_route("/{repo}/git-upload-archive", post(upload_archive))
+        .repo_route("/{repo}/info/refs", get(info_refs))
+        .repo_route("/{repo}/HEAD", get(head_ref))
+        .repo_route("/{repo}/objects/{prefix}/{suffix}", get(loose_object));
+
+    let api = Router::new()
+        .repo_route("/create-repo/{repo}", post(create_repo))
+        .repo_route("/repo/{repo}/fork/{new_name}", post(fork_repo))
+        .repo_route("/repo/{repo}/grant-pusher/{address}", post(grant_pusher_role))
+        .repo_route("/repo/{repo}/revoke-pusher/{address}", post(revoke_pusher_role))
+        .repo_route("/repo/{repo}/grant-pushers", post(grant_pushers))
+        .repo_route("/repo/{repo}/revoke-pushers", post(revoke_pushers))
+        .repo_route("/repo/{repo}/grant-admin/{address}", post(grant_admin_role))
+        .repo_route("/repo/{repo}/revoke-admin/{address}", post(revoke_admin_role))
+        .repo_route("/repo/{repo}/check-pusher/{address}", get(check_pusher_role))
+        .repo_route("/repo/{repo}/check-admin/{address}", get(check_admin_role))
+        .repo_route("/repo/{repo}/grant-reader/{address}", post(grant_reader_role))
+        .repo_route("/repo/{repo}/revoke-reader/{address}", post(revoke_reader_role))
+        .repo_route("/repo/{repo}/check-reader/{address}", get(check_reader_role))
+        .repo_route("/repo/{repo}/roles", get(list_roles))
+        .repo_route("/repo/{repo}/visibility", put(set_visibility))
+        .repo_route("/repo/{repo}/audit", get(get_audit_log))
+        .repo_route("/repo/{repo}/costs", get(get_costs))
+        .repo_route("/repo/{repo}/history", get(repo_history))
+        .repo_route("/repo/{repo}/anchoring-status", get(anchoring_status))
+        .repo_route("/repo/{repo}/has-objects", post(has_objects))
+        .repo_route("/repo/{repo}/refs", get(list_refs))
+        .repo_route("/repo/{repo}/verify", get(verify_repo))
+        .repo_route("/repo/{repo}/fsck", post(fsck_repo))
+        .repo_route("/repo/{repo}/export", get(export_repo))
+        .repo_route("/repo/{repo}/gc", post(gc_repo))
+        .repo_route("/repo/{repo}/prune", post(prune_refs))
+        .repo_route("/repo/{repo}/repin", post(repin_repo))
+        .repo_route("/repo/{repo}/pins", get(list_pins))
+        .repo_route("/repo/{repo}/unpin", post(unpin_repo))
+        .repo_route("/repo/{repo}/webhooks", get(list_webhooks).post(add_webhook).delete(remove_webhook))
+        .repo_route("/repo/{repo}/info", get(repo_info))
+        .repo_route("/repo/{repo}/resolve", get(resolve_repo))
+        .repo_route("/repo/{repo}/config", get(get_repo_config).put(update_repo_config))
+        .repo_route("/repo/{repo}/signing-keys", get(get_signing_keys).put(set_signing_keys))
+        .repo_route("/repo/{repo}/protect", get(list_protection).post(add_protection).delete(remove_protection))
+        .route("/", get(index))
+        .route("/health", get(health_check))
+        .route("/ready", get(ready_check))
+        .route("/version", get(version))
+        .route("/whoami", post(whoami))
+        .route("/metrics", get(metrics))
+        .route("/admin/disk-usage", get(disk_usage))
+        .route("/admin/gc", post(sweep_work_dirs))
+        .route("/admin/rpc", post(switch_rpc));
+    let api = match cors_layer(&DaemonConfig::cors_origins()) {
+        Some(cors) => api.layer(cors),
+        None => api,
+    };
+
+    git.merge(api)
+        .layer(middleware::from_fn_with_state(contract_state.clone(), enforce_limits))
+        .layer(middleware::from_fn(trace_request))
+        .with_state(contract_state)
+}
+
+/// CORS for the origins in `DGIT_CORS_ORIGINS`, so browser dashboards can
+/// call the API, preflights included.
+fn cors_layer(origins: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Ignoring invalid CORS origin '{}'", origin);
+                None
+            },
+        }))
+    };
+    info!("Allowing cross-origin API requests from {}", origins.join(", "));
+
+    Some(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            CONTENT_ENCODING,
+            REQUEST_ID_HEADER,
+            HeaderName::from_static("idempotency-key"),
+        ])
+        .expose_headers([REQUEST_ID_HEADER, RETRY_AFTER, WWW_AUTHENTICATE])
+        .max_age(CORS_MAX_AGE))
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            },
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            },
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutting down, finishing requests in flight");
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::protocol::{self, Packet, FLUSH_PKT};
+
+/// Band carrying the protocol response itself (report-status).
+pub const BAND_DATA: u8 = 1;
+/// Band whose payload git prints to the user prefixed with `remote: `.
+pub const BAND_PROGRESS: u8 = 2;
+
+/// Sideband flavour a client asked for in its receive-pack capabilities.
+/// Both limit the size of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sideband {
+    /// `side-band`: frames of at most 1000 bytes.
+    Small,
+    /// `side-band-64k`: frames of at most 65520 bytes.
+    Large,
+}
+
+impl Sideband {
+    pub fn requested(packets: &[Packet]) -> Option<Self> {
+        let mut requested = None;
+        for capability in protocol::capabilities(packets) {
+            match capability.as_str() {
+                "side-band-64k" => return Some(Sideband::Large),
+                "side-band" => requested = Some(Sideband::Small),
+                _ => {},
+            }
+        }
+        requested
+    }
+
+    fn max_payload(self) -> usize {
+        // Four bytes of pkt-line length plus the band byte.
+        match self {
+            Sideband::Small => 1000 - 5,
+            Sideband::Large => 65520 - 5,
+        }
+    }
+
+    /// Frames `payload` on `band`, split across as many pkt-lines as needed.
+    pub fn frame(self, band: u8, payload: &[u8]) -> Vec<u8> {
+        let mut framed = Vec::with_capacity(payload.len() + 5);
+        for chunk in payload.chunks(self.max_payload()) {
+            let mut data = Vec::with_capacity(chunk.len() + 1);
+            data.push(band);
+            data.extend_from_slice(chunk);
+            framed.extend_from_slice(&protocol::encode(&data));
+        }
+        framed
+    }
+
+    /// Report-status rejecting every ref in `refs` with `reason`, sent in
+    /// place of git's own report when post-processing fails after git had
+    /// already accepted the push.
+    pub fn failure_report(self, refs: &[String], reason: &str) -> Vec<u8> {
+        // Report lines are single pkt-lines, so keep the reason on one line.
+        let reason = reason.replace('\n', " ");
+
+        let mut lines = vec!["unpack ok".to_string()];
+        lines.extend(refs.iter().map(|name| format!("ng {} {}", name, reason)));
+
+        let mut response = self.frame(BAND_DATA, &encode_report(&lines));
+        response.extend_from_slice(FLUSH_PKT);
+        response
+    }
+}
+
+/// Rewrites git's report-status so every ref in `failed` reads
+/// `ng <ref> <reason>` instead of `ok <ref>`. Progress frames git emitted are
+/// kept; a response without a report (the client did not ask for one) is
+/// returned unchanged.
+pub fn rewrite_report(response: &[u8], sideband: Option<Sideband>, failed: &HashMap<String, String>) -> Result<Vec<u8>> {
+    if failed.is_empty() {
+        return Ok(response.to_vec());
+    }
+
+    let packets = protocol::parse(response)?;
+    let (passthrough, report_packets) = match sideband {
+        Some(_) => {
+            let mut passthrough = Vec::new();
+            let mut report = Vec::new();
+            for packet in &packets {
+                match packet {
+                    Packet::Data(data) if data.first() == Some(&BAND_DATA) => report.extend_from_slice(&data[1..]),
+                    Packet::Data(data) => passthrough.extend_from_slice(&protocol::encode(data)),
+                    _ => {},
+                }
+            }
+            (passthrough, protocol::parse(&report)?)
+        },
+        None => (Vec::new(), packets),
+    };
+
+    let mut lines = Vec::new();
+    // report-status-v2 follows `ok` with `option` lines, which no longer
+    // apply once the ref is rejected.
+    let mut skip_options = false;
+    for line in report_packets.iter().filter_map(Packet::text) {
+        if let Some((name, reason)) = line.strip_prefix("ok ").and_then(|name| failed.get_key_value(name)) {
+            lines.push(format!("ng {} {}", name, reason.replace('\n', " ")));
+            skip_options = true;
+            continue;
+        }
+        if skip_options && line.starts_with("option ") {
+            continue;
+        }
+        skip_options = false;
+        lines.push(line.to_string());
+    }
+
+    if !lines.first().is_some_and(|line| line.starts_with("unpack ")) {
+        return Ok(response.to_vec());
+    }
+
+    let report = encode_report(&lines);
+    Ok(match sideband {
+        Some(sideband) => {
+            let mut rewritten = passthrough;
+            rewritten.extend_from_slice(&sideband.frame(BAND_DATA, &report));
+            rewritten.extend_from_slice(FLUSH_PKT);
+            rewritten
+        },
+        None => report,
+    })
+}
+
+fn encode_report(lines: &[String]) -> Vec<u8> {
+    let mut report = Vec::new();
+    for line in lines {
+        report.extend_from_slice(&protocol::encode(format!("{}\n", line).as_bytes()));
+    }
+    report.extend_from_slice(FLUSH_PKT);
+    report
+}
+
+/// Sends progress lines to the client while a push is post-processed.
+/// Without a sideband there is nowhere to send them and messages are dropped.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    channel: Option<(Sideband, UnboundedSender<Vec<u8>>)>,
+}
+
+impl Progress {
+    pub fn new(sideband: Sideband, sender: UnboundedSender<Vec<u8>>) -> Self {
+        Self { channel: Some((sideband, sender)) }
+    }
+
+    pub fn disabled() -> Self {
+        Self { channel: None }
+    }
+
+    /// A line of progress output.
+    pub fn message(&self, message: &str) {
+        self.send(&format!("{}\n", message));
+    }
+
+    /// A progress line that the next update overwrites, like git's counters.
+    pub fn update(&self, message: &str) {
+        self.send(&format!("{}\r", message));
+    }
+
+    fn send(&self, text: &str) {
+        if let Some((sideband, sender)) = &self.channel {
+            // The client hung up; the push still completes server-side.
+            let _ = sender.send(sideband.frame(BAND_PROGRESS, text.as_bytes()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(lines: &[&str]) -> Vec<u8> {
+        encode_report(&lines.iter().map(|line| line.to_string()).collect::<Vec<_>>())
+    }
+
+    fn failed(refs: &[(&str, &str)]) -> HashMap<String, String> {
+        refs.iter().map(|(name, reason)| (name.to_string(), reason.to_string())).collect()
+    }
+
+    /// A sidebanded response the way git sends it: progress, the report,
+    /// and a closing flush.
+    fn sidebanded(sideband: Sideband, progress: &str, lines: &[&str]) -> Vec<u8> {
+        let mut response = sideband.frame(BAND_PROGRESS, progress.as_bytes());
+        response.extend(sideband.frame(BAND_DATA, &report(lines)));
+        response.extend_from_slice(FLUSH_PKT);
+        response
+    }
+
+    #[test]
+    fn failed_refs_are_reported_ng() {
+        let response = report(&["unpack ok", "ok refs/heads/main", "ok refs/heads/dev"]);
+        let rewritten = rewrite_report(&response, None, &failed(&[("refs/heads/main", "ipfs upload failed")])).unwrap();
+        assert_eq!(rewritten, report(&["unpack ok", "ng refs/heads/main ipfs upload failed", "ok refs/heads/dev"]));
+    }
+
+    #[test]
+    fn progress_is_kept_and_only_the_report_is_rewritten() {
+        let response = sidebanded(Sideband::Large, "Resolving deltas: 100% (3/3), done.\n", &["unpack ok", "ok refs/heads/main"]);
+        let rewritten = rewrite_report(&response, Some(Sideband::Large), &failed(&[("refs/heads/main", "chain tx\nreverted")])).unwrap();
+        assert_eq!(rewritten, sidebanded(Sideband::Large, "Resolving deltas: 100% (3/3), done.\n", &["unpack ok", "ng refs/heads/main chain tx reverted"]));
+    }
+
+    #[test]
+    fn options_of_rejected_refs_are_dropped() {
+        let response = report(&[
+            "unpack ok",
+            "ok refs/for/main",
+            "option refname refs/heads/main",
+            "option new-oid 1111111111111111111111111111111111111111",
+            "ok refs/for/dev",
+            "option refname refs/heads/dev",
+        ]);
+        let rewritten = rewrite_report(&response, None, &failed(&[("refs/for/main", "atomic push failed")])).unwrap();
+        assert_eq!(rewritten, report(&["unpack ok", "ng refs/for/main atomic push failed", "ok refs/for/dev", "option refname refs/heads/dev"]));
+    }
+
+    #[test]
+    fn responses_are_left_alone_when_there_is_nothing_to_rewrite() {
+        let response = report(&["unpack ok", "ok refs/heads/main"]);
+        assert_eq!(rewrite_report(&response, None, &HashMap::new()).unwrap(), response);
+
+        // Without report-status git sends nothing but progress.
+        let quiet = Sideband::Small.frame(BAND_PROGRESS, b"done\n");
+        let failures = failed(&[("refs/heads/main", "ipfs upload failed")]);
+        assert_eq!(rewrite_report(&quiet, Some(Sideband::Small), &failures).unwrap(), quiet);
+    }
+
+    #[test]
+    fn failure_reports_reject_every_ref() {
+        let refs = ["refs/heads/main".to_string(), "refs/tags/v1".to_string()];
+        let response = Sideband::Large.failure_report(&refs, "add_refs\nreverted");
+        let mut expected = Sideband::Large.frame(BAND_DATA, &report(&["unpack ok", "ng refs/heads/main add_refs reverted", "ng refs/tags/v1 add_refs reverted"]));
+        expected.extend_from_slice(FLUSH_PKT);
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn frames_are_split_at_the_sideband_limit() {
+        let payload = vec![b'x'; 2500];
+        let packets = protocol::parse(&Sideband::Small.frame(BAND_PROGRESS, &payload)).unwrap();
+        let sizes: Vec<usize> = packets.iter().map(|packet| match packet {
+            Packet::Data(data) => {
+                assert_eq!(data[0], BAND_PROGRESS);
+                data.len() + 4
+            },
+            other => panic!("unexpected {:?}", other),
+        }).collect();
+        assert_eq!(sizes, [1000, 1000, 515]);
+
+        assert_eq!(protocol::parse(&Sideband::Large.frame(BAND_PROGRESS, &payload)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn the_larger_sideband_wins() {
+        let request = |capabilities: &str| {
+            let mut body = protocol::encode(format!("{} {} refs/heads/main\0{}\n", "0".repeat(40), "1".repeat(40), capabilities).as_bytes());
+            body.extend_from_slice(FLUSH_PKT);
+            protocol::parse(&body).unwrap()
+        };
+        assert_eq!(Sideband::requested(&request("report-status side-band side-band-64k")), Some(Sideband::Large));
+        assert_eq!(Sideband::requested(&request("report-status side-band")), Some(Sideband::Small));
+        assert_eq!(Sideband::requested(&request("report-status")), None);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Result};
+use onchain::repo_config::SigningKeys;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::git;
+
+/// A repository's signing keys written out where `git verify-commit` and
+/// `git verify-tag` look for them: a GnuPG home and an SSH allowed signers
+/// file. Removed again when dropped.
+pub struct Keyring {
+    dir: TempDir,
+    has_gpg: bool,
+    allowed_signers: Option<PathBuf>,
+}
+
+impl Keyring {
+    pub async fn materialize(keys: &SigningKeys) -> Result<Self> {
+        let dir = git::tempdir()?;
+
+        let has_gpg = !keys.gpg.is_empty();
+        if has_gpg {
+            let gnupg_home = dir.path().join("gnupg");
+            tokio::fs::create_dir_all(&gnupg_home).await?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                tokio::fs::set_permissions(&gnupg_home, std::fs::Permissions::from_mode(0o700)).await?;
+            }
+
+            let mut cmd = Command::new("gpg");
+            cmd.arg("--batch").arg("--homedir").arg(&gnupg_home).arg("--import");
+            let output = git::run_with_input(cmd, keys.gpg.as_bytes().to_vec()).await?;
+            if !output.status.success() {
+                return Err(anyhow!("Failed to import signing keys: {}", String::from_utf8_lossy(&output.stderr).trim()));
+            }
+        }
+
+        let allowed_signers = if keys.ssh_allowed_signers.is_empty() {
+            None
+        } else {
+            let path = dir.path().join("allowed_signers");
+            tokio::fs::write(&path, &keys.ssh_allowed_signers).await?;
+            Some(path)
+        };
+
+        Ok(Self { dir, has_gpg, allowed_signers })
+    }
+
+    /// Verifies the signature on commit or annotated tag `object`. Returns
+    /// why verification failed, or `None` if it passed.
+    pub async fn verify(&self, repo_path: &Path, object: &str) -> Result<Option<String>> {
+        let kind = object_type(repo_path, object).await?;
+        let verb = match kind.as_str() {
+            "commit" => "verify-commit",
+            "tag" => "verify-tag",
+            other => return Ok(Some(format!("{} is a {}, which cannot be signed", object, other))),
+        };
+
+        let mut cmd = git::command();
+        if let Some(allowed_signers) = &self.allowed_signers {
+            cmd.arg("-c").arg(format!("gpg.ssh.allowedSignersFile={}", allowed_signers.display()));
+        }
+        // An empty GnuPG home trusts nothing, rather than the daemon user's keys.
+        let gnupg_home = self.dir.path().join("gnupg");
+        if !self.has_gpg {
+            tokio::fs::create_dir_all(&gnupg_home).await?;
+        }
+        cmd.env("GNUPGHOME", &gnupg_home)
+            .args([verb, object])
+            .current_dir(repo_path);
+
+        let output = git::output(cmd).await?;
+        if output.status.success() {
+            debug!("Signature on {} verified", object);
+            return Ok(None);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        debug!("git {} {} failed: {}", verb, object, stderr.trim());
+        Ok(Some(format!("{} {} has no valid signature from an allowed key", kind, object)))
+    }
+}
+
+async fn object_type(repo_path: &Path, object: &str) -> Result<String> {
+    let mut cmd = git::command();
+    cmd.args(["cat-file", "-t", object]).current_dir(repo_path);
+    let output = git::output(cmd).await?;
+
+    if !output.status.success() {
+        return Err(anyhow!("git cat-file failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
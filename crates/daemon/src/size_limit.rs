@@ -0,0 +1,78 @@
+use anyhow::{bail, Result};
+use onchain::repo_config::Quota;
+
+use crate::config::DaemonConfig;
+
+/// Caps on what a single push may store. Sizes are those of the loose
+/// objects on disk, i.e. compressed, which is also what goes to IPFS.
+#[derive(Debug, Clone, Copy)]
+pub struct SizeLimits {
+    max_object_bytes: Option<u64>,
+    max_push_bytes: Option<u64>,
+    max_repo_objects: Option<u64>,
+}
+
+impl SizeLimits {
+    /// A limit of 0 disables it.
+    pub fn new(max_object_bytes: u64, max_push_bytes: u64, max_repo_objects: u64) -> Self {
+        Self {
+            max_object_bytes: (max_object_bytes > 0).then_some(max_object_bytes),
+            max_push_bytes: (max_push_bytes > 0).then_some(max_push_bytes),
+            max_repo_objects: (max_repo_objects > 0).then_some(max_repo_objects),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::max_object_bytes(), DaemonConfig::max_push_bytes(), DaemonConfig::max_repo_objects())
+    }
+
+    /// These limits lowered to a repository's quota. Repository admins write
+    /// the quota, so it cannot raise what the operator allows.
+    pub fn for_repo(&self, quota: &Quota) -> Self {
+        let tighten = |daemon: Option<u64>, repo: Option<u64>| match (daemon, repo.filter(|limit| *limit > 0)) {
+            (Some(daemon), Some(repo)) => Some(daemon.min(repo)),
+            (daemon, repo) => daemon.or(repo),
+        };
+
+        Self {
+            max_object_bytes: tighten(self.max_object_bytes, quota.max_object_bytes),
+            max_push_bytes: tighten(self.max_push_bytes, quota.max_push_bytes),
+            max_repo_objects: tighten(self.max_repo_objects, quota.max_repo_objects),
+        }
+    }
+
+    pub fn limits_repo_objects(&self) -> bool {
+        self.max_repo_objects.is_some()
+    }
+
+    /// Checks the `(hash, size)` of every object a push would upload, and
+    /// that they fit next to the `stored` objects already on chain.
+    pub fn check<'a>(&self, objects: impl IntoIterator<Item = (&'a str, u64)>, stored: u64) -> Result<()> {
+        let mut total = 0u64;
+        let mut count = 0u64;
+        for (hash, size) in objects {
+            if let Some(max) = self.max_object_bytes {
+                if size > max {
+                    bail!("object {} is {} bytes, more than the {} bytes allowed per object", hash, size, max);
+                }
+            }
+            total = total.saturating_add(size);
+            count += 1;
+        }
+
+        if let Some(max) = self.max_push_bytes {
+            if total > max {
+                bail!("push is {} bytes, more than the {} bytes allowed per push", total, max);
+            }
+        }
+
+        if let Some(max) = self.max_repo_objects {
+            let after = stored.saturating_add(count);
+            if after > max {
+                bail!("repository would hold {} objects ({} stored, {} new), more than the {} allowed per repository", after, stored, count, max);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{anyhow, bail, Context, Result};
+use onchain::contract_interaction::{ContractInteraction, Ref};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn, Instrument, Span};
+
+use crate::{config::DaemonConfig, git, handlers::write_head, object_store::ObjectStore, ref_cache::collapse, repo_views::RepoViews, validation::normalize_repo_name};
+
+/// Prefix of every snapshot directory, so leftovers can be told apart.
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+
+/// A bare repository materialized from a contract's refs and objects.
+/// Its directory is removed once the last request serving from it is done.
+#[derive(Debug)]
+pub struct Snapshot {
+    path: PathBuf,
+    address: String,
+    refs: Vec<Ref>,
+    /// When the build started reading the chain.
+    taken: Instant,
+}
+
+impl Snapshot {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The active refs the snapshot was built with.
+    pub fn refs(&self) -> &[Ref] {
+        &self.refs
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        let remove = move || {
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                warn!("Failed to remove snapshot {:?}: {}", path, e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(remove);
+            },
+            Err(_) => remove(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    snapshot: Option<Arc<Snapshot>>,
+    /// Bumped on every invalidation, so a build that started before a push
+    /// does not install the refs it read.
+    generation: u64,
+    building: bool,
+}
+
+/// Read-only copies of repositories on disk, so clones and fetches of
+/// repositories that rarely change are served by git directly instead of
+/// rebuilding the repository from the chain and IPFS on every request.
+///
+/// Enabled by `DGIT_SNAPSHOT_DIR`. A snapshot is dropped when a push through
+/// this daemon changes the repository and once it is older than
+/// `DGIT_SNAPSHOT_MAX_AGE_SECS`, which bounds how long a push through
+/// another daemon goes unseen. Requests without a fresh snapshot start a
+/// build in the background and are served the usual way meanwhile.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    dir: Option<Arc<PathBuf>>,
+    max_age: Option<Duration>,
+    rebuild_on_push: bool,
+    views: RepoViews,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl Snapshots {
+    pub fn new(dir: Option<PathBuf>, max_age: Duration, rebuild_on_push: bool, views: RepoViews) -> Self {
+        Self {
+            dir: dir.map(Arc::new),
+            max_age: (!max_age.is_zero()).then_some(max_age),
+            rebuild_on_push,
+            views,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn from_env(views: RepoViews) -> Self {
+        Self::new(
+            DaemonConfig::snapshot_dir(),
+            Duration::from_secs(DaemonConfig::snapshot_max_age_secs()),
+            DaemonConfig::snapshot_rebuild_on_push(),
+            views,
+        )
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref().map(PathBuf::as_path)
+    }
+
+    /// Reads served from a snapshot.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Reads that found no fresh snapshot and rebuilt the repository.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Removes snapshots left behind by an earlier run, which are not
+    /// tracked and may be out of date.
+    pub async fn clear(&self) -> Result<()> {
+        let Some(dir) = self.dir() else {
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(dir).await
+            .with_context(|| format!("Failed to create snapshot directory {:?}", dir))?;
+
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with(SNAPSHOT_PREFIX) {
+                debug!("Removing leftover snapshot {:?}", entry.path());
+                tokio::fs::remove_dir_all(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The snapshot of `repo` if a fresh one exists. Otherwise a build is
+    /// started and the caller serves the request by rebuilding the
+    /// repository itself.
+    pub async fn get(&self, repo: &str, contract: &ContractInteraction) -> Option<Arc<Snapshot>> {
+        self.dir.as_ref()?;
+
+        let key = normalize_repo_name(repo);
+        let mut entries = self.entries.lock().await;
+        let entry = entries.entry(key.clone()).or_default();
+        if let Some(snapshot) = &entry.snapshot {
+            let stale = self.max_age.is_some_and(|max_age| snapshot.taken.elapsed() >= max_age);
+            if snapshot.address == contract.address() && !stale {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                debug!("Serving {} from snapshot {:?}", repo, snapshot.path);
+                return Some(Arc::clone(snapshot));
+            }
+            debug!("Snapshot of {} is out of date", repo);
+            entry.snapshot = None;
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.start_build(&key, entry, contract);
+        None
+    }
+
+    /// Drops the snapshot of `repo` after a write to it; requests already
+    /// serving from it finish first. With `DGIT_SNAPSHOT_REBUILD_ON_PUSH`
+    /// the next one is built right away.
+    pub async fn invalidate(&self, repo: &str, contract: &ContractInteraction) {
+        if self.dir.is_none() {
+            return;
+        }
+
+        let key = normalize_repo_name(repo);
+        let mut entries = self.entries.lock().await;
+        let entry = entries.entry(key.clone()).or_default();
+        entry.generation += 1;
+        // A build in flight read the old state; it is discarded when done.
+        entry.building = false;
+        if entry.snapshot.take().is_some() {
+            info!("Invalidated snapshot of {}", repo);
+        }
+
+        if self.rebuild_on_push {
+            self.start_build(&key, entry, contract);
+        }
+    }
+
+    fn start_build(&self, key: &str, entry: &mut Entry, contract: &ContractInteraction) {
+        if entry.building {
+            return;
+        }
+        entry.building = true;
+
+        let generation = entry.generation;
+        let snapshots = self.clone();
+        let key = key.to_string();
+        let contract = contract.clone();
+        tokio::spawn(async move {
+            let built = snapshots.build(&key, &contract).await;
+
+            let mut entries = snapshots.entries.lock().await;
+            let entry = entries.entry(key.clone()).or_default();
+            if entry.generation != generation {
+                debug!("Discarding snapshot of {}: invalidated while building", key);
+                return;
+            }
+            entry.building = false;
+            match built {
+                Ok(snapshot) => {
+                    info!("Built snapshot of {} with {} refs at {:?}", key, snapshot.refs.len(), snapshot.path);
+                    entry.snapshot = Some(Arc::new(snapshot));
+                },
+                Err(e) => error!("Failed to build snapshot of {}: {:#}", key, e),
+            }
+        }.instrument(Span::current()));
+    }
+
+    async fn build(&self, key: &str, contract: &ContractInteraction) -> Result<Snapshot> {
+        let dir = self.dir().ok_or_else(|| anyhow!("Snapshots are disabled"))?;
+        let taken = Instant::now();
+
+        tokio::fs::create_dir_all(dir).await
+            .with_context(|| format!("Failed to create snapshot directory {:?}", dir))?;
+        let temp_dir = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", SNAPSHOT_PREFIX, key.replace('/', "_")))
+            .tempdir_in(dir)?;
+        let path = temp_dir.path();
+
+        let mut cmd = git::command();
+        cmd.args(["init", "--bare"]).current_dir(path);
+        let output = git::output(cmd).await?;
+        if !output.status.success() {
+            bail!("Failed to initialize snapshot: {}", String::from_utf8_lossy(&output.stderr));
+        }
+
+        // Straight from the synced view: the ref cache may still hold the
+        // refs from before the push that invalidated the last snapshot.
+        let refs = collapse(&self.views.refs(contract).await?);
+        for r in refs.iter().filter(|r| r.is_active) {
+            let ref_path = path.join(&r.name);
+            if let Some(parent) = ref_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&ref_path, format!("{}\n", String::from_utf8_lossy(&r.data).trim())).await?;
+        }
+
+        let config = contract.get_repo_config().await?;
+        write_head(path, &refs, config.default_branch.as_deref()).await?;
+
+        let objects = self.views.objects(contract).await?;
+        let mut store = ObjectStore::new(path, objects)?;
+        store.download_all().await?;
+        debug!("Downloaded {} objects for snapshot of {}", store.download_count(), key);
+
+        // For dumb-protocol clients, which read `info/refs` and `objects/info/packs`.
+        let mut cmd = git::command();
+        cmd.args(["update-server-info"]).current_dir(path);
+        let output = git::output(cmd).await?;
+        if !output.status.success() {
+            warn!("Failed to update server info of snapshot: {}", String::from_utf8_lossy(&output.stderr));
+        }
+
+        Ok(Snapshot {
+            path: temp_dir.into_path(),
+            address: contract.address(),
+            refs,
+            taken,
+        })
+    }
+}
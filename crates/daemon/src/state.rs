@@ -1,25 +1,110 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{debug, info, warn};
 
-use onchain::contract_interaction::ContractInteraction;
+use onchain::{config::Config, contract_interaction::ContractInteraction};
+
+use crate::{anchor_queue::AnchorQueue, audit::AuditLog, cleanup::PendingCleanup, config::DaemonConfig, object_cache::ObjectCache, object_index::ObjectIndex, pin_index::PinIndex, rate_limit::RateLimiter, ref_cache::RefCache, repo_views::RepoViews, request_limits::RequestLimits, size_limit::SizeLimits, snapshots::Snapshots, validation::{normalize_repo_name, split_chain}, webhooks::WebhookStore, work_dirs::WorkDirs, workspaces::WorkspacePool};
 
 #[derive(Debug, Clone)]
 pub struct ContractState {
     inner: Arc<Mutex<ContractStateInner>>,
+    audit: AuditLog,
+    webhooks: WebhookStore,
+    rate_limiter: RateLimiter,
+    pending_cleanup: PendingCleanup,
+    size_limits: SizeLimits,
+    request_limits: RequestLimits,
+    workspaces: WorkspacePool,
+    work_dirs: WorkDirs,
+    ref_cache: RefCache,
+    views: RepoViews,
+    object_cache: ObjectCache,
+    object_index: ObjectIndex,
+    pin_index: PinIndex,
+    snapshots: Snapshots,
+    anchor_queue: AnchorQueue,
 }
 
 #[derive(Debug)]
 pub struct ContractStateInner {
     contracts: HashMap<String, ContractInteraction>,
+    /// Creations in flight; concurrent creates of one name share a slot.
+    pending_creates: HashMap<String, Arc<OnceCell<ContractInteraction>>>,
+    /// `Idempotency-Key` each repository was created with, for replays.
+    idempotency_keys: HashMap<String, String>,
+    /// Names the registry recently did not resolve.
+    registry_misses: RegistryMisses,
+}
+
+/// Names the registry had no contract for, so requests for unknown repos,
+/// which need no credentials, do not each cost an RPC call. A miss is
+/// forgotten after `DGIT_REGISTRY_MISS_TTL_SECS` (0 disables this), or as
+/// soon as this daemon stores a contract under the name.
+#[derive(Debug, Clone)]
+struct RegistryMisses {
+    ttl: Duration,
+    misses: HashMap<String, Instant>,
+}
+
+impl RegistryMisses {
+    fn new(ttl: Duration) -> Self {
+        Self { ttl, misses: HashMap::new() }
+    }
+
+    fn contains(&mut self, key: &str) -> bool {
+        match self.misses.get(key) {
+            Some(at) if at.elapsed() < self.ttl => true,
+            Some(_) => {
+                self.misses.remove(key);
+                false
+            },
+            None => false,
+        }
+    }
+
+    fn record(&mut self, key: &str) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        // Expired entries go first, so the map only holds the last TTL's worth.
+        let ttl = self.ttl;
+        self.misses.retain(|_, at| at.elapsed() < ttl);
+        self.misses.insert(key.to_string(), Instant::now());
+    }
+
+    fn clear(&mut self, key: &str) {
+        self.misses.remove(key);
+    }
 }
 
 impl Default for ContractState {
     fn default() -> Self {
+        let views = RepoViews::from_env();
         Self {
             inner: Arc::new(Mutex::new(ContractStateInner {
                 contracts: HashMap::new(),
+                pending_creates: HashMap::new(),
+                idempotency_keys: HashMap::new(),
+                registry_misses: RegistryMisses::new(Duration::from_secs(DaemonConfig::registry_miss_ttl_secs())),
             })),
+            audit: AuditLog::from_env(),
+            webhooks: WebhookStore::from_env(),
+            rate_limiter: RateLimiter::from_env(),
+            pending_cleanup: PendingCleanup::from_env(),
+            size_limits: SizeLimits::from_env(),
+            request_limits: RequestLimits::from_env(),
+            workspaces: WorkspacePool::from_env(),
+            work_dirs: WorkDirs::from_env(),
+            ref_cache: RefCache::from_env(views.clone()),
+            snapshots: Snapshots::from_env(views.clone()),
+            views,
+            object_cache: ObjectCache::from_env(),
+            object_index: ObjectIndex::from_env(),
+            pin_index: PinIndex::from_env(),
+            anchor_queue: AnchorQueue::from_env(),
         }
     }
 }
@@ -29,14 +114,168 @@ impl ContractState {
         Self::default()
     }
 
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn webhooks(&self) -> &WebhookStore {
+        &self.webhooks
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    pub fn pending_cleanup(&self) -> &PendingCleanup {
+        &self.pending_cleanup
+    }
+
+    pub fn size_limits(&self) -> &SizeLimits {
+        &self.size_limits
+    }
+
+    pub fn request_limits(&self) -> &RequestLimits {
+        &self.request_limits
+    }
+
+    pub fn workspaces(&self) -> &WorkspacePool {
+        &self.workspaces
+    }
+
+    pub fn work_dirs(&self) -> &WorkDirs {
+        &self.work_dirs
+    }
+
+    pub fn ref_cache(&self) -> &RefCache {
+        &self.ref_cache
+    }
+
+    pub fn views(&self) -> &RepoViews {
+        &self.views
+    }
+
+    pub fn object_cache(&self) -> &ObjectCache {
+        &self.object_cache
+    }
+
+    pub fn object_index(&self) -> &ObjectIndex {
+        &self.object_index
+    }
+
+    pub fn pin_index(&self) -> &PinIndex {
+        &self.pin_index
+    }
+
+    pub fn snapshots(&self) -> &Snapshots {
+        &self.snapshots
+    }
+
+    pub fn anchor_queue(&self) -> &AnchorQueue {
+        &self.anchor_queue
+    }
+
+    /// The contract of `repo`. Names this daemon does not know are looked up
+    /// in the registry, if one is configured, and remembered when found;
+    /// names it does not list are not asked about again for a while.
     pub async fn get_contract(&self, repo: &str) -> Option<ContractInteraction> {
+        let key = normalize_repo_name(repo);
+        {
+            let mut inner = self.inner.lock().await;
+            if let Some(contract) = inner.contracts.get(&key).cloned() {
+                return Some(contract);
+            }
+            if inner.registry_misses.contains(&key) {
+                debug!("{} was not in the registry recently, not looking again", key);
+                return None;
+            }
+        }
+
+        let Some(contract) = self.resolve(&key).await else {
+            if Config::registry_address().is_some() {
+                self.inner.lock().await.registry_misses.record(&key);
+            }
+            return None;
+        };
+        let mut inner = self.inner.lock().await;
+        // A create or lookup that finished meanwhile wins. The endpoints
+        // may have been switched during the lookup, hence the reconnect.
+        Some(inner.contracts.entry(key).or_insert_with(|| contract.reconnect()).clone())
+    }
+
+    async fn resolve(&self, key: &str) -> Option<ContractInteraction> {
+        Config::registry_address()?;
+
+        let (name, chain) = split_chain(key);
+        let contract = match ContractInteraction::resolve(name).await {
+            Ok(contract) => contract?,
+            Err(e) => {
+                warn!("Failed to look up {} in the registry: {:#}", name, e);
+                return None;
+            },
+        };
+        if !on_chain(chain, contract.chain_id) {
+            debug!("Registry entry for {} is on chain {:?}, not {:?}", name, contract.chain_id, chain);
+            return None;
+        }
+
+        info!("Found {} at {} in the registry", key, contract.address());
+        Some(contract)
+    }
+
+    /// Every repository this daemon knows, with its contract.
+    pub async fn contracts(&self) -> Vec<(String, ContractInteraction)> {
         let inner = self.inner.lock().await;
-        inner.contracts.get(repo).cloned()
+        inner.contracts.iter().map(|(repo, contract)| (repo.clone(), contract.clone())).collect()
     }
 
+    /// Stores `repo`'s contract, on the current RPC endpoints even if it was
+    /// deployed through ones switched away from meanwhile.
     pub async fn insert_contract(&self, repo: String, contract: ContractInteraction) {
         let mut inner = self.inner.lock().await;
-        inner.contracts.insert(repo, contract);
+        let key = normalize_repo_name(&repo);
+        inner.registry_misses.clear(&key);
+        inner.contracts.insert(key, contract.reconnect());
+    }
+
+    /// Moves every stored contract to the current RPC endpoints, after
+    /// [`rpc::switch`](crate::rpc::switch) changed them; the number moved.
+    pub async fn reconnect_contracts(&self) -> usize {
+        let mut inner = self.inner.lock().await;
+        for contract in inner.contracts.values_mut() {
+            *contract = contract.reconnect();
+        }
+        inner.contracts.len()
+    }
+
+    /// Slot the deployment for `repo` is written to. Every create that
+    /// arrives while one is in flight gets the same slot and so waits for,
+    /// and returns, the first one's contract instead of deploying again.
+    pub async fn creation_slot(&self, repo: &str) -> Arc<OnceCell<ContractInteraction>> {
+        let mut inner = self.inner.lock().await;
+        inner.pending_creates
+            .entry(normalize_repo_name(repo))
+            .or_default()
+            .clone()
+    }
+
+    /// Drops `slot` once its creation finished, so later creates see the
+    /// stored contract rather than replaying the in-flight result.
+    pub async fn finish_creation(&self, repo: &str, slot: &Arc<OnceCell<ContractInteraction>>) {
+        let mut inner = self.inner.lock().await;
+        let key = normalize_repo_name(repo);
+        if inner.pending_creates.get(&key).is_some_and(|current| Arc::ptr_eq(current, slot)) {
+            inner.pending_creates.remove(&key);
+        }
+    }
+
+    pub async fn idempotency_key(&self, repo: &str) -> Option<String> {
+        let inner = self.inner.lock().await;
+        inner.idempotency_keys.get(&normalize_repo_name(repo)).cloned()
+    }
+
+    pub async fn set_idempotency_key(&self, repo: &str, key: String) {
+        let mut inner = self.inner.lock().await;
+        inner.idempotency_keys.insert(normalize_repo_name(repo), key);
     }
 }
 
@@ -44,6 +283,57 @@ impl Clone for ContractStateInner {
     fn clone(&self) -> Self {
         Self {
             contracts: self.contracts.clone(),
+            pending_creates: self.pending_creates.clone(),
+            idempotency_keys: self.idempotency_keys.clone(),
+            registry_misses: self.registry_misses.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether a registry entry on `contract_chain` answers for a name asked
+/// for as `name@<chain>`; a plain name matches any chain.
+fn on_chain(chain: Option<&str>, contract_chain: Option<u64>) -> bool {
+    chain.is_none_or(|chain| chain.parse().ok() == contract_chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_registry_misses_until_they_expire() {
+        let mut misses = RegistryMisses::new(Duration::from_secs(60));
+        assert!(!misses.contains("repo"));
+        misses.record("repo");
+        assert!(misses.contains("repo"));
+        assert!(!misses.contains("other"));
+
+        misses.misses.insert("repo".to_string(), Instant::now() - Duration::from_secs(61));
+        assert!(!misses.contains("repo"));
+        assert!(!misses.misses.contains_key("repo"));
+    }
+
+    #[test]
+    fn created_repositories_are_looked_up_again() {
+        let mut misses = RegistryMisses::new(Duration::from_secs(60));
+        misses.record("repo");
+        misses.clear("repo");
+        assert!(!misses.contains("repo"));
+    }
+
+    #[test]
+    fn zero_ttl_remembers_nothing() {
+        let mut misses = RegistryMisses::new(Duration::ZERO);
+        misses.record("repo");
+        assert!(!misses.contains("repo"));
+    }
+
+    #[test]
+    fn chain_suffix_must_match_the_registry_chain() {
+        assert!(on_chain(None, Some(1)));
+        assert!(on_chain(None, None));
+        assert!(on_chain(Some("1"), Some(1)));
+        assert!(!on_chain(Some("5"), Some(1)));
+        assert!(!on_chain(Some("1"), None));
+    }
+}
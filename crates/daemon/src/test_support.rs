@@ -0,0 +1,16 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Runs git in `dir` with a fixed committer and returns its trimmed
+/// stdout, failing the test if git does.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=dgit", "-c", "user.email=dgit@example.com"])
+        .args(args)
+        .output()
+        .expect("git runs");
+    assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
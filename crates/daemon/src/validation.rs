@@ -0,0 +1,176 @@
+use std::fmt;
+
+pub const REPO_NAME_PATTERN: &str = "[a-z0-9._-]{1,100}";
+pub const MAX_REPO_NAME_LEN: usize = 100;
+
+// Names that would collide with the daemon's own top-level routes.
+const RESERVED_REPO_NAMES: &[&str] = &[
+    "health", "ready", "version", "whoami", "metrics", "admin", "repo", "create-repo",
+];
+
+#[derive(Debug, Clone)]
+pub struct InvalidRepoName {
+    pub name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidRepoName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid repository name '{}': {}. Names must match {} (optionally as owner/name, \
+             with both parts matching, and optionally followed by @<chain id>) and must not \
+             start with one of: {}",
+            self.name,
+            self.reason,
+            REPO_NAME_PATTERN,
+            RESERVED_REPO_NAMES.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidRepoName {}
+
+/// Repository names are case-insensitive; every lookup goes through this.
+pub fn normalize_repo_name(name: &str) -> String {
+    name.trim().to_ascii_lowercase()
+}
+
+/// Splits the optional `@<chain id>` suffix off a repository name, so the
+/// same name deployed on different chains gets a distinct key.
+pub fn split_chain(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('@') {
+        Some((base, chain)) => (base, Some(chain)),
+        None => (name, None),
+    }
+}
+
+/// Appends `@<chain id>` to `name` when a chain is given.
+pub fn qualify_repo_name(name: &str, chain_id: Option<u64>) -> String {
+    match chain_id {
+        Some(chain_id) => format!("{}@{}", name, chain_id),
+        None => name.to_string(),
+    }
+}
+
+/// Normalizes and validates a repository name taken from a request path.
+///
+/// Accepts either a flat `name` or a namespaced `owner/name`, optionally
+/// qualified with the chain it lives on as `name@<chain id>`.
+pub fn validate_repo_name(name: &str) -> Result<String, InvalidRepoName> {
+    let normalized = normalize_repo_name(name);
+    let invalid = |reason: &str| InvalidRepoName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let (base, chain) = split_chain(&normalized);
+    if let Some(chain) = chain {
+        if !chain.bytes().all(|b| b.is_ascii_digit()) || chain.parse::<u64>().is_err() {
+            return Err(invalid("chain id must be a decimal number"));
+        }
+    }
+
+    let segments: Vec<&str> = base.split('/').collect();
+    if segments.len() > 2 {
+        return Err(invalid("at most one '/' separating owner and name is allowed"));
+    }
+
+    for segment in &segments {
+        validate_segment(segment).map_err(|reason| invalid(&reason))?;
+    }
+
+    // Only the leading segment shares the URL space with the daemon's routes.
+    if RESERVED_REPO_NAMES.contains(&segments[0]) {
+        return Err(invalid("name is reserved"));
+    }
+
+    Ok(normalized)
+}
+
+fn validate_segment(segment: &str) -> Result<(), String> {
+    if segment.is_empty() {
+        return Err("name is empty".to_string());
+    }
+
+    if segment.len() > MAX_REPO_NAME_LEN {
+        return Err("name is too long".to_string());
+    }
+
+    if let Some(c) = segment
+        .chars()
+        .find(|c| !matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '-'))
+    {
+        return Err(format!("character '{}' is not allowed", c));
+    }
+
+    if segment.contains("..") || segment == "." {
+        return Err("relative path components are not allowed".to_string());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(name: &str) -> String {
+        validate_repo_name(name).unwrap_err().reason
+    }
+
+    #[test]
+    fn accepts_flat_namespaced_and_chain_qualified_names() {
+        assert_eq!(validate_repo_name("My-Repo").unwrap(), "my-repo");
+        assert_eq!(validate_repo_name("owner/repo.git").unwrap(), "owner/repo.git");
+        assert_eq!(validate_repo_name("project@11155111").unwrap(), "project@11155111");
+        assert_eq!(validate_repo_name("admins").unwrap(), "admins");
+    }
+
+    #[test]
+    fn rejects_non_numeric_chain_ids() {
+        assert_eq!(reason("repo@mainnet"), "chain id must be a decimal number");
+        assert_eq!(reason("repo@"), "chain id must be a decimal number");
+        assert_eq!(reason("repo@99999999999999999999999"), "chain id must be a decimal number");
+    }
+
+    #[test]
+    fn rejects_more_than_one_slash() {
+        assert_eq!(reason("a/b/c"), "at most one '/' separating owner and name is allowed");
+    }
+
+    #[test]
+    fn rejects_empty_segments() {
+        assert_eq!(reason(""), "name is empty");
+        assert_eq!(reason("owner/"), "name is empty");
+    }
+
+    #[test]
+    fn rejects_long_segments() {
+        assert!(validate_repo_name(&"a".repeat(MAX_REPO_NAME_LEN)).is_ok());
+        assert_eq!(reason(&"a".repeat(MAX_REPO_NAME_LEN + 1)), "name is too long");
+    }
+
+    #[test]
+    fn rejects_disallowed_characters() {
+        assert_eq!(reason("my repo"), "character ' ' is not allowed");
+        assert_eq!(reason("repo%2f"), "character '%' is not allowed");
+    }
+
+    #[test]
+    fn rejects_relative_path_components() {
+        assert_eq!(reason("."), "relative path components are not allowed");
+        assert_eq!(reason("owner/.."), "relative path components are not allowed");
+        assert_eq!(reason("a..b"), "relative path components are not allowed");
+    }
+
+    #[test]
+    fn rejects_every_top_level_route() {
+        for name in RESERVED_REPO_NAMES {
+            assert_eq!(reason(name), "name is reserved", "{}", name);
+            assert_eq!(reason(&format!("{}/repo", name)), "name is reserved", "{}", name);
+            assert_eq!(reason(&format!("{}@1", name)), "name is reserved", "{}", name);
+        }
+        assert!(validate_repo_name("owner/admin").is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use reqwest::{redirect::Policy, Client, Url};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn, Instrument, Span};
+
+use onchain::contract_interaction::Costs;
+
+use crate::config::DaemonConfig;
+
+pub const SIGNATURE_HEADER: &str = "X-Dgit-Signature";
+pub const EVENT_HEADER: &str = "X-Dgit-Event";
+
+const DELIVERY_ATTEMPTS: u32 = 3;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ref value git uses for "did not exist", so consumers can spot new refs.
+pub const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefUpdate {
+    pub name: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Body POSTed to every webhook of a repository after a successful push.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushEvent {
+    pub repo: String,
+    pub pusher: Option<String>,
+    /// Signer named with `-o dgit-signer` that is not the authenticated
+    /// pusher; supplied by the client and not verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unverified_signer: Option<String>,
+    pub refs: Vec<RefUpdate>,
+    pub object_count: usize,
+    pub tx_hashes: Vec<String>,
+    /// Gas and fees of `tx_hashes`.
+    #[serde(default)]
+    pub costs: Costs,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub push_options: BTreeMap<String, String>,
+}
+
+/// Per-repository webhooks, persisted as JSON next to the daemon.
+///
+/// Kept off-chain on purpose: the repo config blob is public and webhook
+/// secrets must not be.
+#[derive(Debug, Clone)]
+pub struct WebhookStore {
+    path: Arc<PathBuf>,
+    hooks: Arc<Mutex<HashMap<String, Vec<Webhook>>>>,
+}
+
+impl WebhookStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let hooks = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse webhooks file {:?}, starting empty: {}", path, e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                error!("Failed to read webhooks file {:?}, starting empty: {}", path, e);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: Arc::new(path),
+            hooks: Arc::new(Mutex::new(hooks)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::load(DaemonConfig::webhooks_path())
+    }
+
+    pub async fn list(&self, repo: &str) -> Vec<Webhook> {
+        let hooks = self.hooks.lock().await;
+        hooks.get(repo).cloned().unwrap_or_default()
+    }
+
+    /// Adds `webhook`, replacing an existing one with the same URL.
+    pub async fn add(&self, repo: &str, webhook: Webhook) -> Result<()> {
+        let mut hooks = self.hooks.lock().await;
+        let repo_hooks = hooks.entry(repo.to_string()).or_default();
+        repo_hooks.retain(|hook| hook.url != webhook.url);
+        repo_hooks.push(webhook);
+        self.persist(&hooks).await
+    }
+
+    /// Removes the webhook with `url`, returning whether one existed.
+    pub async fn remove(&self, repo: &str, url: &str) -> Result<bool> {
+        let mut hooks = self.hooks.lock().await;
+        let Some(repo_hooks) = hooks.get_mut(repo) else {
+            return Ok(false);
+        };
+
+        let before = repo_hooks.len();
+        repo_hooks.retain(|hook| hook.url != url);
+        let removed = repo_hooks.len() != before;
+        if repo_hooks.is_empty() {
+            hooks.remove(repo);
+        }
+
+        if removed {
+            self.persist(&hooks).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn persist(&self, hooks: &HashMap<String, Vec<Webhook>>) -> Result<()> {
+        let content = serde_json::to_string_pretty(hooks)?;
+        // Write-then-rename so a crash never leaves a truncated file behind.
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, content).await
+            .with_context(|| format!("Failed to write webhooks file {:?}", tmp))?;
+        tokio::fs::rename(&tmp, self.path.as_path()).await
+            .with_context(|| format!("Failed to replace webhooks file {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+/// `sha256=<hex>` HMAC of `body`, in the same form GitHub uses.
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    format!("sha256={}", digest.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+/// Resolves the host of webhook `url` and fails unless every address it
+/// resolves to is public, so a webhook cannot reach the daemon's own host or
+/// the network behind it. `DGIT_WEBHOOK_ALLOW_PRIVATE` lifts the check.
+/// Returns the host with the addresses checked.
+pub async fn resolve_public(url: &str) -> Result<(String, Vec<SocketAddr>)> {
+    let url = Url::parse(url).context("Invalid webhook URL")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Webhook URL must start with http:// or https://");
+    }
+    let Some(host) = url.host_str() else {
+        bail!("Webhook URL has no host");
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let ip_literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((ip_literal, port)).await
+        .with_context(|| format!("Failed to resolve webhook host {}", host))?
+        .collect();
+    if addrs.is_empty() {
+        bail!("Webhook host {} resolves to no address", host);
+    }
+    if DaemonConfig::webhook_allow_private() {
+        return Ok((host.to_string(), addrs));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        bail!("Webhook host {} resolves to {}, which is not a public address", host, addr.ip());
+    }
+    Ok((host.to_string(), addrs))
+}
+
+/// Whether `ip` is routable on the internet: not loopback, private,
+/// link-local, shared (CGNAT), multicast or otherwise reserved, also when
+/// written as an IPv4-mapped IPv6 address.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        },
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        },
+    }
+}
+
+/// A client for delivering to `url` that connects only to the addresses
+/// [`resolve_public`] checked, so the name cannot be pointed elsewhere in
+/// between, and follows no redirects, which could lead anywhere.
+async fn delivery_client(url: &str) -> Result<Client> {
+    let (host, addrs) = resolve_public(url).await?;
+    Ok(Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(Policy::none())
+        .resolve_to_addrs(&host, &addrs)
+        .build()?)
+}
+
+/// Delivers `event` to every webhook in the background so a slow endpoint
+/// never holds up the git response.
+pub fn dispatch(webhooks: Vec<Webhook>, event: PushEvent) {
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => Arc::new(body),
+        Err(e) => {
+            error!("Failed to serialize push event for {}: {}", event.repo, e);
+            return;
+        }
+    };
+
+    for webhook in webhooks {
+        let body = Arc::clone(&body);
+        let repo = event.repo.clone();
+        tokio::spawn(async move {
+            let client = match delivery_client(&webhook.url).await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Not delivering push event for {} to {}: {:#}", repo, webhook.url, e);
+                    return;
+                }
+            };
+            deliver(&client, &webhook, &body, &repo).await;
+        }.instrument(Span::current()));
+    }
+}
+
+async fn deliver(client: &Client, webhook: &Webhook, body: &[u8], repo: &str) {
+    for attempt in 1..=DELIVERY_ATTEMPTS {
+        if attempt > 1 {
+            let backoff_ms = 1000 * (1 << (attempt - 2));
+            tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+        }
+
+        let mut request = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, "push")
+            .body(body.to_vec());
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, sign_payload(secret, body));
+        }
+
+        match request.send().await {
+            Ok(resp) if resp.status().is_success() => {
+                info!("Delivered push event for {} to {}", repo, webhook.url);
+                return;
+            },
+            Ok(resp) => {
+                warn!("Webhook {} returned {} (attempt {}/{})", webhook.url, resp.status(), attempt, DELIVERY_ATTEMPTS);
+            },
+            Err(e) => {
+                warn!("Webhook {} failed (attempt {}/{}): {}", webhook.url, attempt, DELIVERY_ATTEMPTS, e);
+            },
+        }
+    }
+
+    error!("Failed to deliver push event for {} to {} after {} attempts", repo, webhook.url, DELIVERY_ATTEMPTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "255.255.255.255", "224.0.0.1", "::1", "::", "fc00::1", "fd12::1",
+            "fe80::1", "ff02::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254",
+        ] {
+            assert!(!public(ip), "{} counted as public", ip);
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for ip in ["1.1.1.1", "93.184.216.34", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(public(ip), "{} counted as internal", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn webhooks_to_internal_hosts_are_refused() {
+        for url in ["http://127.0.0.1:8080/hook", "http://[::1]/hook", "http://localhost/hook", "https://169.254.169.254/latest"] {
+            assert!(resolve_public(url).await.is_err(), "{} was allowed", url);
+        }
+        assert!(resolve_public("ftp://example.com/hook").await.is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::{auth::unix_now, config::DaemonConfig};
+
+/// Scratch directories are named `dgit-<pid>-<created>-<random>`, so the
+/// sweep can tell whose they are and how old.
+pub const PREFIX: &str = "dgit-";
+
+/// The name prefix of a scratch directory this process creates now.
+pub fn tag() -> String {
+    format!("{}{}-{}-", PREFIX, std::process::id(), unix_now())
+}
+
+/// What the work directory holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub root: String,
+    pub bytes: u64,
+    pub directories: usize,
+    /// Directories the next sweep removes, and what they use.
+    pub orphaned: usize,
+    pub orphaned_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+    /// Orphans that could not be removed; the next sweep tries again.
+    pub failed: usize,
+}
+
+/// The scratch directories under `DGIT_TMP_DIR`, and the removal of those
+/// a crashed daemon or a request cut short left behind.
+///
+/// A directory is an orphan when the process that created it is gone, or
+/// when it is older than `DGIT_WORK_DIR_TTL_SECS` and not this daemon's:
+/// after a reboot its pid may belong to something else. This daemon's own
+/// directories are removed when their request drops them, however long it
+/// runs. Orphans are swept at startup, every `DGIT_WORK_DIR_SWEEP_SECS`,
+/// and on `POST /admin/gc`.
+#[derive(Debug, Clone)]
+pub struct WorkDirs {
+    root: Arc<PathBuf>,
+    ttl: Duration,
+    swept: Arc<AtomicU64>,
+}
+
+impl WorkDirs {
+    pub fn new(root: PathBuf, ttl: Duration) -> Self {
+        Self { root: Arc::new(root), ttl, swept: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::tmp_dir(), Duration::from_secs(DaemonConfig::work_dir_ttl_secs()))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Orphaned directories removed since startup.
+    pub fn swept(&self) -> u64 {
+        self.swept.load(Ordering::Relaxed)
+    }
+
+    pub async fn usage(&self) -> Result<DiskUsage> {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut usage = DiskUsage {
+                root: this.root.display().to_string(),
+                bytes: 0,
+                directories: 0,
+                orphaned: 0,
+                orphaned_bytes: 0,
+            };
+            let mut alive = HashMap::new();
+            for (path, owner) in this.entries()? {
+                let bytes = dir_size(&path);
+                usage.bytes += bytes;
+                usage.directories += 1;
+                if this.is_orphan(&path, owner, &mut alive) {
+                    usage.orphaned += 1;
+                    usage.orphaned_bytes += bytes;
+                }
+            }
+            Ok(usage)
+        })
+        .await
+        .map_err(|e| anyhow!("Disk usage task failed: {}", e))?
+    }
+
+    /// Removes every orphaned directory now.
+    pub async fn sweep(&self) -> Result<SweepReport> {
+        let this = self.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            let mut report = SweepReport { removed: 0, freed_bytes: 0, failed: 0 };
+            let mut alive = HashMap::new();
+            for (path, owner) in this.entries()? {
+                if !this.is_orphan(&path, owner, &mut alive) {
+                    continue;
+                }
+                let bytes = dir_size(&path);
+                match std::fs::remove_dir_all(&path) {
+                    Ok(()) => {
+                        debug!("Removed orphaned work directory {}", path.display());
+                        report.removed += 1;
+                        report.freed_bytes += bytes;
+                    },
+                    Err(e) => {
+                        warn!("Failed to remove orphaned work directory {}: {}", path.display(), e);
+                        report.failed += 1;
+                    },
+                }
+            }
+            Ok::<_, anyhow::Error>(report)
+        })
+        .await
+        .map_err(|e| anyhow!("Sweep task failed: {}", e))??;
+
+        self.swept.fetch_add(report.removed as u64, Ordering::Relaxed);
+        if report.removed > 0 {
+            info!("Removed {} orphaned work directories ({} bytes)", report.removed, report.freed_bytes);
+        }
+        Ok(report)
+    }
+
+    /// Sweeps every `interval`; the first sweep is the startup one.
+    pub fn spawn_sweeper(&self, interval: Duration) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = this.sweep().await {
+                    warn!("Failed to sweep {}: {:#}", this.root.display(), e);
+                }
+            }
+        });
+    }
+
+    /// The scratch directories under the root, with the pid that created
+    /// them if their name says.
+    fn entries(&self) -> Result<Vec<(PathBuf, Option<u32>)>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(self.root.as_path())? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str().and_then(|name| name.strip_prefix(PREFIX)) else {
+                continue;
+            };
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            entries.push((entry.path(), name.split('-').next().and_then(|pid| pid.parse().ok())));
+        }
+        Ok(entries)
+    }
+
+    fn is_orphan(&self, path: &Path, owner: Option<u32>, alive: &mut HashMap<u32, bool>) -> bool {
+        if owner == Some(std::process::id()) {
+            return false;
+        }
+        // Directories from before they were tagged have only their age.
+        if let Some(pid) = owner {
+            if !*alive.entry(pid).or_insert_with(|| process_alive(pid)) {
+                return true;
+            }
+        }
+        !self.ttl.is_zero() && age(path).is_some_and(|age| age > self.ttl)
+    }
+}
+
+fn age(path: &Path) -> Option<Duration> {
+    // The creation time in the name survives copies and mtime changes.
+    let name = path.file_name()?.to_str()?.strip_prefix(PREFIX)?;
+    let created = match name.split('-').nth(1).and_then(|created| created.parse().ok()) {
+        Some(created) => UNIX_EPOCH + Duration::from_secs(created),
+        None => std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?,
+    };
+    SystemTime::now().duration_since(created).ok()
+}
+
+/// Whether a process with `pid` exists, via `kill -0`.
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Without `kill`, directories of other processes only expire.
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
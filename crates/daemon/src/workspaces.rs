@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use onchain::contract_interaction::ContractInteraction;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::{config::DaemonConfig, git, validation::normalize_repo_name};
+
+/// A bare repository checked out of the pool for one request.
+///
+/// Dropping it removes it, so a request that fails leaves nothing behind
+/// for the next one; `WorkspacePool::release` hands it back instead.
+#[derive(Debug)]
+pub struct Workspace {
+    dir: TempDir,
+    key: String,
+    reused: bool,
+}
+
+impl Workspace {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Whether an earlier request prepared this workspace.
+    pub fn reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Removes the refs an earlier request left, so only the ones written
+    /// next are there. Objects are kept.
+    pub async fn clear_refs(&self) -> Result<()> {
+        let path = self.path();
+        if self.reused {
+            tokio::fs::remove_dir_all(path.join("refs")).await?;
+            match tokio::fs::remove_file(path.join("packed-refs")).await {
+                Ok(()) => {},
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        tokio::fs::create_dir_all(path.join("refs").join("heads")).await?;
+        tokio::fs::create_dir_all(path.join("refs").join("tags")).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Idle {
+    dir: TempDir,
+    key: String,
+    bytes: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Pool {
+    idle: Vec<Idle>,
+    bytes: u64,
+    clock: u64,
+}
+
+/// Bare repositories kept between requests, so reading or pushing to a
+/// repository again only rewrites its refs and downloads the objects it
+/// does not have yet, instead of starting from `git init`.
+///
+/// Up to `DGIT_WORKSPACES_PER_REPO` idle workspaces are kept per repository,
+/// `DGIT_WORKSPACE_MAX_BYTES` in total, dropping the least recently used
+/// first. A workspace is only handed back once its request succeeded; a
+/// push only once everything in it is anchored, so no later push finds
+/// objects in it that are not on chain.
+#[derive(Debug, Clone)]
+pub struct WorkspacePool {
+    per_repo: usize,
+    max_bytes: u64,
+    pool: Arc<Mutex<Pool>>,
+    reused: Arc<AtomicU64>,
+    created: Arc<AtomicU64>,
+}
+
+impl WorkspacePool {
+    pub fn new(per_repo: usize, max_bytes: u64) -> Self {
+        Self {
+            per_repo,
+            max_bytes,
+            pool: Arc::new(Mutex::new(Pool::default())),
+            reused: Arc::new(AtomicU64::new(0)),
+            created: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(DaemonConfig::workspaces_per_repo(), DaemonConfig::workspace_max_bytes())
+    }
+
+    /// Requests served from a workspace an earlier request prepared.
+    pub fn reused(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// Requests that had to create a workspace.
+    pub fn created(&self) -> u64 {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// Disk used by idle workspaces.
+    pub fn idle_bytes(&self) -> u64 {
+        self.lock().bytes
+    }
+
+    /// The most recently used idle workspace of `repo`, or a new one.
+    pub async fn acquire(&self, repo: &str, contract: &ContractInteraction) -> Result<Workspace> {
+        let key = format!("{}@{}", normalize_repo_name(repo), contract.address());
+
+        if let Some(dir) = self.take(&key) {
+            self.reused.fetch_add(1, Ordering::Relaxed);
+            debug!("Reusing workspace {:?} for {}", dir.path(), repo);
+            return Ok(Workspace { dir, key, reused: true });
+        }
+
+        self.created.fetch_add(1, Ordering::Relaxed);
+        let dir = git::tempdir()?;
+        debug!("Created workspace {:?} for {}", dir.path(), repo);
+
+        let mut cmd = git::command();
+        cmd.args(["init", "--bare"]).current_dir(dir.path());
+        let output = git::output(cmd).await?;
+        if !output.status.success() {
+            return Err(anyhow!("Failed to initialize git repo: {}", String::from_utf8_lossy(&output.stderr)));
+        }
+
+        Ok(Workspace { dir, key, reused: false })
+    }
+
+    /// Keeps `workspace` for the next request of its repository, if the
+    /// pool has room for it.
+    pub async fn release(&self, workspace: Workspace) {
+        if self.per_repo == 0 {
+            return;
+        }
+
+        let path = workspace.path().to_path_buf();
+        let bytes = match tokio::task::spawn_blocking(move || dir_size(&path)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Failed to measure workspace {:?}: {}", workspace.path(), e);
+                return;
+            },
+        };
+        if self.max_bytes > 0 && bytes > self.max_bytes {
+            debug!("Not keeping workspace {:?}: {} bytes is over DGIT_WORKSPACE_MAX_BYTES", workspace.path(), bytes);
+            return;
+        }
+
+        let evicted = {
+            let mut pool = self.lock();
+            pool.clock += 1;
+            let last_used = pool.clock;
+            pool.bytes += bytes;
+            pool.idle.push(Idle { dir: workspace.dir, key: workspace.key.clone(), bytes, last_used });
+            self.evict(&mut pool, &workspace.key)
+        };
+
+        if !evicted.is_empty() {
+            debug!("Evicting {} idle workspaces", evicted.len());
+            tokio::task::spawn_blocking(move || drop(evicted));
+        }
+    }
+
+    fn take(&self, key: &str) -> Option<TempDir> {
+        let mut pool = self.lock();
+        let (index, _) = pool
+            .idle
+            .iter()
+            .enumerate()
+            .filter(|(_, idle)| idle.key == key)
+            .max_by_key(|(_, idle)| idle.last_used)?;
+        let idle = pool.idle.swap_remove(index);
+        pool.bytes -= idle.bytes;
+        Some(idle.dir)
+    }
+
+    /// Removes the least recently used workspaces until `key` has at most
+    /// `per_repo` and all of them fit in `max_bytes`.
+    fn evict(&self, pool: &mut Pool, key: &str) -> Vec<TempDir> {
+        let mut evicted = Vec::new();
+        loop {
+            let over_repo = pool.idle.iter().filter(|idle| idle.key == key).count() > self.per_repo;
+            let over_bytes = self.max_bytes > 0 && pool.bytes > self.max_bytes;
+            if !over_repo && !over_bytes {
+                return evicted;
+            }
+
+            let oldest = pool
+                .idle
+                .iter()
+                .enumerate()
+                .filter(|(_, idle)| !over_repo || idle.key == key)
+                .min_by_key(|(_, idle)| idle.last_used)
+                .map(|(index, _)| index);
+            let Some(index) = oldest else {
+                return evicted;
+            };
+            let idle = pool.idle.swap_remove(index);
+            pool.bytes -= idle.bytes;
+            evicted.push(idle.dir);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pool> {
+        self.pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
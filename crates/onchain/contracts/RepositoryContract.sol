@@ -6,6 +6,7 @@ import {AccessControl} from "@openzeppelin/contracts/access/AccessControl.sol";
 contract RepositoryContract is AccessControl {
 
         bytes32 public constant PUSHER_ROLE = keccak256("PUSHER_ROLE");
+        bytes32 public constant READER_ROLE = keccak256("READER_ROLE");
 
 
     constructor() {
@@ -29,6 +30,9 @@ contract RepositoryContract is AccessControl {
     mapping(string => Object) public objects;
     mapping(string => Ref) public refs;
     mapping(string => uint256) private refIndex;
+    // Objects no active ref reaches any more. Their data stays available;
+    // pushing the object again reactivates it.
+    mapping(string => bool) public inactiveObjects;
 
     Object[] public objectsById;
     Ref[] public refsById;
@@ -37,7 +41,9 @@ contract RepositoryContract is AccessControl {
 
     event ObjectSaved(string hash, bytes ipfs_url, address pusher);
     event RefAdded(string ref, bytes ipfs_url, address pusher);
+    event RefDeactivated(string ref, address pusher);
     event ConfigUpdated(bytes config);
+    event ObjectDeactivated(string hash, address admin);
 
     modifier onlyPusher() {
         require(hasRole(PUSHER_ROLE, msg.sender), "Caller is not a pusher");
@@ -51,6 +57,7 @@ contract RepositoryContract is AccessControl {
 
     function saveObject(string memory _hash, bytes memory _ipfs_url) public onlyPusher {
         if (objects[_hash].ipfs_url.length > 0) {
+            delete inactiveObjects[_hash];
             return;
         }
 
@@ -76,7 +83,7 @@ contract RepositoryContract is AccessControl {
         emit RefAdded(_ref, _data, pusher);
     }
 
-    function updateConfig(bytes memory _config) public onlyPusher {
+    function updateConfig(bytes memory _config) public onlyAdmin {
         config = _config;
         emit ConfigUpdated(_config);
     }
@@ -93,6 +100,18 @@ contract RepositoryContract is AccessControl {
         return hasRole(PUSHER_ROLE, _address);
     }
 
+    function grantReaderRole(address _address) public onlyAdmin {
+        _grantRole(READER_ROLE, _address);
+    }
+
+    function revokeReaderRole(address _address) public onlyAdmin {
+        _revokeRole(READER_ROLE, _address);
+    }
+
+    function hasReaderRole(address _address) public view returns (bool) {
+        return hasRole(READER_ROLE, _address);
+    }
+
     function grantAdminRole(address _address) public onlyAdmin {
         _grantRole(DEFAULT_ADMIN_ROLE, _address);
     }
@@ -132,6 +151,7 @@ contract RepositoryContract is AccessControl {
     function addObjects(string[] memory _hashes, bytes[] memory _ipfs_urls) public onlyPusher {
         for (uint256 i = 0; i < _hashes.length; i++) {
             if (objects[_hashes[i]].ipfs_url.length > 0) {
+                delete inactiveObjects[_hashes[i]];
                 continue;
             }
             Object memory object = Object(_hashes[i], _ipfs_urls[i], msg.sender);
@@ -163,6 +183,36 @@ contract RepositoryContract is AccessControl {
         }
     }
 
+    // Deleted refs stay listed but inactive; pushing one again adds a new
+    // entry for it.
+    function deactivateRefs(string[] memory _refsArr) public onlyPusher {
+        address pusher = msg.sender;
+        for (uint256 i = 0; i < _refsArr.length; i++) {
+            string memory name = _refsArr[i];
+            if (!refs[name].is_active) {
+                continue;
+            }
+            refs[name].is_active = false;
+            refsById[refIndex[name]].is_active = false;
+            emit RefDeactivated(name, pusher);
+        }
+    }
+
+    function deactivateObjects(string[] memory _hashes) public onlyAdmin {
+        for (uint256 i = 0; i < _hashes.length; i++) {
+            string memory hash = _hashes[i];
+            if (objects[hash].ipfs_url.length == 0 || inactiveObjects[hash]) {
+                continue;
+            }
+            inactiveObjects[hash] = true;
+            emit ObjectDeactivated(hash, msg.sender);
+        }
+    }
+
+    function isObjectActive(string memory _hash) public view returns (bool) {
+        return objects[_hash].ipfs_url.length > 0 && !inactiveObjects[_hash];
+    }
+
     function getRefs() public view returns (Ref[] memory) {
         return refsById;
     }
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {RepositoryContract} from "./RepositoryContract.sol";
+
+/// Maps repository names to their contracts, so every daemon on a chain
+/// resolves a name to the same repository.
+///
+/// Repositories created here are deployed with CREATE2 salted with the
+/// name's hash, so their address is known before they exist. Names are
+/// first come, first served.
+contract RepositoryRegistry {
+
+    mapping(bytes32 => address) public repositories;
+
+    event RepositoryRegistered(bytes32 indexed nameHash, string name, address repository, address owner);
+
+    /// Deploys the repository for `name` at `predict(name)` and hands its
+    /// admin and pusher roles to the caller.
+    function create(string memory _name) public returns (address) {
+        bytes32 nameHash = keccak256(bytes(_name));
+        require(repositories[nameHash] == address(0), "Name already registered");
+
+        RepositoryContract repository = new RepositoryContract{salt: nameHash}();
+        repository.grantRole(repository.DEFAULT_ADMIN_ROLE(), msg.sender);
+        repository.grantRole(repository.PUSHER_ROLE(), msg.sender);
+        repository.renounceRole(repository.PUSHER_ROLE(), address(this));
+        repository.renounceRole(repository.DEFAULT_ADMIN_ROLE(), address(this));
+
+        repositories[nameHash] = address(repository);
+        emit RepositoryRegistered(nameHash, _name, address(repository), msg.sender);
+        return address(repository);
+    }
+
+    /// Registers a repository deployed elsewhere. Only one of its admins can.
+    function register(string memory _name, address _repository) public {
+        bytes32 nameHash = keccak256(bytes(_name));
+        require(repositories[nameHash] == address(0), "Name already registered");
+        require(
+            RepositoryContract(_repository).hasAdminRole(msg.sender),
+            "Caller is not an admin of the repository"
+        );
+
+        repositories[nameHash] = _repository;
+        emit RepositoryRegistered(nameHash, _name, _repository, msg.sender);
+    }
+
+    /// The repository registered for `name`, or the zero address.
+    function resolve(string memory _name) public view returns (address) {
+        return repositories[keccak256(bytes(_name))];
+    }
+
+    /// Where `create(name)` deploys the repository for `name`.
+    function predict(string memory _name) public view returns (address) {
+        bytes32 hash = keccak256(
+            abi.encodePacked(
+                bytes1(0xff),
+                address(this),
+                keccak256(bytes(_name)),
+                keccak256(type(RepositoryContract).creationCode)
+            )
+        );
+        return address(uint160(uint256(hash)));
+    }
+}
@@ -0,0 +1,9 @@
+import {buildModule} from "@nomicfoundation/hardhat-ignition/modules";
+
+const RegistryModule = buildModule("RepositoryRegistryModule", (m) => {
+    const registry = m.contract("RepositoryRegistry", [], {});
+
+    return {registry};
+});
+
+export default RegistryModule;
@@ -1,5 +1,10 @@
+use std::sync::RwLock;
 use tracing::{debug, warn};
 
+/// Endpoints switched to while running, which replace `RPC_URLS` and
+/// `RPC_URL` until the process exits.
+static RPC_URLS_OVERRIDE: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
 pub struct Config;
 
 impl Config {
@@ -30,6 +35,72 @@ impl Config {
         }
     }
 
+    /// RPC endpoints in order of preference: those set with
+    /// [`set_rpc_urls`](Self::set_rpc_urls), else `RPC_URLS`
+    /// (comma-separated) if set, else `RPC_URL`.
+    pub fn rpc_urls() -> Vec<String> {
+        if let Some(urls) = RPC_URLS_OVERRIDE.read().unwrap().as_ref() {
+            return urls.clone();
+        }
+
+        let urls = split_urls(&dotenv::var("RPC_URLS").unwrap_or_default());
+        if urls.is_empty() {
+            vec![Self::rpc_url()]
+        } else {
+            debug!("Loaded {} RPC URLs", urls.len());
+            urls
+        }
+    }
+
+    /// Replaces the RPC endpoints for everything built from now on.
+    pub fn set_rpc_urls(urls: Vec<String>) {
+        *RPC_URLS_OVERRIDE.write().unwrap() = Some(urls);
+    }
+
+    /// `RPC_URLS`, else `RPC_URL`, as the `.env` file has them now, for
+    /// picking up an edit without a restart. `None` if the file names
+    /// neither or cannot be read.
+    pub fn rpc_urls_from_env_file() -> Option<Vec<String>> {
+        let mut rpc_url = None;
+        for item in dotenv::dotenv_iter().ok()? {
+            match item {
+                Ok((key, value)) if key == "RPC_URLS" => {
+                    let urls = split_urls(&value);
+                    if !urls.is_empty() {
+                        return Some(urls);
+                    }
+                },
+                Ok((key, value)) if key == "RPC_URL" && !value.trim().is_empty() => {
+                    rpc_url = Some(vec![value.trim().to_string()]);
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    warn!("Failed to read .env: {}", e);
+                    return None;
+                },
+            }
+        }
+        rpc_url
+    }
+
+    /// Chain the RPC endpoints must be on; endpoints reporting another
+    /// chain id are not used. Unset accepts any chain.
+    pub fn chain_id() -> Option<u64> {
+        let value = dotenv::var("CHAIN_ID").ok()?;
+        match value.parse() {
+            Ok(chain_id) => Some(chain_id),
+            Err(_) => {
+                warn!("Invalid CHAIN_ID '{}', accepting any chain", value);
+                None
+            }
+        }
+    }
+
+    /// How long an RPC endpoint that failed to answer is skipped.
+    pub fn rpc_failover_cooldown_secs() -> u64 {
+        Self::number("RPC_FAILOVER_COOLDOWN_SECS", 30)
+    }
+
     pub fn ipfs_prefix() -> String {
         match dotenv::var("IPFS_PREFIX") {
             Ok(prefix) => {
@@ -46,4 +117,186 @@ impl Config {
     pub fn ipfs_api_url() -> Option<String> {
         std::env::var("IPFS_API_URL").ok()
     }
+
+    pub fn ipfs_backend() -> String {
+        dotenv::var("IPFS_BACKEND").unwrap_or_else(|_| "kubo".to_string())
+    }
+
+    pub fn ipfs_api_token() -> Option<String> {
+        dotenv::var("IPFS_API_TOKEN").ok()
+    }
+
+    /// Where pushed objects are stored: `ipfs`, `http` or `s3`.
+    pub fn object_store() -> String {
+        dotenv::var("OBJECT_STORE").unwrap_or_else(|_| "ipfs".to_string())
+    }
+
+    /// Base URL objects are PUT under with `OBJECT_STORE=http`.
+    pub fn object_store_url() -> Option<String> {
+        dotenv::var("OBJECT_STORE_URL").ok().filter(|url| !url.is_empty())
+    }
+
+    /// Bearer token sent to `OBJECT_STORE_URL`.
+    pub fn object_store_token() -> Option<String> {
+        dotenv::var("OBJECT_STORE_TOKEN").ok().filter(|token| !token.is_empty())
+    }
+
+    pub fn s3_bucket() -> Option<String> {
+        dotenv::var("S3_BUCKET").ok().filter(|bucket| !bucket.is_empty())
+    }
+
+    pub fn s3_region() -> String {
+        dotenv::var("S3_REGION").or_else(|_| dotenv::var("AWS_REGION")).unwrap_or_else(|_| "us-east-1".to_string())
+    }
+
+    /// S3-compatible endpoint, such as MinIO; AWS for the region otherwise.
+    pub fn s3_endpoint() -> Option<String> {
+        dotenv::var("S3_ENDPOINT").ok().filter(|endpoint| !endpoint.is_empty())
+    }
+
+    /// Key prefix objects are stored under in the bucket.
+    pub fn s3_prefix() -> String {
+        dotenv::var("S3_PREFIX").unwrap_or_default()
+    }
+
+    pub fn s3_access_key_id() -> Option<String> {
+        dotenv::var("S3_ACCESS_KEY_ID").or_else(|_| dotenv::var("AWS_ACCESS_KEY_ID")).ok()
+    }
+
+    pub fn s3_secret_access_key() -> Option<String> {
+        dotenv::var("S3_SECRET_ACCESS_KEY").or_else(|_| dotenv::var("AWS_SECRET_ACCESS_KEY")).ok()
+    }
+
+    pub fn signer() -> String {
+        dotenv::var("SIGNER").unwrap_or_else(|_| "env".to_string())
+    }
+
+    pub fn keystore_path() -> Option<String> {
+        dotenv::var("KEYSTORE_PATH").ok()
+    }
+
+    pub fn keystore_password() -> Option<String> {
+        if let Ok(password) = dotenv::var("KEYSTORE_PASSWORD") {
+            return Some(password);
+        }
+
+        let path = dotenv::var("KEYSTORE_PASSWORD_FILE").ok()?;
+        match std::fs::read_to_string(&path) {
+            Ok(password) => Some(password.trim_end().to_string()),
+            Err(e) => {
+                warn!("Failed to read keystore password file {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn remote_signer_url() -> Option<String> {
+        dotenv::var("REMOTE_SIGNER_URL").ok()
+    }
+
+    pub fn signer_address() -> Option<String> {
+        dotenv::var("SIGNER_ADDRESS").ok()
+    }
+
+    /// Registry contract repositories are created in and resolved from.
+    /// Unset keeps repositories known only to the daemon that created them.
+    pub fn registry_address() -> Option<String> {
+        dotenv::var("REGISTRY_ADDRESS").ok().filter(|address| !address.is_empty())
+    }
+
+    /// Public gateways objects are fetched from when re-pinning, besides
+    /// the backend's own; comma-separated prefixes ending in `/ipfs/`.
+    pub fn ipfs_repin_gateways() -> Vec<String> {
+        dotenv::var("IPFS_REPIN_GATEWAYS")
+            .unwrap_or_else(|_| "https://ipfs.io/ipfs/,https://dweb.link/ipfs/".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|gateway| !gateway.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Whether uploads are followed by a HEAD request against the gateway.
+    pub fn ipfs_verify_upload() -> bool {
+        Self::number("IPFS_VERIFY_UPLOAD", false)
+    }
+
+    pub fn ipfs_verify_retries() -> u32 {
+        Self::number("IPFS_VERIFY_RETRIES", 3)
+    }
+
+    pub fn ipfs_verify_delay_ms() -> u64 {
+        Self::number("IPFS_VERIFY_DELAY_MS", 1000)
+    }
+
+    /// Retries for read-only RPC calls that hit a transport error; 0 disables.
+    pub fn rpc_read_retries() -> u32 {
+        Self::number("RPC_READ_RETRIES", 3)
+    }
+
+    pub fn rpc_retry_backoff_ms() -> u64 {
+        Self::number("RPC_RETRY_BACKOFF_MS", 200)
+    }
+
+    /// Lists longer than this are read by index in pages rather than with
+    /// one `getObjects`/`getRefs` call.
+    pub fn paged_read_threshold() -> usize {
+        Self::number("RPC_PAGED_READ_THRESHOLD", 2000)
+    }
+
+    pub fn page_size() -> usize {
+        Self::number("RPC_PAGE_SIZE", 500)
+    }
+
+    /// Index reads in flight at once while paging.
+    pub fn page_concurrency() -> usize {
+        Self::number("RPC_PAGE_CONCURRENCY", 8)
+    }
+
+    /// Blocks per `eth_getLogs` query when syncing a repository from its
+    /// events; many providers cap the range.
+    pub fn sync_block_range() -> u64 {
+        Self::number("SYNC_BLOCK_RANGE", 5000)
+    }
+
+    /// Blocks behind the head a synced repository view is considered final
+    /// at; newer changes are re-read on every sync in case of a reorg.
+    pub fn sync_reorg_depth() -> u64 {
+        Self::number("SYNC_REORG_DEPTH", 12)
+    }
+
+    /// Blocks a transaction must be buried under, counting its own, before
+    /// the daemon treats it as final.
+    pub fn confirmations() -> u64 {
+        Self::number("DGIT_CONFIRMATIONS", 1).max(1)
+    }
+
+    /// How long to wait for a sent transaction to be mined and confirmed.
+    pub fn tx_confirm_timeout_secs() -> u64 {
+        Self::number("TX_CONFIRM_TIMEOUT_SECS", 300)
+    }
+
+    /// First delay between receipt polls over HTTP; doubles up to 8s.
+    pub fn tx_poll_interval_ms() -> u64 {
+        Self::number("TX_POLL_INTERVAL_MS", 500)
+    }
+
+    fn number<T: std::str::FromStr + std::fmt::Display>(name: &str, default: T) -> T {
+        match dotenv::var(name) {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                warn!("Invalid {} '{}', using default: {}", name, value, default);
+                default
+            }),
+            Err(_) => default,
+        }
+    }
+}
+
+fn split_urls(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
 }
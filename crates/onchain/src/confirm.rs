@@ -0,0 +1,113 @@
+use crate::config::Config;
+use crate::transport::is_websocket;
+use anyhow::{bail, Result};
+use ethcontract::web3::api::SubscriptionStream;
+use ethcontract::web3::transports::WebSocket;
+use ethcontract::web3::types::{BlockHeader, TransactionReceipt, H256, U64};
+use ethcontract::web3::{Transport, Web3};
+use futures_util::StreamExt;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, instrument, warn};
+
+/// Longest wait between receipt checks, whether polling or subscribed.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(8);
+
+/// Waits until `tx_hash` is mined and buried under `DGIT_CONFIRMATIONS`
+/// blocks, counting its own, and returns its receipt.
+///
+/// A reverted transaction is an error, as is one not confirmed within
+/// `TX_CONFIRM_TIMEOUT_SECS`. Receipts are checked on every new block when
+/// one of `urls` is a WebSocket endpoint, otherwise polled with backoff.
+#[instrument(skip(client, urls), err)]
+pub async fn wait_for_confirmation<T: Transport>(client: &Web3<T>, urls: &[String], tx_hash: H256) -> Result<TransactionReceipt> {
+    let confirmations = Config::confirmations();
+    let timeout = Duration::from_secs(Config::tx_confirm_timeout_secs());
+    let deadline = Instant::now() + timeout;
+    let mut heads = Heads::open(urls).await;
+
+    loop {
+        // Checked again on every block: a reorg can drop a mined receipt.
+        if let Some(receipt) = client.eth().transaction_receipt(tx_hash).await? {
+            if receipt.status != Some(U64::from(1)) {
+                bail!("Transaction {:?} reverted in block {:?}", tx_hash, receipt.block_number);
+            }
+
+            if let Some(mined) = receipt.block_number {
+                let latest = client.eth().block_number().await?;
+                let depth = latest.saturating_sub(mined).as_u64() + 1;
+                if depth >= confirmations {
+                    info!("Transaction {:?} confirmed in block {} ({} confirmations)", tx_hash, mined, depth);
+                    return Ok(receipt);
+                }
+                debug!("Transaction {:?} has {}/{} confirmations", tx_hash, depth, confirmations);
+            }
+        }
+
+        if Instant::now() >= deadline {
+            bail!("Transaction {:?} was not confirmed within {:?}", tx_hash, timeout);
+        }
+        heads.next().await;
+    }
+}
+
+/// When to look at the receipt again: on each block announced by a
+/// `newHeads` subscription, or after a growing delay when polling.
+enum Heads {
+    Subscribed(SubscriptionStream<WebSocket, BlockHeader>),
+    Polling(Duration),
+}
+
+impl Heads {
+    async fn open(urls: &[String]) -> Self {
+        let Some(url) = urls.iter().find(|url| is_websocket(url)) else {
+            return Self::polling();
+        };
+
+        let subscribed = match WebSocket::new(url).await {
+            Ok(ws) => Web3::new(ws).eth_subscribe().subscribe_new_heads().await,
+            Err(e) => Err(e),
+        };
+        match subscribed {
+            Ok(stream) => Self::Subscribed(stream),
+            Err(e) => {
+                warn!("Failed to subscribe to new blocks, polling for receipts instead: {}", e);
+                Self::polling()
+            },
+        }
+    }
+
+    fn polling() -> Self {
+        Self::Polling(Duration::from_millis(Config::tx_poll_interval_ms()).min(MAX_POLL_INTERVAL))
+    }
+
+    async fn next(&mut self) {
+        let ended = match self {
+            Self::Subscribed(stream) => match tokio::time::timeout(MAX_POLL_INTERVAL, stream.next()).await {
+                Ok(Some(Ok(head))) => {
+                    debug!("New block {:?}", head.number);
+                    false
+                },
+                Ok(Some(Err(e))) => {
+                    warn!("Block subscription failed, polling for receipts instead: {}", e);
+                    true
+                },
+                Ok(None) => {
+                    warn!("Block subscription closed, polling for receipts instead");
+                    true
+                },
+                // No block for a while; look anyway in case one was missed.
+                Err(_) => false,
+            },
+            Self::Polling(delay) => {
+                tokio::time::sleep(*delay).await;
+                *delay = (*delay * 2).min(MAX_POLL_INTERVAL);
+                false
+            },
+        };
+
+        if ended {
+            *self = Self::polling();
+        }
+    }
+}
@@ -1,25 +1,150 @@
 use crate::config::Config;
+use crate::confirm::wait_for_confirmation;
+use crate::registry::{self, RepositoryRegistry};
+use crate::repo_config::RepoConfig;
+use crate::signer::Signer;
+use crate::transport::{FailoverTransport, RetryTransport, WrongChain};
 use anyhow::Result;
 use ethcontract::prelude::*;
+use serde::{Deserialize, Serialize};
+use ethcontract::transaction::{ResolveCondition, TransactionResult};
+use ethcontract::web3::signing;
+use ethcontract::web3::types::TransactionReceipt;
+use std::future::Future;
+use std::ops::Range;
 use std::str::FromStr;
-use tracing::{debug, info, error, trace, instrument, warn};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, error, trace, instrument};
 
 ethcontract::contract!("crates/onchain/artifacts/contracts/RepositoryContract.sol/RepositoryContract.json");
 
+/// The compiled artifact the bindings above are generated from.
+const CONTRACT_ARTIFACT: &str = include_str!("../artifacts/contracts/RepositoryContract.sol/RepositoryContract.json");
+
+/// Keccak-256 of the contract ABI this build was compiled against, so two
+/// builds can be checked for talking to the same contract interface.
+pub fn abi_hash() -> Result<String> {
+    let artifact: serde_json::Value = serde_json::from_str(CONTRACT_ARTIFACT)?;
+    let abi = artifact.get("abi").ok_or_else(|| anyhow::anyhow!("Contract artifact has no ABI"))?;
+    Ok(format!("{:?}", H256::from(signing::keccak256(abi.to_string().as_bytes()))))
+}
+
+const DEFAULT_ADMIN_ROLE: [u8; 32] = [0; 32];
+
 #[derive(Debug, Clone)]
 pub struct ContractInteraction {
     pub contract: RepositoryContract,
-    pub client: Web3<Http>,
+    pub client: Web3<RetryTransport<FailoverTransport>>,
+    pub signer: Signer,
+    /// Set when this process deployed the contract.
+    pub deployment: Option<Deployment>,
+    /// Chain the contract was deployed on; unknown for contracts this
+    /// process did not deploy.
+    pub chain_id: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
+/// The transaction that deployed a repository contract.
+#[derive(Debug, Clone, Copy)]
+pub struct Deployment {
+    pub tx_hash: H256,
+    /// Block the deployment was mined in, if the node returned a receipt.
+    pub block_number: Option<u64>,
+    /// What creating the repository cost: the deployment, plus any roles
+    /// granted along with it.
+    pub costs: Costs,
+}
+
+/// Gas and fees paid by the signer for one or more transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Costs {
+    /// Number of transactions the figures add up.
+    pub txs: u64,
+    pub gas_used: u64,
+    /// Gas used times the effective gas price, in wei. Receipts without a
+    /// price, from nodes predating EIP-1559, count towards gas only.
+    pub fee_wei: u128,
+}
+
+impl Costs {
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Self {
+        let gas_used = receipt.gas_used.unwrap_or_default();
+        let fee = receipt.effective_gas_price
+            .map(|price| gas_used.saturating_mul(price))
+            .unwrap_or_default();
+        Self {
+            txs: 1,
+            gas_used: gas_used.low_u64(),
+            fee_wei: fee.low_u128(),
+        }
+    }
+
+    pub fn add(&mut self, other: Costs) {
+        self.txs += other.txs;
+        self.gas_used = self.gas_used.saturating_add(other.gas_used);
+        self.fee_wei = self.fee_wei.saturating_add(other.fee_wei);
+    }
+}
+
+/// Reads as `412,331 gas across 2 txs (0.000824662 ether)`.
+impl std::fmt::Display for Costs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} gas across {} tx{}", format_gas(self.gas_used), self.txs, if self.txs == 1 { "" } else { "s" })?;
+        if self.fee_wei > 0 {
+            write!(f, " ({} ether)", format_ether(self.fee_wei))?;
+        }
+        Ok(())
+    }
+}
+
+const WEI_PER_ETHER: u128 = 1_000_000_000_000_000_000;
+
+/// `412331` as `412,331`.
+pub fn format_gas(gas: u64) -> String {
+    let digits = gas.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// An amount in wei as ether, without trailing zeros.
+pub fn format_ether(wei: u128) -> String {
+    let ether = format!("{}.{:018}", wei / WEI_PER_ETHER, wei % WEI_PER_ETHER);
+    ether.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// A confirmed transaction and what it cost.
+#[derive(Debug, Clone, Copy)]
+pub struct Confirmed {
+    pub tx_hash: H256,
+    pub costs: Costs,
+}
+
+impl Confirmed {
+    /// Costs are zero when the transaction was sent without waiting for
+    /// its receipt.
+    fn from_result(tx: &TransactionResult) -> Self {
+        Self {
+            tx_hash: tx.hash(),
+            costs: tx.as_receipt().map(Costs::from_receipt).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Object {
     pub hash: String,
     pub ipfs_url: Vec<u8>,
     pub pusher: Address,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ref {
     pub name: String,
     pub data: Vec<u8>,
@@ -27,13 +152,72 @@ pub struct Ref {
     pub pusher: Address,
 }
 
-impl Default for ContractInteraction {
-    fn default() -> Self {
-        let rpc_url = Config::rpc_url();
-        debug!("Initializing ContractInteraction with RPC URL: {}", rpc_url);
-        
-        let http = Http::new(&rpc_url).unwrap();
-        let client = Web3::new(http);
+/// A change to a repository's objects or refs, from the contract's events.
+#[derive(Debug, Clone)]
+pub enum RepoEvent {
+    /// A new object was appended to the object list.
+    ObjectSaved(Object),
+    /// A ref was set, in place if it already existed.
+    RefAdded(Ref),
+    /// A ref was deleted and is inactive until it is pushed again.
+    RefDeactivated { name: String, pusher: Address },
+}
+
+/// A [`RepoEvent`] with the block and transaction that made it.
+#[derive(Debug, Clone)]
+pub struct RepoChange {
+    pub block_number: u64,
+    pub tx_hash: H256,
+    pub event: RepoEvent,
+}
+
+fn with_signer(mut contract: RepositoryContract, signer: &Signer) -> RepositoryContract {
+    if let Some(account) = signer.account() {
+        contract.defaults_mut().from = Some(account);
+    }
+    contract
+}
+
+type Client = Web3<RetryTransport<FailoverTransport>>;
+
+/// Client for lookups that belong to no contract yet, with the endpoints
+/// it was built for. Rebuilt when those change.
+static SHARED_CLIENT: Mutex<Option<(Vec<String>, Client)>> = Mutex::new(None);
+
+fn shared_client(signer: &Signer) -> Client {
+    let rpc_urls = signer.rpc_urls();
+    let mut shared = SHARED_CLIENT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((urls, client)) = shared.as_ref() {
+        if *urls == rpc_urls {
+            return client.clone();
+        }
+    }
+    let client = Web3::new(RetryTransport::from_env(FailoverTransport::from_env(&rpc_urls)));
+    *shared = Some((rpc_urls, client.clone()));
+    client
+}
+
+fn registry_at(client: &Web3<RetryTransport<FailoverTransport>>, signer: &Signer) -> Result<Option<RepositoryRegistry>> {
+    let Some(address) = registry::address()? else {
+        return Ok(None);
+    };
+    let mut registry = RepositoryRegistry::at(client, address);
+    if let Some(account) = signer.account() {
+        registry.defaults_mut().from = Some(account);
+    }
+    Ok(Some(registry))
+}
+
+impl ContractInteraction {
+    /// A contract handle at the zero address through the configured
+    /// endpoints. Fails when the signer cannot be loaded, rather than
+    /// quietly sending from the node's account.
+    pub fn new() -> Result<Self> {
+        let signer = Signer::shared()?;
+        let rpc_urls = signer.rpc_urls();
+        debug!("Initializing ContractInteraction with {} RPC endpoints", rpc_urls.len());
+
+        let client = Web3::new(RetryTransport::from_env(FailoverTransport::from_env(&rpc_urls)));
 
         let contract = RepositoryContract::at(
             &client,
@@ -41,34 +225,154 @@ impl Default for ContractInteraction {
         );
 
         info!("ContractInteraction initialized with default zero address");
-        ContractInteraction { contract, client }
-    }
-}
-
-impl ContractInteraction {
-    pub fn new() -> Self {
-        debug!("Creating new ContractInteraction using default implementation");
-        Self::default()
+        Ok(ContractInteraction { contract: with_signer(contract, &signer), client, signer, deployment: None, chain_id: None })
     }
 
     #[instrument(err)]
     pub async fn deploy() -> Result<Self> {
-        let rpc_url = dotenv::var("RPC_URL").unwrap_or("http://localhost:8545".to_string());
-        info!("Deploying new contract to RPC endpoint: {}", rpc_url);
+        let signer = Signer::shared()?;
+        let rpc_urls = signer.rpc_urls();
+        info!("Deploying new contract through {} RPC endpoints", rpc_urls.len());
 
-        let http = Http::new(&rpc_url).unwrap();
-        let client = Web3::new(http);
+        let client = Web3::new(RetryTransport::from_env(FailoverTransport::from_env(&rpc_urls)));
 
-        debug!("Initiating contract deployment");
-        let contract = RepositoryContract::builder(&client)
-            .gas(4_000_000.into())
-            .deploy()
-            .await?;
+        // With `CHAIN_ID` set, the transport refuses endpoints on other chains.
+        let chain_id = client.eth().chain_id().await?.as_u64();
+        info!("Deploying on chain {}", chain_id);
+
+        debug!("Initiating contract deployment with signer: {:?}", signer);
+        let mut builder = RepositoryContract::builder(&client)
+            .gas(4_000_000.into());
+        if let Some(account) = signer.account() {
+            builder = builder.from(account);
+        }
+        let contract = builder.deploy().await?;
 
         let address = contract.address();
         info!("Contract successfully deployed at address: {:?}", address);
 
-        Ok(ContractInteraction { contract, client })
+        let deployment = match contract.deployment_information() {
+            Some(ethcontract::common::DeploymentInformation::TransactionHash(tx_hash)) => {
+                let receipt = client.eth().transaction_receipt(tx_hash).await?;
+                let block_number = receipt.as_ref().and_then(|receipt| receipt.block_number).map(|block| block.as_u64());
+                let costs = receipt.as_ref().map(Costs::from_receipt).unwrap_or_default();
+                debug!("Deployment transaction {:?} mined in block {:?}, {}", tx_hash, block_number, costs);
+                Some(Deployment { tx_hash, block_number, costs })
+            },
+            _ => None,
+        };
+
+        Ok(ContractInteraction { contract: with_signer(contract, &signer), client, signer, deployment, chain_id: Some(chain_id) })
+    }
+
+    /// Creates the repository for `name` through the registry at
+    /// `REGISTRY_ADDRESS`, which deploys it at an address derived from the
+    /// name and hands its roles to the signer.
+    #[instrument(err)]
+    pub async fn deploy_registered(name: &str) -> Result<Self> {
+        let signer = Signer::shared()?;
+        let rpc_urls = signer.rpc_urls();
+        let client = Web3::new(RetryTransport::from_env(FailoverTransport::from_env(&rpc_urls)));
+        let registry = registry_at(&client, &signer)?
+            .ok_or_else(|| anyhow::anyhow!("REGISTRY_ADDRESS is not set"))?;
+
+        let chain_id = client.eth().chain_id().await?.as_u64();
+        let address = registry.predict(name.to_string()).call().await?;
+        info!("Creating {} through registry {:?} at {:?} on chain {}", name, registry.address(), address, chain_id);
+
+        let tx = registry.create(name.to_string()).send().await?;
+        let confirmed = Confirmed::from_result(&tx);
+        let block_number = tx.as_receipt()
+            .and_then(|receipt| receipt.block_number)
+            .map(|block| block.as_u64());
+        info!("Created {} at {:?}, tx hash: {:?}, {}", name, address, confirmed.tx_hash, confirmed.costs);
+
+        let deployment = Deployment { tx_hash: confirmed.tx_hash, block_number, costs: confirmed.costs };
+        let contract = RepositoryContract::at(&client, address);
+        Ok(ContractInteraction { contract: with_signer(contract, &signer), client, signer, deployment: Some(deployment), chain_id: Some(chain_id) })
+    }
+
+    /// The contract the registry at `REGISTRY_ADDRESS` lists for `name`;
+    /// `None` without a registry or for unregistered names.
+    #[instrument(err)]
+    pub async fn resolve(name: &str) -> Result<Option<Self>> {
+        let signer = Signer::shared()?;
+        let client = shared_client(&signer);
+        let Some(registry) = registry_at(&client, &signer)? else {
+            return Ok(None);
+        };
+
+        let address = registry.resolve(name.to_string()).call().await?;
+        if address.is_zero() {
+            debug!("{} is not in registry {:?}", name, registry.address());
+            return Ok(None);
+        }
+
+        // Registry and repository live on the chain the endpoints are on.
+        let chain_id = client.eth().chain_id().await?.as_u64();
+        info!("Resolved {} to {:?} through registry {:?}", name, address, registry.address());
+        let contract = RepositoryContract::at(&client, address);
+        Ok(Some(ContractInteraction { contract: with_signer(contract, &signer), client, signer, deployment: None, chain_id: Some(chain_id) }))
+    }
+
+    /// Every repository the registry at `REGISTRY_ADDRESS` lists, by the
+    /// name it was registered under; empty without a registry. The registry
+    /// has no enumeration getter, so its `RepositoryRegistered` history is
+    /// replayed.
+    #[instrument(err)]
+    pub async fn registered() -> Result<Vec<(String, Self)>> {
+        let signer = Signer::shared()?;
+        let client = shared_client(&signer);
+        let Some(registry) = registry_at(&client, &signer)? else {
+            return Ok(Vec::new());
+        };
+
+        let events = registry
+            .events()
+            .repository_registered()
+            .from_block(BlockNumber::Earliest)
+            .query()
+            .await?;
+
+        let chain_id = client.eth().chain_id().await?.as_u64();
+        info!("Registry {:?} lists {} repositories", registry.address(), events.len());
+        Ok(events
+            .into_iter()
+            .map(|event| {
+                let contract = RepositoryContract::at(&client, event.data.repository);
+                let contract = ContractInteraction { contract: with_signer(contract, &signer), client: client.clone(), signer: signer.clone(), deployment: None, chain_id: Some(chain_id) };
+                (event.data.name, contract)
+            })
+            .collect())
+    }
+
+    /// This contract through a new client on the endpoints configured now,
+    /// for after [`Config::set_rpc_urls`] switched them. Address, signer and
+    /// deployment details stay as they are.
+    pub fn reconnect(&self) -> Self {
+        let client = Web3::new(RetryTransport::from_env(FailoverTransport::from_env(&self.signer.rpc_urls())));
+        let contract = RepositoryContract::at(&client, self.contract.address());
+        ContractInteraction {
+            contract: with_signer(contract, &self.signer),
+            client,
+            signer: self.signer.clone(),
+            deployment: self.deployment,
+            chain_id: self.chain_id,
+        }
+    }
+
+    /// Lists this contract under `name` in the registry at
+    /// `REGISTRY_ADDRESS`. The signer must be one of its admins.
+    #[instrument(skip(self), err)]
+    pub async fn register(&self, name: &str) -> Result<Confirmed> {
+        let registry = registry_at(&self.client, &self.signer)?
+            .ok_or_else(|| anyhow::anyhow!("REGISTRY_ADDRESS is not set"))?;
+
+        self.ensure_chain().await?;
+
+        let tx = registry.register(name.to_string(), self.contract.address()).send().await?;
+        info!("Registered {} as {} in registry {:?}, tx hash: {:?}", self.address(), name, registry.address(), tx.hash());
+        Ok(Confirmed::from_result(&tx))
     }
 
     pub fn address(&self) -> String {
@@ -81,11 +385,28 @@ impl ContractInteraction {
         address
     }
 
+    /// Fails with [`WrongChain`] when the RPC endpoints are on another chain
+    /// than the one the contract was deployed on, so nothing is sent to a
+    /// contract address that means something else there.
+    pub async fn ensure_chain(&self) -> Result<()> {
+        let Some(expected) = self.chain_id else {
+            return Ok(());
+        };
+        let actual = self.client.eth().chain_id().await?.as_u64();
+        if actual != expected {
+            error!("Contract {} is on chain {}, but the RPC endpoint is on chain {}", self.address(), expected, actual);
+            return Err(WrongChain { expected, actual }.into());
+        }
+        Ok(())
+    }
+
     #[instrument(skip(self, ipfs_url), fields(hash_len = hash.len(), ipfs_url_len = ipfs_url.len()), err)]
     pub async fn save_object(&self, hash: String, ipfs_url: Vec<u8>) -> Result<()> {
         info!("Saving object with hash: {}", hash);
         trace!("IPFS URL length: {} bytes", ipfs_url.len());
 
+        self.ensure_chain().await?;
+
         match self.contract
             .save_object(hash.clone(), Bytes(ipfs_url))
             .send()
@@ -106,6 +427,8 @@ impl ContractInteraction {
     pub async fn add_ref(&self, reference: String, data: Vec<u8>) -> Result<()> {
         info!("Adding ref: {}, data length: {} bytes", reference, data.len());
 
+        self.ensure_chain().await?;
+
         match self.contract
             .add_ref(reference.clone(), Bytes(data))
             .send()
@@ -122,10 +445,15 @@ impl ContractInteraction {
             }
     }
 
+    /// Replaces the repository config. The contract only lets admins do
+    /// this, since the config holds protection rules, signing keys,
+    /// visibility and quotas.
     #[instrument(skip(self, config), fields(config_len = config.len()), err)]
     pub async fn update_config(&self, config: Vec<u8>) -> Result<()> {
         info!("Updating contract config, data size: {} bytes", config.len());
 
+        self.ensure_chain().await?;
+
         match self.contract
             .update_config(Bytes(config))
             .send()
@@ -231,6 +559,50 @@ impl ContractInteraction {
             }
     }
 
+    #[instrument(skip(self), err)]
+    pub async fn is_object_active(&self, hash: String) -> Result<bool> {
+        debug!("Checking if object is active with hash: {}", hash);
+
+        match self.contract
+            .is_object_active(hash.clone())
+            .call()
+            .await {
+                Ok(active) => {
+                    debug!("Object check for hash {}: active = {}", hash, active);
+                    Ok(active)
+                },
+                Err(e) => {
+                    error!("Failed to check if object is active with hash {}: {}", hash, e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
+    /// Marks objects no active ref reaches as inactive. Their data is kept;
+    /// pushing an object again reactivates it.
+    #[instrument(skip(self, hashes), fields(count = hashes.len()), err)]
+    pub async fn deactivate_objects(&self, hashes: Vec<String>) -> Result<H256> {
+        info!("Deactivating {} objects", hashes.len());
+        trace!("Object hashes: {:?}", hashes);
+
+        self.ensure_chain().await?;
+
+        match self.contract
+            .deactivate_objects(hashes.clone())
+            .send()
+            .await {
+                Ok(tx) => {
+                    info!("Deactivated {} objects, tx hash: {:?}", hashes.len(), tx.hash());
+                    debug!("Transaction details: {:?}", tx);
+                    Ok(tx.hash())
+                },
+                Err(e) => {
+                    error!("Failed to deactivate objects: {}", e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
     #[instrument(skip(self), fields(hashes_count = hashes.len()), err)]
     pub async fn check_objects(&self, hashes: Vec<String>) -> Result<Vec<bool>> {
         info!("Checking existence of {} objects", hashes.len());
@@ -253,8 +625,14 @@ impl ContractInteraction {
             }
     }
 
+    /// Waits for a transaction sent through this client to be confirmed.
+    async fn confirm(&self, tx_hash: H256) -> Result<Confirmed> {
+        let receipt = wait_for_confirmation(&self.client, &self.signer.rpc_urls(), tx_hash).await?;
+        Ok(Confirmed { tx_hash, costs: Costs::from_receipt(&receipt) })
+    }
+
     #[instrument(skip(self, hashes, ipfs_urls), fields(count = hashes.len()), err)]
-    pub async fn add_objects(&self, hashes: Vec<String>, ipfs_urls: Vec<Vec<u8>>) -> Result<()> {
+    pub async fn add_objects(&self, hashes: Vec<String>, ipfs_urls: Vec<Vec<u8>>) -> Result<Confirmed> {
         info!("Adding batch of {} objects", hashes.len());
         trace!("Object hashes: {:?}", hashes);
 
@@ -268,6 +646,8 @@ impl ContractInteraction {
             .map(|e| Bytes(e.clone()))
             .collect::<Vec<Bytes<Vec<u8>>>>();
 
+        self.ensure_chain().await?;
+
         let max_retries = 3;
 
         for retry in 0..max_retries {
@@ -279,34 +659,19 @@ impl ContractInteraction {
 
             let tx_result = self.contract
                 .add_objects(hashes.clone(), bytes_ipfs_urls.clone())
+                .into_inner()
+                .resolve(ResolveCondition::Pending)
                 .send()
                 .await;
 
             match tx_result {
                 Ok(tx) => {
-                    info!("Successfully added {} objects, tx hash: {:?}", hashes.len(), tx.hash());
+                    info!("Sent {} objects, tx hash: {:?}", hashes.len(), tx.hash());
                     debug!("Transaction details: {:?}", tx);
 
-                    let receipt_result = self.client.eth().transaction_receipt(tx.hash()).await;
-
-                    match receipt_result {
-                        Ok(Some(receipt)) => {
-                            if receipt.status == Some(1.into()) {
-                                info!("Transaction confirmed with success status");
-                                return Ok(());
-                            } else {
-                                error!("Transaction failed with status: {:?}", receipt.status);
-                                // Continue to retry
-                            }
-                        },
-                        Ok(None) => {
-                            warn!("Transaction receipt not available yet, assuming success");
-                            return Ok(());
-                        },
-                        Err(e) => {
-                            error!("Failed to check transaction receipt: {}", e);
-                        }
-                    }
+                    // A sent transaction is never resent: a revert would fail
+                    // again, and after a timeout it may still be mined.
+                    return self.confirm(tx.hash()).await;
                 },
                 Err(e) => {
                     error!("Failed to add objects batch (attempt {}/{}): {}", retry + 1, max_retries, e);
@@ -329,7 +694,7 @@ impl ContractInteraction {
     }
 
     #[instrument(skip(self, references, data), fields(count = references.len()), err)]
-    pub async fn add_refs(&self, references: Vec<String>, data: Vec<Vec<u8>>) -> Result<()> {
+    pub async fn add_refs(&self, references: Vec<String>, data: Vec<Vec<u8>>) -> Result<Confirmed> {
         info!("Adding batch of {} refs", references.len());
         trace!("Ref names: {:?}", references);
 
@@ -343,6 +708,8 @@ impl ContractInteraction {
             .map(|e| Bytes(e.clone()))
             .collect::<Vec<Bytes<Vec<u8>>>>();
 
+        self.ensure_chain().await?;
+
         let max_retries = 3;
 
         for retry in 0..max_retries {
@@ -355,35 +722,19 @@ impl ContractInteraction {
             let tx_result = self.contract
                 .add_refs(references.clone(), bytes_data.clone())
                 .gas(4_000_000.into())
+                .into_inner()
+                .resolve(ResolveCondition::Pending)
                 .send()
                 .await;
 
             match tx_result {
                 Ok(tx) => {
-                    info!("Successfully added {} refs, tx hash: {:?}", references.len(), tx.hash());
+                    info!("Sent {} refs, tx hash: {:?}", references.len(), tx.hash());
                     debug!("Transaction details: {:?}", tx);
 
-                    let receipt_result = self.client.eth().transaction_receipt(tx.hash()).await;
-
-                    match receipt_result {
-                        Ok(Some(receipt)) => {
-                            if receipt.status == Some(1.into()) {
-                                info!("Transaction confirmed with success status");
-                                return Ok(());
-                            } else {
-                                error!("Transaction failed with status: {:?}", receipt.status);
-                                // Continue to retry
-                            }
-                        },
-                        Ok(None) => {
-                            warn!("Transaction receipt not available yet, assuming success");
-                            return Ok(());
-                        },
-                        Err(e) => {
-                            error!("Failed to check transaction receipt: {}", e);
-                            // Continue to retry
-                        }
-                    }
+                    // A sent transaction is never resent: a revert would fail
+                    // again, and after a timeout it may still be mined.
+                    return self.confirm(tx.hash()).await;
                 },
                 Err(e) => {
                     error!("Failed to add refs batch (attempt {}/{}): {}", retry + 1, max_retries, e);
@@ -405,6 +756,34 @@ impl ContractInteraction {
         Err(anyhow::anyhow!("Failed to add refs after {} retries", max_retries))
     }
 
+    /// Marks deleted refs inactive, so they drop out of the repository's
+    /// refs. Pushing a ref again adds it anew.
+    #[instrument(skip(self, references), fields(count = references.len()), err)]
+    pub async fn deactivate_refs(&self, references: Vec<String>) -> Result<Confirmed> {
+        info!("Deactivating {} refs", references.len());
+        trace!("Ref names: {:?}", references);
+
+        self.ensure_chain().await?;
+
+        match self.contract
+            .deactivate_refs(references.clone())
+            .gas(4_000_000.into())
+            .into_inner()
+            .resolve(ResolveCondition::Pending)
+            .send()
+            .await {
+                Ok(tx) => {
+                    info!("Sent deactivation of {} refs, tx hash: {:?}", references.len(), tx.hash());
+                    debug!("Transaction details: {:?}", tx);
+                    self.confirm(tx.hash()).await
+                },
+                Err(e) => {
+                    error!("Failed to deactivate refs: {}", e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
     #[instrument(skip(self), err)]
     pub async fn get_objects(&self) -> Result<Vec<Object>> {
         info!("Retrieving all objects");
@@ -527,10 +906,103 @@ impl ContractInteraction {
             }
     }
 
+    /// Objects `offset..offset + limit`, read by index so no single call
+    /// returns more than one entry.
+    #[instrument(skip(self), err)]
+    pub async fn get_objects_paged(&self, offset: usize, limit: usize) -> Result<Vec<Object>> {
+        let length = self.get_objects_length().await?.as_usize();
+        let ids = offset.min(length)..length.min(offset.saturating_add(limit));
+        self.read_by_id(ids, |contract, id| async move { contract.get_object_by_id(id).await }).await
+    }
+
+    /// Ref entries `offset..offset + limit`, read by index.
     #[instrument(skip(self), err)]
-    pub async fn grant_pusher_role(&self, address: Address) -> Result<()> {
+    pub async fn get_refs_paged(&self, offset: usize, limit: usize) -> Result<Vec<Ref>> {
+        let length = self.get_refs_length().await?.as_usize();
+        let ids = offset.min(length)..length.min(offset.saturating_add(limit));
+        self.read_by_id(ids, |contract, id| async move { contract.get_ref_by_id(id).await }).await
+    }
+
+    /// Every object, `chunk_size` at a time.
+    #[instrument(skip(self), err)]
+    pub async fn get_all_objects_chunked(&self, chunk_size: usize) -> Result<Vec<Object>> {
+        let length = self.get_objects_length().await?.as_usize();
+        let mut objects = Vec::with_capacity(length);
+        for start in (0..length).step_by(chunk_size.max(1)) {
+            let ids = start..length.min(start + chunk_size.max(1));
+            objects.extend(self.read_by_id(ids, |contract, id| async move { contract.get_object_by_id(id).await }).await?);
+        }
+        Ok(objects)
+    }
+
+    /// Every ref entry, `chunk_size` at a time.
+    #[instrument(skip(self), err)]
+    pub async fn get_all_refs_chunked(&self, chunk_size: usize) -> Result<Vec<Ref>> {
+        let length = self.get_refs_length().await?.as_usize();
+        let mut refs = Vec::with_capacity(length);
+        for start in (0..length).step_by(chunk_size.max(1)) {
+            let ids = start..length.min(start + chunk_size.max(1));
+            refs.extend(self.read_by_id(ids, |contract, id| async move { contract.get_ref_by_id(id).await }).await?);
+        }
+        Ok(refs)
+    }
+
+    /// Every object: in one `getObjects` call while the list is at most
+    /// `RPC_PAGED_READ_THRESHOLD` long, in pages of `RPC_PAGE_SIZE` beyond,
+    /// since some providers cap the size of a call's response.
+    pub async fn get_all_objects(&self) -> Result<Vec<Object>> {
+        let length = self.get_objects_length().await?.as_usize();
+        if length <= Config::paged_read_threshold() {
+            return self.get_objects().await;
+        }
+        self.get_all_objects_chunked(Config::page_size()).await
+    }
+
+    /// Every ref entry, paged like [`Self::get_all_objects`].
+    pub async fn get_all_refs(&self) -> Result<Vec<Ref>> {
+        let length = self.get_refs_length().await?.as_usize();
+        if length <= Config::paged_read_threshold() {
+            return self.get_refs().await;
+        }
+        self.get_all_refs_chunked(Config::page_size()).await
+    }
+
+    /// Runs `read` for each index in `ids` with at most
+    /// `RPC_PAGE_CONCURRENCY` calls in flight, returning results in order.
+    async fn read_by_id<T, F, Fut>(&self, ids: Range<usize>, read: F) -> Result<Vec<T>>
+    where
+        T: Send + 'static,
+        F: Fn(ContractInteraction, U256) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let semaphore = Arc::new(Semaphore::new(Config::page_concurrency().max(1)));
+        let mut reads = JoinSet::new();
+        for id in ids.clone() {
+            let permit = Arc::clone(&semaphore).acquire_owned().await?;
+            let read = read(self.clone(), U256::from(id));
+            reads.spawn(async move {
+                let _permit = permit;
+                (id, read.await)
+            });
+        }
+
+        let mut results = Vec::with_capacity(ids.len());
+        while let Some(joined) = reads.join_next().await {
+            let (id, result) = joined?;
+            results.push((id, result?));
+        }
+        results.sort_by_key(|(id, _)| *id);
+
+        debug!("Read {} entries by index", results.len());
+        Ok(results.into_iter().map(|(_, item)| item).collect())
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn grant_pusher_role(&self, address: Address) -> Result<Confirmed> {
         info!("Granting pusher role to address: {}", address);
 
+        self.ensure_chain().await?;
+
         match self.contract
             .grant_pusher_role(address)
             .send()
@@ -538,7 +1010,7 @@ impl ContractInteraction {
                 Ok(tx) => {
                     info!("Pusher role granted successfully, tx hash: {:?}", tx.hash());
                     debug!("Transaction details: {:?}", tx);
-                    Ok(())
+                    Ok(Confirmed::from_result(&tx))
                 },
                 Err(e) => {
                     error!("Failed to grant pusher role to address {}: {}", address, e);
@@ -548,9 +1020,11 @@ impl ContractInteraction {
     }
 
     #[instrument(skip(self), err)]
-    pub async fn revoke_pusher_role(&self, address: Address) -> Result<()> {
+    pub async fn revoke_pusher_role(&self, address: Address) -> Result<Confirmed> {
         info!("Revoking pusher role from address: {}", address);
 
+        self.ensure_chain().await?;
+
         match self.contract
             .revoke_pusher_role(address)
             .send()
@@ -558,7 +1032,7 @@ impl ContractInteraction {
                 Ok(tx) => {
                     info!("Pusher role revoked successfully, tx hash: {:?}", tx.hash());
                     debug!("Transaction details: {:?}", tx);
-                    Ok(())
+                    Ok(Confirmed::from_result(&tx))
                 },
                 Err(e) => {
                     error!("Failed to revoke pusher role from address {}: {}", address, e);
@@ -568,9 +1042,11 @@ impl ContractInteraction {
     }
 
     #[instrument(skip(self), err)]
-    pub async fn grant_admin_role(&self, address: Address) -> Result<()> {
+    pub async fn grant_admin_role(&self, address: Address) -> Result<Confirmed> {
         info!("Granting admin role to address: {}", address);
 
+        self.ensure_chain().await?;
+
         match self.contract
             .grant_admin_role(address)
             .send()
@@ -578,7 +1054,7 @@ impl ContractInteraction {
                 Ok(tx) => {
                     info!("Admin role granted successfully, tx hash: {:?}", tx.hash());
                     debug!("Transaction details: {:?}", tx);
-                    Ok(())
+                    Ok(Confirmed::from_result(&tx))
                 },
                 Err(e) => {
                     error!("Failed to grant admin role to address {}: {}", address, e);
@@ -588,9 +1064,11 @@ impl ContractInteraction {
     }
 
     #[instrument(skip(self), err)]
-    pub async fn revoke_admin_role(&self, address: Address) -> Result<()> {
+    pub async fn revoke_admin_role(&self, address: Address) -> Result<Confirmed> {
         info!("Revoking admin role from address: {}", address);
 
+        self.ensure_chain().await?;
+
         match self.contract
             .revoke_admin_role(address)
             .send()
@@ -598,7 +1076,7 @@ impl ContractInteraction {
                 Ok(tx) => {
                     info!("Admin role revoked successfully, tx hash: {:?}", tx.hash());
                     debug!("Transaction details: {:?}", tx);
-                    Ok(())
+                    Ok(Confirmed::from_result(&tx))
                 },
                 Err(e) => {
                     error!("Failed to revoke admin role from address {}: {}", address, e);
@@ -644,4 +1122,220 @@ impl ContractInteraction {
                 }
             }
     }
+
+    #[instrument(skip(self), err)]
+    pub async fn grant_reader_role(&self, address: Address) -> Result<Confirmed> {
+        info!("Granting reader role to address: {}", address);
+
+        self.ensure_chain().await?;
+
+        match self.contract
+            .grant_reader_role(address)
+            .send()
+            .await {
+                Ok(tx) => {
+                    info!("Reader role granted successfully, tx hash: {:?}", tx.hash());
+                    debug!("Transaction details: {:?}", tx);
+                    Ok(Confirmed::from_result(&tx))
+                },
+                Err(e) => {
+                    error!("Failed to grant reader role to address {}: {}", address, e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn revoke_reader_role(&self, address: Address) -> Result<Confirmed> {
+        info!("Revoking reader role from address: {}", address);
+
+        self.ensure_chain().await?;
+
+        match self.contract
+            .revoke_reader_role(address)
+            .send()
+            .await {
+                Ok(tx) => {
+                    info!("Reader role revoked successfully, tx hash: {:?}", tx.hash());
+                    debug!("Transaction details: {:?}", tx);
+                    Ok(Confirmed::from_result(&tx))
+                },
+                Err(e) => {
+                    error!("Failed to revoke reader role from address {}: {}", address, e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn has_reader_role(&self, address: Address) -> Result<bool> {
+        debug!("Checking if address {} has reader role", address);
+
+        match self.contract
+            .has_reader_role(address)
+            .call()
+            .await {
+                Ok(has_role) => {
+                    info!("Address {} has reader role: {}", address, has_role);
+                    Ok(has_role)
+                },
+                Err(e) => {
+                    error!("Failed to check if address {} has reader role: {}", address, e);
+                    Err(anyhow::Error::from(e))
+                }
+            }
+    }
+
+    pub async fn get_admins(&self) -> Result<Vec<Address>> {
+        self.get_role_members(DEFAULT_ADMIN_ROLE).await
+    }
+
+    pub async fn get_pushers(&self) -> Result<Vec<Address>> {
+        self.get_role_members(signing::keccak256(b"PUSHER_ROLE")).await
+    }
+
+    pub async fn get_readers(&self) -> Result<Vec<Address>> {
+        self.get_role_members(signing::keccak256(b"READER_ROLE")).await
+    }
+
+    /// Rebuilds the current holders of `role` by replaying the contract's
+    /// `RoleGranted`/`RoleRevoked` history, since `AccessControl` has no
+    /// enumeration getters. Members are returned in the order they were granted.
+    #[instrument(skip(self), err)]
+    async fn get_role_members(&self, role: [u8; 32]) -> Result<Vec<Address>> {
+        debug!("Querying role events for role 0x{}", role.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+
+        let mut events = match self.contract
+            .all_events()
+            .from_block(BlockNumber::Earliest)
+            .query()
+            .await {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Failed to query role events: {}", e);
+                    return Err(anyhow::Error::from(e));
+                }
+            };
+
+        // Grant and revoke must be applied in chain order or a later revoke
+        // could be undone by an earlier grant.
+        events.sort_by_key(|event| {
+            event.meta.as_ref().map(|meta| (meta.block_number, meta.log_index))
+        });
+
+        let mut members: Vec<Address> = Vec::new();
+        for event in events {
+            match event.data {
+                repository_contract::Event::RoleGranted(granted) if granted.role.0 == role => {
+                    if !members.contains(&granted.account) {
+                        members.push(granted.account);
+                    }
+                },
+                repository_contract::Event::RoleRevoked(revoked) if revoked.role.0 == role => {
+                    members.retain(|member| *member != revoked.account);
+                },
+                _ => {},
+            }
+        }
+
+        info!("Found {} role members", members.len());
+        Ok(members)
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn block_number(&self) -> Result<u64> {
+        let block = self.client.eth().block_number().await?;
+        Ok(block.as_u64())
+    }
+
+    /// Object and ref changes mined in blocks `from..=to`, in chain order.
+    #[instrument(skip(self), err)]
+    pub async fn get_repo_events(&self, from: u64, to: u64) -> Result<Vec<RepoEvent>> {
+        let changes = self.query_changes(from, to).await?;
+        Ok(changes.into_iter().map(|change| change.event).collect())
+    }
+
+    /// Like [`get_repo_events`](Self::get_repo_events), with the block and
+    /// transaction of each change. Ranges wider than `SYNC_BLOCK_RANGE` are
+    /// queried a chunk at a time, since nodes cap how many blocks one log
+    /// query may span.
+    #[instrument(skip(self), err)]
+    pub async fn get_repo_changes(&self, from: u64, to: u64) -> Result<Vec<RepoChange>> {
+        let chunk = Config::sync_block_range().max(1);
+        let mut changes = Vec::new();
+        let mut start = from;
+        while start <= to {
+            let end = start.saturating_add(chunk - 1).min(to);
+            changes.extend(self.query_changes(start, end).await?);
+            match end.checked_add(1) {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        Ok(changes)
+    }
+
+    async fn query_changes(&self, from: u64, to: u64) -> Result<Vec<RepoChange>> {
+        debug!("Querying repository events in blocks {}..={}", from, to);
+
+        let mut events = match self.contract
+            .all_events()
+            .from_block(BlockNumber::Number(from.into()))
+            .to_block(BlockNumber::Number(to.into()))
+            .query()
+            .await {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Failed to query repository events: {}", e);
+                    return Err(anyhow::Error::from(e));
+                }
+            };
+
+        events.sort_by_key(|event| {
+            event.meta.as_ref().map(|meta| (meta.block_number, meta.log_index))
+        });
+
+        let changes: Vec<RepoChange> = events
+            .into_iter()
+            .filter_map(|event| {
+                let repo_event = match event.data {
+                    repository_contract::Event::ObjectSaved(saved) => RepoEvent::ObjectSaved(Object {
+                        hash: saved.hash,
+                        ipfs_url: saved.ipfs_url.0,
+                        pusher: saved.pusher,
+                    }),
+                    repository_contract::Event::RefAdded(added) => RepoEvent::RefAdded(Ref {
+                        name: added.ref_,
+                        data: added.ipfs_url.0,
+                        is_active: true,
+                        pusher: added.pusher,
+                    }),
+                    repository_contract::Event::RefDeactivated(deactivated) => RepoEvent::RefDeactivated {
+                        name: deactivated.ref_,
+                        pusher: deactivated.pusher,
+                    },
+                    _ => return None,
+                };
+                Some(RepoChange {
+                    block_number: event.meta.as_ref().map(|meta| meta.block_number).unwrap_or_default(),
+                    tx_hash: event.meta.as_ref().map(|meta| meta.transaction_hash).unwrap_or_default(),
+                    event: repo_event,
+                })
+            })
+            .collect();
+
+        debug!("Found {} repository events", changes.len());
+        Ok(changes)
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn get_repo_config(&self) -> Result<RepoConfig> {
+        let data = self.get_config().await?;
+        RepoConfig::from_bytes(&data)
+    }
+
+    #[instrument(skip(self, config), err)]
+    pub async fn update_repo_config(&self, config: &RepoConfig) -> Result<()> {
+        self.update_config(config.to_bytes()?).await
+    }
 }
@@ -0,0 +1,62 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ethcontract::web3::{signing, transports::Http, types::{Bytes, CallRequest}, Web3};
+use ethcontract::{Address, H256};
+use std::str::FromStr;
+
+/// The ENS registry, at the same address on mainnet and its testnets.
+const ENS_REGISTRY: &str = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+/// Whether `name` looks like an ENS name rather than an address or alias.
+pub fn is_ens_name(name: &str) -> bool {
+    name.ends_with(".eth") && name.len() > ".eth".len()
+}
+
+/// The ENS node of `name` (EIP-137 namehash).
+pub fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    for label in name.rsplit('.').filter(|label| !label.is_empty()) {
+        let mut joined = node.to_vec();
+        joined.extend_from_slice(&signing::keccak256(label.to_lowercase().as_bytes()));
+        node = signing::keccak256(&joined);
+    }
+    node
+}
+
+/// The address `name` resolves to, looked up through the node at `rpc_url`:
+/// the registry gives the name's resolver, and the resolver its address.
+pub async fn resolve(rpc_url: &str, name: &str) -> Result<Address> {
+    let transport = Http::new(rpc_url).with_context(|| format!("Invalid RPC URL '{}'", rpc_url))?;
+    let web3 = Web3::new(transport);
+    let node = namehash(name);
+
+    let registry = Address::from_str(ENS_REGISTRY).map_err(|e| anyhow!("Invalid ENS registry address: {}", e))?;
+    let resolver = call_address(&web3, registry, "resolver(bytes32)", node).await
+        .with_context(|| format!("Failed to look up the resolver of {}", name))?;
+    if resolver.is_zero() {
+        bail!("{} is not registered, or has no resolver", name);
+    }
+
+    let address = call_address(&web3, resolver, "addr(bytes32)", node).await
+        .with_context(|| format!("Failed to resolve {}", name))?;
+    if address.is_zero() {
+        bail!("{} does not resolve to an address", name);
+    }
+    Ok(address)
+}
+
+/// Calls `signature` on `to` with `node` and reads the returned address.
+async fn call_address(web3: &Web3<Http>, to: Address, signature: &str, node: [u8; 32]) -> Result<Address> {
+    let mut data = signing::keccak256(signature.as_bytes())[..4].to_vec();
+    data.extend_from_slice(&node);
+
+    let request = CallRequest {
+        to: Some(to),
+        data: Some(Bytes(data)),
+        ..Default::default()
+    };
+    let result = web3.eth().call(request, None).await?;
+    if result.0.len() < 32 {
+        bail!("Unexpected response of {} bytes", result.0.len());
+    }
+    Ok(Address::from(H256::from_slice(&result.0[..32])))
+}
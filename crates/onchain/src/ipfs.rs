@@ -1,13 +1,177 @@
 use crate::config::Config;
 use anyhow::{bail, Result};
 use reqwest::multipart::{Form, Part};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::Deserialize;
+use std::fmt;
 use std::path::Path;
 use tokio::fs::{create_dir_all, File, read};
 use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, error, instrument, warn};
 
+/// Why an IPFS operation failed, so callers can tell the user whether the
+/// daemon is unreachable, misconfigured, or returning bad data.
+#[derive(Debug)]
+pub enum IpfsError {
+    ConnectionRefused { url: String },
+    Timeout { url: String },
+    Unauthorized { url: String, status: u16 },
+    BadResponse { url: String, message: String },
+    VerificationFailed { cid: String, reason: String },
+    Io { path: String, source: std::io::Error },
+    Misconfigured { message: String },
+}
+
+impl fmt::Display for IpfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpfsError::ConnectionRefused { url } => write!(
+                f, "IPFS node at {} is unreachable. Make sure your IPFS daemon is running", url
+            ),
+            IpfsError::Timeout { url } => write!(
+                f, "Request to IPFS node at {} timed out. Is your IPFS daemon running?", url
+            ),
+            IpfsError::Unauthorized { url, status } => write!(
+                f, "IPFS node at {} rejected the request with status {}. Check the configured credentials", url, status
+            ),
+            IpfsError::BadResponse { url, message } => write!(
+                f, "IPFS node at {} returned an invalid response: {}", url, message
+            ),
+            IpfsError::VerificationFailed { cid, reason } => write!(
+                f, "Content for CID {} failed verification: {}", cid, reason
+            ),
+            IpfsError::Io { path, source } => write!(f, "Failed to access {}: {}", path, source),
+            IpfsError::Misconfigured { message } => write!(f, "IPFS backend is misconfigured: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for IpfsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IpfsError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl IpfsError {
+    fn from_request(err: reqwest::Error, url: &str) -> Self {
+        if err.is_timeout() {
+            IpfsError::Timeout { url: url.to_string() }
+        } else if err.is_connect() {
+            IpfsError::ConnectionRefused { url: url.to_string() }
+        } else {
+            IpfsError::BadResponse { url: url.to_string(), message: err.to_string() }
+        }
+    }
+
+    fn from_status(status: StatusCode, url: &str, body: &str) -> Self {
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            IpfsError::Unauthorized { url: url.to_string(), status: status.as_u16() }
+        } else {
+            IpfsError::BadResponse { url: url.to_string(), message: format!("status {}: {}", status, body) }
+        }
+    }
+
+    fn io(path: &str, source: std::io::Error) -> Self {
+        IpfsError::Io { path: path.to_string(), source }
+    }
+}
+
+const PINATA_PIN_URL: &str = "https://api.pinata.cloud/pinning/pinFileToIPFS";
+const PINATA_GATEWAY: &str = "https://gateway.pinata.cloud/ipfs/";
+const WEB3_STORAGE_UPLOAD_URL: &str = "https://api.web3.storage/upload";
+const WEB3_STORAGE_GATEWAY: &str = "https://w3s.link/ipfs/";
+
+/// Service objects are pinned to, selected with
+/// `IPFS_BACKEND=kubo|pinata|web3storage`.
+///
+/// Kubo talks to a local (or `IPFS_API_URL`) daemon; the remote backends use
+/// the pinning service's HTTP API with `IPFS_API_TOKEN` as a bearer token and
+/// read content back through a public gateway.
+#[derive(Debug, Clone)]
+pub enum IpfsBackend {
+    Kubo { api_url: String },
+    Pinata { token: String, gateway: String },
+    Web3Storage { token: String, gateway: String },
+}
+
+impl IpfsBackend {
+    pub fn from_env() -> Result<Self, IpfsError> {
+        let backend = Config::ipfs_backend();
+        let gateway = |default: &str| {
+            let prefix = Config::ipfs_prefix();
+            if prefix.is_empty() { default.to_string() } else { prefix }
+        };
+        let token = || {
+            Config::ipfs_api_token().ok_or_else(|| IpfsError::Misconfigured {
+                message: format!("IPFS_BACKEND={} requires IPFS_API_TOKEN", backend),
+            })
+        };
+
+        match backend.as_str() {
+            "kubo" => Ok(IpfsBackend::Kubo {
+                api_url: Config::ipfs_api_url().unwrap_or_else(|| "http://127.0.0.1:5001".to_string()),
+            }),
+            "pinata" => Ok(IpfsBackend::Pinata { token: token()?, gateway: gateway(PINATA_GATEWAY) }),
+            "web3storage" => Ok(IpfsBackend::Web3Storage { token: token()?, gateway: gateway(WEB3_STORAGE_GATEWAY) }),
+            other => Err(IpfsError::Misconfigured {
+                message: format!("unknown IPFS_BACKEND '{}', expected kubo, pinata or web3storage", other),
+            }),
+        }
+    }
+
+    /// Endpoint reported in errors and logs.
+    fn api_url(&self) -> &str {
+        match self {
+            IpfsBackend::Kubo { api_url } => api_url,
+            IpfsBackend::Pinata { .. } => PINATA_PIN_URL,
+            IpfsBackend::Web3Storage { .. } => WEB3_STORAGE_UPLOAD_URL,
+        }
+    }
+
+    /// Gateway prefix content can be fetched from by CID, if any.
+    fn gateway(&self) -> Option<String> {
+        match self {
+            IpfsBackend::Kubo { .. } => {
+                let prefix = Config::ipfs_prefix();
+                (!prefix.is_empty()).then_some(prefix)
+            },
+            IpfsBackend::Pinata { gateway, .. } | IpfsBackend::Web3Storage { gateway, .. } => {
+                Some(gateway.clone())
+            },
+        }
+    }
+}
+
+/// Strips URI and path forms (`ipfs://`, `/ipfs/`, gateway URLs) so the same
+/// object is recorded on-chain identically whichever backend pinned it.
+pub fn normalize_cid(cid: &str) -> String {
+    let cid = cid.trim();
+    let cid = match cid.rfind("/ipfs/") {
+        Some(pos) => &cid[pos + "/ipfs/".len()..],
+        None => cid.strip_prefix("ipfs://").unwrap_or(cid),
+    };
+
+    cid.trim_matches('/')
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct PinataPinResponse {
+    #[serde(rename = "IpfsHash")]
+    ipfs_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Web3StorageUploadResponse {
+    cid: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct IPFSAddResponse {
     #[allow(dead_code)]
@@ -41,18 +205,47 @@ fn extract_git_object(content: &[u8]) -> Result<(String, Vec<u8>)> {
     }
 }
 
+/// Checks that a freshly uploaded `cid` can be fetched from `gateway`,
+/// retrying while it propagates. Only logs: the upload itself succeeded.
+async fn verify_upload(client: &Client, gateway: &str, cid: &str) {
+    debug!("Verifying content is retrievable from gateway: {}", gateway);
+    let verification_url = format!("{}{}", gateway, cid);
+    let attempts = Config::ipfs_verify_retries() + 1;
+    let delay = std::time::Duration::from_millis(Config::ipfs_verify_delay_ms());
+
+    for attempt in 1..=attempts {
+        let outcome = match client.head(&verification_url).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                info!("CID {} verified as retrievable from gateway", cid);
+                return;
+            }
+            Ok(resp) => format!("gateway returned status code {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if attempt == attempts {
+            warn!("Could not verify CID {} after {} attempts: {}", cid, attempts, outcome);
+            warn!("Content may not be immediately retrievable, may need time to propagate");
+            return;
+        }
+
+        debug!("CID {} not retrievable yet (attempt {}/{}): {}", cid, attempt, attempts, outcome);
+        tokio::time::sleep(delay).await;
+    }
+}
+
 #[instrument(skip_all, fields(file_path = file_path), err)]
-pub async fn load_to_ipfs(file_path: &str) -> Result<String> {
-    info!("Loading file to local IPFS daemon: {}", file_path);
+pub async fn load_to_ipfs(file_path: &str) -> Result<String, IpfsError> {
+    info!("Loading file to IPFS: {}", file_path);
 
-    let ipfs_api = Config::ipfs_api_url().unwrap_or_else(|| "http://127.0.0.1:5001".to_string());
-    debug!("Using IPFS API URL: {}", ipfs_api);
+    let backend = IpfsBackend::from_env()?;
+    debug!("Using IPFS backend: {:?}", backend.api_url());
 
     let content = match read(file_path).await {
         Ok(content) => content,
         Err(e) => {
             error!("Failed to read file {}: {}", file_path, e);
-            bail!("Failed to read file: {}", e);
+            return Err(IpfsError::io(file_path, e));
         }
     };
     debug!("Read file content, size: {} bytes", content.len());
@@ -62,38 +255,31 @@ pub async fn load_to_ipfs(file_path: &str) -> Result<String> {
         .and_then(|n| n.to_str())
         .unwrap_or("git_object");
 
+    add_to_ipfs(&backend, &content, filename).await
+}
+
+/// Uploads and pins `content` on `backend` as `filename`, retrying
+/// failures, and returns its CID.
+pub async fn add_to_ipfs(backend: &IpfsBackend, content: &[u8], filename: &str) -> Result<String, IpfsError> {
     debug!("Using filename for upload: {}", filename);
 
     let client = Client::builder()
         .timeout(std::time::Duration::from_secs(30))
         .connect_timeout(std::time::Duration::from_secs(5))
-        .build()?;
+        .build()
+        .map_err(|e| IpfsError::from_request(e, backend.api_url()))?;
 
-    for attempt in 1..=3 {
-        info!("Uploading to local IPFS daemon (attempt {}/3)", attempt);
+    let mut attempt = 1;
+    loop {
+        info!("Uploading to IPFS (attempt {}/3)", attempt);
 
-        match upload_to_ipfs(&client, &ipfs_api, &content, filename).await {
+        match upload_to_ipfs(&client, backend, content, filename).await {
             Ok(cid) => {
                 info!("Successfully uploaded file to IPFS, CID: {}", cid);
 
-                let gateway = Config::ipfs_prefix();
-                if !gateway.is_empty() {
-                    debug!("Verifying content is retrievable from gateway: {}", gateway);
-                    let verification_url = format!("{}{}", gateway, cid);
-
-                    match client.head(&verification_url).send().await {
-                        Ok(resp) => {
-                            if resp.status().is_success() {
-                                info!("CID {} verified as retrievable from gateway", cid);
-                            } else {
-                                warn!("CID {} returned status code {} from gateway", cid, resp.status());
-                                warn!("Content may not be immediately retrievable, may need time to propagate");
-                            }
-                        },
-                        Err(e) => {
-                            warn!("Failed to verify CID availability: {}", e);
-                            warn!("Content may not be immediately retrievable, may need time to propagate");
-                        }
+                if Config::ipfs_verify_upload() {
+                    if let Some(gateway) = backend.gateway() {
+                        verify_upload(&client, &gateway, &cid).await;
                     }
                 }
 
@@ -102,7 +288,7 @@ pub async fn load_to_ipfs(file_path: &str) -> Result<String> {
             Err(e) => {
                 if attempt == 3 {
                     error!("All upload attempts failed. Last error: {}", e);
-                    bail!("Failed to upload file to IPFS after 3 attempts: {}", e);
+                    return Err(e);
                 }
 
                 warn!("Upload attempt {} failed: {}. Retrying...", attempt, e);
@@ -111,13 +297,13 @@ pub async fn load_to_ipfs(file_path: &str) -> Result<String> {
                 tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
             }
         }
-    }
 
-    bail!("Failed to upload to IPFS after maximum retries");
+        attempt += 1;
+    }
 }
 
-async fn upload_to_ipfs(client: &Client, ipfs_api: &str, content: &[u8], filename: &str) -> Result<String> {
-    debug!("Uploading to IPFS daemon with filename: {}", filename);
+async fn upload_to_ipfs(client: &Client, backend: &IpfsBackend, content: &[u8], filename: &str) -> Result<String, IpfsError> {
+    debug!("Uploading to IPFS with filename: {}", filename);
 
     let upload_content = if content.len() > 10 {
         if let Ok((obj_type, _)) = extract_git_object(content) {
@@ -130,32 +316,63 @@ async fn upload_to_ipfs(client: &Client, ipfs_api: &str, content: &[u8], filenam
         content.to_vec()
     };
 
+    let cid = match backend {
+        IpfsBackend::Kubo { api_url } => {
+            let upload_url = format!("{}/api/v0/add?pin=true&raw-leaves=true", api_url);
+            let form = file_form(upload_content, filename, &upload_url)?;
+            let body = send_upload(client.post(&upload_url).multipart(form), api_url).await?;
+            let response: IPFSAddResponse = parse_upload_response(&body, api_url)?;
+            response.hash
+        },
+        IpfsBackend::Pinata { token, .. } => {
+            let form = file_form(upload_content, filename, PINATA_PIN_URL)?;
+            let body = send_upload(client.post(PINATA_PIN_URL).bearer_auth(token).multipart(form), PINATA_PIN_URL).await?;
+            let response: PinataPinResponse = parse_upload_response(&body, PINATA_PIN_URL)?;
+            response.ipfs_hash
+        },
+        IpfsBackend::Web3Storage { token, .. } => {
+            let request = client
+                .post(WEB3_STORAGE_UPLOAD_URL)
+                .bearer_auth(token)
+                .header("X-Name", filename)
+                .body(upload_content);
+            let body = send_upload(request, WEB3_STORAGE_UPLOAD_URL).await?;
+            let response: Web3StorageUploadResponse = parse_upload_response(&body, WEB3_STORAGE_UPLOAD_URL)?;
+            response.cid
+        },
+    };
+
+    let cid = normalize_cid(&cid);
+    if cid.is_empty() {
+        error!("Empty hash received from IPFS");
+        return Err(IpfsError::BadResponse {
+            url: backend.api_url().to_string(),
+            message: "no hash returned".to_string(),
+        });
+    }
+
+    debug!("Successfully extracted CID from response: {}", cid);
+    Ok(cid)
+}
+
+fn file_form(content: Vec<u8>, filename: &str, url: &str) -> Result<Form, IpfsError> {
     // Important: Don't modify Git object binary format
-    let file_part = Part::bytes(upload_content)
+    let file_part = Part::bytes(content)
         .file_name(filename.to_owned())
-        .mime_str("application/octet-stream")?;
+        .mime_str("application/octet-stream")
+        .map_err(|e| IpfsError::from_request(e, url))?;
 
-    let upload_url = format!("{}/api/v0/add?pin=true&raw-leaves=true", ipfs_api);
-    debug!("Sending POST request to IPFS API: {}", upload_url);
+    Ok(Form::new().part("file", file_part))
+}
 
-    let form = Form::new().part("file", file_part);
+async fn send_upload(request: reqwest::RequestBuilder, url: &str) -> Result<String, IpfsError> {
+    debug!("Sending upload request to IPFS API: {}", url);
 
-    let resp = match client
-        .post(&upload_url)
-        .multipart(form)
-        .send()
-        .await 
-    {
+    let resp = match request.send().await {
         Ok(response) => response,
         Err(e) => {
             error!("Failed to send request to IPFS: {}", e);
-            if e.is_timeout() {
-                bail!("Request to IPFS timed out. Is your IPFS daemon running?");
-            } else if e.is_connect() {
-                bail!("Connection error to IPFS API. Make sure your IPFS daemon is running at {}", ipfs_api);
-            } else {
-                bail!("Failed to send request to IPFS: {}", e);
-            }
+            return Err(IpfsError::from_request(e, url));
         }
     };
 
@@ -164,7 +381,7 @@ async fn upload_to_ipfs(client: &Client, ipfs_api: &str, content: &[u8], filenam
         Ok(text) => text,
         Err(e) => {
             error!("Failed to get response text: {}", e);
-            bail!("Failed to parse IPFS response: {}", e);
+            return Err(IpfsError::from_request(e, url));
         }
     };
 
@@ -172,29 +389,222 @@ async fn upload_to_ipfs(client: &Client, ipfs_api: &str, content: &[u8], filenam
 
     if !status.is_success() {
         error!("IPFS upload failed with status: {}", status);
-        bail!("Failed to upload to IPFS: {}", resp_text);
+        return Err(IpfsError::from_status(status, url, &resp_text));
     }
 
-    match serde_json::from_str::<IPFSAddResponse>(&resp_text) {
-        Ok(response) => {
-            if !response.hash.is_empty() {
-                debug!("Successfully extracted CID from response: {}", response.hash);
-                return Ok(response.hash);
-            }
+    Ok(resp_text)
+}
+
+fn parse_upload_response<T: serde::de::DeserializeOwned>(body: &str, url: &str) -> Result<T, IpfsError> {
+    serde_json::from_str(body).map_err(|e| {
+        error!("Failed to parse IPFS response as JSON: {}", e);
+        error!("Response body: {}", body);
+        IpfsError::BadResponse {
+            url: url.to_string(),
+            message: format!("failed to parse response: {}", e),
+        }
+    })
+}
+
+async fn fetch_from_source(request: reqwest::RequestBuilder, url: &str, source: &str) -> Result<Vec<u8>, IpfsError> {
+    let resp = match request.send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            warn!("Failed to download via {}: {}", source, e);
+            return Err(IpfsError::from_request(e, url));
+        }
+    };
+
+    let status = resp.status();
+    if !status.is_success() {
+        warn!("{} returned status {}", source, status);
+        let body = resp.text().await.unwrap_or_default();
+        return Err(IpfsError::from_status(status, url, &body));
+    }
 
-            error!("Empty hash received from IPFS");
-            bail!("Invalid response from IPFS: no hash returned");
+    match resp.bytes().await {
+        Ok(bytes) => {
+            debug!("Downloaded {} bytes from {}", bytes.len(), source);
+            Ok(bytes.to_vec())
         },
         Err(e) => {
-            error!("Failed to parse IPFS response as JSON: {}", e);
-            error!("Response body: {}", resp_text);
-            bail!("Failed to parse IPFS response: {}", e);
+            warn!("Failed to read response body from {}: {}", source, e);
+            Err(IpfsError::from_request(e, url))
         }
     }
 }
 
+async fn write_downloaded(ipfs_hash: &str, file_path: &str, content: &[u8]) -> Result<(), IpfsError> {
+    // Git objects are never empty, so an empty body means the source lied.
+    if content.is_empty() {
+        return Err(IpfsError::VerificationFailed {
+            cid: ipfs_hash.to_string(),
+            reason: "downloaded content is empty".to_string(),
+        });
+    }
+
+    let mut dest = match File::create(file_path).await {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to create output file {}: {}", file_path, e);
+            return Err(IpfsError::io(file_path, e));
+        }
+    };
+
+    if let Err(e) = dest.write_all(content).await {
+        error!("Failed to write data to file: {}", e);
+        return Err(IpfsError::io(file_path, e));
+    }
+
+    info!("Successfully downloaded IPFS content ({} bytes) to {}", content.len(), file_path);
+    Ok(())
+}
+
+/// Confirms `cid` is retrievable without downloading it: a `block/stat` on
+/// Kubo, or a HEAD request against the gateway for remote backends.
+#[instrument(skip(backend, client), err)]
+pub async fn check_available(backend: &IpfsBackend, client: &Client, cid: &str) -> Result<(), IpfsError> {
+    let (request, url) = match backend {
+        IpfsBackend::Kubo { api_url } => {
+            let stat_url = format!("{}/api/v0/block/stat?arg={}", api_url, cid);
+            (client.post(stat_url), api_url.clone())
+        },
+        _ => {
+            let gateway = backend.gateway().unwrap_or_default();
+            (client.head(format!("{}{}", gateway, cid)), gateway)
+        },
+    };
+
+    let resp = request.send().await.map_err(|e| IpfsError::from_request(e, &url))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(IpfsError::from_status(status, &url, &body));
+    }
+
+    debug!("CID {} is available", cid);
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockStat {
+    #[serde(rename = "Size")]
+    size: u64,
+}
+
+/// Size in bytes of the block `cid` names, from Kubo's `block/stat`.
+#[instrument(skip(backend, client), err)]
+pub async fn block_size(backend: &IpfsBackend, client: &Client, cid: &str) -> Result<u64, IpfsError> {
+    let api_url = kubo_api_url(backend)?;
+    let url = format!("{}/api/v0/block/stat?arg={}", api_url, cid);
+    let body = fetch_from_source(client.post(&url), api_url, "IPFS block API").await?;
+    let stat: BlockStat = parse_upload_response(&String::from_utf8_lossy(&body), api_url)?;
+    Ok(stat.size)
+}
+
+/// Removes Kubo's pin on `cid` with `pin/rm`, leaving the content to its
+/// garbage collector. Content that was not pinned counts as unpinned.
+#[instrument(skip(backend, client), err)]
+pub async fn unpin(backend: &IpfsBackend, client: &Client, cid: &str) -> Result<(), IpfsError> {
+    let api_url = kubo_api_url(backend)?;
+    let url = format!("{}/api/v0/pin/rm?arg={}", api_url, cid);
+    match fetch_from_source(client.post(&url), api_url, "IPFS pin API").await {
+        Ok(_) => {
+            info!("Unpinned {}", cid);
+            Ok(())
+        },
+        Err(IpfsError::BadResponse { message, .. }) if message.contains("not pinned") => {
+            debug!("{} was not pinned", cid);
+            Ok(())
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Pins are only managed on Kubo; the pinning services keep their own.
+fn kubo_api_url(backend: &IpfsBackend) -> Result<&str, IpfsError> {
+    match backend {
+        IpfsBackend::Kubo { api_url } => Ok(api_url),
+        _ => Err(IpfsError::Misconfigured {
+            message: "managing pins needs IPFS_BACKEND=kubo".to_string(),
+        }),
+    }
+}
+
+/// What [`repin`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepinOutcome {
+    /// The backend already had the content pinned.
+    Present,
+    /// The content was fetched and pinned again.
+    Repinned,
+}
+
+/// Makes sure `cid` is pinned on the configured backend. Kubo is first asked
+/// to pin it from the IPFS network; otherwise the content is fetched from
+/// the backend's gateway or `IPFS_REPIN_GATEWAYS` and uploaded again, which
+/// must give back the same CID.
+#[instrument(skip(backend, client), err)]
+pub async fn repin(backend: &IpfsBackend, client: &Client, cid: &str) -> Result<RepinOutcome, IpfsError> {
+    let pinned = match backend {
+        IpfsBackend::Kubo { api_url } => {
+            let url = format!("{}/api/v0/pin/ls?arg={}&type=recursive", api_url, cid);
+            fetch_from_source(client.post(&url), api_url, "IPFS pin API").await.is_ok()
+        },
+        _ => check_available(backend, client, cid).await.is_ok(),
+    };
+    if pinned {
+        return Ok(RepinOutcome::Present);
+    }
+
+    if let IpfsBackend::Kubo { api_url } = backend {
+        let url = format!("{}/api/v0/pin/add?arg={}", api_url, cid);
+        match fetch_from_source(client.post(&url), api_url, "IPFS pin API").await {
+            Ok(_) => {
+                info!("Re-pinned {} from the IPFS network", cid);
+                return Ok(RepinOutcome::Repinned);
+            },
+            Err(e) => debug!("Kubo could not pin {} from the network: {}", cid, e),
+        }
+    }
+
+    let mut last_error = None;
+    for gateway in backend.gateway().into_iter().chain(Config::ipfs_repin_gateways()) {
+        let url = format!("{}{}", gateway, cid);
+        let content = match fetch_from_source(client.get(&url), &gateway, "IPFS gateway").await {
+            Ok(content) if !content.is_empty() => content,
+            Ok(_) => {
+                last_error = Some(IpfsError::VerificationFailed {
+                    cid: cid.to_string(),
+                    reason: format!("{} returned empty content", gateway),
+                });
+                continue;
+            },
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            },
+        };
+
+        let added = upload_to_ipfs(client, backend, &content, cid).await?;
+        if added != cid {
+            return Err(IpfsError::VerificationFailed {
+                cid: cid.to_string(),
+                reason: format!("content from {} was added as {}", gateway, added),
+            });
+        }
+        info!("Re-pinned {} from {}", cid, gateway);
+        return Ok(RepinOutcome::Repinned);
+    }
+
+    Err(last_error.unwrap_or_else(|| IpfsError::BadResponse {
+        url: backend.api_url().to_string(),
+        message: format!("no source returned content for {}", cid),
+    }))
+}
+
 #[instrument(skip_all, fields(ipfs_hash = ipfs_hash, file_path = file_path), err)]
-pub async fn download_from_ipfs(ipfs_hash: &str, file_path: &str) -> Result<()> {
+pub async fn download_from_ipfs(ipfs_hash: &str, file_path: &str) -> Result<(), IpfsError> {
     info!("Downloading from IPFS: {} -> {}", ipfs_hash, file_path);
 
     if let Some(parent) = Path::new(file_path).parent() {
@@ -203,17 +613,27 @@ pub async fn download_from_ipfs(ipfs_hash: &str, file_path: &str) -> Result<()>
             Ok(_) => debug!("Parent directories created successfully"),
             Err(e) => {
                 error!("Failed to create parent directories: {}", e);
-                return Err(anyhow::anyhow!("Failed to create directories: {}", e));
+                return Err(IpfsError::io(&parent.to_string_lossy(), e));
             }
         }
     }
 
-    let gateway_prefix = Config::ipfs_prefix();
-    let ipfs_api = Config::ipfs_api_url().unwrap_or_else(|| "http://127.0.0.1:5001".to_string());
+    let backend = IpfsBackend::from_env()?;
+    let content = fetch_from_ipfs(&backend, ipfs_hash).await?;
+    write_downloaded(ipfs_hash, file_path, &content).await
+}
+
+/// Reads `ipfs_hash` from `backend`: Kubo's block and cat APIs, then the
+/// backend's gateway, retrying failures.
+pub async fn fetch_from_ipfs(backend: &IpfsBackend, ipfs_hash: &str) -> Result<Vec<u8>, IpfsError> {
+    let gateway_prefix = backend.gateway();
 
     let client = Client::builder()
         .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+        .build()
+        .map_err(|e| IpfsError::from_request(e, backend.api_url()))?;
+
+    let mut last_error = None;
 
     for attempt in 1..=3 {
         info!("Attempting to download from IPFS (attempt {}/3)", attempt);
@@ -224,144 +644,39 @@ pub async fn download_from_ipfs(ipfs_hash: &str, file_path: &str) -> Result<()>
             tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
         }
 
-        let block_url = format!("{}/api/v0/block/get?arg={}", ipfs_api, ipfs_hash);
-        debug!("Trying to download raw block from IPFS API: {}", block_url);
-
-        let downloaded_content = match client.post(&block_url).send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    match resp.bytes().await {
-                        Ok(bytes) => {
-                            debug!("Downloaded {} bytes from IPFS block API", bytes.len());
-                            Some(bytes.to_vec())
-                        },
-                        Err(e) => {
-                            warn!("Failed to read response body from IPFS block API: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    warn!("IPFS block/get API returned status {}, trying alternative", resp.status());
-                    None
-                }
-            },
-            Err(e) => {
-                warn!("Failed to download via IPFS block API: {}", e);
-                None
-            }
-        };
-
-        if let Some(content) = downloaded_content {
-            let mut dest = match File::create(file_path).await {
-                Ok(file) => file,
-                Err(e) => {
-                    error!("Failed to create output file {}: {}", file_path, e);
-                    return Err(anyhow::anyhow!("Failed to create file: {}", e));
-                }
-            };
-
-            if let Err(e) = dest.write_all(&content).await {
-                error!("Failed to write data to file: {}", e);
-                return Err(anyhow::anyhow!("Failed to write file: {}", e));
-            }
+        // Pinning services expose no block API, so only Kubo gets these.
+        if let IpfsBackend::Kubo { api_url } = backend {
+            let block_url = format!("{}/api/v0/block/get?arg={}", api_url, ipfs_hash);
+            debug!("Trying to download raw block from IPFS API: {}", block_url);
 
-            info!("Successfully downloaded IPFS content ({} bytes) to {}", content.len(), file_path);
-            return Ok(());
-        }
-
-        let cat_url = format!("{}/api/v0/cat?arg={}", ipfs_api, ipfs_hash);
-        debug!("Trying to download from IPFS cat API: {}", cat_url);
-
-        let downloaded_content = match client.post(&cat_url).send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    match resp.bytes().await {
-                        Ok(bytes) => {
-                            debug!("Downloaded {} bytes from IPFS cat API", bytes.len());
-                            Some(bytes.to_vec())
-                        },
-                        Err(e) => {
-                            warn!("Failed to read response body from IPFS cat API: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    warn!("IPFS cat API returned status {}", resp.status());
-                    None
-                }
-            },
-            Err(e) => {
-                warn!("Failed to download via IPFS cat API: {}", e);
-                None
+            match fetch_from_source(client.post(&block_url), api_url, "IPFS block API").await {
+                Ok(content) => return Ok(content),
+                Err(e) => last_error = Some(e),
             }
-        };
 
-        if let Some(content) = downloaded_content {
-            let mut dest = match File::create(file_path).await {
-                Ok(file) => file,
-                Err(e) => {
-                    error!("Failed to create output file {}: {}", file_path, e);
-                    return Err(anyhow::anyhow!("Failed to create file: {}", e));
-                }
-            };
+            let cat_url = format!("{}/api/v0/cat?arg={}", api_url, ipfs_hash);
+            debug!("Trying to download from IPFS cat API: {}", cat_url);
 
-            if let Err(e) = dest.write_all(&content).await {
-                error!("Failed to write data to file: {}", e);
-                return Err(anyhow::anyhow!("Failed to write file: {}", e));
+            match fetch_from_source(client.post(&cat_url), api_url, "IPFS cat API").await {
+                Ok(content) => return Ok(content),
+                Err(e) => last_error = Some(e),
             }
-
-            info!("Successfully downloaded IPFS content ({} bytes) to {}", content.len(), file_path);
-            return Ok(());
         }
 
-        if !gateway_prefix.is_empty() {
+        if let Some(gateway_prefix) = &gateway_prefix {
             let gateway_url = format!("{}{}", gateway_prefix, ipfs_hash);
             debug!("Trying to download from IPFS gateway: {}", gateway_url);
 
-            let downloaded_content = match client.get(&gateway_url).send().await {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        match resp.bytes().await {
-                            Ok(bytes) => {
-                                debug!("Downloaded {} bytes from IPFS gateway", bytes.len());
-                                Some(bytes.to_vec())
-                            },
-                            Err(e) => {
-                                warn!("Failed to read response body from gateway: {}", e);
-                                None
-                            }
-                        }
-                    } else {
-                        warn!("Gateway returned status {}", resp.status());
-                        None
-                    }
-                },
-                Err(e) => {
-                    warn!("Failed to connect to gateway: {}", e);
-                    None
-                }
-            };
-
-            if let Some(content) = downloaded_content {
-                let mut dest = match File::create(file_path).await {
-                    Ok(file) => file,
-                    Err(e) => {
-                        error!("Failed to create output file {}: {}", file_path, e);
-                        return Err(anyhow::anyhow!("Failed to create file: {}", e));
-                    }
-                };
-
-                if let Err(e) = dest.write_all(&content).await {
-                    error!("Failed to write data to file: {}", e);
-                    return Err(anyhow::anyhow!("Failed to write file: {}", e));
-                }
-
-                info!("Successfully downloaded IPFS content ({} bytes) to {}", content.len(), file_path);
-                return Ok(());
+            match fetch_from_source(client.get(&gateway_url), gateway_prefix, "IPFS gateway").await {
+                Ok(content) => return Ok(content),
+                Err(e) => last_error = Some(e),
             }
         }
     }
 
     error!("Failed to download from IPFS after maximum retries");
-    Err(anyhow::anyhow!("Failed to download from IPFS after all attempts"))
+    Err(last_error.unwrap_or_else(|| IpfsError::BadResponse {
+        url: backend.api_url().to_string(),
+        message: format!("no source returned content for {}", ipfs_hash),
+    }))
 }
@@ -1,5 +1,14 @@
 pub mod config;
+pub mod confirm;
 pub mod contract_interaction;
+pub mod ens;
 pub mod ipfs;
+pub mod registry;
+pub mod repo_config;
+pub mod s3;
+pub mod signer;
+pub mod storage;
+pub mod sync;
+pub mod transport;
 
 pub use tracing;
@@ -0,0 +1,14 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use ethcontract::Address;
+use std::str::FromStr;
+
+ethcontract::contract!("crates/onchain/artifacts/contracts/RepositoryRegistry.sol/RepositoryRegistry.json");
+
+/// The registry repositories are created in and resolved from, if
+/// `REGISTRY_ADDRESS` is set.
+pub fn address() -> Result<Option<Address>> {
+    Config::registry_address()
+        .map(|address| Address::from_str(&address).with_context(|| format!("Invalid REGISTRY_ADDRESS '{}'", address)))
+        .transpose()
+}
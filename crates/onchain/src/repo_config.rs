@@ -0,0 +1,150 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Schema version written with every config. Configs from before versioning
+/// read as version 0 and are upgraded on load.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Who may read a repository through `info/refs` and `upload-pack`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Private,
+}
+
+/// Restrictions on updating the refs matching `pattern`. A `*` in the
+/// pattern matches any run of characters, e.g. `refs/tags/*`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtectionRule {
+    pub pattern: String,
+    /// Reject updates that are not fast-forwards.
+    #[serde(default)]
+    pub deny_force_push: bool,
+    #[serde(default)]
+    pub deny_delete: bool,
+    /// Only admins may update matching refs at all.
+    #[serde(default)]
+    pub admins_only: bool,
+}
+
+impl ProtectionRule {
+    pub fn matches(&self, ref_name: &str) -> bool {
+        glob_match(self.pattern.as_bytes(), ref_name.as_bytes())
+    }
+}
+
+/// Public keys commits and tags pushed to the repository may be signed with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningKeys {
+    /// ASCII-armored OpenPGP public keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub gpg: String,
+    /// SSH keys in `gpg.ssh.allowedSignersFile` format.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub ssh_allowed_signers: String,
+}
+
+impl SigningKeys {
+    pub fn is_empty(&self) -> bool {
+        self.gpg.is_empty() && self.ssh_allowed_signers.is_empty()
+    }
+}
+
+/// Per-repository storage limits. They only tighten the daemon's own
+/// limits; `None` or 0 leaves the daemon's limit in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_object_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_push_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repo_objects: Option<u64>,
+}
+
+impl Quota {
+    pub fn is_empty(&self) -> bool {
+        self.max_object_bytes.is_none() && self.max_push_bytes.is_none() && self.max_repo_objects.is_none()
+    }
+}
+
+/// Repository settings stored as JSON in the contract's config bytes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepoConfig {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Branch clients should check out, e.g. `refs/heads/main`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected_refs: Vec<ProtectionRule>,
+    /// Reject ref updates bringing in a tip or commit not signed by one of
+    /// `signing_keys`.
+    #[serde(default)]
+    pub require_signed_commits: bool,
+    #[serde(default, skip_serializing_if = "SigningKeys::is_empty")]
+    pub signing_keys: SigningKeys,
+    #[serde(default, skip_serializing_if = "Quota::is_empty")]
+    pub quota: Quota,
+    /// Ref namespaces pushes may write to, each ending in `/`. They narrow
+    /// the daemon's namespaces; empty means the daemon's as they are.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ref_namespaces: Vec<String>,
+    /// Free-form settings for tools built on dgit.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl RepoConfig {
+    /// Parses the raw contract config; an unset config means the defaults.
+    /// Older schema versions are upgraded, newer ones are an error rather
+    /// than silently dropping fields this version does not know.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut config: Self = if data.is_empty() {
+            Self::default()
+        } else {
+            serde_json::from_slice(data).context("Failed to parse repository config")?
+        };
+
+        if config.version > CONFIG_VERSION {
+            bail!(
+                "Repository config version {} is newer than the supported version {}",
+                config.version,
+                CONFIG_VERSION
+            );
+        }
+        // Version 0 only lacked the version field itself.
+        config.version = CONFIG_VERSION;
+
+        Ok(config)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let config = Self { version: CONFIG_VERSION, ..self.clone() };
+        serde_json::to_vec(&config).context("Failed to serialize repository config")
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.visibility == Visibility::Private
+    }
+
+    /// Rules that apply to `ref_name`.
+    pub fn protection_for<'a>(&'a self, ref_name: &'a str) -> impl Iterator<Item = &'a ProtectionRule> {
+        self.protected_refs.iter().filter(move |rule| rule.matches(ref_name))
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
@@ -0,0 +1,179 @@
+use crate::config::Config;
+use crate::storage::{with_retries, StorageBackend};
+use anyhow::{anyhow, bail, Result};
+use futures_util::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, Url};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// S3 or S3-compatible storage (MinIO, R2, ...), with requests signed
+/// with AWS Signature Version 4. Objects are recorded on chain as
+/// `s3://<bucket>/<key>` and addressed path-style, which every
+/// S3-compatible store accepts.
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    bucket: String,
+    region: String,
+    endpoint: Url,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+    client: Client,
+}
+
+impl S3Store {
+    pub fn from_env() -> Result<Self> {
+        let bucket = Config::s3_bucket().ok_or_else(|| anyhow!("OBJECT_STORE=s3 requires S3_BUCKET"))?;
+        let region = Config::s3_region();
+        let endpoint = Config::s3_endpoint().unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let endpoint = Url::parse(&endpoint).map_err(|e| anyhow!("Invalid S3_ENDPOINT '{}': {}", endpoint, e))?;
+        if endpoint.host_str().is_none() {
+            bail!("S3_ENDPOINT {} has no host", endpoint);
+        }
+
+        Ok(Self {
+            bucket,
+            region,
+            endpoint,
+            prefix: Config::s3_prefix(),
+            access_key_id: Config::s3_access_key_id()
+                .ok_or_else(|| anyhow!("OBJECT_STORE=s3 requires S3_ACCESS_KEY_ID or AWS_ACCESS_KEY_ID"))?,
+            secret_access_key: Config::s3_secret_access_key()
+                .ok_or_else(|| anyhow!("OBJECT_STORE=s3 requires S3_SECRET_ACCESS_KEY or AWS_SECRET_ACCESS_KEY"))?,
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .connect_timeout(Duration::from_secs(5))
+                .build()?,
+        })
+    }
+
+    /// Sends a signed `method` request for `key` in `bucket`.
+    async fn send(&self, method: Method, bucket: &str, key: &str, body: Vec<u8>) -> Result<reqwest::Response> {
+        let path = format!("/{}/{}", uri_encode(bucket, false), uri_encode(key, true));
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        let amz_date = amz_date(now);
+        let date = &amz_date[..8];
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, host, payload_hash, amz_date, payload_hash,
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())),
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key_id, scope, signature,
+        );
+        Ok(self.client.request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+            .body(body)
+            .send()
+            .await?)
+    }
+}
+
+impl StorageBackend for S3Store {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn put<'a>(&'a self, name: &'a str, content: Vec<u8>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let key = &format!("{}{}", self.prefix, name);
+            let url = format!("s3://{}/{}", self.bucket, key);
+            let content = &content;
+            with_retries(&url, || async move {
+                let response = self.send(Method::PUT, &self.bucket, key, content.clone()).await?;
+                let status = response.status();
+                if !status.is_success() {
+                    bail!("S3 PUT {} returned {}: {}", key, status, response.text().await.unwrap_or_default());
+                }
+                Ok(())
+            }).await?;
+            Ok(url)
+        })
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let (bucket, key) = url.strip_prefix("s3://")
+                .and_then(|rest| rest.split_once('/'))
+                .ok_or_else(|| anyhow!("Invalid S3 URL '{}'", url))?;
+            with_retries(url, || async move {
+                let response = self.send(Method::GET, bucket, key, Vec::new()).await?;
+                let status = response.status();
+                if !status.is_success() {
+                    bail!("S3 GET {} returned {}", url, status);
+                }
+                Ok(response.bytes().await?.to_vec())
+            }).await
+        })
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Percent-encodes all but the characters S3 leaves as they are; `/`
+/// separates key segments and stays when `keep_slash` is set.
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `YYYYMMDD'T'HHMMSS'Z'` for the Unix time `secs`.
+fn amz_date(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60,
+    )
+}
@@ -0,0 +1,166 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Context, Result};
+use ethcontract::web3::signing;
+use ethcontract::{Account, Address, PrivateKey};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+static SHARED: OnceLock<Signer> = OnceLock::new();
+
+/// Source of the key used to sign contract transactions.
+///
+/// Selected with `SIGNER=env|keystore|remote`; defaults to the plaintext `PK`
+/// variable for backward compatibility.
+#[derive(Clone)]
+pub enum Signer {
+    /// Raw private key taken from the `PK` environment variable.
+    Env(PrivateKey),
+    /// Key decrypted from an encrypted JSON keystore file.
+    Keystore(PrivateKey),
+    /// External signer (clef, web3signer) that signs `eth_sendTransaction`
+    /// requests on behalf of `address` and proxies everything else.
+    Remote { url: String, address: Address },
+    /// No key configured: transactions are sent from the node's own account.
+    Node,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signer::Env(key) => write!(f, "Signer::Env({:?})", key.public_address()),
+            Signer::Keystore(key) => write!(f, "Signer::Keystore({:?})", key.public_address()),
+            Signer::Remote { url, address } => write!(f, "Signer::Remote({}, {:?})", url, address),
+            Signer::Node => write!(f, "Signer::Node"),
+        }
+    }
+}
+
+impl Signer {
+    /// The signer from the environment, loaded on first use and shared by
+    /// every contract after that, so a keystore goes through its slow scrypt
+    /// decryption once per process. A failure is not remembered; the next
+    /// call tries again.
+    pub fn shared() -> Result<Self> {
+        if let Some(signer) = SHARED.get() {
+            return Ok(signer.clone());
+        }
+        let signer = Self::from_env()?;
+        Ok(SHARED.get_or_init(|| signer).clone())
+    }
+
+    pub fn from_env() -> Result<Self> {
+        match Config::signer().as_str() {
+            "env" => {
+                let pk = Config::pk();
+                if pk.is_empty() {
+                    warn!("No private key configured, falling back to the node's default account");
+                    return Ok(Signer::Node);
+                }
+                Self::from_private_key(&pk)
+            },
+            "keystore" => {
+                let path = Config::keystore_path()
+                    .ok_or_else(|| anyhow!("SIGNER=keystore requires KEYSTORE_PATH"))?;
+                let password = Config::keystore_password()
+                    .ok_or_else(|| anyhow!("SIGNER=keystore requires KEYSTORE_PASSWORD or KEYSTORE_PASSWORD_FILE"))?;
+                Self::from_keystore(&path, &password)
+            },
+            "remote" => {
+                let url = Config::remote_signer_url()
+                    .ok_or_else(|| anyhow!("SIGNER=remote requires REMOTE_SIGNER_URL"))?;
+                let address = Config::signer_address()
+                    .ok_or_else(|| anyhow!("SIGNER=remote requires SIGNER_ADDRESS"))?;
+                let address = Address::from_str(&address)
+                    .map_err(|_| anyhow!("Invalid SIGNER_ADDRESS: {}", address))?;
+                info!("Using remote signer at {} for {:?}", url, address);
+                Ok(Signer::Remote { url, address })
+            },
+            other => bail!("Unknown SIGNER '{}', expected env, keystore or remote", other),
+        }
+    }
+
+    pub fn from_private_key(key: &str) -> Result<Self> {
+        let key = PrivateKey::from_hex_str(key.trim())
+            .map_err(|e| anyhow!("Invalid private key: {}", e))?;
+        debug!("Using private key signer for {:?}", key.public_address());
+        Ok(Signer::Env(key))
+    }
+
+    pub fn from_keystore(path: &str, password: &str) -> Result<Self> {
+        let secret = eth_keystore::decrypt_key(path, password)
+            .with_context(|| format!("Failed to decrypt keystore {}", path))?;
+        let secret: [u8; 32] = secret
+            .try_into()
+            .map_err(|_| anyhow!("Keystore {} does not contain a 32-byte key", path))?;
+        let key = PrivateKey::from_raw(secret)
+            .map_err(|e| anyhow!("Invalid key in keystore {}: {}", path, e))?;
+        info!("Loaded keystore signer for {:?}", key.public_address());
+        Ok(Signer::Keystore(key))
+    }
+
+    pub fn address(&self) -> Option<Address> {
+        match self {
+            Signer::Env(key) | Signer::Keystore(key) => Some(key.public_address()),
+            Signer::Remote { address, .. } => Some(*address),
+            Signer::Node => None,
+        }
+    }
+
+    /// Account to attach as the `from` of every transaction.
+    pub fn account(&self) -> Option<Account> {
+        match self {
+            Signer::Env(key) | Signer::Keystore(key) => Some(Account::Offline(key.clone(), None)),
+            Signer::Remote { address, .. } => Some(Account::Local(*address, None)),
+            Signer::Node => None,
+        }
+    }
+
+    /// JSON-RPC endpoints calls go through, in order of preference. A remote
+    /// signer proxies calls to the node, so it replaces the configured ones.
+    pub fn rpc_urls(&self) -> Vec<String> {
+        match self {
+            Signer::Remote { url, .. } => vec![url.clone()],
+            _ => Config::rpc_urls(),
+        }
+    }
+}
+
+/// Address that signed `message` as an EIP-191 personal message, the way
+/// `personal_sign` and wallets sign text. `signature` is `r || s || v`, 65
+/// bytes, with `v` either 0/1 or 27/28.
+pub fn recover_signer(message: &[u8], signature: &[u8]) -> Result<Address> {
+    if signature.len() != 65 {
+        bail!("Signature must be 65 bytes, got {}", signature.len());
+    }
+    let recovery_id = match signature[64] {
+        v @ 0..=1 => v as i32,
+        v @ 27..=28 => (v - 27) as i32,
+        v => bail!("Invalid signature recovery id {}", v),
+    };
+
+    let hash = signing::hash_message(message);
+    signing::recover(hash.as_bytes(), &signature[..64], recovery_id)
+        .map_err(|e| anyhow!("Failed to recover signer: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keystore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret = [7u8; 32];
+        let name = eth_keystore::encrypt_key(dir.path(), &mut rand::thread_rng(), secret, "correct horse", Some("signer.json")).unwrap();
+        let path = dir.path().join(name);
+        let path = path.to_str().unwrap();
+
+        let signer = Signer::from_keystore(path, "correct horse").unwrap();
+        assert!(matches!(signer, Signer::Keystore(_)));
+        assert_eq!(signer.address(), Some(PrivateKey::from_raw(secret).unwrap().public_address()));
+
+        assert!(Signer::from_keystore(path, "wrong password").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::config::Config;
+use crate::ipfs::{self, IpfsBackend};
+use crate::s3::S3Store;
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::BoxFuture;
+use reqwest::{header::LOCATION, Client, RequestBuilder};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const ATTEMPTS: u32 = 3;
+
+/// Where pushed objects are kept. The contract records whatever URL `put`
+/// returns as the object's `ipfs_url`, and reads go back to the backend
+/// that URL names, so objects stay readable after `OBJECT_STORE` changes.
+pub trait StorageBackend: fmt::Debug + Send + Sync {
+    /// Name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Stores `content`, a loose git object named by its hash, and returns
+    /// the URL to record on chain.
+    fn put<'a>(&'a self, name: &'a str, content: Vec<u8>) -> BoxFuture<'a, Result<String>>;
+
+    /// Reads back what `put` stored under `url`.
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>>;
+}
+
+/// The backend new objects are written to, selected with
+/// `OBJECT_STORE=ipfs|http|s3`.
+pub fn from_env() -> Result<Arc<dyn StorageBackend>> {
+    match Config::object_store().as_str() {
+        "ipfs" => Ok(Arc::new(IpfsBackend::from_env()?)),
+        "http" => Ok(Arc::new(HttpStore::from_env()?)),
+        "s3" => Ok(Arc::new(S3Store::from_env()?)),
+        other => bail!("Unknown OBJECT_STORE '{}', expected ipfs, http or s3", other),
+    }
+}
+
+/// Whether `url` is an IPFS CID or gateway URL rather than an object kept
+/// in HTTP or S3 storage.
+pub fn is_ipfs(url: &str) -> bool {
+    if url.starts_with("s3://") {
+        return false;
+    }
+    !(url.starts_with("http://") || url.starts_with("https://")) || url.contains("/ipfs/")
+}
+
+/// The backend that can read `url`, whichever one is configured for writes.
+pub fn for_url(url: &str) -> Result<Arc<dyn StorageBackend>> {
+    if url.starts_with("s3://") {
+        return Ok(Arc::new(S3Store::from_env()?));
+    }
+    if is_ipfs(url) {
+        return Ok(Arc::new(IpfsBackend::from_env()?));
+    }
+    // The configured store's token only goes to the configured store.
+    match HttpStore::from_env() {
+        Ok(store) if url.starts_with(&store.base) => Ok(Arc::new(store)),
+        _ => Ok(Arc::new(HttpStore::anonymous()?)),
+    }
+}
+
+/// Downloads the object recorded as `url` to `file_path`.
+pub async fn download(url: &str, file_path: &Path) -> Result<()> {
+    if is_ipfs(url) {
+        ipfs::download_from_ipfs(url, &file_path.to_string_lossy()).await?;
+        return Ok(());
+    }
+
+    let content = for_url(url)?.get(url).await?;
+    // Git objects are never empty, so an empty body means the store lied.
+    if content.is_empty() {
+        bail!("{} returned empty content", url);
+    }
+    if let Some(parent) = file_path.parent() {
+        tokio::fs::create_dir_all(parent).await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    tokio::fs::write(file_path, &content).await
+        .with_context(|| format!("Failed to write {}", file_path.display()))?;
+    info!("Downloaded {} ({} bytes) to {}", url, content.len(), file_path.display());
+    Ok(())
+}
+
+impl StorageBackend for IpfsBackend {
+    fn name(&self) -> &'static str {
+        "ipfs"
+    }
+
+    fn put<'a>(&'a self, name: &'a str, content: Vec<u8>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { Ok(ipfs::add_to_ipfs(self, &content, name).await?) })
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move { Ok(ipfs::fetch_from_ipfs(self, &ipfs::normalize_cid(url)).await?) })
+    }
+}
+
+/// Plain HTTP storage: objects are PUT to `OBJECT_STORE_URL/<hash>` and
+/// read back with GET, with `OBJECT_STORE_TOKEN` as a bearer token if set.
+/// A `Location` header in the PUT response is recorded instead of the PUT
+/// URL, for stores that serve objects from elsewhere.
+#[derive(Debug, Clone)]
+pub struct HttpStore {
+    base: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl HttpStore {
+    pub fn new(base: &str, token: Option<String>) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(5))
+            .build()?;
+        Ok(Self { base: base.trim_end_matches('/').to_string(), token, client })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let base = Config::object_store_url()
+            .ok_or_else(|| anyhow!("OBJECT_STORE=http requires OBJECT_STORE_URL"))?;
+        Self::new(&base, Config::object_store_token())
+    }
+
+    /// For reading objects recorded under another store's URLs.
+    fn anonymous() -> Result<Self> {
+        Self::new("", None)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+impl StorageBackend for HttpStore {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn put<'a>(&'a self, name: &'a str, content: Vec<u8>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let url = &format!("{}/{}", self.base, name);
+            let content = &content;
+            with_retries(url, || async move {
+                let response = self.authorize(self.client.put(url.as_str()))
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .body(content.clone())
+                    .send()
+                    .await?;
+                let status = response.status();
+                if !status.is_success() {
+                    bail!("PUT {} returned {}: {}", url, status, response.text().await.unwrap_or_default());
+                }
+                let location = response.headers().get(LOCATION).and_then(|location| location.to_str().ok());
+                Ok(match location {
+                    Some(location) if location.starts_with("http://") || location.starts_with("https://") => location.to_string(),
+                    _ => url.to_string(),
+                })
+            }).await
+        })
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            with_retries(url, || async move {
+                let response = self.authorize(self.client.get(url)).send().await?;
+                let status = response.status();
+                if !status.is_success() {
+                    bail!("GET {} returned {}", url, status);
+                }
+                Ok(response.bytes().await?.to_vec())
+            }).await
+        })
+    }
+}
+
+/// Runs `attempt` up to `ATTEMPTS` times, backing off 1s, then 2s.
+pub(crate) async fn with_retries<T, F, Fut>(url: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut tries = 1;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if tries == ATTEMPTS => return Err(e),
+            Err(e) => {
+                let backoff = Duration::from_millis(1000 * (1 << (tries - 1)));
+                warn!("Request to {} failed (attempt {}/{}): {:#}; retrying in {:?}", url, tries, ATTEMPTS, e, backoff);
+                tokio::time::sleep(backoff).await;
+                tries += 1;
+            },
+        }
+    }
+}
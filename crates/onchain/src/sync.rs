@@ -0,0 +1,192 @@
+use crate::config::Config;
+use crate::contract_interaction::{ContractInteraction, Object, Ref, RepoEvent};
+use anyhow::Result;
+use ethcontract::prelude::U256;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info, instrument, warn};
+
+/// A repository contract's objects and refs as of `block`, kept current
+/// from its `ObjectSaved`/`RefAdded` events rather than by reading the whole
+/// arrays with `getObjects`/`getRefs` on every request.
+///
+/// The first sync reads the arrays one entry at a time. Later syncs apply
+/// the events mined since, then compare the counts with the contract; if
+/// the events are unavailable or do not add up (pruned logs, a reorg), the
+/// view is re-read by index instead.
+///
+/// Only blocks `SYNC_REORG_DEPTH` deep are folded into the view itself. The
+/// newer ones are re-read into [`RepoView::current`] on every sync, so a
+/// reorg near the head is picked up without starting over.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoView {
+    /// Last block whose changes are included; `None` before the first sync.
+    pub block: Option<u64>,
+    pub objects: Vec<Object>,
+    /// In contract order; an update to an existing ref replaces it in place.
+    pub refs: Vec<Ref>,
+    #[serde(skip)]
+    object_hashes: HashSet<String>,
+    #[serde(skip)]
+    ref_positions: HashMap<String, usize>,
+    /// The view with the blocks above `block` applied, up to the head.
+    #[serde(skip)]
+    tip: Option<Box<RepoView>>,
+}
+
+impl RepoView {
+    /// The view as of the head at the last sync.
+    pub fn current(&self) -> &RepoView {
+        self.tip.as_deref().unwrap_or(self)
+    }
+
+    /// Whether `hash` is recorded, as of the head at the last sync.
+    pub fn has_object(&self, hash: &str) -> bool {
+        self.current().object_hashes.contains(hash)
+    }
+
+    /// Brings the view up to the chain's latest block.
+    #[instrument(skip_all, fields(address = %contract.address()), err)]
+    pub async fn sync(&mut self, contract: &ContractInteraction) -> Result<()> {
+        let latest = contract.block_number().await?;
+        if self.current().block.is_some_and(|block| block >= latest) {
+            return Ok(());
+        }
+        self.index();
+
+        let Some(from) = self.block.map(|block| block + 1) else {
+            info!("Reading repository state by index up to block {}", latest);
+            self.reload(contract).await?;
+            self.block = Some(latest);
+            self.tip = None;
+            return Ok(());
+        };
+
+        let safe = latest.saturating_sub(Config::sync_reorg_depth()).max(from - 1);
+        let synced = match self.apply_events(contract, from, safe).await {
+            Ok(()) => self.sync_tip(contract, safe, latest).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = synced {
+            warn!("Event sync failed, reading repository state by index instead: {:#}", e);
+            self.reload(contract).await?;
+            self.block = Some(latest);
+            self.tip = None;
+            return Ok(());
+        }
+        self.block = Some(safe);
+
+        if !self.current().matches_counts(contract).await? {
+            warn!("Events since block {} do not match the contract's counts, reading by index", from);
+            self.reload(contract).await?;
+            self.block = Some(latest);
+            self.tip = None;
+        }
+        Ok(())
+    }
+
+    /// Re-reads the blocks above `safe` on top of the view, so changes a
+    /// reorg dropped since the last sync are dropped here too.
+    async fn sync_tip(&mut self, contract: &ContractInteraction, safe: u64, latest: u64) -> Result<()> {
+        if safe >= latest {
+            self.tip = None;
+            return Ok(());
+        }
+
+        let mut tip = RepoView {
+            block: Some(latest),
+            objects: self.objects.clone(),
+            refs: self.refs.clone(),
+            object_hashes: self.object_hashes.clone(),
+            ref_positions: self.ref_positions.clone(),
+            tip: None,
+        };
+        tip.apply_events(contract, safe + 1, latest).await?;
+        self.tip = Some(Box::new(tip));
+        Ok(())
+    }
+
+    async fn apply_events(&mut self, contract: &ContractInteraction, from: u64, to: u64) -> Result<()> {
+        let range = Config::sync_block_range().max(1);
+        let mut start = from;
+        while start <= to {
+            let end = to.min(start.saturating_add(range - 1));
+            for event in contract.get_repo_events(start, end).await? {
+                match event {
+                    RepoEvent::ObjectSaved(object) => self.add_object(object),
+                    RepoEvent::RefAdded(r) => self.set_ref(r),
+                    RepoEvent::RefDeactivated { name, .. } => self.deactivate_ref(&name),
+                }
+            }
+            start = end + 1;
+        }
+
+        debug!("Applied events of blocks {}..={}: {} objects, {} refs", from, to, self.objects.len(), self.refs.len());
+        Ok(())
+    }
+
+    async fn matches_counts(&self, contract: &ContractInteraction) -> Result<bool> {
+        let objects = contract.get_objects_length().await?;
+        let refs = contract.get_refs_length().await?;
+        Ok(objects == U256::from(self.objects.len()) && refs == U256::from(self.refs.len()))
+    }
+
+    /// Reads objects appended since the view's last entry, since the object
+    /// list only grows, and every ref, since refs change in place.
+    async fn reload(&mut self, contract: &ContractInteraction) -> Result<()> {
+        let objects = contract.get_objects_length().await?.as_usize();
+        if objects < self.objects.len() {
+            warn!("Contract has {} objects, fewer than the {} known; starting over", objects, self.objects.len());
+            self.objects.clear();
+            self.object_hashes.clear();
+        }
+        let appended = if self.objects.is_empty() {
+            contract.get_all_objects().await?
+        } else {
+            contract.get_objects_paged(self.objects.len(), objects - self.objects.len()).await?
+        };
+        for object in appended {
+            self.add_object(object);
+        }
+
+        self.refs = contract.get_all_refs().await?;
+        self.ref_positions = self.refs.iter().enumerate().map(|(position, r)| (r.name.clone(), position)).collect();
+
+        debug!("Read {} objects and {} refs by index", self.objects.len(), self.refs.len());
+        Ok(())
+    }
+
+    fn add_object(&mut self, object: Object) {
+        if self.object_hashes.insert(object.hash.clone()) {
+            self.objects.push(object);
+        }
+    }
+
+    /// Like the contract, changes an active ref in place and adds a deleted
+    /// or new one as a new entry.
+    fn set_ref(&mut self, r: Ref) {
+        match self.ref_positions.get(&r.name) {
+            Some(&position) if self.refs[position].is_active => self.refs[position] = r,
+            _ => {
+                self.ref_positions.insert(r.name.clone(), self.refs.len());
+                self.refs.push(r);
+            },
+        }
+    }
+
+    fn deactivate_ref(&mut self, name: &str) {
+        if let Some(&position) = self.ref_positions.get(name) {
+            self.refs[position].is_active = false;
+        }
+    }
+
+    /// Rebuilds the lookups that are not persisted.
+    fn index(&mut self) {
+        if self.object_hashes.len() != self.objects.len() {
+            self.object_hashes = self.objects.iter().map(|object| object.hash.clone()).collect();
+        }
+        if self.ref_positions.len() != self.refs.len() {
+            self.ref_positions = self.refs.iter().enumerate().map(|(position, r)| (r.name.clone(), position)).collect();
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use crate::config::Config;
+use ethcontract::jsonrpc::{Call, Value};
+use ethcontract::web3::{error::{Error, TransportError}, helpers, transports::{Http, WebSocket}, RequestId, Transport, Web3};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// RPC methods that only read chain state and so are safe to send again.
+/// Transaction submission is deliberately absent: a resend after a lost
+/// response could submit the same transaction twice. Those have their own
+/// retry loops that check receipts first.
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "eth_call",
+    "eth_getTransactionReceipt",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_estimateGas",
+    "eth_gasPrice",
+    "eth_blockNumber",
+    "eth_getBlockByNumber",
+    "eth_getCode",
+    "eth_getBalance",
+    "eth_chainId",
+    "net_version",
+];
+
+/// Retries idempotent reads that fail on transport errors (connection
+/// resets, 502s from the node) with exponential backoff.
+#[derive(Debug, Clone)]
+pub struct RetryTransport<T> {
+    inner: T,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl<T> RetryTransport<T> {
+    pub fn new(inner: T, retries: u32, backoff: Duration) -> Self {
+        Self { inner, retries, backoff }
+    }
+
+    pub fn from_env(inner: T) -> Self {
+        Self::new(inner, Config::rpc_read_retries(), Duration::from_millis(Config::rpc_retry_backoff_ms()))
+    }
+}
+
+impl<T> Transport for RetryTransport<T>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send + 'static,
+{
+    type Out = Pin<Box<dyn Future<Output = ethcontract::web3::error::Result<Value>> + Send>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        self.inner.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let inner = self.inner.clone();
+        let method = match &request {
+            Call::MethodCall(call) => call.method.clone(),
+            _ => String::new(),
+        };
+        let retries = if IDEMPOTENT_METHODS.contains(&method.as_str()) { self.retries } else { 0 };
+        let backoff = self.backoff;
+
+        Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                match inner.send(id, request.clone()).await {
+                    Err(e) if attempt < retries && is_transient(&e) => {
+                        let delay = backoff * 2u32.saturating_pow(attempt);
+                        attempt += 1;
+                        warn!("{} failed: {}. Retrying ({}/{}) in {:?}", method, e, attempt, retries, delay);
+                        tokio::time::sleep(delay).await;
+                    },
+                    result => {
+                        if attempt > 0 && result.is_ok() {
+                            debug!("{} succeeded after {} retries", method, attempt);
+                        }
+                        return result;
+                    },
+                }
+            }
+        })
+    }
+}
+
+/// Errors from reaching the node rather than from the call itself; a
+/// revert or bad response would fail the same way again.
+fn is_transient(error: &Error) -> bool {
+    matches!(error, Error::Transport(_) | Error::Unreachable)
+}
+
+/// An RPC endpoint, or a repository contract, on another chain than the
+/// one expected.
+#[derive(Debug, Clone, Copy)]
+pub struct WrongChain {
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for WrongChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC endpoint is on chain {}, expected chain {}", self.actual, self.expected)
+    }
+}
+
+impl std::error::Error for WrongChain {}
+
+/// Whether `url` can be an RPC endpoint: HTTP(S) or WebSocket.
+pub fn is_rpc_url(url: &str) -> bool {
+    is_websocket(url) || url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Whether `url` is a WebSocket endpoint rather than an HTTP one.
+pub(crate) fn is_websocket(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// The connection to one RPC endpoint.
+#[derive(Debug)]
+enum Endpoint {
+    Http(Http),
+    /// Connected on first use, and again after the connection drops.
+    Ws(tokio::sync::Mutex<Option<WebSocket>>),
+}
+
+impl Endpoint {
+    fn new(url: &str) -> Result<Self, Error> {
+        if is_websocket(url) {
+            Ok(Self::Ws(tokio::sync::Mutex::new(None)))
+        } else {
+            Http::new(url).map(Self::Http)
+        }
+    }
+
+    async fn send(&self, url: &str, id: RequestId, request: Call) -> ethcontract::web3::error::Result<Value> {
+        match self {
+            Self::Http(http) => http.send(id, request).await,
+            Self::Ws(socket) => {
+                let ws = {
+                    let mut socket = socket.lock().await;
+                    match socket.as_ref() {
+                        Some(ws) => ws.clone(),
+                        None => {
+                            debug!("Connecting to {}", redact(url));
+                            let ws = WebSocket::new(url).await?;
+                            *socket = Some(ws.clone());
+                            ws
+                        },
+                    }
+                };
+
+                let result = ws.send(id, request).await;
+                if matches!(&result, Err(e) if is_transient(e)) {
+                    *socket.lock().await = None;
+                }
+                result
+            },
+        }
+    }
+}
+
+/// One RPC endpoint of a [`FailoverTransport`] and whether it is usable.
+#[derive(Debug)]
+struct Provider {
+    url: String,
+    endpoint: Endpoint,
+    state: Mutex<ProviderState>,
+}
+
+#[derive(Debug, Default)]
+struct ProviderState {
+    /// Transport errors since the last success.
+    failures: u32,
+    /// Skipped until then, unless every provider is.
+    unhealthy_until: Option<Instant>,
+    /// Chain id the endpoint last reported.
+    chain_id: Option<u64>,
+    /// Whether `chain_id` is current; cleared on failure, since the endpoint
+    /// may come back as another node.
+    chain_checked: bool,
+}
+
+impl Provider {
+    fn is_available(&self, now: Instant) -> bool {
+        self.state.lock().unwrap().unhealthy_until.is_none_or(|until| until <= now)
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.unhealthy_until.is_some() {
+            info!("RPC endpoint {} is healthy again", redact(&self.url));
+        }
+        state.failures = 0;
+        state.unhealthy_until = None;
+    }
+
+    fn failed(&self, cooldown: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        state.unhealthy_until = Some(Instant::now() + cooldown);
+        state.chain_checked = false;
+    }
+
+    /// Fails unless the endpoint is on `expected`, asking it with
+    /// `eth_chainId` the first time and again after it failed.
+    async fn check_chain(&self, expected: u64, id: RequestId) -> ethcontract::web3::error::Result<()> {
+        let known = {
+            let state = self.state.lock().unwrap();
+            state.chain_id.filter(|_| state.chain_checked)
+        };
+        let actual = match known {
+            Some(chain_id) => chain_id,
+            None => {
+                let value = self.endpoint.send(&self.url, id, helpers::build_request(id, "eth_chainId", vec![])).await?;
+                let actual = value
+                    .as_str()
+                    .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+                    .ok_or_else(|| Error::InvalidResponse(format!("eth_chainId returned {}", value)))?;
+
+                let mut state = self.state.lock().unwrap();
+                state.chain_id = Some(actual);
+                state.chain_checked = true;
+                actual
+            },
+        };
+
+        if actual != expected {
+            return Err(Error::Transport(TransportError::Message(WrongChain { expected, actual }.to_string())));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ProviderPool {
+    providers: Vec<Provider>,
+    cooldown: Duration,
+    /// `CHAIN_ID`; endpoints on other chains are treated as failed.
+    chain_id: Option<u64>,
+    /// Request IDs, shared so they stay unique on a WebSocket whichever
+    /// endpoint prepared the call.
+    next_id: AtomicUsize,
+}
+
+impl ProviderPool {
+    fn next_id(&self) -> RequestId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Providers to try, in configured order: those not cooling down, or all
+    /// of them if none is, so a recovered endpoint is still found.
+    fn candidates(&self) -> Vec<&Provider> {
+        let now = Instant::now();
+        let available: Vec<&Provider> = self.providers.iter().filter(|provider| provider.is_available(now)).collect();
+        if available.is_empty() {
+            self.providers.iter().collect()
+        } else {
+            available
+        }
+    }
+}
+
+/// Pools by endpoint list, so every client of the same endpoints shares
+/// one view of their health.
+static POOLS: LazyLock<Mutex<HashMap<Vec<String>, Arc<ProviderPool>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sends each call to the first healthy endpoint of a list. An endpoint that
+/// fails to answer is skipped for `RPC_FAILOVER_COOLDOWN_SECS`; once that
+/// passes, the next call through it is the probe that brings it back.
+///
+/// With `CHAIN_ID` set, each endpoint's chain id is checked before its
+/// first call and after every failure, and an endpoint on another chain is
+/// skipped like one that is down, so nothing is sent to the wrong network.
+///
+/// Reads move on to the next endpoint at once. Anything else, transaction
+/// submission in particular, is sent to one endpoint only: after a lost
+/// response it may already be broadcast, and sending it again elsewhere
+/// could submit it twice.
+#[derive(Debug, Clone)]
+pub struct FailoverTransport {
+    pool: Arc<ProviderPool>,
+}
+
+impl FailoverTransport {
+    pub fn new(urls: &[String], cooldown: Duration, chain_id: Option<u64>) -> Self {
+        let mut pools = POOLS.lock().unwrap();
+        let pool = pools.entry(urls.to_vec()).or_insert_with(|| {
+            let mut providers: Vec<Provider> = urls
+                .iter()
+                .filter_map(|url| match Endpoint::new(url) {
+                    Ok(endpoint) => Some(Provider { url: url.clone(), endpoint, state: Mutex::default() }),
+                    Err(e) => {
+                        error!("Ignoring invalid RPC endpoint {}: {}", redact(url), e);
+                        None
+                    }
+                })
+                .collect();
+            if providers.is_empty() {
+                let url = "http://localhost:8545".to_string();
+                warn!("No usable RPC endpoint configured, using {}", url);
+                providers.push(Provider { endpoint: Endpoint::new(&url).unwrap(), url, state: Mutex::default() });
+            }
+            Arc::new(ProviderPool { providers, cooldown, chain_id, next_id: AtomicUsize::new(1) })
+        });
+
+        Self { pool: Arc::clone(pool) }
+    }
+
+    pub fn from_env(urls: &[String]) -> Self {
+        Self::new(urls, Duration::from_secs(Config::rpc_failover_cooldown_secs()), Config::chain_id())
+    }
+}
+
+impl Transport for FailoverTransport {
+    type Out = Pin<Box<dyn Future<Output = ethcontract::web3::error::Result<Value>> + Send>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.pool.next_id();
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let pool = Arc::clone(&self.pool);
+        let method = match &request {
+            Call::MethodCall(call) => call.method.clone(),
+            _ => String::new(),
+        };
+        let can_fail_over = IDEMPOTENT_METHODS.contains(&method.as_str());
+
+        Box::pin(async move {
+            let candidates = pool.candidates();
+            let attempts = if can_fail_over { candidates.len() } else { 1 };
+
+            let mut last_error = None;
+            let mut sent = 0;
+            for provider in candidates {
+                if sent == attempts {
+                    break;
+                }
+                // Checked before sending, so even a transaction may move on.
+                if let Some(expected) = pool.chain_id {
+                    if let Err(e) = provider.check_chain(expected, pool.next_id()).await {
+                        warn!("Skipping RPC endpoint {}: {}", redact(&provider.url), e);
+                        provider.failed(pool.cooldown);
+                        last_error = Some(e);
+                        continue;
+                    }
+                }
+
+                sent += 1;
+                match provider.endpoint.send(&provider.url, id, request.clone()).await {
+                    Err(e) if is_transient(&e) => {
+                        warn!("{} via {} failed, marking it unhealthy: {}", method, redact(&provider.url), e);
+                        provider.failed(pool.cooldown);
+                        last_error = Some(e);
+                    },
+                    result => {
+                        provider.succeeded();
+                        return result;
+                    },
+                }
+            }
+
+            Err(last_error.unwrap_or(Error::Unreachable))
+        })
+    }
+}
+
+/// How an RPC endpoint is doing, for readiness checks.
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointHealth {
+    /// Scheme and host only; paths often carry API keys.
+    pub endpoint: String,
+    pub healthy: bool,
+    /// Calls go here first.
+    pub primary: bool,
+    pub failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_secs: Option<u64>,
+    /// Chain id the endpoint last reported, when `CHAIN_ID` is checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+}
+
+/// Health of the configured RPC endpoints, as the clients see them.
+pub fn rpc_health() -> Vec<EndpointHealth> {
+    let transport = FailoverTransport::from_env(&Config::rpc_urls());
+    let now = Instant::now();
+    let primary = transport.pool.candidates().first().map(|provider| provider.url.clone());
+
+    transport.pool.providers
+        .iter()
+        .map(|provider| {
+            let state = provider.state.lock().unwrap();
+            let retry_in = state.unhealthy_until.and_then(|until| until.checked_duration_since(now));
+            let wrong_chain = transport.pool.chain_id.is_some_and(|expected| state.chain_id.is_some_and(|actual| actual != expected));
+            EndpointHealth {
+                endpoint: redact(&provider.url),
+                healthy: retry_in.is_none() && !wrong_chain,
+                primary: primary.as_deref() == Some(provider.url.as_str()),
+                failures: state.failures,
+                retry_in_secs: retry_in.map(|retry_in| retry_in.as_secs().max(1)),
+                chain_id: state.chain_id,
+            }
+        })
+        .collect()
+}
+
+/// The chain id of the configured RPC endpoints. With `CHAIN_ID` set, this
+/// goes through the same check as every call and fails when no endpoint is
+/// on that chain.
+pub async fn current_chain_id() -> anyhow::Result<u64> {
+    let client = Web3::new(FailoverTransport::from_env(&Config::rpc_urls()));
+    Ok(client.eth().chain_id().await?.as_u64())
+}
+
+fn redact(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => format!("{}://{}", scheme, rest.split(['/', '?']).next().unwrap_or_default()),
+        None => url.split(['/', '?']).next().unwrap_or_default().to_string(),
+    }
+}
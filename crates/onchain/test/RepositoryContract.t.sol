@@ -1,8 +1,9 @@
 // SPDX-License-Identifier: UNLICENSED
 pragma solidity ^0.8.13;
 
-import {Test, console} from "forge-std/Test.sol";
+import {Test, Vm, console} from "forge-std/Test.sol";
 import {RepositoryContract} from "../contracts/RepositoryContract.sol";
+import {IAccessControl} from "@openzeppelin/contracts/access/IAccessControl.sol";
 
 contract RepositoryContractTest is Test {
     RepositoryContract public repositoryContract;
@@ -69,6 +70,27 @@ contract RepositoryContractTest is Test {
         assertEq(repositoryContract.hasAdminRole(newAdmin), false);
     }
 
+    function test_transferAdminRole() public {
+        address newAdmin = makeAddr("newAdmin");
+
+        // Grant first, then revoke: the repository is never without an admin
+        repositoryContract.grantAdminRole(newAdmin);
+        assertEq(repositoryContract.hasAdminRole(admin), true);
+        assertEq(repositoryContract.hasAdminRole(newAdmin), true);
+
+        repositoryContract.revokeAdminRole(admin);
+        assertEq(repositoryContract.hasAdminRole(admin), false);
+        assertEq(repositoryContract.hasAdminRole(newAdmin), true);
+
+        // The old admin is locked out, the new one is in charge
+        vm.expectRevert("Caller is not an admin");
+        repositoryContract.grantAdminRole(admin);
+
+        vm.prank(newAdmin);
+        repositoryContract.grantPusherRole(newAdmin);
+        assertEq(repositoryContract.hasPusherRole(newAdmin), true);
+    }
+
     function test_onlyAdminCanGrantRoles() public {
         vm.prank(unauthorized);
         vm.expectRevert("Caller is not an admin");
@@ -89,6 +111,30 @@ contract RepositoryContractTest is Test {
         repositoryContract.revokeAdminRole(admin);
     }
 
+    function test_grantReaderRole() public {
+        address reader = makeAddr("reader");
+        repositoryContract.grantReaderRole(reader);
+        assertEq(repositoryContract.hasReaderRole(reader), true);
+        assertEq(repositoryContract.hasPusherRole(reader), false);
+    }
+
+    function test_revokeReaderRole() public {
+        address reader = makeAddr("reader");
+        repositoryContract.grantReaderRole(reader);
+        repositoryContract.revokeReaderRole(reader);
+        assertEq(repositoryContract.hasReaderRole(reader), false);
+    }
+
+    function test_onlyAdminCanManageReaderRole() public {
+        vm.prank(unauthorized);
+        vm.expectRevert("Caller is not an admin");
+        repositoryContract.grantReaderRole(unauthorized);
+
+        vm.prank(unauthorized);
+        vm.expectRevert("Caller is not an admin");
+        repositoryContract.revokeReaderRole(pusher1);
+    }
+
     // ============ Object Management Tests ============
 
     function test_saveObject() public {
@@ -181,6 +227,62 @@ contract RepositoryContractTest is Test {
         assertEq(results[2], true);
     }
 
+    function test_deactivateObjects() public {
+        vm.prank(pusher1);
+        repositoryContract.saveObject(HASH1, IPFS_URL1);
+        vm.prank(pusher1);
+        repositoryContract.saveObject(HASH2, IPFS_URL2);
+
+        string[] memory dangling = new string[](2);
+        dangling[0] = HASH2;
+        dangling[1] = HASH3; // Never saved, skipped
+
+        vm.expectEmit(true, true, true, true);
+        emit RepositoryContract.ObjectDeactivated(HASH2, admin);
+        repositoryContract.deactivateObjects(dangling);
+
+        assertEq(repositoryContract.isObjectActive(HASH1), true);
+        assertEq(repositoryContract.isObjectActive(HASH2), false);
+        assertEq(repositoryContract.isObjectActive(HASH3), false);
+
+        // Deactivated objects keep their data
+        assertEq(repositoryContract.isObjectExist(HASH2), true);
+        assertEq(repositoryContract.getObject(HASH2).ipfs_url, IPFS_URL2);
+        assertEq(repositoryContract.getObjectsLength(), 2);
+    }
+
+    function test_deactivateObjectsOnlyAdmin() public {
+        vm.prank(pusher1);
+        repositoryContract.saveObject(HASH1, IPFS_URL1);
+
+        string[] memory hashes = new string[](1);
+        hashes[0] = HASH1;
+
+        vm.prank(pusher1);
+        vm.expectRevert("Caller is not an admin");
+        repositoryContract.deactivateObjects(hashes);
+
+        assertEq(repositoryContract.isObjectActive(HASH1), true);
+    }
+
+    function test_pushingDeactivatedObjectReactivatesIt() public {
+        vm.prank(pusher1);
+        repositoryContract.saveObject(HASH1, IPFS_URL1);
+
+        string[] memory hashes = new string[](1);
+        hashes[0] = HASH1;
+        repositoryContract.deactivateObjects(hashes);
+        assertEq(repositoryContract.isObjectActive(HASH1), false);
+
+        bytes[] memory ipfsUrls = new bytes[](1);
+        ipfsUrls[0] = IPFS_URL1;
+        vm.prank(pusher2);
+        repositoryContract.addObjects(hashes, ipfsUrls);
+
+        assertEq(repositoryContract.isObjectActive(HASH1), true);
+        assertEq(repositoryContract.getObjectsLength(), 1);
+    }
+
     function test_getObjects() public {
         vm.prank(pusher1);
         repositoryContract.saveObject(HASH1, IPFS_URL1);
@@ -290,10 +392,75 @@ contract RepositoryContractTest is Test {
         assertEq(refs[1].name, REF2);
     }
 
+    function test_deactivateRefs() public {
+        vm.prank(pusher1);
+        repositoryContract.addRef(REF1, REF_DATA1);
+        vm.prank(pusher1);
+        repositoryContract.addRef(REF2, REF_DATA2);
+
+        string[] memory names = new string[](1);
+        names[0] = REF1;
+
+        vm.prank(pusher2);
+        vm.expectEmit(true, true, true, true);
+        emit RepositoryContract.RefDeactivated(REF1, pusher2);
+        repositoryContract.deactivateRefs(names);
+
+        (, , bool is_active, ) = repositoryContract.refs(REF1);
+        assertEq(is_active, false);
+        assertEq(repositoryContract.getRefById(0).is_active, false);
+        assertEq(repositoryContract.getRefById(1).is_active, true);
+    }
+
+    function test_deactivateRefsOnlyPusher() public {
+        string[] memory names = new string[](1);
+        names[0] = REF1;
+
+        vm.prank(unauthorized);
+        vm.expectRevert("Caller is not a pusher");
+        repositoryContract.deactivateRefs(names);
+    }
+
+    function test_deactivateRefsSkipsUnknownAndInactiveRefs() public {
+        vm.prank(pusher1);
+        repositoryContract.addRef(REF1, REF_DATA1);
+
+        string[] memory names = new string[](2);
+        names[0] = REF1;
+        names[1] = REF2;
+
+        vm.startPrank(pusher1);
+        repositoryContract.deactivateRefs(names);
+        vm.recordLogs();
+        repositoryContract.deactivateRefs(names);
+        vm.stopPrank();
+
+        assertEq(vm.getRecordedLogs().length, 0);
+        assertEq(repositoryContract.getRefsLength(), 1);
+    }
+
+    function test_pushingDeletedRefAddsItAgain() public {
+        vm.prank(pusher1);
+        repositoryContract.addRef(REF1, REF_DATA1);
+
+        string[] memory names = new string[](1);
+        names[0] = REF1;
+        vm.prank(pusher1);
+        repositoryContract.deactivateRefs(names);
+
+        vm.prank(pusher1);
+        repositoryContract.addRef(REF1, REF_DATA2);
+
+        assertEq(repositoryContract.getRefsLength(), 2);
+        assertEq(repositoryContract.getRefById(0).is_active, false);
+        RepositoryContract.Ref memory readded = repositoryContract.getRefById(1);
+        assertEq(readded.data, REF_DATA2);
+        assertEq(readded.is_active, true);
+    }
+
     // ============ Config Management Tests ============
 
     function test_updateConfig() public {
-        vm.prank(pusher1);
         vm.expectEmit(true, false, false, true);
         emit RepositoryContract.ConfigUpdated(CONFIG_DATA);
         repositoryContract.updateConfig(CONFIG_DATA);
@@ -302,9 +469,17 @@ contract RepositoryContractTest is Test {
         assertEq(config, CONFIG_DATA);
     }
 
-    function test_updateConfigOnlyPusher() public {
+    function test_updateConfigOnlyAdmin() public {
         vm.prank(unauthorized);
-        vm.expectRevert("Caller is not a pusher");
+        vm.expectRevert("Caller is not an admin");
+        repositoryContract.updateConfig(CONFIG_DATA);
+    }
+
+    // The config holds protection rules, signing keys, visibility and quotas,
+    // so a pusher must not be able to rewrite it.
+    function test_pusherCannotUpdateConfig() public {
+        vm.prank(pusher1);
+        vm.expectRevert("Caller is not an admin");
         repositoryContract.updateConfig(CONFIG_DATA);
     }
 
@@ -397,8 +572,9 @@ contract RepositoryContractTest is Test {
         vm.expectRevert("Caller is not a pusher");
         repositoryContract.addRef(REF1, REF_DATA1);
 
-        vm.expectRevert("Caller is not a pusher");
+        // The config is the admin's to change, pusher or not.
         repositoryContract.updateConfig(CONFIG_DATA);
+        assertEq(repositoryContract.getConfig(), CONFIG_DATA);
     }
 
     function test_pusherCannotGrantRoles() public {
@@ -451,9 +627,182 @@ contract RepositoryContractTest is Test {
     }
 
     function test_configUpdatedEventEmission() public {
-        vm.prank(pusher1);
         vm.expectEmit(true, false, false, true);
         emit RepositoryContract.ConfigUpdated(CONFIG_DATA);
         repositoryContract.updateConfig(CONFIG_DATA);
     }
+
+    // Role holders are listed off-chain by replaying these events, so every
+    // grant and revoke must emit exactly once and in call order.
+    function test_roleGrantedEventEmission() public {
+        bytes32 role = repositoryContract.PUSHER_ROLE();
+        vm.expectEmit(true, true, true, false);
+        emit IAccessControl.RoleGranted(role, pusher1, admin);
+        repositoryContract.grantPusherRole(pusher1);
+    }
+
+    function test_roleRevokedEventEmission() public {
+        bytes32 role = repositoryContract.PUSHER_ROLE();
+        repositoryContract.grantPusherRole(pusher1);
+
+        vm.expectEmit(true, true, true, false);
+        emit IAccessControl.RoleRevoked(role, pusher1, admin);
+        repositoryContract.revokePusherRole(pusher1);
+    }
+
+    function test_grantRevokeGrantEventOrder() public {
+        vm.recordLogs();
+        repositoryContract.grantPusherRole(pusher1);
+        repositoryContract.revokePusherRole(pusher1);
+        repositoryContract.grantPusherRole(pusher2);
+        // Granting a role twice must not emit a second event.
+        repositoryContract.grantPusherRole(pusher2);
+
+        Vm.Log[] memory logs = vm.getRecordedLogs();
+        assertEq(logs.length, 3);
+        assertEq(logs[0].topics[0], IAccessControl.RoleGranted.selector);
+        assertEq(logs[0].topics[2], bytes32(uint256(uint160(pusher1))));
+        assertEq(logs[1].topics[0], IAccessControl.RoleRevoked.selector);
+        assertEq(logs[1].topics[2], bytes32(uint256(uint160(pusher1))));
+        assertEq(logs[2].topics[0], IAccessControl.RoleGranted.selector);
+        assertEq(logs[2].topics[2], bytes32(uint256(uint160(pusher2))));
+
+        assertFalse(repositoryContract.hasPusherRole(pusher1));
+        assertTrue(repositoryContract.hasPusherRole(pusher2));
+    }
+
+    function test_objectEventsMatchObjectList() public {
+        string[] memory hashes = new string[](2);
+        bytes[] memory ipfsUrls = new bytes[](2);
+        hashes[0] = HASH1;
+        hashes[1] = HASH2;
+        ipfsUrls[0] = IPFS_URL1;
+        ipfsUrls[1] = IPFS_URL2;
+
+        vm.recordLogs();
+        vm.startPrank(pusher1);
+        repositoryContract.saveObject(HASH1, IPFS_URL1);
+        // Only HASH2 is new; the daemon's synced view counts on one
+        // ObjectSaved per entry appended to the object list.
+        repositoryContract.addObjects(hashes, ipfsUrls);
+        vm.stopPrank();
+
+        Vm.Log[] memory logs = vm.getRecordedLogs();
+        assertEq(logs.length, repositoryContract.getObjectsLength());
+        assertEq(logs[0].topics[0], RepositoryContract.ObjectSaved.selector);
+        assertEq(logs[1].topics[0], RepositoryContract.ObjectSaved.selector);
+        (string memory hash, , ) = abi.decode(logs[1].data, (string, bytes, address));
+        assertEq(hash, HASH2);
+    }
+
+    function test_refEventsReplayToRefList() public {
+        vm.recordLogs();
+        vm.startPrank(pusher1);
+        repositoryContract.addRef(REF1, REF_DATA1);
+        repositoryContract.addRef(REF2, REF_DATA2);
+        repositoryContract.addRef(REF1, REF_DATA3);
+        vm.stopPrank();
+
+        // Every update is an event; replaying them in order, in place by
+        // name, gives the ref list.
+        Vm.Log[] memory logs = vm.getRecordedLogs();
+        assertEq(logs.length, 3);
+        assertEq(repositoryContract.getRefsLength(), 2);
+        (string memory name, bytes memory data, ) = abi.decode(logs[2].data, (string, bytes, address));
+        assertEq(name, REF1);
+        assertEq(data, REF_DATA3);
+
+        RepositoryContract.Ref memory first = repositoryContract.getRefById(0);
+        assertEq(first.name, REF1);
+        assertEq(first.data, REF_DATA3);
+    }
+
+    function test_readByIdMatchesFullLists() public {
+        uint256 count = 300;
+        string[] memory hashes = new string[](count);
+        bytes[] memory ipfsUrls = new bytes[](count);
+        for (uint256 i = 0; i < count; i++) {
+            hashes[i] = vm.toString(i);
+            ipfsUrls[i] = abi.encodePacked("Qm", vm.toString(i));
+        }
+
+        vm.startPrank(pusher1);
+        repositoryContract.addObjects(hashes, ipfsUrls);
+        for (uint256 i = 0; i < 20; i++) {
+            repositoryContract.addRef(string.concat("refs/heads/b", vm.toString(i)), ipfsUrls[i]);
+        }
+        // An in-place update must show up the same through both getters.
+        repositoryContract.addRef("refs/heads/b3", ipfsUrls[42]);
+        vm.stopPrank();
+
+        // The daemon pages through getObjectById/getRefById for large
+        // repositories; the pages must line up with the full lists.
+        RepositoryContract.Object[] memory objects = repositoryContract.getObjects();
+        assertEq(objects.length, repositoryContract.getObjectsLength());
+        for (uint256 i = 0; i < objects.length; i++) {
+            RepositoryContract.Object memory byId = repositoryContract.getObjectById(i);
+            assertEq(byId.hash, objects[i].hash);
+            assertEq(byId.ipfs_url, objects[i].ipfs_url);
+        }
+
+        RepositoryContract.Ref[] memory refs = repositoryContract.getRefs();
+        assertEq(refs.length, repositoryContract.getRefsLength());
+        for (uint256 i = 0; i < refs.length; i++) {
+            RepositoryContract.Ref memory byId = repositoryContract.getRefById(i);
+            assertEq(byId.name, refs[i].name);
+            assertEq(byId.data, refs[i].data);
+        }
+        assertEq(refs[3].data, ipfsUrls[42]);
+    }
+
+    function test_failedBatchKeepsEarlierBatches() public {
+        uint256 batchSize = 50;
+        string[] memory hashes = new string[](batchSize * 2);
+        for (uint256 i = 0; i < hashes.length; i++) {
+            hashes[i] = string.concat("obj", vm.toString(i));
+        }
+        (string[] memory batch1, bytes[] memory urls1) = _objectBatch(hashes, 0, batchSize);
+        (string[] memory batch2, bytes[] memory urls2) = _objectBatch(hashes, batchSize, batchSize);
+
+        vm.prank(pusher1);
+        repositoryContract.addObjects(batch1, urls1);
+
+        // The second batch fails, e.g. because the daemon lost its role mid-push.
+        repositoryContract.revokePusherRole(pusher1);
+        vm.prank(pusher1);
+        vm.expectRevert();
+        repositoryContract.addObjects(batch2, urls2);
+
+        // The first batch is committed on its own; no ref points at it yet.
+        assertEq(repositoryContract.getObjectsLength(), batchSize);
+        assertEq(repositoryContract.getRefsLength(), 0);
+
+        // A retried push re-checks existence and only sends what is missing.
+        bool[] memory exists = repositoryContract.checkObjects(hashes);
+        for (uint256 i = 0; i < hashes.length; i++) {
+            assertEq(exists[i], i < batchSize);
+        }
+
+        repositoryContract.grantPusherRole(pusher1);
+        vm.startPrank(pusher1);
+        repositoryContract.addObjects(batch2, urls2);
+        repositoryContract.addRef(REF1, REF_DATA1);
+        vm.stopPrank();
+
+        assertEq(repositoryContract.getObjectsLength(), batchSize * 2);
+        assertEq(repositoryContract.getObjectById(batchSize).hash, batch2[0]);
+    }
+
+    function _objectBatch(string[] memory hashes, uint256 start, uint256 count)
+        internal
+        pure
+        returns (string[] memory batch, bytes[] memory ipfsUrls)
+    {
+        batch = new string[](count);
+        ipfsUrls = new bytes[](count);
+        for (uint256 i = 0; i < count; i++) {
+            batch[i] = hashes[start + i];
+            ipfsUrls[i] = abi.encodePacked("Qm", hashes[start + i]);
+        }
+    }
 }
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.13;
+
+import {Test} from "forge-std/Test.sol";
+import {RepositoryContract} from "../contracts/RepositoryContract.sol";
+import {RepositoryRegistry} from "../contracts/RepositoryRegistry.sol";
+
+contract RepositoryRegistryTest is Test {
+    RepositoryRegistry public registry;
+
+    address public daemon1;
+    address public daemon2;
+
+    string constant NAME = "alice/app";
+
+    function setUp() public {
+        registry = new RepositoryRegistry();
+        daemon1 = makeAddr("daemon1");
+        daemon2 = makeAddr("daemon2");
+    }
+
+    function test_createDeploysAtPredictedAddress() public {
+        address predicted = registry.predict(NAME);
+
+        vm.prank(daemon1);
+        address repository = registry.create(NAME);
+
+        assertEq(repository, predicted);
+        assertEq(registry.resolve(NAME), repository);
+    }
+
+    function test_createHandsRolesToCaller() public {
+        vm.prank(daemon1);
+        RepositoryContract repository = RepositoryContract(registry.create(NAME));
+
+        assertEq(repository.hasAdminRole(daemon1), true);
+        assertEq(repository.hasPusherRole(daemon1), true);
+        assertEq(repository.hasAdminRole(address(registry)), false);
+        assertEq(repository.hasPusherRole(address(registry)), false);
+    }
+
+    function test_secondDaemonResolvesSameRepository() public {
+        vm.prank(daemon1);
+        address repository = registry.create(NAME);
+
+        vm.prank(daemon2);
+        vm.expectRevert("Name already registered");
+        registry.create(NAME);
+
+        assertEq(registry.resolve(NAME), repository);
+    }
+
+    function test_unknownNameResolvesToZero() public view {
+        assertEq(registry.resolve("nobody/nothing"), address(0));
+    }
+
+    function test_registerExistingRepository() public {
+        vm.prank(daemon1);
+        RepositoryContract repository = new RepositoryContract();
+
+        vm.prank(daemon2);
+        vm.expectRevert("Caller is not an admin of the repository");
+        registry.register(NAME, address(repository));
+
+        vm.prank(daemon1);
+        registry.register(NAME, address(repository));
+        assertEq(registry.resolve(NAME), address(repository));
+    }
+}